@@ -0,0 +1,167 @@
+/*
+ * grpr - A CLI tool for recursively executing git commands.
+ *
+ * Copyright (c) 2025 Anupam Sengupta
+ *
+ * This source code is licensed under the MIT license found in the LICENSE file
+ * in the root directory of this source tree.
+ */
+
+use crate::grpgit;
+use crate::paths;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+const CACHE_FILE_NAME: &str = "discovery.toml";
+
+/// Repositories discovered under one root with one set of discovery options.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+struct CacheEntry {
+    root: PathBuf,
+    fingerprint: String,
+    modified_nanos: u64,
+    repositories: Vec<PathBuf>,
+}
+
+/// Previously discovered repositories, keyed by root, discovery options, and
+/// the root's modification time.
+#[derive(Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DiscoveryCache {
+    #[serde(default, rename = "entry")]
+    entries: Vec<CacheEntry>,
+}
+
+/// Returns the cache location, `$XDG_CACHE_HOME/grpr/discovery.toml`, falling
+/// back to `~/.cache/grpr/discovery.toml`.
+pub fn default_cache_path() -> Option<PathBuf> {
+    paths::cache_dir().map(|dir| dir.join(CACHE_FILE_NAME))
+}
+
+/// Returns the modification time of `root` in nanoseconds since the epoch.
+fn root_modified_nanos(root: &Path) -> Option<u64> {
+    let modified = fs::metadata(root).ok()?.modified().ok()?;
+    let nanos = modified.duration_since(UNIX_EPOCH).ok()?.as_nanos();
+    u64::try_from(nanos).ok()
+}
+
+impl DiscoveryCache {
+    /// Loads the cache at `path`. A missing file is an empty cache.
+    pub fn load(path: &Path) -> Result<Self, io::Error> {
+        let contents = match fs::read_to_string(path) {
+            Ok(contents) => contents,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(Self::default()),
+            Err(err) => return Err(err),
+        };
+
+        toml::from_str(&contents).map_err(|err| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("invalid discovery cache {}: {err}", path.display()),
+            )
+        })
+    }
+
+    /// Writes the cache to `path`, creating parent directories as needed.
+    pub fn save(&self, path: &Path) -> Result<(), io::Error> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        let contents = toml::to_string(self).map_err(io::Error::other)?;
+        fs::write(path, contents)
+    }
+
+    /// Returns the cached repositories for `root` when the entry was recorded
+    /// with the same `fingerprint`, the root has not been modified since, and
+    /// every cached repository still exists.
+    pub fn lookup(&self, root: &Path, fingerprint: &str) -> Option<Vec<PathBuf>> {
+        let modified_nanos = root_modified_nanos(root)?;
+        let entry = self
+            .entries
+            .iter()
+            .find(|entry| entry.root == root && entry.fingerprint == fingerprint)?;
+
+        let is_fresh = entry.modified_nanos == modified_nanos
+            && entry
+                .repositories
+                .iter()
+                .all(|repo_path| grpgit::detect_repository(repo_path).is_some());
+
+        is_fresh.then(|| entry.repositories.clone())
+    }
+
+    /// Records the repositories discovered under `root`, replacing any
+    /// previous entry for the same root and fingerprint.
+    pub fn store(&mut self, root: &Path, fingerprint: &str, repositories: &[PathBuf]) {
+        let Some(modified_nanos) = root_modified_nanos(root) else {
+            return;
+        };
+
+        self.entries
+            .retain(|entry| entry.root != root || entry.fingerprint != fingerprint);
+        self.entries.push(CacheEntry {
+            root: root.to_path_buf(),
+            fingerprint: fingerprint.to_string(),
+            modified_nanos,
+            repositories: repositories.to_vec(),
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    fn create_regular_repo(path: &Path) {
+        let git_dir = path.join(".git");
+        fs::create_dir_all(&git_dir).unwrap();
+        fs::write(git_dir.join("config"), "[core]\n").unwrap();
+    }
+
+    #[test]
+    fn lookup_returns_stored_repositories_for_unchanged_root() {
+        let dir = tempdir().unwrap();
+        let repo_dir = dir.path().join("repo");
+        fs::create_dir_all(&repo_dir).unwrap();
+        create_regular_repo(&repo_dir);
+
+        let mut cache = DiscoveryCache::default();
+        cache.store(dir.path(), "default", std::slice::from_ref(&repo_dir));
+
+        assert_eq!(cache.lookup(dir.path(), "default"), Some(vec![repo_dir]));
+        assert_eq!(cache.lookup(dir.path(), "nested=true"), None);
+    }
+
+    #[test]
+    fn lookup_misses_when_a_cached_repository_disappears() {
+        let dir = tempdir().unwrap();
+        let repo_dir = dir.path().join("repo");
+        fs::create_dir_all(&repo_dir).unwrap();
+        create_regular_repo(&repo_dir);
+
+        let mut cache = DiscoveryCache::default();
+        cache.store(dir.path(), "default", std::slice::from_ref(&repo_dir));
+        fs::remove_dir_all(repo_dir.join(".git")).unwrap();
+
+        assert_eq!(cache.lookup(dir.path(), "default"), None);
+    }
+
+    #[test]
+    fn save_and_load_round_trip_entries() {
+        let dir = tempdir().unwrap();
+        let cache_path = dir.path().join("cache").join("discovery.toml");
+        let mut cache = DiscoveryCache::default();
+        cache.store(dir.path(), "default", &[dir.path().join("repo")]);
+        cache.store(dir.path(), "default", &[]);
+        cache.save(&cache_path).unwrap();
+
+        let loaded = DiscoveryCache::load(&cache_path).unwrap();
+
+        assert_eq!(loaded, cache);
+        assert_eq!(loaded.entries.len(), 1);
+    }
+}
@@ -0,0 +1,410 @@
+/*
+ * grpr - A CLI tool for recursively executing git commands.
+ *
+ * Copyright (c) 2025 Anupam Sengupta
+ *
+ * This source code is licensed under the MIT license found in the LICENSE file
+ * in the root directory of this source tree.
+ */
+
+use crate::paths;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::env;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+const CONFIG_FILE_NAME: &str = "config.toml";
+
+/// Git commands that destroy work, which grpr refuses to run in several
+/// repositories without `--force` unless the config file lists others.
+const DEFAULT_DESTRUCTIVE_COMMANDS: [&str; 9] = [
+    "reset --hard",
+    "clean -f",
+    "clean --force",
+    "push --force",
+    "push -f",
+    "checkout .",
+    "restore .",
+    "branch -D",
+    "stash clear",
+];
+
+/// One or more directories configured under a single workspace name.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(untagged)]
+pub enum RootPaths {
+    One(String),
+    Many(Vec<String>),
+}
+
+/// One rule of a task: the git command to run in the repositories a filter
+/// expression selects.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct TaskRule {
+    /// A `--where` expression choosing the repositories. A rule without one
+    /// takes every repository no earlier rule took.
+    #[serde(default, rename = "where")]
+    pub condition: Option<String>,
+    /// The git command, written as in an alias.
+    pub command: String,
+}
+
+impl TaskRule {
+    /// Splits the rule's command into git arguments, followed by `rest`.
+    pub fn expand(&self, rest: &[String]) -> Vec<String> {
+        let mut expanded = split_words(&self.command);
+        expanded.extend_from_slice(rest);
+        expanded
+    }
+}
+
+/// User settings read from grpr's configuration file.
+#[derive(Debug, Default, PartialEq, Eq, Deserialize)]
+pub struct Config {
+    /// Named discovery roots, selected with `--workspace`.
+    #[serde(default)]
+    pub roots: BTreeMap<String, RootPaths>,
+    /// Named git commands, such as `up = "pull --rebase --autostash"`, run
+    /// as `grpr up`.
+    #[serde(default)]
+    pub aliases: BTreeMap<String, String>,
+    /// Named sets of grpr flags, such as `ci = "--fail-fast --net-jobs 4"`,
+    /// applied with `--profile`.
+    #[serde(default)]
+    pub profiles: BTreeMap<String, String>,
+    /// Named commands that differ between repositories, run as `grpr
+    /// <name>`. Each repository runs the command of the first rule that
+    /// selects it.
+    #[serde(default)]
+    pub tasks: BTreeMap<String, Vec<TaskRule>>,
+    /// Ordering constraints for `--dependency-order`: each repository
+    /// pattern maps to the patterns of the repositories it must run after.
+    #[serde(default)]
+    pub dependencies: BTreeMap<String, Vec<String>>,
+    /// Git invocations that need `--force` to run in several repositories,
+    /// replacing the built-in list.
+    #[serde(default)]
+    pub destructive_commands: Option<Vec<String>>,
+    /// The directory relative root paths are resolved against.
+    #[serde(skip)]
+    base_dir: PathBuf,
+}
+
+/// Returns the configuration file location, `$XDG_CONFIG_HOME/grpr/config.toml`,
+/// falling back to `~/.config/grpr/config.toml`.
+pub fn default_config_path() -> Option<PathBuf> {
+    paths::config_dir().map(|dir| dir.join(CONFIG_FILE_NAME))
+}
+
+/// Expands a leading `~` to the home directory and resolves relative paths
+/// against `base_dir`.
+fn resolve_path(path: &str, base_dir: &Path) -> PathBuf {
+    let home = || env::var_os("HOME").map(PathBuf::from);
+    let expanded = match path.strip_prefix('~') {
+        Some("") => home(),
+        Some(rest) if rest.starts_with('/') => {
+            home().map(|home| home.join(rest.trim_start_matches('/')))
+        }
+        _ => None,
+    };
+
+    base_dir.join(expanded.unwrap_or_else(|| PathBuf::from(path)))
+}
+
+/// Splits an alias into words at whitespace. Single or double quotes keep
+/// whitespace inside a word, and a backslash outside single quotes escapes
+/// the next character.
+fn split_words(line: &str) -> Vec<String> {
+    let mut words = Vec::new();
+    let mut word: Option<String> = None;
+    let mut quote = None;
+    let mut chars = line.chars();
+
+    while let Some(ch) = chars.next() {
+        match (quote, ch) {
+            (Some(open), ch) if ch == open => quote = None,
+            (Some('\''), ch) => word.get_or_insert_default().push(ch),
+            (_, '\\') => {
+                let escaped = chars.next().unwrap_or('\\');
+                word.get_or_insert_default().push(escaped);
+            }
+            (Some(_), ch) => word.get_or_insert_default().push(ch),
+            (None, '\'' | '"') => {
+                quote = Some(ch);
+                word.get_or_insert_default();
+            }
+            (None, ch) if ch.is_whitespace() => words.extend(word.take()),
+            (None, ch) => word.get_or_insert_default().push(ch),
+        }
+    }
+    words.extend(word);
+    words
+}
+
+impl Config {
+    /// Loads the configuration at `path`. A missing file is an empty
+    /// configuration.
+    pub fn load(path: &Path) -> Result<Self, io::Error> {
+        let contents = match fs::read_to_string(path) {
+            Ok(contents) => contents,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(Self::default()),
+            Err(err) => return Err(err),
+        };
+
+        let mut config: Self = toml::from_str(&contents).map_err(|err| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("invalid config {}: {err}", path.display()),
+            )
+        })?;
+        config.base_dir = path.parent().map(Path::to_path_buf).unwrap_or_default();
+        Ok(config)
+    }
+
+    /// Returns the roots configured for the workspace `name`, with `~`
+    /// expanded, or `None` when no such workspace is defined.
+    pub fn workspace_roots(&self, name: &str) -> Option<Vec<PathBuf>> {
+        let paths = match self.roots.get(name)? {
+            RootPaths::One(path) => std::slice::from_ref(path),
+            RootPaths::Many(paths) => paths.as_slice(),
+        };
+
+        Some(
+            paths
+                .iter()
+                .map(|path| resolve_path(path, &self.base_dir))
+                .collect(),
+        )
+    }
+
+    /// Expands a git command whose first word is an alias into the alias's
+    /// words followed by the remaining arguments. Returns `None` when the
+    /// command does not start with an alias. Aliases are expanded once, so
+    /// an alias may use a git command of the same name.
+    pub fn expand_alias(&self, args: &[String]) -> Option<Vec<String>> {
+        let (name, rest) = args.split_first()?;
+        let mut expanded = split_words(self.aliases.get(name)?);
+        expanded.extend_from_slice(rest);
+        Some(expanded)
+    }
+
+    /// Returns the rules of the task named by the first word of a git
+    /// command, with the remaining arguments, or `None` when the command
+    /// does not start with a task.
+    pub fn task<'a>(&self, args: &'a [String]) -> Option<(&[TaskRule], &'a [String])> {
+        let (name, rest) = args.split_first()?;
+        Some((self.tasks.get(name)?, rest))
+    }
+
+    /// Returns the git invocations that need `--force`, such as
+    /// `reset --hard`: the configured list, or the built-in one.
+    pub fn destructive_commands(&self) -> Vec<String> {
+        match &self.destructive_commands {
+            Some(commands) => commands.clone(),
+            None => DEFAULT_DESTRUCTIVE_COMMANDS.map(String::from).into(),
+        }
+    }
+
+    /// Returns the names of the configured workspaces, sorted.
+    pub fn workspace_names(&self) -> Vec<&str> {
+        self.roots.keys().map(String::as_str).collect()
+    }
+
+    /// Returns the flags of the profile `name`, split into words like an
+    /// alias, or `None` when there is no such profile.
+    pub fn profile(&self, name: &str) -> Option<Vec<String>> {
+        self.profiles.get(name).map(|flags| split_words(flags))
+    }
+
+    /// Returns the names of the configured profiles, sorted.
+    pub fn profile_names(&self) -> Vec<&str> {
+        self.profiles.keys().map(String::as_str).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn load_returns_empty_config_for_missing_file() {
+        let dir = tempdir().unwrap();
+
+        let config = Config::load(&dir.path().join("config.toml")).unwrap();
+
+        assert!(config.roots.is_empty());
+    }
+
+    #[test]
+    fn workspace_roots_accept_single_and_multiple_paths() {
+        let dir = tempdir().unwrap();
+        let config_path = dir.path().join("config.toml");
+        fs::write(
+            &config_path,
+            "[roots]\nwork = \"/src/work\"\noss = [\"/src/oss\", \"forks\"]\n",
+        )
+        .unwrap();
+
+        let config = Config::load(&config_path).unwrap();
+
+        assert_eq!(
+            config.workspace_roots("work"),
+            Some(vec![PathBuf::from("/src/work")])
+        );
+        assert_eq!(
+            config.workspace_roots("oss"),
+            Some(vec![PathBuf::from("/src/oss"), dir.path().join("forks")])
+        );
+        assert_eq!(config.workspace_roots("home"), None);
+        assert_eq!(config.workspace_names(), vec!["oss", "work"]);
+    }
+
+    #[test]
+    fn resolve_path_expands_home_directory() {
+        let Some(home) = env::var_os("HOME").map(PathBuf::from) else {
+            return;
+        };
+
+        assert_eq!(
+            resolve_path("~/src/work", Path::new("/etc")),
+            home.join("src/work")
+        );
+        assert_eq!(resolve_path("~", Path::new("/etc")), home);
+        assert_eq!(
+            resolve_path("~other/src", Path::new("/etc")),
+            PathBuf::from("/etc/~other/src")
+        );
+    }
+
+    #[test]
+    fn aliases_expand_into_git_arguments() {
+        let dir = tempdir().unwrap();
+        let config_path = dir.path().join("config.toml");
+        fs::write(
+            &config_path,
+            "[aliases]\nup = \"pull --rebase --autostash\"\nwip = \"commit -am 'work in progress'\"\n",
+        )
+        .unwrap();
+        let config = Config::load(&config_path).unwrap();
+        let args = |words: &[&str]| {
+            words
+                .iter()
+                .map(|word| word.to_string())
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            config.expand_alias(&args(&["up", "origin"])),
+            Some(args(&["pull", "--rebase", "--autostash", "origin"]))
+        );
+        assert_eq!(
+            config.expand_alias(&args(&["wip"])),
+            Some(args(&["commit", "-am", "work in progress"]))
+        );
+        assert_eq!(config.expand_alias(&args(&["pull"])), None);
+        assert_eq!(config.expand_alias(&[]), None);
+    }
+
+    #[test]
+    fn profiles_split_into_flags() {
+        let dir = tempdir().unwrap();
+        let config_path = dir.path().join("config.toml");
+        fs::write(
+            &config_path,
+            "[profiles]\nci = \"--fail-fast --net-jobs 4 --env 'GIT_TRACE=1'\"\n",
+        )
+        .unwrap();
+        let config = Config::load(&config_path).unwrap();
+
+        assert_eq!(
+            config.profile("ci"),
+            Some(
+                ["--fail-fast", "--net-jobs", "4", "--env", "GIT_TRACE=1"]
+                    .map(String::from)
+                    .into()
+            )
+        );
+        assert_eq!(config.profile("local"), None);
+        assert_eq!(config.profile_names(), vec!["ci"]);
+    }
+
+    #[test]
+    fn tasks_list_rules_in_order() {
+        let dir = tempdir().unwrap();
+        let config_path = dir.path().join("config.toml");
+        fs::write(
+            &config_path,
+            "[[tasks.sync-all]]\nwhere = 'remote =~ \"me/\"'\ncommand = \"pull --rebase\"\n\n\
+             [[tasks.sync-all]]\ncommand = \"fetch\"\n",
+        )
+        .unwrap();
+        let config = Config::load(&config_path).unwrap();
+        let args = ["sync-all".to_string(), "-q".to_string()];
+
+        let (rules, rest) = config.task(&args).unwrap();
+
+        assert_eq!(rules.len(), 2);
+        assert_eq!(rules[0].condition.as_deref(), Some("remote =~ \"me/\""));
+        assert_eq!(rules[0].expand(rest), vec!["pull", "--rebase", "-q"]);
+        assert_eq!(rules[1].condition, None);
+        assert!(config.task(&["fetch".to_string()]).is_none());
+    }
+
+    #[test]
+    fn dependencies_map_patterns_to_prerequisites() {
+        let dir = tempdir().unwrap();
+        let config_path = dir.path().join("config.toml");
+        fs::write(
+            &config_path,
+            "[dependencies]\n\"apps/*\" = [\"libs/core\", \"libs/ui\"]\n",
+        )
+        .unwrap();
+
+        let config = Config::load(&config_path).unwrap();
+
+        assert_eq!(
+            config.dependencies.get("apps/*"),
+            Some(&vec!["libs/core".to_string(), "libs/ui".to_string()])
+        );
+    }
+
+    #[test]
+    fn destructive_commands_default_to_the_built_in_list() {
+        let dir = tempdir().unwrap();
+        let config_path = dir.path().join("config.toml");
+
+        let config = Config::load(&config_path).unwrap();
+        assert!(
+            config
+                .destructive_commands()
+                .contains(&"reset --hard".to_string())
+        );
+
+        fs::write(&config_path, "destructive_commands = [\"push --force\"]\n").unwrap();
+        let config = Config::load(&config_path).unwrap();
+        assert_eq!(config.destructive_commands(), vec!["push --force"]);
+    }
+
+    #[test]
+    fn split_words_honors_quotes_and_escapes() {
+        assert_eq!(
+            split_words(r#"log --format="%h %s" a\ b '' 'it''s' "\"q\"""#),
+            vec!["log", "--format=%h %s", "a b", "", "its", "\"q\""]
+        );
+        assert!(split_words("   ").is_empty());
+    }
+
+    #[test]
+    fn load_rejects_malformed_config() {
+        let dir = tempdir().unwrap();
+        let config_path = dir.path().join("config.toml");
+        fs::write(&config_path, "roots = 3\n").unwrap();
+
+        let err = Config::load(&config_path).unwrap_err();
+
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+}
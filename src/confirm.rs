@@ -0,0 +1,116 @@
+/*
+ * grpr - A CLI tool for recursively executing git commands.
+ *
+ * Copyright (c) 2025 Anupam Sengupta
+ *
+ * This source code is licensed under the MIT license found in the LICENSE file
+ * in the root directory of this source tree.
+ */
+
+use std::io::{self, BufRead, Write};
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// What to do with a repository after asking the user.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Decision {
+    /// Run the command in this repository.
+    Run,
+    /// Leave this repository alone and ask about the next one.
+    Skip,
+    /// Run nothing more.
+    Quit,
+}
+
+/// Asks before the command runs in each repository. Answering "all" stops
+/// the questions and runs the command everywhere that is left.
+pub struct Confirmation {
+    command: String,
+    approve_all: AtomicBool,
+}
+
+impl Confirmation {
+    /// Creates a confirmation for `command`, as it should be shown to the user.
+    pub fn new(command: String) -> Self {
+        Self {
+            command,
+            approve_all: AtomicBool::new(false),
+        }
+    }
+
+    /// Asks on the terminal whether to run the command in `repo_path`.
+    pub fn ask(&self, repo_path: &Path) -> Result<Decision, io::Error> {
+        self.ask_with(repo_path, &mut io::stdin().lock(), &mut io::stderr())
+    }
+
+    /// Prompts on `output` and reads answers from `input` until one is
+    /// understood. The end of input quits.
+    fn ask_with(
+        &self,
+        repo_path: &Path,
+        input: &mut impl BufRead,
+        output: &mut impl Write,
+    ) -> Result<Decision, io::Error> {
+        if self.approve_all.load(Ordering::Relaxed) {
+            return Ok(Decision::Run);
+        }
+
+        loop {
+            write!(
+                output,
+                "run '{}' in {}? [y/N/a/q] ",
+                self.command,
+                repo_path.display()
+            )?;
+            output.flush()?;
+
+            let mut answer = String::new();
+            if input.read_line(&mut answer)? == 0 {
+                writeln!(output)?;
+                return Ok(Decision::Quit);
+            }
+            match answer.trim().to_ascii_lowercase().as_str() {
+                "y" | "yes" => return Ok(Decision::Run),
+                "" | "n" | "no" => return Ok(Decision::Skip),
+                "a" | "all" => {
+                    self.approve_all.store(true, Ordering::Relaxed);
+                    return Ok(Decision::Run);
+                }
+                "q" | "quit" => return Ok(Decision::Quit),
+                _ => writeln!(output, "please answer y, n, a, or q")?,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn confirmation_understands_each_answer() {
+        let confirmation = Confirmation::new("git reset --hard".to_string());
+        let repo_path = Path::new("/src/app");
+        let ask = |answers: &str| {
+            let mut output = Vec::new();
+            let decision = confirmation
+                .ask_with(repo_path, &mut answers.as_bytes(), &mut output)
+                .unwrap();
+            (decision, String::from_utf8(output).unwrap())
+        };
+
+        assert_eq!(
+            ask("y\n"),
+            (
+                Decision::Run,
+                "run 'git reset --hard' in /src/app? [y/N/a/q] ".to_string()
+            )
+        );
+        assert_eq!(ask("\n").0, Decision::Skip);
+        assert_eq!(ask("No\n").0, Decision::Skip);
+        assert_eq!(ask("maybe\nq\n").0, Decision::Quit);
+        assert_eq!(ask("").0, Decision::Quit);
+        assert_eq!(ask("a\n").0, Decision::Run);
+        assert_eq!(ask(""), (Decision::Run, String::new()));
+    }
+}
@@ -0,0 +1,500 @@
+/*
+ * grpr - A CLI tool for recursively executing git commands.
+ *
+ * Copyright (c) 2025 Anupam Sengupta
+ *
+ * This source code is licensed under the MIT license found in the LICENSE file
+ * in the root directory of this source tree.
+ */
+
+use crate::grpgit::{Captured, RepoCommand};
+use crate::interrupt;
+use ratatui::Frame;
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEventKind, KeyModifiers};
+use ratatui::layout::{Constraint, Layout};
+use ratatui::style::{Color, Modifier, Style};
+use ratatui::text::Line;
+use ratatui::widgets::{Block, Borders, Cell, Paragraph, Row, Table, TableState, Wrap};
+use std::collections::HashMap;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// How often the dashboard is redrawn while nothing is pressed.
+const REDRAW_INTERVAL: Duration = Duration::from_millis(200);
+
+/// Where a repository is in the run.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RepoState {
+    Queued,
+    Running,
+    Succeeded,
+    Failed,
+    /// Left out, with the reason.
+    Skipped(String),
+}
+
+impl RepoState {
+    fn label(&self) -> &'static str {
+        match self {
+            RepoState::Queued => "queued",
+            RepoState::Running => "running",
+            RepoState::Succeeded => "done",
+            RepoState::Failed => "failed",
+            RepoState::Skipped(_) => "skipped",
+        }
+    }
+
+    fn color(&self) -> Color {
+        match self {
+            RepoState::Queued => Color::DarkGray,
+            RepoState::Running => Color::Cyan,
+            RepoState::Succeeded => Color::Green,
+            RepoState::Failed => Color::Red,
+            RepoState::Skipped(_) => Color::Yellow,
+        }
+    }
+}
+
+/// One repository on the dashboard.
+#[derive(Debug)]
+struct Entry {
+    path: PathBuf,
+    state: RepoState,
+    started: Option<Instant>,
+    elapsed: Option<Duration>,
+    /// The command last run there, to run it again.
+    command: Option<RepoCommand>,
+    output: String,
+}
+
+/// How many repositories are in each state.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct Counts {
+    pub queued: usize,
+    pub running: usize,
+    pub succeeded: usize,
+    pub failed: usize,
+    pub skipped: usize,
+}
+
+/// The state of every repository in a run shown with `--tui`. Workers
+/// update it as commands start and finish while the dashboard draws it.
+#[derive(Debug)]
+pub struct Dashboard {
+    entries: Mutex<Vec<Entry>>,
+    positions: HashMap<PathBuf, usize>,
+    /// grpr's notes about the run, shown at the bottom instead of on
+    /// stderr.
+    notes: Mutex<Vec<String>>,
+}
+
+impl Dashboard {
+    /// Lists `repositories` as queued.
+    pub fn new(repositories: &[PathBuf]) -> Self {
+        let entries = repositories
+            .iter()
+            .map(|repo_path| Entry {
+                path: repo_path.clone(),
+                state: RepoState::Queued,
+                started: None,
+                elapsed: None,
+                command: None,
+                output: String::new(),
+            })
+            .collect();
+        let positions = repositories
+            .iter()
+            .enumerate()
+            .map(|(position, repo_path)| (repo_path.clone(), position))
+            .collect();
+        Self {
+            entries: Mutex::new(entries),
+            positions,
+            notes: Mutex::new(Vec::new()),
+        }
+    }
+
+    fn update(&self, repo_path: &Path, change: impl FnOnce(&mut Entry)) {
+        let Some(&position) = self.positions.get(repo_path) else {
+            return;
+        };
+        let mut entries = self.entries.lock().unwrap_or_else(|err| err.into_inner());
+        change(&mut entries[position]);
+    }
+
+    /// Marks the command as running in `repo_path`.
+    pub fn started(&self, repo_path: &Path) {
+        self.update(repo_path, |entry| {
+            entry.state = RepoState::Running;
+            entry.started = Some(Instant::now());
+            entry.elapsed = None;
+        });
+    }
+
+    /// Records the captured run of `command` in `repo_path`.
+    pub fn finished(&self, repo_path: &Path, command: &RepoCommand, captured: &Captured) {
+        let mut output = String::from_utf8_lossy(&captured.stdout).into_owned();
+        output.push_str(&String::from_utf8_lossy(&captured.stderr));
+        if let Err(err) = &captured.result {
+            output.push_str(&format!("\n{err}\n"));
+        }
+        self.update(repo_path, |entry| {
+            entry.state = if captured.result.is_ok() {
+                RepoState::Succeeded
+            } else {
+                RepoState::Failed
+            };
+            entry.elapsed = entry.started.map(|started| started.elapsed());
+            entry.command = Some(command.clone());
+            entry.output = output;
+        });
+    }
+
+    /// Records that the command is not run in `repo_path` because of
+    /// `reason`.
+    pub fn skipped(&self, repo_path: &Path, reason: &str) {
+        self.update(repo_path, |entry| {
+            entry.state = RepoState::Skipped(reason.to_string());
+        });
+    }
+
+    /// Adds a note about the run.
+    pub fn note(&self, message: &str) {
+        self.notes
+            .lock()
+            .unwrap_or_else(|err| err.into_inner())
+            .push(message.to_string());
+    }
+
+    /// Returns the command to run again in `repo_path` when it failed there.
+    pub fn failed_command(&self, repo_path: &Path) -> Option<RepoCommand> {
+        let position = *self.positions.get(repo_path)?;
+        let entries = self.entries.lock().unwrap_or_else(|err| err.into_inner());
+        let entry = &entries[position];
+        (entry.state == RepoState::Failed)
+            .then(|| entry.command.clone())
+            .flatten()
+    }
+
+    /// Counts the repositories in each state.
+    pub fn counts(&self) -> Counts {
+        let entries = self.entries.lock().unwrap_or_else(|err| err.into_inner());
+        let mut counts = Counts::default();
+        for entry in entries.iter() {
+            match entry.state {
+                RepoState::Queued => counts.queued += 1,
+                RepoState::Running => counts.running += 1,
+                RepoState::Succeeded => counts.succeeded += 1,
+                RepoState::Failed => counts.failed += 1,
+                RepoState::Skipped(_) => counts.skipped += 1,
+            }
+        }
+        counts
+    }
+
+    fn path_at(&self, position: usize) -> Option<PathBuf> {
+        let entries = self.entries.lock().unwrap_or_else(|err| err.into_inner());
+        entries.get(position).map(|entry| entry.path.clone())
+    }
+
+    fn len(&self) -> usize {
+        self.positions.len()
+    }
+}
+
+/// What the user has done on the dashboard.
+#[derive(Debug, Default)]
+struct View {
+    table: TableState,
+    /// How far the output of the selected repository is scrolled.
+    scroll: u16,
+    /// How many times the run was asked to stop.
+    aborts: usize,
+}
+
+/// Formats how long a command took or has been running.
+fn format_duration(elapsed: Duration) -> String {
+    format!("{:.1}s", elapsed.as_secs_f64())
+}
+
+/// Draws the dashboard: a summary line, the table of repositories beside
+/// the output of the selected one, and the keys at the bottom.
+fn render(frame: &mut Frame, dashboard: &Dashboard, view: &mut View, title: &str, done: bool) {
+    let [top, middle, bottom] = Layout::vertical([
+        Constraint::Length(1),
+        Constraint::Min(3),
+        Constraint::Length(1),
+    ])
+    .areas(frame.area());
+    let [left, right] =
+        Layout::horizontal([Constraint::Percentage(45), Constraint::Percentage(55)]).areas(middle);
+
+    let counts = dashboard.counts();
+    let finished = counts.succeeded + counts.failed + counts.skipped;
+    let mut summary = format!(
+        " {title}  {finished}/{} done, {} failed, {} running",
+        dashboard.len(),
+        counts.failed,
+        counts.running
+    );
+    if done {
+        summary.push_str("  (finished)");
+    } else if view.aborts > 0 {
+        summary.push_str("  (stopping)");
+    }
+    frame.render_widget(
+        Paragraph::new(summary).style(Style::default().add_modifier(Modifier::BOLD)),
+        top,
+    );
+
+    let entries = dashboard
+        .entries
+        .lock()
+        .unwrap_or_else(|err| err.into_inner());
+    let rows = entries.iter().map(|entry| {
+        let elapsed = match (&entry.state, entry.elapsed, entry.started) {
+            (RepoState::Running, _, Some(started)) => format_duration(started.elapsed()),
+            (_, Some(elapsed), _) => format_duration(elapsed),
+            _ => String::new(),
+        };
+        Row::new([
+            Cell::from(entry.state.label()).style(Style::default().fg(entry.state.color())),
+            Cell::from(entry.path.display().to_string()),
+            Cell::from(elapsed),
+        ])
+    });
+    let table = Table::new(
+        rows,
+        [
+            Constraint::Length(8),
+            Constraint::Min(10),
+            Constraint::Length(8),
+        ],
+    )
+    .header(
+        Row::new(["State", "Repository", "Time"])
+            .style(Style::default().add_modifier(Modifier::BOLD)),
+    )
+    .block(
+        Block::default()
+            .borders(Borders::ALL)
+            .title(" Repositories "),
+    )
+    .row_highlight_style(Style::default().add_modifier(Modifier::REVERSED));
+    frame.render_stateful_widget(table, left, &mut view.table);
+
+    let selected = view
+        .table
+        .selected()
+        .and_then(|position| entries.get(position));
+    let (detail_title, detail) = match selected {
+        Some(entry) => {
+            let detail = match &entry.state {
+                RepoState::Queued => "Waiting to run.".to_string(),
+                RepoState::Running => "Running...".to_string(),
+                RepoState::Skipped(reason) => format!("Skipped: {reason}"),
+                RepoState::Succeeded | RepoState::Failed if entry.output.trim().is_empty() => {
+                    "(no output)".to_string()
+                }
+                RepoState::Succeeded | RepoState::Failed => entry.output.clone(),
+            };
+            (format!(" {} ", entry.path.display()), detail)
+        }
+        None => (" Output ".to_string(), String::new()),
+    };
+    drop(entries);
+    frame.render_widget(
+        Paragraph::new(detail)
+            .block(Block::default().borders(Borders::ALL).title(detail_title))
+            .wrap(Wrap { trim: false })
+            .scroll((view.scroll, 0)),
+        right,
+    );
+
+    let keys = if done {
+        "q quit"
+    } else if view.aborts > 0 {
+        "q stop running commands"
+    } else {
+        "q stop the run"
+    };
+    let notes = dashboard
+        .notes
+        .lock()
+        .unwrap_or_else(|err| err.into_inner());
+    let mut footer = format!(" ↑/↓ select  PgUp/PgDn scroll  r retry a failed repository  {keys}");
+    if let Some(note) = notes.last() {
+        footer.push_str(&format!("  | {note}"));
+    }
+    frame.render_widget(
+        Line::from(footer).style(Style::default().fg(Color::DarkGray)),
+        bottom,
+    );
+}
+
+/// Moves the selection by `delta` rows within the `len` repositories.
+fn select(view: &mut View, len: usize, delta: isize) {
+    if len == 0 {
+        return;
+    }
+    let current = view.table.selected().unwrap_or(0);
+    let next = current.saturating_add_signed(delta).min(len - 1);
+    if view.table.selected() != Some(next) {
+        view.scroll = 0;
+    }
+    view.table.select(Some(next));
+}
+
+/// Shows `dashboard` on the terminal until the user quits once the run is
+/// `done`. `retry` runs the command again in a failed repository. Quitting
+/// before the run is done stops it like Ctrl-C: first no further
+/// repositories are started, and then the running commands are stopped.
+pub fn show(
+    dashboard: &Dashboard,
+    title: &str,
+    done: impl Fn() -> bool,
+    mut retry: impl FnMut(&Path),
+) -> io::Result<()> {
+    let mut terminal = ratatui::try_init()?;
+    let mut view = View::default();
+    select(&mut view, dashboard.len(), 0);
+    let result = loop {
+        let finished = done();
+        // The run ended before it started, such as when it was refused.
+        if finished && dashboard.counts().queued == dashboard.len() {
+            break Ok(());
+        }
+        if let Err(err) =
+            terminal.draw(|frame| render(frame, dashboard, &mut view, title, finished))
+        {
+            break Err(err);
+        }
+        match event::poll(REDRAW_INTERVAL) {
+            Ok(false) => continue,
+            Ok(true) => {}
+            Err(err) => break Err(err),
+        }
+        let key = match event::read() {
+            Ok(Event::Key(key)) if key.kind == KeyEventKind::Press => key,
+            Ok(_) => continue,
+            Err(err) => break Err(err),
+        };
+        let quit = matches!(key.code, KeyCode::Char('q') | KeyCode::Esc)
+            || key.code == KeyCode::Char('c') && key.modifiers.contains(KeyModifiers::CONTROL);
+        match key.code {
+            _ if quit => {
+                if done() {
+                    break Ok(());
+                }
+                if view.aborts < 2 {
+                    view.aborts += 1;
+                    interrupt::request();
+                }
+            }
+            KeyCode::Up | KeyCode::Char('k') => select(&mut view, dashboard.len(), -1),
+            KeyCode::Down | KeyCode::Char('j') => select(&mut view, dashboard.len(), 1),
+            KeyCode::Home => select(&mut view, dashboard.len(), isize::MIN),
+            KeyCode::End => select(&mut view, dashboard.len(), isize::MAX),
+            KeyCode::PageUp => view.scroll = view.scroll.saturating_sub(10),
+            KeyCode::PageDown => view.scroll = view.scroll.saturating_add(10),
+            KeyCode::Char('r') if view.aborts == 0 => {
+                let selected = view.table.selected();
+                if let Some(repo_path) = selected.and_then(|position| dashboard.path_at(position)) {
+                    retry(&repo_path);
+                }
+            }
+            _ => {}
+        }
+    };
+    ratatui::try_restore()?;
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ratatui::Terminal;
+    use ratatui::backend::TestBackend;
+
+    fn captured(failed: bool) -> Captured {
+        Captured {
+            stdout: b"fetched\n".to_vec(),
+            stderr: Vec::new(),
+            exit_code: Some(i32::from(failed)),
+            result: if failed {
+                Err(io::Error::other("git command failed"))
+            } else {
+                Ok(())
+            },
+            spilled: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn dashboard_follows_each_repository_through_the_run() {
+        let repositories: Vec<PathBuf> = ["/src/a", "/src/b", "/src/c", "/src/d"]
+            .map(PathBuf::from)
+            .into();
+        let dashboard = Dashboard::new(&repositories);
+        let command = RepoCommand::git(vec!["fetch".to_string()]);
+
+        dashboard.started(&repositories[0]);
+        dashboard.finished(&repositories[0], &command, &captured(false));
+        dashboard.started(&repositories[1]);
+        dashboard.finished(&repositories[1], &command, &captured(true));
+        dashboard.started(&repositories[2]);
+        dashboard.skipped(&repositories[3], "dirty");
+
+        assert_eq!(
+            dashboard.counts(),
+            Counts {
+                queued: 0,
+                running: 1,
+                succeeded: 1,
+                failed: 1,
+                skipped: 1,
+            }
+        );
+        assert_eq!(dashboard.failed_command(&repositories[0]), None);
+        assert_eq!(dashboard.failed_command(&repositories[1]), Some(command));
+    }
+
+    #[test]
+    fn render_shows_the_table_and_the_selected_output() {
+        let repositories: Vec<PathBuf> = ["/src/a", "/src/b"].map(PathBuf::from).into();
+        let dashboard = Dashboard::new(&repositories);
+        let command = RepoCommand::git(vec!["fetch".to_string()]);
+        dashboard.started(&repositories[0]);
+        dashboard.finished(&repositories[0], &command, &captured(true));
+        let mut view = View::default();
+        select(&mut view, dashboard.len(), 0);
+
+        let mut terminal = Terminal::new(TestBackend::new(100, 10)).unwrap();
+        terminal
+            .draw(|frame| render(frame, &dashboard, &mut view, "git fetch", false))
+            .unwrap();
+        let screen: String = terminal
+            .backend()
+            .buffer()
+            .content()
+            .iter()
+            .map(|cell| cell.symbol())
+            .collect();
+
+        assert!(screen.contains("git fetch  1/2 done, 1 failed, 0 running"));
+        assert!(screen.contains("failed   /src/a"));
+        assert!(screen.contains("queued   /src/b"));
+        assert!(screen.contains("fetched"));
+    }
+
+    #[test]
+    fn selection_stays_within_the_table() {
+        let mut view = View::default();
+        select(&mut view, 3, isize::MAX);
+        assert_eq!(view.table.selected(), Some(2));
+        select(&mut view, 3, isize::MIN);
+        assert_eq!(view.table.selected(), Some(0));
+    }
+}
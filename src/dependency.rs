@@ -0,0 +1,305 @@
+/*
+ * grpr - A CLI tool for recursively executing git commands.
+ *
+ * Copyright (c) 2025 Anupam Sengupta
+ *
+ * This source code is licensed under the MIT license found in the LICENSE file
+ * in the root directory of this source tree.
+ */
+
+use crate::selector::relative_path;
+use globset::{Glob, GlobMatcher, GlobSet, GlobSetBuilder};
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, BinaryHeap};
+use std::path::PathBuf;
+use std::sync::{Condvar, Mutex, MutexGuard};
+use std::thread;
+
+/// Ordering constraints between repositories from the config file: the
+/// repositories matching a pattern run only after those matching each of its
+/// prerequisite patterns have succeeded. Patterns are globs matched against
+/// repository paths relative to their root.
+#[derive(Debug)]
+pub struct Dependencies {
+    rules: Vec<(GlobMatcher, GlobSet)>,
+}
+
+fn glob(pattern: &str) -> Result<Glob, String> {
+    Glob::new(pattern).map_err(|err| format!("invalid dependency pattern `{pattern}`: {err}"))
+}
+
+impl Dependencies {
+    /// Compiles the `[dependencies]` table, which maps a pattern to the
+    /// patterns of its prerequisites.
+    pub fn new(rules: &BTreeMap<String, Vec<String>>) -> Result<Self, String> {
+        let rules = rules
+            .iter()
+            .map(|(dependent, prerequisites)| {
+                let mut builder = GlobSetBuilder::new();
+                for prerequisite in prerequisites {
+                    builder.add(glob(prerequisite)?);
+                }
+                let prerequisites = builder.build().map_err(|err| err.to_string())?;
+                Ok((glob(dependent)?.compile_matcher(), prerequisites))
+            })
+            .collect::<Result<_, String>>()?;
+        Ok(Self { rules })
+    }
+
+    /// Returns, for each of `repositories`, the indices of the repositories
+    /// it depends on. Fails when the dependencies form a cycle, naming the
+    /// repositories that cannot run because of it.
+    pub fn graph(
+        &self,
+        repositories: &[PathBuf],
+        roots: &[PathBuf],
+    ) -> Result<DependencyGraph, String> {
+        let paths: Vec<String> = repositories
+            .iter()
+            .map(|repo_path| relative_path(repo_path, roots))
+            .collect();
+        let prerequisites: Vec<Vec<usize>> = paths
+            .iter()
+            .enumerate()
+            .map(|(index, path)| {
+                let rules: Vec<&GlobSet> = self
+                    .rules
+                    .iter()
+                    .filter(|(dependent, _)| dependent.is_match(path))
+                    .map(|(_, prerequisites)| prerequisites)
+                    .collect();
+                (0..paths.len())
+                    .filter(|other| *other != index)
+                    .filter(|other| rules.iter().any(|rules| rules.is_match(&paths[*other])))
+                    .collect()
+            })
+            .collect();
+
+        let graph = DependencyGraph::new(prerequisites);
+        let cycle = graph.unordered();
+        if !cycle.is_empty() {
+            let names: Vec<&str> = cycle.iter().map(|index| paths[*index].as_str()).collect();
+            return Err(format!(
+                "cannot order {}: their dependencies form a cycle",
+                names.join(", ")
+            ));
+        }
+        Ok(graph)
+    }
+}
+
+/// Which repositories each repository of a run waits for.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DependencyGraph {
+    prerequisites: Vec<Vec<usize>>,
+    dependents: Vec<Vec<usize>>,
+}
+
+/// Progress through a [`DependencyGraph`] while it runs.
+struct Progress {
+    /// How many prerequisites of each repository have not finished.
+    waiting: Vec<usize>,
+    /// A prerequisite of each repository that failed or was blocked.
+    failed_prerequisite: Vec<Option<usize>>,
+    /// Repositories whose prerequisites have all succeeded, lowest first.
+    ready: BinaryHeap<Reverse<usize>>,
+    /// Repositories that have neither finished nor been blocked.
+    unfinished: usize,
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(|err| err.into_inner())
+}
+
+impl DependencyGraph {
+    fn new(prerequisites: Vec<Vec<usize>>) -> Self {
+        let mut dependents = vec![Vec::new(); prerequisites.len()];
+        for (index, prerequisites) in prerequisites.iter().enumerate() {
+            for prerequisite in prerequisites {
+                dependents[*prerequisite].push(index);
+            }
+        }
+        Self {
+            prerequisites,
+            dependents,
+        }
+    }
+
+    /// Returns the repositories that can never start because they are on,
+    /// or wait for, a cycle.
+    fn unordered(&self) -> Vec<usize> {
+        let mut waiting: Vec<usize> = self.prerequisites.iter().map(Vec::len).collect();
+        let mut ready: Vec<usize> = (0..waiting.len()).filter(|i| waiting[*i] == 0).collect();
+        while let Some(index) = ready.pop() {
+            for dependent in &self.dependents[index] {
+                waiting[*dependent] -= 1;
+                if waiting[*dependent] == 0 {
+                    ready.push(*dependent);
+                }
+            }
+        }
+        (0..waiting.len()).filter(|i| waiting[*i] > 0).collect()
+    }
+
+    /// Runs every repository with `run` on up to `threads` threads, each
+    /// only after all of its prerequisites have run and `run` reported
+    /// success for them. Repositories whose prerequisite failed are passed
+    /// to `blocked` with that prerequisite instead of being run. Among ready
+    /// repositories, those listed first start first.
+    pub fn execute(
+        &self,
+        threads: usize,
+        run: impl Fn(usize) -> bool + Sync,
+        blocked: impl Fn(usize, usize) + Sync,
+    ) {
+        let waiting: Vec<usize> = self.prerequisites.iter().map(Vec::len).collect();
+        let ready = (0..waiting.len())
+            .filter(|index| waiting[*index] == 0)
+            .map(Reverse)
+            .collect();
+        let progress = Mutex::new(Progress {
+            unfinished: waiting.len(),
+            failed_prerequisite: vec![None; waiting.len()],
+            waiting,
+            ready,
+        });
+        let changed = Condvar::new();
+
+        let worker = || {
+            loop {
+                let index = {
+                    let progress = lock(&progress);
+                    let mut progress = changed
+                        .wait_while(progress, |progress| {
+                            progress.ready.is_empty() && progress.unfinished > 0
+                        })
+                        .unwrap_or_else(|err| err.into_inner());
+                    match progress.ready.pop() {
+                        Some(Reverse(index)) => index,
+                        None => return,
+                    }
+                };
+                let succeeded = run(index);
+                self.finish(&mut lock(&progress), index, succeeded, &blocked);
+                changed.notify_all();
+            }
+        };
+        thread::scope(|scope| {
+            for _ in 0..threads.max(1) {
+                scope.spawn(worker);
+            }
+        });
+    }
+
+    /// Records that `index` has finished, releasing or blocking the
+    /// repositories that wait for it.
+    fn finish(
+        &self,
+        progress: &mut Progress,
+        index: usize,
+        succeeded: bool,
+        blocked: &impl Fn(usize, usize),
+    ) {
+        progress.unfinished -= 1;
+        for dependent in &self.dependents[index] {
+            if !succeeded {
+                progress.failed_prerequisite[*dependent].get_or_insert(index);
+            }
+            progress.waiting[*dependent] -= 1;
+            if progress.waiting[*dependent] > 0 {
+                continue;
+            }
+            match progress.failed_prerequisite[*dependent] {
+                Some(prerequisite) => {
+                    blocked(*dependent, prerequisite);
+                    self.finish(progress, *dependent, false, blocked);
+                }
+                None => progress.ready.push(Reverse(*dependent)),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    fn dependencies(rules: &[(&str, &[&str])]) -> Dependencies {
+        let rules = rules
+            .iter()
+            .map(|(dependent, prerequisites)| {
+                let prerequisites = prerequisites.iter().map(|p| p.to_string()).collect();
+                (dependent.to_string(), prerequisites)
+            })
+            .collect();
+        Dependencies::new(&rules).unwrap()
+    }
+
+    fn repositories(names: &[&str]) -> (Vec<PathBuf>, Vec<PathBuf>) {
+        let root = PathBuf::from("/src");
+        let repositories = names.iter().map(|name| root.join(name)).collect();
+        (repositories, vec![root])
+    }
+
+    #[test]
+    fn graph_links_matching_repositories() {
+        let (repositories, roots) = repositories(&["apps/web", "apps/cli", "libs/core"]);
+        let dependencies = dependencies(&[("apps/*", &["libs/core"])]);
+
+        let graph = dependencies.graph(&repositories, &roots).unwrap();
+
+        assert_eq!(graph.prerequisites, vec![vec![2], vec![2], vec![]]);
+        assert_eq!(graph.dependents, vec![vec![], vec![], vec![0, 1]]);
+    }
+
+    #[test]
+    fn graph_rejects_cycles() {
+        let (repositories, roots) = repositories(&["a", "b", "c"]);
+        let dependencies = dependencies(&[("a", &["b"]), ("b", &["a"])]);
+
+        let err = dependencies.graph(&repositories, &roots).unwrap_err();
+
+        assert_eq!(err, "cannot order a, b: their dependencies form a cycle");
+    }
+
+    #[test]
+    fn execute_runs_prerequisites_first() {
+        // 0 and 1 wait for 2, which waits for 3.
+        let graph = DependencyGraph::new(vec![vec![2], vec![2], vec![3], vec![]]);
+        let finished = Mutex::new(Vec::new());
+
+        graph.execute(
+            4,
+            |index| {
+                lock(&finished).push(index);
+                true
+            },
+            |_, _| panic!("nothing is blocked"),
+        );
+
+        let finished = finished.into_inner().unwrap();
+        assert_eq!(finished[..2], [3, 2]);
+        assert_eq!(finished.len(), 4);
+    }
+
+    #[test]
+    fn execute_blocks_dependents_of_failures() {
+        // 1 waits for 0, and 2 waits for 1.
+        let graph = DependencyGraph::new(vec![vec![], vec![0], vec![1], vec![]]);
+        let runs = AtomicUsize::new(0);
+        let blocked = Mutex::new(Vec::new());
+
+        graph.execute(
+            2,
+            |index| {
+                runs.fetch_add(1, Ordering::SeqCst);
+                index != 0
+            },
+            |index, prerequisite| lock(&blocked).push((index, prerequisite)),
+        );
+
+        assert_eq!(runs.load(Ordering::SeqCst), 2);
+        assert_eq!(blocked.into_inner().unwrap(), vec![(1, 0), (2, 1)]);
+    }
+}
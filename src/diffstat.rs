@@ -0,0 +1,495 @@
+/*
+ * grpr - A CLI tool for recursively executing git commands.
+ *
+ * Copyright (c) 2025 Anupam Sengupta
+ *
+ * This source code is licensed under the MIT license found in the LICENSE file
+ * in the root directory of this source tree.
+ */
+
+use crate::grpgit;
+use crate::output::{self, Highlight};
+use std::collections::BTreeSet;
+use std::fmt;
+use std::io::{self, Write};
+use std::ops::AddAssign;
+use std::path::Path;
+
+/// The commands whose output is rendered with colors and statistics.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChangeKind {
+    /// `git status`, in its long or short format.
+    Status,
+    /// `git diff`, as a patch or a `--stat`.
+    Diff,
+}
+
+/// The changes shown by `status` or `diff`, counted as by `git diff
+/// --shortstat`.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct DiffStat {
+    pub files: usize,
+    /// Lines added; `status` does not show them, see [`status_lines`].
+    pub insertions: usize,
+    /// Lines removed; `status` does not show them either.
+    pub deletions: usize,
+}
+
+impl DiffStat {
+    /// Returns true when no file changed.
+    pub fn is_empty(&self) -> bool {
+        self.files == 0
+    }
+}
+
+impl AddAssign for DiffStat {
+    fn add_assign(&mut self, other: Self) {
+        self.files += other.files;
+        self.insertions += other.insertions;
+        self.deletions += other.deletions;
+    }
+}
+
+/// Formats `count` with the `singular` or `plural` noun to match.
+fn counted(count: usize, singular: &str, plural: &str) -> String {
+    let noun = if count == 1 { singular } else { plural };
+    format!("{count} {noun}")
+}
+
+impl fmt::Display for DiffStat {
+    /// Formats the changes as git does, such as `2 files changed, 5
+    /// insertions(+), 1 deletion(-)`, leaving out line counts of zero.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} changed", counted(self.files, "file", "files"))?;
+        if self.insertions > 0 {
+            write!(
+                f,
+                ", {}",
+                counted(self.insertions, "insertion(+)", "insertions(+)")
+            )?;
+        }
+        if self.deletions > 0 {
+            write!(
+                f,
+                ", {}",
+                counted(self.deletions, "deletion(-)", "deletions(-)")
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Parses the summary line of `git diff --stat` or `--shortstat`, such as
+/// ` 2 files changed, 5 insertions(+), 1 deletion(-)`.
+fn parse_shortstat(line: &str) -> Option<DiffStat> {
+    let mut parts = line.trim().split(", ");
+    let files = parts.next()?;
+    let files = files
+        .strip_suffix(" files changed")
+        .or_else(|| files.strip_suffix(" file changed"))?
+        .parse()
+        .ok()?;
+    let mut stat = DiffStat {
+        files,
+        ..DiffStat::default()
+    };
+    for part in parts {
+        let (count, noun) = part.split_once(' ')?;
+        let count = count.parse().ok()?;
+        match noun {
+            "insertion(+)" | "insertions(+)" => stat.insertions = count,
+            "deletion(-)" | "deletions(-)" => stat.deletions = count,
+            _ => return None,
+        }
+    }
+    Some(stat)
+}
+
+/// Where a line of a diff falls, which tells its headers from its changed
+/// lines.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum DiffSection {
+    /// Before the first file, or in the output of `--stat`.
+    Outside,
+    /// The headers of a file, from its `diff` line to its first hunk.
+    Header,
+    Hunk,
+}
+
+/// Reads a diff line by line, keeping track of where it is up to.
+struct DiffLines {
+    section: DiffSection,
+}
+
+impl DiffLines {
+    fn new() -> Self {
+        Self {
+            section: DiffSection::Outside,
+        }
+    }
+
+    /// Returns how `line`, the next one, is colored.
+    fn classify(&mut self, line: &str) -> Highlight {
+        if line.starts_with("diff ") {
+            self.section = DiffSection::Header;
+            return Highlight::Meta;
+        }
+        if line.starts_with("@@") && self.section != DiffSection::Outside {
+            self.section = DiffSection::Hunk;
+            return Highlight::Hunk;
+        }
+        match self.section {
+            DiffSection::Outside => Highlight::Plain,
+            DiffSection::Header => Highlight::Meta,
+            DiffSection::Hunk => match line.bytes().next() {
+                Some(b'+') => Highlight::Added,
+                Some(b'-') => Highlight::Removed,
+                Some(b' ' | b'\\') | None => Highlight::Plain,
+                Some(_) => {
+                    self.section = DiffSection::Outside;
+                    Highlight::Plain
+                }
+            },
+        }
+    }
+}
+
+/// The labels git puts before the paths in the long format of `status`.
+const STATUS_LABELS: [&str; 13] = [
+    "modified",
+    "new file",
+    "deleted",
+    "renamed",
+    "copied",
+    "typechange",
+    "both modified",
+    "both added",
+    "both deleted",
+    "added by us",
+    "added by them",
+    "deleted by us",
+    "deleted by them",
+];
+
+/// Returns true when `line` is an entry of the short format of `status`,
+/// such as ` M src/main.rs` or `?? notes.txt`.
+fn is_short_entry(line: &str) -> bool {
+    let bytes = line.as_bytes();
+    bytes.len() > 3
+        && bytes[2] == b' '
+        && bytes[..2].iter().all(|code| b" MTADRCU?!".contains(code))
+}
+
+/// Returns the path in `line` of `status` output, if it lists one.
+fn status_path(line: &str) -> Option<&str> {
+    if let Some(entry) = line.strip_prefix('\t') {
+        let path = entry
+            .split_once(':')
+            .filter(|(label, _)| STATUS_LABELS.contains(label))
+            .map_or(entry, |(_, path)| path.trim_start());
+        return Some(path);
+    }
+    is_short_entry(line).then(|| &line[3..])
+}
+
+/// Counts the changes in the `output` of a command of `kind`: the files and
+/// lines of a diff, or the files `status` lists.
+pub fn count(kind: ChangeKind, output: &[u8]) -> DiffStat {
+    let output = String::from_utf8_lossy(output);
+    match kind {
+        ChangeKind::Status => DiffStat {
+            files: output
+                .lines()
+                .filter_map(status_path)
+                .collect::<BTreeSet<_>>()
+                .len(),
+            ..DiffStat::default()
+        },
+        ChangeKind::Diff => {
+            let mut stat = DiffStat::default();
+            let mut shortstat = None;
+            let mut lines = DiffLines::new();
+            for line in output.lines() {
+                match lines.classify(line) {
+                    Highlight::Meta if line.starts_with("diff ") => stat.files += 1,
+                    Highlight::Added => stat.insertions += 1,
+                    Highlight::Removed => stat.deletions += 1,
+                    Highlight::Plain => shortstat = parse_shortstat(line).or(shortstat),
+                    _ => {}
+                }
+            }
+            // Only a `--stat` or `--shortstat` has no patch to count.
+            match shortstat {
+                Some(shortstat) if stat.is_empty() => shortstat,
+                _ => stat,
+            }
+        }
+    }
+}
+
+/// Counts the lines changed in `repo_path` since `HEAD`, staged or not, to
+/// go with the files `status` lists. Untracked files add no lines. Returns
+/// `None` before the first commit, or when git fails.
+pub fn status_lines(repo_path: &Path) -> Option<DiffStat> {
+    let shortstat = grpgit::git_output(
+        repo_path,
+        &[
+            "diff",
+            "--no-ext-diff",
+            "--no-textconv",
+            "--shortstat",
+            "HEAD",
+        ],
+    )?;
+    Some(parse_shortstat(&shortstat).unwrap_or_default())
+}
+
+/// Writes the short format `line` of `status` to `out`, with the staged
+/// change in green, and the change that is not staged in red.
+fn render_short_entry(out: &mut String, line: &str) {
+    let (staged, rest) = line.split_at(1);
+    let (unstaged, rest) = rest.split_at(1);
+    let staged_highlight = if matches!(staged, "?" | "!") {
+        Highlight::Removed
+    } else {
+        Highlight::Added
+    };
+    out.push_str(&output::highlight(staged, staged_highlight));
+    out.push_str(&output::highlight(unstaged, Highlight::Removed));
+    out.push_str(rest);
+}
+
+/// Returns the `output` of a command of `kind` colored as git colors it on a
+/// terminal, when stdout is colored.
+pub fn render(kind: ChangeKind, output: &[u8]) -> Vec<u8> {
+    let Ok(text) = std::str::from_utf8(output) else {
+        return output.to_vec();
+    };
+    let mut rendered = String::with_capacity(text.len());
+    let mut lines = DiffLines::new();
+    let mut staged = false;
+    for line in text.split_inclusive('\n') {
+        let (line, end) = match line.strip_suffix('\n') {
+            Some(line) => (line, "\n"),
+            None => (line, ""),
+        };
+        match kind {
+            ChangeKind::Diff => {
+                rendered.push_str(&output::highlight(line, lines.classify(line)));
+            }
+            ChangeKind::Status if is_short_entry(line) => {
+                render_short_entry(&mut rendered, line);
+            }
+            ChangeKind::Status => {
+                if line.ends_with(':') && !line.starts_with('\t') {
+                    staged = line == "Changes to be committed:";
+                }
+                let highlight = match (line.starts_with('\t'), staged) {
+                    (false, _) => Highlight::Plain,
+                    (true, true) => Highlight::Added,
+                    (true, false) => Highlight::Removed,
+                };
+                rendered.push_str(&output::highlight(line, highlight));
+            }
+        }
+        rendered.push_str(end);
+    }
+    rendered.into_bytes()
+}
+
+/// Writes the changes in each of the `repositories` with any, by name and
+/// aligned, then their total across the run.
+pub fn write_fleet(mut out: impl Write, repositories: &[(String, DiffStat)]) -> io::Result<()> {
+    let changed: Vec<&(String, DiffStat)> = repositories
+        .iter()
+        .filter(|(_, stat)| !stat.is_empty())
+        .collect();
+    if changed.is_empty() {
+        return Ok(());
+    }
+    let width = changed
+        .iter()
+        .map(|(name, _)| name.chars().count())
+        .max()
+        .unwrap_or_default();
+    let mut total = DiffStat::default();
+    for (name, stat) in &changed {
+        writeln!(out, " {name:<width$} | {stat}")?;
+        total += *stat;
+    }
+    writeln!(
+        out,
+        " {total} in {}",
+        counted(changed.len(), "repository", "repositories")
+    )?;
+    out.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::git;
+
+    const PATCH: &str = "diff --git a/app.rs b/app.rs\n\
+                         index 1234567..89abcde 100644\n\
+                         --- a/app.rs\n\
+                         +++ b/app.rs\n\
+                         @@ -1,3 +1,3 @@\n\
+                         \x20fn main() {\n\
+                         -    old();\n\
+                         --- a removed comment\n\
+                         +    new();\n\
+                         +    more();\n\
+                         \x20}\n\
+                         diff --git a/notes.txt b/notes.txt\n\
+                         new file mode 100644\n\
+                         --- /dev/null\n\
+                         +++ b/notes.txt\n\
+                         @@ -0,0 +1 @@\n\
+                         +hello\n";
+
+    #[test]
+    fn diff_counts_files_and_changed_lines() {
+        assert_eq!(
+            count(ChangeKind::Diff, PATCH.as_bytes()),
+            DiffStat {
+                files: 2,
+                insertions: 3,
+                deletions: 2,
+            }
+        );
+    }
+
+    #[test]
+    fn diff_lines_tell_headers_from_changes() {
+        let mut lines = DiffLines::new();
+        let highlights: Vec<Highlight> = PATCH.lines().map(|line| lines.classify(line)).collect();
+        assert_eq!(
+            highlights[..8],
+            [
+                Highlight::Meta,
+                Highlight::Meta,
+                Highlight::Meta,
+                Highlight::Meta,
+                Highlight::Hunk,
+                Highlight::Plain,
+                Highlight::Removed,
+                Highlight::Removed,
+            ]
+        );
+    }
+
+    #[test]
+    fn diff_stat_summary_is_counted_without_a_patch() {
+        let output = " app.rs | 4 ++--\n 1 file changed, 2 insertions(+), 2 deletions(-)\n";
+        assert_eq!(
+            count(ChangeKind::Diff, output.as_bytes()),
+            DiffStat {
+                files: 1,
+                insertions: 2,
+                deletions: 2,
+            }
+        );
+        assert_eq!(
+            parse_shortstat(" 3 files changed, 1 deletion(-)")
+                .unwrap()
+                .deletions,
+            1
+        );
+        assert_eq!(parse_shortstat("3 files were changed"), None);
+    }
+
+    #[test]
+    fn status_counts_each_listed_file_once() {
+        let long = "On branch main\n\
+                    Changes to be committed:\n\
+                    \x20 (use \"git restore --staged <file>...\" to unstage)\n\
+                    \tmodified:   app.rs\n\
+                    \tnew file:   notes.txt\n\
+                    \n\
+                    Changes not staged for commit:\n\
+                    \tmodified:   app.rs\n\
+                    \n\
+                    Untracked files:\n\
+                    \tscratch/\n";
+        assert_eq!(count(ChangeKind::Status, long.as_bytes()).files, 3);
+
+        let short = "## main\nM  app.rs\n M lib.rs\n?? scratch/\n";
+        assert_eq!(count(ChangeKind::Status, short.as_bytes()).files, 3);
+        assert!(
+            count(
+                ChangeKind::Status,
+                b"nothing to commit, working tree clean\n"
+            )
+            .is_empty()
+        );
+    }
+
+    #[test]
+    fn status_lines_count_staged_and_unstaged_changes() {
+        let dir = tempfile::tempdir().unwrap();
+        let git = |args: &[&str]| git(dir.path(), args);
+        git(&["init", "--quiet"]);
+        assert_eq!(status_lines(dir.path()), None);
+
+        std::fs::write(dir.path().join("app.rs"), "one\ntwo\n").unwrap();
+        git(&["add", "app.rs"]);
+        git(&["commit", "--quiet", "-m", "first"]);
+        assert_eq!(status_lines(dir.path()), Some(DiffStat::default()));
+
+        std::fs::write(dir.path().join("app.rs"), "one\n2\nthree\n").unwrap();
+        std::fs::write(dir.path().join("notes.txt"), "new\n").unwrap();
+        git(&["add", "notes.txt"]);
+        std::fs::write(dir.path().join("scratch.txt"), "untracked\n").unwrap();
+        assert_eq!(
+            status_lines(dir.path()),
+            Some(DiffStat {
+                files: 2,
+                insertions: 3,
+                deletions: 1,
+            })
+        );
+    }
+
+    #[test]
+    fn render_keeps_the_output_without_colors() {
+        assert_eq!(render(ChangeKind::Diff, PATCH.as_bytes()), PATCH.as_bytes());
+        let short = "M  app.rs\n?? scratch/";
+        assert_eq!(
+            render(ChangeKind::Status, short.as_bytes()),
+            short.as_bytes()
+        );
+    }
+
+    #[test]
+    fn fleet_lists_changed_repositories_and_the_total() {
+        let repositories = [
+            (
+                "app".to_string(),
+                DiffStat {
+                    files: 2,
+                    insertions: 5,
+                    deletions: 1,
+                },
+            ),
+            ("clean".to_string(), DiffStat::default()),
+            (
+                "libs/core".to_string(),
+                DiffStat {
+                    files: 1,
+                    insertions: 0,
+                    deletions: 2,
+                },
+            ),
+        ];
+
+        let mut out = Vec::new();
+        write_fleet(&mut out, &repositories).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            " app       | 2 files changed, 5 insertions(+), 1 deletion(-)\n \
+             libs/core | 1 file changed, 2 deletions(-)\n \
+             3 files changed, 5 insertions(+), 3 deletions(-) in 2 repositories\n"
+        );
+    }
+}
@@ -0,0 +1,170 @@
+/*
+ * grpr - A CLI tool for recursively executing git commands.
+ *
+ * Copyright (c) 2025 Anupam Sengupta
+ *
+ * This source code is licensed under the MIT license found in the LICENSE file
+ * in the root directory of this source tree.
+ */
+
+use std::time::{Duration, SystemTime};
+
+const SECS_PER_MINUTE: u64 = 60;
+const SECS_PER_HOUR: u64 = 60 * SECS_PER_MINUTE;
+const SECS_PER_DAY: u64 = 24 * SECS_PER_HOUR;
+const SECS_PER_WEEK: u64 = 7 * SECS_PER_DAY;
+
+/// Parses a duration such as `90s`, `15m`, `12h`, `30d`, or `2w`. A number
+/// without a unit is a number of days.
+pub fn parse_duration(value: &str) -> Result<Duration, String> {
+    parse_with_default_unit(value, SECS_PER_DAY)
+}
+
+/// Parses a short pause, as [`parse_duration`] does, except that a number
+/// without a unit is a number of seconds.
+pub fn parse_delay(value: &str) -> Result<Duration, String> {
+    parse_with_default_unit(value, 1)
+}
+
+/// Parses a duration, reading a number without a unit as a number of
+/// `default_unit_secs`.
+fn parse_with_default_unit(value: &str, default_unit_secs: u64) -> Result<Duration, String> {
+    let value = value.trim();
+    let split = value
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(value.len());
+    let (amount, unit) = value.split_at(split);
+
+    let amount: u64 = amount
+        .parse()
+        .map_err(|_| format!("invalid duration `{value}`: expected a number and a unit"))?;
+    let unit_secs = match unit {
+        "s" => 1,
+        "m" => SECS_PER_MINUTE,
+        "h" => SECS_PER_HOUR,
+        "" => default_unit_secs,
+        "d" => SECS_PER_DAY,
+        "w" => SECS_PER_WEEK,
+        _ => {
+            return Err(format!(
+                "invalid duration `{value}`: unit must be one of s, m, h, d, w"
+            ));
+        }
+    };
+
+    amount
+        .checked_mul(unit_secs)
+        .map(Duration::from_secs)
+        .ok_or_else(|| format!("invalid duration `{value}`: too large"))
+}
+
+/// Parses a point in time given either as a `YYYY-MM-DD` date, meaning
+/// midnight UTC at its start, or as a duration before now, as accepted by
+/// [`parse_duration`].
+pub fn parse_time(value: &str) -> Result<SystemTime, String> {
+    let value = value.trim();
+    if value.contains('-') {
+        return parse_date(value);
+    }
+
+    let ago = parse_duration(value)?;
+    SystemTime::now()
+        .checked_sub(ago)
+        .ok_or_else(|| format!("invalid time `{value}`: too far in the past"))
+}
+
+/// Parses a `YYYY-MM-DD` date as midnight UTC at its start.
+fn parse_date(value: &str) -> Result<SystemTime, String> {
+    let invalid = || format!("invalid date `{value}`: expected YYYY-MM-DD");
+    let mut parts = value.splitn(3, '-');
+    let mut next = |len: usize| {
+        parts
+            .next()
+            .filter(|part| part.len() == len && part.bytes().all(|b| b.is_ascii_digit()))
+            .and_then(|part| part.parse::<u32>().ok())
+            .ok_or_else(invalid)
+    };
+    let (year, month, day) = (next(4)?, next(2)?, next(2)?);
+
+    if !(1..=12).contains(&month) || day == 0 || day > days_in_month(year, month) {
+        return Err(invalid());
+    }
+    let days = days_since_epoch(year, month, day);
+    let secs = u64::try_from(days)
+        .map_err(|_| format!("invalid date `{value}`: before 1970-01-01"))?
+        * SECS_PER_DAY;
+    Ok(SystemTime::UNIX_EPOCH + Duration::from_secs(secs))
+}
+
+fn days_in_month(year: u32, month: u32) -> u32 {
+    match month {
+        2 if year % 4 == 0 && (year % 100 != 0 || year % 400 == 0) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+/// Counts the days from 1970-01-01 to the given date in the proleptic
+/// Gregorian calendar.
+fn days_since_epoch(year: u32, month: u32, day: u32) -> i64 {
+    let (year, month, day) = (i64::from(year), i64::from(month), i64::from(day));
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146_097 + day_of_era - 719_468
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_duration_accepts_each_unit() {
+        assert_eq!(parse_duration("90s"), Ok(Duration::from_secs(90)));
+        assert_eq!(parse_duration("15m"), Ok(Duration::from_secs(15 * 60)));
+        assert_eq!(parse_duration("12h"), Ok(Duration::from_secs(12 * 3600)));
+        assert_eq!(parse_duration("30d"), Ok(Duration::from_secs(30 * 86400)));
+        assert_eq!(parse_duration("2w"), Ok(Duration::from_secs(14 * 86400)));
+        assert_eq!(parse_duration("7"), Ok(Duration::from_secs(7 * 86400)));
+    }
+
+    #[test]
+    fn parse_delay_reads_a_bare_number_as_seconds() {
+        assert_eq!(parse_delay("5"), Ok(Duration::from_secs(5)));
+        assert_eq!(parse_delay("5s"), Ok(Duration::from_secs(5)));
+        assert_eq!(parse_delay("2m"), Ok(Duration::from_secs(120)));
+        assert!(parse_delay("5x").is_err());
+    }
+
+    #[test]
+    fn parse_time_accepts_dates_and_durations() {
+        let at = |secs| SystemTime::UNIX_EPOCH + Duration::from_secs(secs);
+
+        assert_eq!(parse_time("1970-01-01"), Ok(at(0)));
+        assert_eq!(parse_time("2000-03-01"), Ok(at(951_868_800)));
+        assert_eq!(parse_time("2024-02-29"), Ok(at(1_709_164_800)));
+        let week_ago = parse_time("1w").unwrap();
+        assert!(week_ago < SystemTime::now() - Duration::from_secs(6 * 86400));
+    }
+
+    #[test]
+    fn parse_time_rejects_invalid_dates() {
+        assert!(parse_time("2023-02-29").is_err());
+        assert!(parse_time("2024-13-01").is_err());
+        assert!(parse_time("2024-1-01").is_err());
+        assert!(parse_time("1969-12-31").is_err());
+        assert!(parse_time("yesterday").is_err());
+    }
+
+    #[test]
+    fn parse_duration_rejects_malformed_values() {
+        assert!(parse_duration("").is_err());
+        assert!(parse_duration("d").is_err());
+        assert!(parse_duration("3y").is_err());
+        assert!(parse_duration("1.5h").is_err());
+        assert!(parse_duration("99999999999999999999w").is_err());
+    }
+}
@@ -0,0 +1,565 @@
+/*
+ * grpr - A CLI tool for recursively executing git commands.
+ *
+ * Copyright (c) 2025 Anupam Sengupta
+ *
+ * This source code is licensed under the MIT license found in the LICENSE file
+ * in the root directory of this source tree.
+ */
+
+use regex::Regex;
+use std::fmt;
+
+/// A repository property that filter expressions can refer to by name.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Attribute {
+    /// The checked-out branch; unknown on a detached `HEAD`.
+    Branch,
+    /// Whether the working tree has uncommitted changes.
+    Dirty,
+    /// Whether `HEAD` points at a commit rather than a branch.
+    Detached,
+    /// Commits on the branch that its upstream lacks; unknown without one.
+    Ahead,
+    /// Commits on the upstream that the branch lacks; unknown without one.
+    Behind,
+    /// The `origin` URL; unknown without an `origin` remote.
+    Remote,
+    /// The repository path relative to the root it was found under.
+    Path,
+    /// The tags pointing at `HEAD`.
+    Tags,
+}
+
+impl Attribute {
+    const ALL: [Attribute; 8] = [
+        Attribute::Branch,
+        Attribute::Dirty,
+        Attribute::Detached,
+        Attribute::Ahead,
+        Attribute::Behind,
+        Attribute::Remote,
+        Attribute::Path,
+        Attribute::Tags,
+    ];
+
+    /// The name the attribute has in expressions.
+    pub fn name(self) -> &'static str {
+        match self {
+            Attribute::Branch => "branch",
+            Attribute::Dirty => "dirty",
+            Attribute::Detached => "detached",
+            Attribute::Ahead => "ahead",
+            Attribute::Behind => "behind",
+            Attribute::Remote => "remote",
+            Attribute::Path => "path",
+            Attribute::Tags => "tags",
+        }
+    }
+
+    fn from_name(name: &str) -> Option<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|attribute| attribute.name() == name)
+    }
+
+    fn kind(self) -> Kind {
+        match self {
+            Attribute::Dirty | Attribute::Detached => Kind::Bool,
+            Attribute::Ahead | Attribute::Behind => Kind::Int,
+            Attribute::Branch | Attribute::Remote | Attribute::Path => Kind::Str,
+            Attribute::Tags => Kind::List,
+        }
+    }
+}
+
+/// The value of an attribute for one repository.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Value {
+    Bool(bool),
+    Int(i64),
+    Str(String),
+    List(Vec<String>),
+    /// The attribute does not apply or could not be read. Unknown values
+    /// are false and never compare equal, unequal, or ordered.
+    Unknown,
+}
+
+/// The type of a value, checked when an expression is parsed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Kind {
+    Bool,
+    Int,
+    Str,
+    List,
+}
+
+impl fmt::Display for Kind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Kind::Bool => "a boolean",
+            Kind::Int => "a number",
+            Kind::Str => "a string",
+            Kind::List => "a list",
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Comparison {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+/// An operand of a comparison.
+#[derive(Debug, Clone)]
+enum Term {
+    Attribute(Attribute),
+    Literal(Value),
+}
+
+/// A parsed and type-checked filter expression, such as
+/// `dirty && branch == "main"`.
+#[derive(Debug, Clone)]
+pub struct Expr {
+    source: String,
+    node: Node,
+}
+
+#[derive(Debug, Clone)]
+enum Node {
+    Term(Term),
+    Not(Box<Node>),
+    And(Box<Node>, Box<Node>),
+    Or(Box<Node>, Box<Node>),
+    Compare(Term, Comparison, Term),
+    Matches(Term, Regex),
+    In(Term, Term),
+}
+
+impl Expr {
+    /// Evaluates the expression, looking up attributes with `lookup`.
+    /// Attributes are only looked up when the outcome depends on them.
+    pub fn evaluate(&self, lookup: &mut impl FnMut(Attribute) -> Value) -> bool {
+        self.node.evaluate(lookup)
+    }
+}
+
+impl fmt::Display for Expr {
+    /// Writes the expression as it was given.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.source)
+    }
+}
+
+impl Node {
+    fn evaluate(&self, lookup: &mut impl FnMut(Attribute) -> Value) -> bool {
+        match self {
+            Node::Term(term) => term.value(lookup) == Value::Bool(true),
+            Node::Not(expr) => !expr.evaluate(lookup),
+            Node::And(left, right) => left.evaluate(lookup) && right.evaluate(lookup),
+            Node::Or(left, right) => left.evaluate(lookup) || right.evaluate(lookup),
+            Node::Compare(left, comparison, right) => {
+                compare(&left.value(lookup), *comparison, &right.value(lookup))
+            }
+            Node::Matches(term, regex) => match term.value(lookup) {
+                Value::Str(value) => regex.is_match(&value),
+                _ => false,
+            },
+            Node::In(needle, list) => match (needle.value(lookup), list.value(lookup)) {
+                (Value::Str(needle), Value::List(items)) => items.contains(&needle),
+                _ => false,
+            },
+        }
+    }
+}
+
+impl Term {
+    fn value(&self, lookup: &mut impl FnMut(Attribute) -> Value) -> Value {
+        match self {
+            Term::Attribute(attribute) => lookup(*attribute),
+            Term::Literal(value) => value.clone(),
+        }
+    }
+}
+
+fn compare(left: &Value, comparison: Comparison, right: &Value) -> bool {
+    let ordering = match (left, right) {
+        (Value::Bool(left), Value::Bool(right)) => left.cmp(right),
+        (Value::Int(left), Value::Int(right)) => left.cmp(right),
+        (Value::Str(left), Value::Str(right)) => left.cmp(right),
+        _ => return false,
+    };
+
+    match comparison {
+        Comparison::Eq => ordering.is_eq(),
+        Comparison::Ne => ordering.is_ne(),
+        Comparison::Lt => ordering.is_lt(),
+        Comparison::Le => ordering.is_le(),
+        Comparison::Gt => ordering.is_gt(),
+        Comparison::Ge => ordering.is_ge(),
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Token {
+    Ident(String),
+    Str(String),
+    Int(i64),
+    LParen,
+    RParen,
+    Not,
+    And,
+    Or,
+    Compare(Comparison),
+    Matches,
+    In,
+}
+
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Token::Ident(name) => write!(f, "`{name}`"),
+            Token::Str(value) => write!(f, "{value:?}"),
+            Token::Int(value) => write!(f, "`{value}`"),
+            Token::LParen => f.write_str("`(`"),
+            Token::RParen => f.write_str("`)`"),
+            Token::Not => f.write_str("`!`"),
+            Token::And => f.write_str("`&&`"),
+            Token::Or => f.write_str("`||`"),
+            Token::Compare(comparison) => f.write_str(match comparison {
+                Comparison::Eq => "`==`",
+                Comparison::Ne => "`!=`",
+                Comparison::Lt => "`<`",
+                Comparison::Le => "`<=`",
+                Comparison::Gt => "`>`",
+                Comparison::Ge => "`>=`",
+            }),
+            Token::Matches => f.write_str("`=~`"),
+            Token::In => f.write_str("`in`"),
+        }
+    }
+}
+
+/// Splits an expression into tokens.
+fn tokenize(input: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = Vec::new();
+    let mut chars = input.char_indices().peekable();
+
+    while let Some((start, c)) = chars.next() {
+        let mut followed_by = |next: char| chars.next_if(|&(_, c)| c == next).is_some();
+        let token = match c {
+            _ if c.is_whitespace() => continue,
+            '(' => Token::LParen,
+            ')' => Token::RParen,
+            '&' if followed_by('&') => Token::And,
+            '|' if followed_by('|') => Token::Or,
+            '=' if followed_by('=') => Token::Compare(Comparison::Eq),
+            '=' if followed_by('~') => Token::Matches,
+            '!' if followed_by('=') => Token::Compare(Comparison::Ne),
+            '!' => Token::Not,
+            '<' if followed_by('=') => Token::Compare(Comparison::Le),
+            '<' => Token::Compare(Comparison::Lt),
+            '>' if followed_by('=') => Token::Compare(Comparison::Ge),
+            '>' => Token::Compare(Comparison::Gt),
+            '"' | '\'' => {
+                let mut value = String::new();
+                loop {
+                    match chars.next() {
+                        Some((_, end)) if end == c => break,
+                        Some((_, '\\')) => match chars.next() {
+                            Some((_, escaped)) => value.push(escaped),
+                            None => return Err("unterminated string".to_string()),
+                        },
+                        Some((_, other)) => value.push(other),
+                        None => return Err("unterminated string".to_string()),
+                    }
+                }
+                Token::Str(value)
+            }
+            _ if c.is_ascii_digit() => {
+                let mut end = start + c.len_utf8();
+                while let Some((index, digit)) = chars.next_if(|(_, c)| c.is_ascii_digit()) {
+                    end = index + digit.len_utf8();
+                }
+                let digits = &input[start..end];
+                Token::Int(
+                    digits
+                        .parse()
+                        .map_err(|_| format!("number `{digits}` is too large"))?,
+                )
+            }
+            _ if c.is_alphabetic() || c == '_' => {
+                let mut end = start + c.len_utf8();
+                while let Some((index, next)) =
+                    chars.next_if(|(_, c)| c.is_alphanumeric() || *c == '_')
+                {
+                    end = index + next.len_utf8();
+                }
+                match &input[start..end] {
+                    "in" => Token::In,
+                    name => Token::Ident(name.to_string()),
+                }
+            }
+            _ => return Err(format!("unexpected `{c}`")),
+        };
+        tokens.push(token);
+    }
+
+    Ok(tokens)
+}
+
+/// A recursive-descent parser over the tokens of one expression.
+struct Parser {
+    tokens: Vec<Token>,
+    position: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.position)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.position).cloned();
+        self.position += 1;
+        token
+    }
+
+    fn eat(&mut self, token: &Token) -> bool {
+        if self.peek() == Some(token) {
+            self.position += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn or(&mut self) -> Result<Node, String> {
+        let mut expr = self.and()?;
+        while self.eat(&Token::Or) {
+            expr = Node::Or(Box::new(expr), Box::new(self.and()?));
+        }
+        Ok(expr)
+    }
+
+    fn and(&mut self) -> Result<Node, String> {
+        let mut expr = self.unary()?;
+        while self.eat(&Token::And) {
+            expr = Node::And(Box::new(expr), Box::new(self.unary()?));
+        }
+        Ok(expr)
+    }
+
+    fn unary(&mut self) -> Result<Node, String> {
+        if self.eat(&Token::Not) {
+            return Ok(Node::Not(Box::new(self.unary()?)));
+        }
+        if self.eat(&Token::LParen) {
+            let expr = self.or()?;
+            if !self.eat(&Token::RParen) {
+                return Err(self.unexpected("`)`"));
+            }
+            return Ok(expr);
+        }
+        self.comparison()
+    }
+
+    fn comparison(&mut self) -> Result<Node, String> {
+        let (left, left_kind) = self.term()?;
+
+        match self.peek().cloned() {
+            Some(Token::Compare(comparison)) => {
+                self.position += 1;
+                let (right, right_kind) = self.term()?;
+                if left_kind != right_kind {
+                    return Err(format!(
+                        "cannot compare {left_kind} with {right_kind} using {}",
+                        Token::Compare(comparison)
+                    ));
+                }
+                let ordered = !matches!(comparison, Comparison::Eq | Comparison::Ne);
+                if left_kind == Kind::List || (ordered && left_kind == Kind::Bool) {
+                    return Err(format!(
+                        "{} cannot be applied to {left_kind}",
+                        Token::Compare(comparison)
+                    ));
+                }
+                Ok(Node::Compare(left, comparison, right))
+            }
+            Some(Token::Matches) => {
+                self.position += 1;
+                let pattern = match self.next() {
+                    Some(Token::Str(pattern)) => pattern,
+                    _ => return Err("`=~` must be followed by a quoted regex".to_string()),
+                };
+                if left_kind != Kind::Str {
+                    return Err(format!("`=~` cannot be applied to {left_kind}"));
+                }
+                let regex = Regex::new(&pattern).map_err(|err| err.to_string())?;
+                Ok(Node::Matches(left, regex))
+            }
+            Some(Token::In) => {
+                self.position += 1;
+                let (list, list_kind) = self.term()?;
+                if left_kind != Kind::Str || list_kind != Kind::List {
+                    return Err(format!(
+                        "`in` needs a string and a list, not {left_kind} and {list_kind}"
+                    ));
+                }
+                Ok(Node::In(left, list))
+            }
+            _ if left_kind == Kind::Bool => Ok(Node::Term(left)),
+            _ => Err(format!(
+                "{} is {left_kind}; compare it with a value, as in `ahead > 0`",
+                describe_term(&left)
+            )),
+        }
+    }
+
+    fn term(&mut self) -> Result<(Term, Kind), String> {
+        match self.next() {
+            Some(Token::Ident(name)) => match name.as_str() {
+                "true" => Ok((Term::Literal(Value::Bool(true)), Kind::Bool)),
+                "false" => Ok((Term::Literal(Value::Bool(false)), Kind::Bool)),
+                _ => {
+                    let attribute = Attribute::from_name(&name).ok_or_else(|| {
+                        let names: Vec<_> = Attribute::ALL.iter().map(|a| a.name()).collect();
+                        format!(
+                            "unknown attribute `{name}`; expected one of {}",
+                            names.join(", ")
+                        )
+                    })?;
+                    Ok((Term::Attribute(attribute), attribute.kind()))
+                }
+            },
+            Some(Token::Str(value)) => Ok((Term::Literal(Value::Str(value)), Kind::Str)),
+            Some(Token::Int(value)) => Ok((Term::Literal(Value::Int(value)), Kind::Int)),
+            _ => {
+                self.position -= 1;
+                Err(self.unexpected("an attribute or a value"))
+            }
+        }
+    }
+
+    fn unexpected(&self, expected: &str) -> String {
+        match self.peek() {
+            Some(token) => format!("expected {expected}, found {token}"),
+            None => format!("expected {expected} at the end of the expression"),
+        }
+    }
+}
+
+fn describe_term(term: &Term) -> String {
+    match term {
+        Term::Attribute(attribute) => format!("`{}`", attribute.name()),
+        Term::Literal(Value::Str(value)) => format!("{value:?}"),
+        Term::Literal(Value::Int(value)) => format!("`{value}`"),
+        Term::Literal(_) => "the value".to_string(),
+    }
+}
+
+/// Parses and type-checks a filter expression. Expressions combine
+/// attribute tests with `!`, `&&`, `||`, and parentheses; compare values with
+/// `==`, `!=`, `<`, `<=`, `>`, and `>=`; match strings against a regex with
+/// `=~`; and test list membership with `in`.
+pub fn parse(input: &str) -> Result<Expr, String> {
+    let mut parser = Parser {
+        tokens: tokenize(input).map_err(|err| format!("invalid expression: {err}"))?,
+        position: 0,
+    };
+
+    let node = parser
+        .or()
+        .and_then(|expr| match parser.peek() {
+            None => Ok(expr),
+            Some(_) => Err(parser.unexpected("`&&`, `||`, or the end of the expression")),
+        })
+        .map_err(|err| format!("invalid expression: {err}"))?;
+    Ok(Expr {
+        source: input.trim().to_string(),
+        node,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn repository(attribute: Attribute) -> Value {
+        match attribute {
+            Attribute::Branch => Value::Str("main".to_string()),
+            Attribute::Dirty => Value::Bool(true),
+            Attribute::Detached => Value::Bool(false),
+            Attribute::Ahead => Value::Int(2),
+            Attribute::Behind => Value::Int(0),
+            Attribute::Remote => Value::Unknown,
+            Attribute::Path => Value::Str("clients/acme/backend".to_string()),
+            Attribute::Tags => Value::List(vec!["v1.0".to_string()]),
+        }
+    }
+
+    fn holds(input: &str) -> bool {
+        parse(input).unwrap().evaluate(&mut repository)
+    }
+
+    #[test]
+    fn evaluate_combines_attribute_tests() {
+        assert!(holds(r#"dirty && branch == "main""#));
+        assert!(holds("ahead > 0 && behind == 0"));
+        assert!(holds("!detached"));
+        assert!(holds(r#"branch != 'develop' || false"#));
+        assert!(holds(r#"path =~ "^clients/[^/]+/backend$""#));
+        assert!(holds(r#""v1.0" in tags"#));
+        assert!(!holds(r#""v2.0" in tags"#));
+        assert!(!holds("!(dirty || detached)"));
+        assert!(holds("true || dirty && detached"));
+    }
+
+    #[test]
+    fn evaluate_treats_unknown_values_as_not_matching() {
+        assert!(!holds(r#"remote == "x""#));
+        assert!(!holds(r#"remote != "x""#));
+        assert!(!holds(r#"remote =~ ".""#));
+    }
+
+    #[test]
+    fn evaluate_looks_up_only_the_attributes_it_needs() {
+        let expr = parse("detached && ahead > 0").unwrap();
+        let mut looked_up = Vec::new();
+
+        expr.evaluate(&mut |attribute| {
+            looked_up.push(attribute);
+            repository(attribute)
+        });
+
+        assert_eq!(looked_up, vec![Attribute::Detached]);
+    }
+
+    #[test]
+    fn parse_rejects_malformed_and_ill_typed_expressions() {
+        for input in [
+            "",
+            "dirty &&",
+            "(dirty",
+            "branch",
+            "ahead",
+            "owner == \"me\"",
+            "branch == 1",
+            "dirty < true",
+            "tags == tags",
+            "path =~ path",
+            "path =~ \"(\"",
+            "ahead in tags",
+            "dirty dirty",
+            "\"unterminated",
+            "dirty & detached",
+        ] {
+            assert!(parse(input).is_err(), "{input} should be rejected");
+        }
+    }
+}
@@ -7,230 +7,4283 @@
  * in the root directory of this source tree.
  */
 
+use crate::diffstat::ChangeKind;
+use crate::interrupt;
+use crate::output::{self, Outcome};
+use crate::priority::Priority;
+use globset::{Glob, GlobSet, GlobSetBuilder};
+use ignore::Match;
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
+use rayon::ThreadPoolBuilder;
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::collections::{BTreeSet, HashSet};
 use std::ffi::OsStr;
+use std::fmt;
 use std::fs;
-use std::io;
+use std::io::{self, Read, Write};
 use std::path::{Path, PathBuf};
-use std::process::{Command, Stdio};
-use walkdir::WalkDir;
+use std::process::{Command, ExitStatus, Stdio};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, mpsc};
+use std::thread;
+use std::time::{Instant, SystemTime};
 
 const GIT_PATH_NAME: &str = ".git";
 const GIT_CONFIG_NAME: &str = "config";
 const GITDIR_PREFIX: &str = "gitdir:";
+const HEAD_FILE_NAME: &str = "HEAD";
+const INDEX_FILE_NAME: &str = "index";
+const OBJECTS_DIR_NAME: &str = "objects";
+const REFS_DIR_NAME: &str = "refs";
+const WORKTREES_DIR_NAME: &str = "worktrees";
+const MODULES_DIR_NAME: &str = "modules";
+const GITMODULES_FILE_NAME: &str = ".gitmodules";
+/// Ignore files read from each directory. Later files take precedence over
+/// earlier ones, so `.grprignore` can override `.gitignore`.
+const IGNORE_FILE_NAMES: [&str; 3] = [".gitignore", ".ignore", ".grprignore"];
+/// Marker file that fences off a directory and everything beneath it.
+const STOP_MARKER_NAME: &str = ".grprstop";
 
 /// Classifies the git repository type discovered at a directory path.
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum RepositoryKind {
     Regular,
     Worktree,
+    Submodule,
+    Bare,
+}
+
+/// Classifies a `.git` file by the git directory it points at. Submodule
+/// checkouts reference `.git/modules/<name>`, while linked worktrees reference
+/// `.git/worktrees/<name>`. Any other target is treated as a worktree.
+fn classify_gitdir_file(contents: &str) -> Option<RepositoryKind> {
+    let gitdir = contents.trim_start().strip_prefix(GITDIR_PREFIX)?;
+    let gitdir = Path::new(gitdir.trim());
+    let has_component = |name: &str| gitdir.components().any(|c| c.as_os_str() == name);
+
+    if !has_component(WORKTREES_DIR_NAME) && has_component(MODULES_DIR_NAME) {
+        Some(RepositoryKind::Submodule)
+    } else {
+        Some(RepositoryKind::Worktree)
+    }
+}
+
+/// Returns true when `path` has the layout of a bare repository: a `HEAD`
+/// file alongside `objects` and `refs` directories.
+fn is_bare_repository(path: &Path) -> bool {
+    path.join(HEAD_FILE_NAME).is_file()
+        && path.join(OBJECTS_DIR_NAME).is_dir()
+        && path.join(REFS_DIR_NAME).is_dir()
 }
 
 /// Detects whether `path` is a supported git repository root.
 ///
-/// Regular repositories must contain a `.git/config` file. Worktrees are
-/// identified by a `.git` file whose trimmed contents start with `gitdir:`.
+/// Regular repositories must contain a `.git/config` file. Worktrees and
+/// submodule checkouts are identified by a `.git` file whose trimmed contents
+/// start with `gitdir:`. Directories without a `.git` entry are reported as
+/// bare repositories when they contain `HEAD`, `objects/`, and `refs/`.
 pub fn detect_repository(path: &Path) -> Option<RepositoryKind> {
     if !path.is_dir() {
         return None;
     }
 
-    let git_path = path.join(GIT_PATH_NAME);
-    let git_metadata = fs::metadata(&git_path).ok()?;
+    let git_path = path.join(GIT_PATH_NAME);
+    let Ok(git_metadata) = fs::metadata(&git_path) else {
+        return is_bare_repository(path).then_some(RepositoryKind::Bare);
+    };
+
+    if git_metadata.is_dir() {
+        let config_path = git_path.join(GIT_CONFIG_NAME);
+        return config_path.is_file().then_some(RepositoryKind::Regular);
+    }
+
+    if git_metadata.is_file() {
+        let contents = fs::read_to_string(&git_path).ok()?;
+        return classify_gitdir_file(&contents);
+    }
+
+    None
+}
+
+/// Decides what happens to repositories found inside other repositories, such
+/// as vendored checkouts and submodules.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+pub enum NestedPolicy {
+    /// Stop at the outermost repository and never look inside it.
+    #[default]
+    Skip,
+    /// Process outer and nested repositories alike.
+    Include,
+    /// Process only repositories found inside another repository.
+    Only,
+}
+
+/// The order in which the directory tree is walked, which is also the order
+/// in which repositories are reported.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+pub enum Traversal {
+    /// Finish each directory's subtree before moving on to the next one.
+    #[default]
+    Dfs,
+    /// Walk one level at a time, reporting repositories closest to the root
+    /// first.
+    Bfs,
+}
+
+/// Controls how [`discover_repositories`] walks the directory tree.
+#[derive(Debug, Clone)]
+pub struct DiscoveryOptions {
+    /// Maximum number of directory levels to descend below the root. The root
+    /// itself is depth 0. `None` walks the entire tree.
+    pub max_depth: Option<usize>,
+    /// Directories matching any of these globs are pruned together with their
+    /// descendants. Patterns are matched against both the directory name and
+    /// its path relative to the root.
+    pub exclude: GlobSet,
+    /// Descend into hidden directories (names starting with `.`). Hidden
+    /// directories are skipped by default.
+    pub include_hidden: bool,
+    /// Honor `.gitignore`, `.ignore`, and `.grprignore` files found while
+    /// walking. Enabled by default.
+    pub ignore_files: bool,
+    /// Whether to descend below discovered repositories, and which of the
+    /// repositories found there to report. `.git` directories are never
+    /// descended into.
+    pub nested: NestedPolicy,
+    /// Process linked worktrees. Enabled by default.
+    pub include_worktrees: bool,
+    /// Process submodule checkouts. Enabled by default.
+    pub include_submodules: bool,
+    /// Process bare repositories. Disabled by default; bare repositories are
+    /// still treated as repository boundaries and never descended into.
+    pub include_bare: bool,
+    /// Follow symbolic links to directories. Repositories reachable through
+    /// several links are reported once, under the first path found.
+    pub follow_symlinks: bool,
+    /// Never cross into directories on a different filesystem from the root,
+    /// such as network mounts or external drives.
+    pub same_file_system: bool,
+    /// Number of threads used to walk the tree. `0` uses one thread per CPU.
+    pub threads: usize,
+    /// Walk the tree depth-first or breadth-first. Siblings are always
+    /// visited in path order.
+    pub traversal: Traversal,
+}
+
+impl DiscoveryOptions {
+    /// Returns true when repositories of `kind` should be processed.
+    fn includes(&self, kind: RepositoryKind) -> bool {
+        match kind {
+            RepositoryKind::Regular => true,
+            RepositoryKind::Worktree => self.include_worktrees,
+            RepositoryKind::Submodule => self.include_submodules,
+            RepositoryKind::Bare => self.include_bare,
+        }
+    }
+}
+
+impl Default for DiscoveryOptions {
+    fn default() -> Self {
+        Self {
+            max_depth: None,
+            exclude: GlobSet::empty(),
+            include_hidden: false,
+            ignore_files: true,
+            nested: NestedPolicy::Skip,
+            include_worktrees: true,
+            include_submodules: true,
+            include_bare: false,
+            follow_symlinks: false,
+            same_file_system: false,
+            threads: 0,
+            traversal: Traversal::Dfs,
+        }
+    }
+}
+
+/// Ignore matchers loaded from the directories on the current walk path,
+/// ordered from the shallowest to the deepest directory. Each directory owns
+/// its stack, so sibling subtrees can be walked independently.
+#[derive(Clone, Default)]
+struct IgnoreStack {
+    matchers: Vec<Arc<Gitignore>>,
+}
+
+impl IgnoreStack {
+    /// Returns true when the deepest matcher with an opinion ignores `path`.
+    fn is_ignored(&self, path: &Path) -> bool {
+        for matcher in self.matchers.iter().rev() {
+            match matcher.matched(path, true) {
+                Match::Ignore(_) => return true,
+                Match::Whitelist(_) => return false,
+                Match::None => {}
+            }
+        }
+
+        false
+    }
+
+    /// Returns the stack that applies to the descendants of `dir`, adding the
+    /// ignore files in `dir`, if any.
+    fn with_dir(&self, dir: &Path) -> Self {
+        let mut stack = self.clone();
+        let mut builder = GitignoreBuilder::new(dir);
+        let mut found = false;
+
+        for name in IGNORE_FILE_NAMES {
+            let ignore_path = dir.join(name);
+            if !ignore_path.is_file() {
+                continue;
+            }
+
+            found = true;
+            if let Some(err) = builder.add(&ignore_path) {
+                tracing::warn!("cannot read ignore file: {err}");
+            }
+        }
+
+        if !found {
+            return stack;
+        }
+
+        match builder.build() {
+            Ok(matcher) if !matcher.is_empty() => stack.matchers.push(Arc::new(matcher)),
+            Ok(_) => {}
+            Err(err) => tracing::warn!("cannot read ignore file: {err}"),
+        }
+        stack
+    }
+}
+
+/// Returns true when the entry name marks it as a hidden directory.
+fn is_hidden(path: &Path) -> bool {
+    path.file_name()
+        .and_then(OsStr::to_str)
+        .is_some_and(|name| name.starts_with('.'))
+}
+
+/// Compiles exclusion globs into a matcher suitable for
+/// [`DiscoveryOptions::exclude`].
+pub fn build_exclude_set(patterns: &[Glob]) -> Result<GlobSet, globset::Error> {
+    let mut builder = GlobSetBuilder::new();
+    for pattern in patterns {
+        builder.add(pattern.clone());
+    }
+    builder.build()
+}
+
+/// Returns true when `path`, found below `root`, matches an exclusion glob.
+fn is_excluded(root: &Path, path: &Path, exclude: &GlobSet) -> bool {
+    if exclude.is_empty() {
+        return false;
+    }
+
+    let name_matches = path
+        .file_name()
+        .is_some_and(|name| exclude.is_match(Path::new(name)));
+
+    name_matches
+        || path
+            .strip_prefix(root)
+            .is_ok_and(|relative| exclude.is_match(relative))
+}
+
+/// Identifies a repository independently of the path it was reached by, so
+/// symbolic links, bind mounts, and case-insensitive paths are recognized as
+/// the same repository.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum RepositoryIdentity {
+    /// The device and inode of the repository directory.
+    Inode(u64, u64),
+    /// The canonical path, where inodes are unavailable.
+    Path(PathBuf),
+}
+
+/// Returns the identity of the repository at `repo_path`.
+pub fn repository_identity(repo_path: &Path) -> RepositoryIdentity {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::MetadataExt;
+
+        if let Ok(metadata) = fs::metadata(repo_path) {
+            return RepositoryIdentity::Inode(metadata.dev(), metadata.ino());
+        }
+    }
+
+    RepositoryIdentity::Path(
+        fs::canonicalize(repo_path).unwrap_or_else(|_| repo_path.to_path_buf()),
+    )
+}
+
+/// Drops repositories that were already listed under another path, keeping
+/// the first path each repository was listed under.
+pub fn dedupe_repositories(repositories: Vec<PathBuf>) -> Vec<PathBuf> {
+    let mut seen = HashSet::new();
+    repositories
+        .into_iter()
+        .filter(|repo_path| seen.insert(repository_identity(repo_path)))
+        .collect()
+}
+
+/// Returns the device the directory at `path` lives on.
+#[cfg(unix)]
+fn device_id(path: &Path) -> Option<u64> {
+    use std::os::unix::fs::MetadataExt;
+
+    fs::metadata(path).ok().map(|metadata| metadata.dev())
+}
+
+/// Device numbers are not available on this platform, so every directory is
+/// treated as being on the root's filesystem.
+#[cfg(not(unix))]
+fn device_id(_path: &Path) -> Option<u64> {
+    None
+}
+
+/// A directory that could not be read during discovery. Everything below it
+/// was skipped.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ScanError {
+    pub path: PathBuf,
+    pub message: String,
+}
+
+impl ScanError {
+    fn new(path: &Path, err: &io::Error) -> Self {
+        Self {
+            path: path.to_path_buf(),
+            message: err.to_string(),
+        }
+    }
+}
+
+impl fmt::Display for ScanError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.path.display(), self.message)
+    }
+}
+
+/// Repositories found below a root, and the directories that could not be
+/// read while searching for them.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Scan {
+    pub repositories: Vec<PathBuf>,
+    pub errors: Vec<ScanError>,
+}
+
+/// Receives progress from [`walk_repositories`]. Subtrees are walked in
+/// parallel, so callbacks may arrive from several threads at once and in no
+/// particular order.
+pub trait DiscoveryVisitor: Sync {
+    /// Called for each repository as soon as it is found.
+    fn repository(&self, _repo_path: &Path) {}
+
+    /// Called with the subdirectories of `dir` once it has been listed and
+    /// before any of them is walked.
+    fn listed(&self, _dir: &Path, _depth: usize, _children: &[PathBuf]) {}
+
+    /// Called once everything below `dir` has been walked or pruned.
+    /// Breadth-first walks only report the root and its immediate children.
+    fn finished(&self, _dir: &Path, _depth: usize) {}
+
+    /// Called when a directory cannot be read. The default logs the error.
+    fn error(&self, error: ScanError) {
+        tracing::warn!("cannot walk directory tree: {error}");
+    }
+
+    /// Returning true stops the walk before any further directory is visited.
+    fn cancelled(&self) -> bool {
+        false
+    }
+}
+
+/// The unit visitor ignores progress and prints errors.
+impl DiscoveryVisitor for () {}
+
+/// Collects traversal errors instead of printing them.
+#[derive(Default)]
+struct ErrorCollector {
+    errors: Mutex<Vec<ScanError>>,
+}
+
+impl ErrorCollector {
+    /// Returns the collected errors, sorted by path.
+    fn into_errors(self) -> Vec<ScanError> {
+        let mut errors = self
+            .errors
+            .into_inner()
+            .unwrap_or_else(|err| err.into_inner());
+        errors.sort_by(|a, b| a.path.cmp(&b.path));
+        errors
+    }
+}
+
+impl DiscoveryVisitor for ErrorCollector {
+    fn error(&self, error: ScanError) {
+        self.errors
+            .lock()
+            .unwrap_or_else(|err| err.into_inner())
+            .push(error);
+    }
+}
+
+/// Repositories found below a directory, and whether its whole subtree was
+/// walked before the walk was cancelled.
+struct Subtree {
+    repositories: Vec<PathBuf>,
+    complete: bool,
+}
+
+impl Subtree {
+    fn new(complete: bool) -> Self {
+        Self {
+            repositories: Vec::new(),
+            complete,
+        }
+    }
+
+    /// Adds the results of a walk below this subtree.
+    fn merge(&mut self, other: Subtree) {
+        self.complete &= other.complete;
+        self.repositories.extend(other.repositories);
+    }
+}
+
+/// A directory waiting to be walked, with the state inherited from the
+/// directories above it.
+struct PendingDir {
+    path: PathBuf,
+    depth: usize,
+    ignore_stack: IgnoreStack,
+    /// Canonical paths of the directories above `path` when following
+    /// symbolic links, so that link cycles are cut off.
+    ancestors: Vec<PathBuf>,
+    /// True below a repository found by this walk.
+    inside_repository: bool,
+}
+
+/// A walk of a single root, shared by the threads walking its subtrees.
+struct Walk<'a, V> {
+    root: &'a Path,
+    options: &'a DiscoveryOptions,
+    visitor: &'a V,
+    root_device: Option<u64>,
+    /// Canonical paths of the repositories found so far, used to report a
+    /// repository once when it is reachable through several links.
+    seen_repositories: Mutex<HashSet<PathBuf>>,
+}
+
+impl<V: DiscoveryVisitor> Walk<'_, V> {
+    /// Returns true when the directory at `path`, below the root, is skipped
+    /// together with everything beneath it.
+    fn is_pruned(&self, path: &Path, ignore_stack: &IgnoreStack) -> bool {
+        path.file_name() == Some(OsStr::new(GIT_PATH_NAME))
+            || (!self.options.include_hidden && is_hidden(path))
+            || is_excluded(self.root, path, &self.options.exclude)
+            || ignore_stack.is_ignored(path)
+            || path.join(STOP_MARKER_NAME).exists()
+            || (self.options.same_file_system && device_id(path) != self.root_device)
+    }
+
+    /// Lists the directories directly inside `dir`, sorted by path so results
+    /// do not depend on the order in which the filesystem returns entries.
+    /// Unreadable directories and entries are reported to the visitor.
+    fn child_directories(&self, dir: &Path) -> Vec<PathBuf> {
+        let entries = match fs::read_dir(dir) {
+            Ok(entries) => entries,
+            Err(err) => {
+                self.visitor.error(ScanError::new(dir, &err));
+                return Vec::new();
+            }
+        };
+
+        let mut children: Vec<PathBuf> = entries
+            .filter_map(|entry| match entry {
+                Ok(entry) => Some(entry),
+                Err(err) => {
+                    self.visitor.error(ScanError::new(dir, &err));
+                    None
+                }
+            })
+            .filter(|entry| match entry.file_type() {
+                Ok(file_type) if file_type.is_symlink() => {
+                    self.options.follow_symlinks && entry.path().is_dir()
+                }
+                Ok(file_type) => file_type.is_dir(),
+                Err(_) => false,
+            })
+            .map(|entry| entry.path())
+            .collect();
+        children.sort();
+        children
+    }
+
+    /// Returns true the first time the repository at `path` is reached.
+    fn first_visit(&self, path: &Path) -> bool {
+        if !self.options.follow_symlinks {
+            return true;
+        }
+
+        let canonical = fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+        self.seen_repositories
+            .lock()
+            .unwrap_or_else(|err| err.into_inner())
+            .insert(canonical)
+    }
+
+    /// Checks a single directory, adding it to `repositories` when it is a
+    /// repository to report. Returns the subdirectories to walk next, which
+    /// is empty when the walk stops at `dir`.
+    fn examine(&self, dir: &PendingDir, repositories: &mut Vec<PathBuf>) -> Vec<PendingDir> {
+        let path = dir.path.as_path();
+        if dir.depth > 0 && self.is_pruned(path, &dir.ignore_stack) {
+            return Vec::new();
+        }
+
+        let mut ancestors = dir.ancestors.clone();
+        if self.options.follow_symlinks {
+            let canonical = fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+            // Symlink cycles lead back to a directory that is already being
+            // walked, so they are skipped quietly.
+            if ancestors.contains(&canonical) {
+                return Vec::new();
+            }
+            ancestors.push(canonical);
+        }
+
+        tracing::trace!("examining {}", path.display());
+        let kind = detect_repository(path);
+        if let Some(kind) = kind {
+            if !self.first_visit(path) {
+                return Vec::new();
+            }
+            let reported = match self.options.nested {
+                NestedPolicy::Only => dir.inside_repository,
+                NestedPolicy::Skip | NestedPolicy::Include => true,
+            };
+            if reported && self.options.includes(kind) {
+                tracing::debug!("found repository {}", path.display());
+                self.visitor.repository(path);
+                repositories.push(path.to_path_buf());
+            }
+            if self.options.nested == NestedPolicy::Skip {
+                return Vec::new();
+            }
+        }
+
+        if self
+            .options
+            .max_depth
+            .is_some_and(|max_depth| dir.depth >= max_depth)
+        {
+            return Vec::new();
+        }
+
+        let ignore_stack = if self.options.ignore_files {
+            dir.ignore_stack.with_dir(path)
+        } else {
+            dir.ignore_stack.clone()
+        };
+        let children = self.child_directories(path);
+        self.visitor.listed(path, dir.depth, &children);
+
+        children
+            .into_iter()
+            .map(|child| PendingDir {
+                path: child,
+                depth: dir.depth + 1,
+                ignore_stack: ignore_stack.clone(),
+                ancestors: ancestors.clone(),
+                inside_repository: dir.inside_repository || kind.is_some(),
+            })
+            .collect()
+    }
+
+    /// Walks `dir` and everything below it, finishing each subdirectory before
+    /// moving on to the next one.
+    fn walk_depth_first(&self, dir: PendingDir) -> Subtree {
+        if self.visitor.cancelled() {
+            return Subtree::new(false);
+        }
+
+        let mut subtree = Subtree::new(true);
+        let children = self.examine(&dir, &mut subtree.repositories);
+        let walked: Vec<Subtree> = children
+            .into_par_iter()
+            .map(|child| self.walk_depth_first(child))
+            .collect();
+        for child in walked {
+            subtree.merge(child);
+        }
+
+        if subtree.complete {
+            self.visitor.finished(&dir.path, dir.depth);
+        }
+        subtree
+    }
+
+    /// Walks everything below `root` one level at a time, so repositories
+    /// close to the root are found first.
+    fn walk_breadth_first(&self, root: PendingDir) -> Subtree {
+        let mut subtree = Subtree::new(true);
+        let mut level = vec![root];
+        // Immediate children of the root that still have directories
+        // waiting to be walked.
+        let mut open = BTreeSet::new();
+
+        while !level.is_empty() {
+            let examined: Vec<(Subtree, Vec<PendingDir>)> = level
+                .par_iter()
+                .map(|dir| {
+                    if self.visitor.cancelled() {
+                        return (Subtree::new(false), Vec::new());
+                    }
+                    let mut found = Subtree::new(true);
+                    let children = self.examine(dir, &mut found.repositories);
+                    (found, children)
+                })
+                .collect();
+
+            let mut next = Vec::new();
+            for (found, children) in examined {
+                subtree.merge(found);
+                next.extend(children);
+            }
+            if !subtree.complete {
+                return subtree;
+            }
+
+            let still_open: BTreeSet<PathBuf> = next
+                .iter()
+                .filter_map(|dir| self.top_level_dir(&dir.path))
+                .collect();
+            for dir in open.difference(&still_open) {
+                self.visitor.finished(dir, 1);
+            }
+            open = still_open;
+            level = next;
+        }
+
+        self.visitor.finished(self.root, 0);
+        subtree
+    }
+
+    /// Returns the immediate child of the root that contains `path`.
+    fn top_level_dir(&self, path: &Path) -> Option<PathBuf> {
+        let first = path.strip_prefix(self.root).ok()?.components().next()?;
+        Some(self.root.join(first))
+    }
+}
+
+/// Discovers git repositories under `root`, skipping descendants of any
+/// repository that is found, and collects the directories that could not be
+/// read.
+pub fn discover_repositories(root: &Path, options: &DiscoveryOptions) -> Scan {
+    let collector = ErrorCollector::default();
+    let repositories = walk_repositories(root, options, &collector);
+
+    Scan {
+        repositories,
+        errors: collector.into_errors(),
+    }
+}
+
+/// Walks `root` the way [`discover_repositories`] does, reporting progress to
+/// `visitor`. Subtrees are walked in parallel on `options.threads` threads,
+/// but the repositories are returned in the same order, as set by
+/// `options.traversal`, however the work was split.
+pub fn walk_repositories<V: DiscoveryVisitor>(
+    root: &Path,
+    options: &DiscoveryOptions,
+    visitor: &V,
+) -> Vec<PathBuf> {
+    let walk = Walk {
+        root,
+        options,
+        visitor,
+        root_device: options.same_file_system.then(|| device_id(root)).flatten(),
+        seen_repositories: Mutex::new(HashSet::new()),
+    };
+    let walk_root = || {
+        let root_dir = PendingDir {
+            path: root.to_path_buf(),
+            depth: 0,
+            ignore_stack: IgnoreStack::default(),
+            ancestors: Vec::new(),
+            inside_repository: false,
+        };
+        match options.traversal {
+            Traversal::Dfs => walk.walk_depth_first(root_dir),
+            Traversal::Bfs => walk.walk_breadth_first(root_dir),
+        }
+    };
+
+    let subtree = match ThreadPoolBuilder::new()
+        .num_threads(options.threads)
+        .build()
+    {
+        Ok(pool) => pool.install(walk_root),
+        Err(_) => walk_root(),
+    };
+    subtree.repositories
+}
+
+/// Progress of a scan running on a background thread.
+#[derive(Default)]
+struct ScanProgress {
+    repositories: Vec<PathBuf>,
+    errors: Vec<ScanError>,
+    /// Directories whose subtrees have not been completely walked yet.
+    unscanned: BTreeSet<PathBuf>,
+}
+
+/// Records scan progress shared with the thread waiting on the deadline.
+struct ProgressVisitor {
+    progress: Arc<Mutex<ScanProgress>>,
+    cancelled: Arc<AtomicBool>,
+}
+
+impl ProgressVisitor {
+    fn progress(&self) -> std::sync::MutexGuard<'_, ScanProgress> {
+        self.progress.lock().unwrap_or_else(|err| err.into_inner())
+    }
+}
+
+impl DiscoveryVisitor for ProgressVisitor {
+    fn repository(&self, repo_path: &Path) {
+        self.progress().repositories.push(repo_path.to_path_buf());
+    }
+
+    /// Once the root is listed, its unfinished children are tracked instead.
+    fn listed(&self, dir: &Path, depth: usize, children: &[PathBuf]) {
+        if depth == 0 {
+            let mut progress = self.progress();
+            progress.unscanned.remove(dir);
+            progress.unscanned.extend(children.iter().cloned());
+        }
+    }
+
+    fn finished(&self, dir: &Path, depth: usize) {
+        if depth <= 1 {
+            self.progress().unscanned.remove(dir);
+        }
+    }
+
+    fn error(&self, error: ScanError) {
+        self.progress().errors.push(error);
+    }
+
+    fn cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed)
+    }
+}
+
+/// Discovers repositories under `root` like [`discover_repositories`], but
+/// gives up at `deadline`. Returns what was found in time, followed by the
+/// directories whose subtrees were not completely scanned: the root itself if
+/// its listing did not finish, or its unfinished immediate children.
+///
+/// The walk runs on a background thread so a hung filesystem call cannot
+/// block past the deadline. A timed-out walk is cancelled and left to wind
+/// down on its own.
+pub fn discover_repositories_until(
+    root: &Path,
+    options: &DiscoveryOptions,
+    deadline: Instant,
+) -> (Scan, Vec<PathBuf>) {
+    if Instant::now() >= deadline {
+        return (Scan::default(), vec![root.to_path_buf()]);
+    }
+
+    let progress = Arc::new(Mutex::new(ScanProgress {
+        unscanned: BTreeSet::from([root.to_path_buf()]),
+        ..ScanProgress::default()
+    }));
+    let cancelled = Arc::new(AtomicBool::new(false));
+    let (done_sender, done) = mpsc::channel();
+    let visitor = ProgressVisitor {
+        progress: Arc::clone(&progress),
+        cancelled: Arc::clone(&cancelled),
+    };
+    let root_path = root.to_path_buf();
+    let walk_options = options.clone();
+
+    thread::spawn(move || {
+        let repositories = walk_repositories(&root_path, &walk_options, &visitor);
+        let _ = done_sender.send(repositories);
+    });
+
+    let finished = done.recv_timeout(deadline.saturating_duration_since(Instant::now()));
+    if finished.is_err() {
+        cancelled.store(true, Ordering::Relaxed);
+    }
+
+    let progress = progress.lock().unwrap_or_else(|err| err.into_inner());
+    let mut errors = progress.errors.clone();
+    errors.sort_by(|a, b| a.path.cmp(&b.path));
+    match finished {
+        Ok(repositories) => (
+            Scan {
+                repositories,
+                errors,
+            },
+            Vec::new(),
+        ),
+        Err(_) => {
+            let mut repositories = progress.repositories.clone();
+            repositories.sort();
+            (
+                Scan {
+                    repositories,
+                    errors,
+                },
+                progress.unscanned.iter().cloned().collect(),
+            )
+        }
+    }
+}
+
+/// Returns the most recent modification time recorded for a repository: that
+/// of its `.git` entry, or of the directory itself for bare repositories.
+pub fn repository_modified(repo_path: &Path) -> Option<SystemTime> {
+    let git_path = repo_path.join(GIT_PATH_NAME);
+    fs::metadata(&git_path)
+        .or_else(|_| fs::metadata(repo_path))
+        .ok()?
+        .modified()
+        .ok()
+}
+
+/// Returns the git directory of a repository: its `.git` directory, the
+/// directory a `.git` file points at, or the repository itself when bare.
+pub fn git_dir(repo_path: &Path) -> Option<PathBuf> {
+    let git_path = repo_path.join(GIT_PATH_NAME);
+    let metadata = match fs::metadata(&git_path) {
+        Ok(metadata) => metadata,
+        Err(_) => return is_bare_repository(repo_path).then(|| repo_path.to_path_buf()),
+    };
+    if metadata.is_dir() {
+        return Some(git_path);
+    }
+
+    let contents = fs::read_to_string(&git_path).ok()?;
+    let gitdir = contents.trim_start().strip_prefix(GITDIR_PREFIX)?.trim();
+    Some(repo_path.join(gitdir))
+}
+
+/// Returns when the repository was last used: the latest modification of its
+/// `HEAD` or index, which change on checkouts, commits, and staging. Falls
+/// back to the date of the last commit when neither file can be read.
+pub fn last_activity(repo_path: &Path) -> Option<SystemTime> {
+    let modified = |name: &str| {
+        let git_dir = git_dir(repo_path)?;
+        fs::metadata(git_dir.join(name)).ok()?.modified().ok()
+    };
+    let touched = modified(HEAD_FILE_NAME).max(modified(INDEX_FILE_NAME));
+    if touched.is_some() {
+        return touched;
+    }
+
+    head_commit_time(repo_path)
+}
+
+/// Returns the committer date of the `HEAD` commit, or `None` when the
+/// repository has no commits.
+pub fn head_commit_time(repo_path: &Path) -> Option<SystemTime> {
+    let committed = git_output(repo_path, &["log", "-1", "--format=%ct"])?;
+    let secs = committed.parse().ok()?;
+    SystemTime::UNIX_EPOCH.checked_add(std::time::Duration::from_secs(secs))
+}
+
+/// Converts the exit status of `command` into the error reported for a
+/// failed run.
+fn check_status(
+    repo_path: &Path,
+    command: &RepoCommand,
+    status: ExitStatus,
+) -> Result<(), io::Error> {
+    if status.success() {
+        Ok(())
+    } else {
+        Err(io::Error::other(format!(
+            "{} failed in {} with status {status}",
+            command.description,
+            repo_path.display()
+        )))
+    }
+}
+
+/// Whether commands are run or only shown.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum RunMode {
+    /// Spawn the command and wait for it.
+    #[default]
+    Execute,
+    /// Print the command line instead of running it.
+    DryRun,
+}
+
+/// Quotes `arg` for a POSIX shell, leaving it bare when that is safe.
+fn shell_quote(arg: &str) -> Cow<'_, str> {
+    let safe = |c: char| c.is_ascii_alphanumeric() || "%+,-./:=@_^".contains(c);
+    if !arg.is_empty() && arg.chars().all(safe) {
+        Cow::Borrowed(arg)
+    } else {
+        Cow::Owned(format!("'{}'", arg.replace('\'', r"'\''")))
+    }
+}
+
+/// Which constructor of [`RepoCommand`] made a command.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum CommandKind {
+    /// [`RepoCommand::git`], given the arguments after `git`.
+    Git,
+    /// [`RepoCommand::exec`], given the words of the command.
+    Exec,
+    /// [`RepoCommand::script`], given the script's path and arguments.
+    Script,
+}
+
+/// How a command was made, so that it can be made again the same way, as
+/// `--resume` does from the run journal.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Invocation {
+    pub kind: CommandKind,
+    pub words: Vec<String>,
+}
+
+/// A command run in each repository: git with arguments, any other program
+/// for `grpr exec`, or a hook script.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RepoCommand {
+    program: String,
+    args: Vec<String>,
+    env: Vec<(String, String)>,
+    /// Names the command in failure messages.
+    description: String,
+    /// Whether the command may read grpr's stdin and prompt on the terminal.
+    terminal: bool,
+    /// The CPU and I/O priority the command runs at.
+    priority: Priority,
+    /// Whether the command is told the repository's branch and remote
+    /// through [`REPO_BRANCH_ENV`] and [`REPO_REMOTE_ENV`].
+    metadata: bool,
+    /// Whether the command inherits grpr's [`LOCAL_REPO_ENV`] variables.
+    keep_git_env: bool,
+    invocation: Invocation,
+}
+
+impl RepoCommand {
+    /// Runs git with `args`.
+    pub fn git(args: Vec<String>) -> Self {
+        Self {
+            program: "git".to_string(),
+            args: args.clone(),
+            env: Vec::new(),
+            description: "git command".to_string(),
+            terminal: true,
+            priority: Priority::default(),
+            metadata: false,
+            keep_git_env: false,
+            invocation: Invocation {
+                kind: CommandKind::Git,
+                words: args,
+            },
+        }
+    }
+
+    /// Runs an arbitrary command. A single word is handed to the shell, so it
+    /// may use pipes, `&&`, and globs; several words are run as a program and
+    /// its arguments, exactly as given.
+    pub fn exec(mut words: Vec<String>) -> Self {
+        if words.len() == 1 {
+            return Self::shell(words.remove(0), "command");
+        }
+
+        let invocation = Invocation {
+            kind: CommandKind::Exec,
+            words: words.clone(),
+        };
+        let program = if words.is_empty() {
+            String::new()
+        } else {
+            words.remove(0)
+        };
+        Self {
+            program,
+            args: words,
+            env: Vec::new(),
+            description: "command".to_string(),
+            terminal: true,
+            priority: Priority::default(),
+            metadata: false,
+            keep_git_env: false,
+            invocation,
+        }
+    }
+
+    /// Runs the script at `path` with `args`, telling it about the
+    /// repository through environment variables. Executable scripts run
+    /// directly, so their `#!` line chooses the interpreter; others are run
+    /// by `sh`, or `cmd /C` on Windows. `path` should be absolute, as each
+    /// repository is a different working directory.
+    pub fn script(path: &Path, args: Vec<String>) -> Self {
+        let path = path.to_string_lossy().into_owned();
+        let invocation = Invocation {
+            kind: CommandKind::Script,
+            words: std::iter::once(path.clone()).chain(args.clone()).collect(),
+        };
+        let (program, leading) = if cfg!(windows) {
+            ("cmd".to_string(), vec!["/C".to_string(), path])
+        } else if is_executable(Path::new(&path)) {
+            (path, Vec::new())
+        } else {
+            ("sh".to_string(), vec![path])
+        };
+        Self {
+            program,
+            args: leading.into_iter().chain(args).collect(),
+            env: Vec::new(),
+            description: "script".to_string(),
+            terminal: true,
+            priority: Priority::default(),
+            metadata: true,
+            keep_git_env: false,
+            invocation,
+        }
+    }
+
+    fn shell(script: String, description: &str) -> Self {
+        let (shell, flag) = if cfg!(windows) {
+            ("cmd", "/C")
+        } else {
+            ("sh", "-c")
+        };
+        Self {
+            program: shell.to_string(),
+            args: vec![flag.to_string(), script.clone()],
+            env: Vec::new(),
+            description: description.to_string(),
+            terminal: true,
+            priority: Priority::default(),
+            metadata: false,
+            keep_git_env: false,
+            invocation: Invocation {
+                kind: CommandKind::Exec,
+                words: vec![script],
+            },
+        }
+    }
+
+    /// Sets the environment variable `key` for the command.
+    pub fn with_env(mut self, key: &str, value: impl Into<String>) -> Self {
+        self.env.push((key.to_string(), value.into()));
+        self
+    }
+
+    /// Runs the command, and its hooks, at `priority`.
+    pub fn with_priority(self, priority: Priority) -> Self {
+        Self { priority, ..self }
+    }
+
+    /// Lets the command inherit the [`LOCAL_REPO_ENV`] variables grpr was
+    /// started with, which are otherwise cleared.
+    pub fn keeping_git_env(self) -> Self {
+        Self {
+            keep_git_env: true,
+            ..self
+        }
+    }
+
+    /// Detaches the command from the terminal for runs that share it between
+    /// several repositories at once: stdin is empty, and git fails instead of
+    /// prompting for credentials.
+    pub fn without_terminal(self) -> Self {
+        Self {
+            terminal: false,
+            ..self.with_env("GIT_TERMINAL_PROMPT", "0")
+        }
+    }
+
+    /// Splits a git command into its subcommand and the arguments before any
+    /// `--`, skipping git's own options such as `-c key=value`. Returns `None`
+    /// for other programs and for git without a subcommand.
+    fn git_subcommand(&self) -> Option<(&str, Vec<&str>)> {
+        if self.program != "git" {
+            return None;
+        }
+
+        let mut args = self.args.iter().map(String::as_str);
+        let subcommand = loop {
+            match args.next()? {
+                "-c" | "-C" => {
+                    args.next();
+                }
+                arg if arg.starts_with('-') => {}
+                arg => break arg,
+            }
+        };
+        let options = args.take_while(|arg| *arg != "--").collect();
+        Some((subcommand, options))
+    }
+
+    /// Returns true when the command is the git invocation `pattern`, such as
+    /// `reset --hard` or `clean -fd`: the same subcommand, with every other
+    /// word of the pattern among its arguments. Short options may be
+    /// combined or split, so `clean -fd` also matches `clean -d -f -x`.
+    /// Other words, such as the `.` of `checkout .`, also match the paths
+    /// after `--`, so `checkout -- .` matches too.
+    pub fn matches_invocation(&self, pattern: &str) -> bool {
+        let Some((subcommand, options)) = self.git_subcommand() else {
+            return false;
+        };
+        let paths: Vec<&str> = self
+            .args
+            .iter()
+            .skip_while(|arg| *arg != "--")
+            .skip(1)
+            .map(String::as_str)
+            .collect();
+        let mut words = pattern.split_whitespace();
+        if words.next() != Some(subcommand) {
+            return false;
+        }
+
+        let short_flags: String = options
+            .iter()
+            .filter(|option| option.starts_with('-') && !option.starts_with("--"))
+            .flat_map(|option| option.chars().skip(1))
+            .collect();
+        words.all(|word| match word.strip_prefix('-') {
+            Some(flags) if !flags.starts_with('-') && !flags.is_empty() => {
+                flags.chars().all(|flag| short_flags.contains(flag))
+            }
+            Some(_) => options
+                .iter()
+                .any(|option| *option == word || option.starts_with(&format!("{word}="))),
+            None => options.contains(&word) || paths.contains(&word),
+        })
+    }
+
+    /// Returns true when the command is a git command that talks to remotes,
+    /// such as `fetch`, `pull`, or `push`, and is limited by the network
+    /// rather than the CPU.
+    pub fn uses_network(&self) -> bool {
+        match self.git_subcommand() {
+            Some(("fetch" | "pull" | "push" | "ls-remote" | "clone", _)) => true,
+            Some(("remote", options)) => options
+                .first()
+                .is_some_and(|action| matches!(*action, "update" | "prune" | "show")),
+            Some(("submodule", options)) => options.contains(&"update"),
+            _ => false,
+        }
+    }
+
+    /// Returns true when the command may use the terminal while it runs: a
+    /// git command that works over the network, where git and ssh can ask
+    /// for passwords or host keys, one that interacts with the user, or any
+    /// other program, which grpr knows nothing about.
+    pub fn may_prompt(&self) -> bool {
+        self.git_subcommand().is_none() || self.uses_network() || self.needs_terminal()
+    }
+
+    /// Returns true when the command is a git command known to interact with
+    /// the user, such as `add -p`, `rebase -i`, or `commit` without a
+    /// message, which cannot work while other repositories share the
+    /// terminal.
+    pub fn needs_terminal(&self) -> bool {
+        let Some((subcommand, options)) = self.git_subcommand() else {
+            return false;
+        };
+        let has_short = |flags: &str| {
+            options.iter().any(|option| {
+                option.len() > 1
+                    && option.starts_with('-')
+                    && !option.starts_with("--")
+                    && option[1..].chars().any(|flag| flags.contains(flag))
+            })
+        };
+        let has_long = |names: &[&str]| {
+            options.iter().any(|option| {
+                names
+                    .iter()
+                    .any(|name| *option == *name || option.starts_with(&format!("{name}=")))
+            })
+        };
+
+        match subcommand {
+            "mergetool" => true,
+            "add" | "checkout" | "reset" | "restore" | "stash" => {
+                has_long(&["--patch", "--interactive"])
+                    || (subcommand == "add" && has_short("pi"))
+                    || (subcommand != "add" && has_short("p"))
+            }
+            "rebase" | "clean" => has_short("i") || has_long(&["--interactive"]),
+            "commit" => {
+                let message_given = has_short("mFC")
+                    || has_long(&[
+                        "--message",
+                        "--file",
+                        "--reuse-message",
+                        "--no-edit",
+                        "--fixup",
+                    ]);
+                !message_given || has_short("pe") || has_long(&["--patch", "--edit"])
+            }
+            _ => false,
+        }
+    }
+
+    /// Returns the kind of changes the command shows when it is `status` or
+    /// `diff`, so that its output can be colored and counted. Output
+    /// already colored, separated by NULs, or in the second porcelain
+    /// format is left alone.
+    pub fn change_kind(&self) -> Option<ChangeKind> {
+        let (subcommand, options) = self.git_subcommand()?;
+        let unreadable = options.iter().any(|option| {
+            *option == "-z"
+                || (option.starts_with("--color") && *option != "--color=never")
+                || option.starts_with("--porcelain=v2")
+                || *option == "--porcelain=2"
+        });
+        match subcommand {
+            _ if unreadable => None,
+            "status" => Some(ChangeKind::Status),
+            "diff" => Some(ChangeKind::Diff),
+            _ => None,
+        }
+    }
+
+    /// Returns true when `output` holds nothing worth reporting: nothing but
+    /// blank lines, or the lines a git command prints when there was nothing
+    /// for it to do, such as `Already up to date.` from `pull` or a clean
+    /// `status`.
+    pub fn is_routine_output(&self, output: &[u8]) -> bool {
+        let routine: &[&str] = match self.git_subcommand() {
+            Some(("pull" | "merge", _)) => &["Already up to date.", "Already up-to-date."],
+            Some(("push", _)) => &["Everything up-to-date"],
+            Some(("fetch" | "remote", _)) => &["Fetching *"],
+            Some(("status", _)) => &[
+                "On branch *",
+                "Your branch is up to date with *",
+                "nothing to commit, working tree clean",
+            ],
+            _ => &[],
+        };
+        String::from_utf8_lossy(output)
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty())
+            .all(|line| {
+                routine
+                    .iter()
+                    .any(|pattern| match pattern.strip_suffix('*') {
+                        Some(prefix) => line.starts_with(prefix),
+                        None => line == *pattern,
+                    })
+            })
+    }
+
+    /// The program followed by its arguments, from which
+    /// [`RepoCommand::from_words`] rebuilds the command.
+    pub fn words(&self) -> Vec<String> {
+        std::iter::once(&self.program)
+            .chain(&self.args)
+            .cloned()
+            .collect()
+    }
+
+    /// Returns how the command was made, to make it again with
+    /// [`RepoCommand::from_invocation`].
+    pub fn invocation(&self) -> &Invocation {
+        &self.invocation
+    }
+
+    /// Makes a command again from its [`RepoCommand::invocation`], with the
+    /// constructor that first made it.
+    pub fn from_invocation(invocation: Invocation) -> Self {
+        let Invocation { kind, mut words } = invocation;
+        match kind {
+            CommandKind::Git => Self::git(words),
+            CommandKind::Script if !words.is_empty() => {
+                let path = PathBuf::from(words.remove(0));
+                Self::script(&path, words)
+            }
+            CommandKind::Exec | CommandKind::Script => Self::exec(words),
+        }
+    }
+
+    /// Rebuilds a command from its [`RepoCommand::words`]: a git command when
+    /// the program is git, and a program with its arguments otherwise. Only
+    /// for journals written before commands recorded their invocation.
+    pub fn from_words(mut words: Vec<String>) -> Self {
+        if words.first().is_some_and(|program| program == "git") {
+            words.remove(0);
+            return Self::git(words);
+        }
+        Self::exec(words)
+    }
+
+    /// Formats the command as a shell command line.
+    pub fn command_line(&self) -> String {
+        std::iter::once(&self.program)
+            .chain(&self.args)
+            .map(|word| shell_quote(word))
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+
+    /// Builds the process for the command in `repo_path`, telling it which
+    /// repository it runs in through [`REPO_PATH_ENV`] and [`REPO_NAME_ENV`].
+    /// Commands without the terminal are shielded from Ctrl-C so they can
+    /// finish after grpr is interrupted.
+    fn process(&self, repo_path: &Path) -> Command {
+        let name = repo_path.file_name().unwrap_or(repo_path.as_os_str());
+        let mut process = Command::new(&self.program);
+        if !self.keep_git_env {
+            clear_local_repo_env(&mut process);
+        }
+        guard_local_config(&mut process, repo_path);
+        process
+            .args(self.args.iter().map(OsStr::new))
+            .env(REPO_PATH_ENV, repo_path)
+            .env(REPO_NAME_ENV, name)
+            .envs(self.env.iter().map(|(key, value)| (key, value)))
+            .stdin(if self.terminal {
+                Stdio::inherit()
+            } else {
+                Stdio::null()
+            })
+            .current_dir(repo_path);
+        if self.metadata {
+            if let Some(branch) = current_branch(repo_path) {
+                process.env(REPO_BRANCH_ENV, branch);
+            }
+            if let Some(url) = remote_url(repo_path, "origin") {
+                process.env(REPO_REMOTE_ENV, url);
+            }
+        }
+        if !self.terminal {
+            interrupt::shield(&mut process);
+        }
+        self.priority.apply(&mut process);
+        process
+    }
+
+    /// A git command that prepares or cleans up the repository around this
+    /// command, with the same extra environment.
+    fn helper(&self, description: &str, args: &[&str]) -> Self {
+        Self {
+            env: self.env.clone(),
+            terminal: self.terminal,
+            priority: self.priority,
+            description: description.to_string(),
+            ..Self::git(args.iter().map(|arg| arg.to_string()).collect())
+        }
+    }
+
+    /// The git command adding a detached worktree at `worktree` with `rev`
+    /// checked out.
+    fn add_worktree(&self, worktree: &Path, rev: &str) -> Self {
+        let worktree = worktree.to_string_lossy();
+        let args = ["worktree", "add", "--quiet", "--detach", &worktree, rev];
+        self.helper("adding a worktree", &args)
+    }
+
+    /// The git command removing the worktree at `worktree`, with any changes
+    /// the command made there.
+    fn remove_worktree(&self, worktree: &Path) -> Self {
+        let worktree = worktree.to_string_lossy();
+        self.helper(
+            "removing the worktree",
+            &["worktree", "remove", "--force", &worktree],
+        )
+    }
+
+    /// A hook that runs `script` with the same extra environment as this
+    /// command.
+    fn hook(&self, name: &str, script: &str) -> Self {
+        Self {
+            env: self.env.clone(),
+            terminal: self.terminal,
+            priority: self.priority,
+            metadata: self.metadata,
+            keep_git_env: self.keep_git_env,
+            ..Self::shell(script.to_string(), &format!("{name} hook"))
+        }
+    }
+}
+
+/// Environment variables that point git at a particular repository, as
+/// listed by `git rev-parse --local-env-vars`. Git clears them itself before
+/// running a command in another repository, such as a submodule. grpr
+/// inherits them when it runs from a git hook or alias, and left in place
+/// they would make every command act on that one repository.
+const LOCAL_REPO_ENV: [&str; 16] = [
+    "GIT_ALTERNATE_OBJECT_DIRECTORIES",
+    "GIT_CONFIG",
+    "GIT_CONFIG_PARAMETERS",
+    "GIT_CONFIG_COUNT",
+    "GIT_OBJECT_DIRECTORY",
+    "GIT_DIR",
+    "GIT_WORK_TREE",
+    "GIT_IMPLICIT_WORK_TREE",
+    "GIT_GRAFT_FILE",
+    "GIT_INDEX_FILE",
+    "GIT_NO_REPLACE_OBJECTS",
+    "GIT_REPLACE_REF_BASE",
+    "GIT_PREFIX",
+    "GIT_INTERNAL_SUPER_PREFIX",
+    "GIT_SHALLOW_FILE",
+    "GIT_COMMON_DIR",
+];
+
+/// Removes the [`LOCAL_REPO_ENV`] variables from the environment `process`
+/// inherits.
+fn clear_local_repo_env(process: &mut Command) {
+    for name in LOCAL_REPO_ENV {
+        process.env_remove(name);
+    }
+}
+
+/// Set by `--no-local-config` to keep each repository's own config from
+/// running programs in git commands grpr starts.
+static DISTRUST_LOCAL_CONFIG: AtomicBool = AtomicBool::new(false);
+
+/// Settings that override those through which a repository's own config
+/// could run a program: hooks, the file system monitor, the SSH command, and
+/// credential helpers, where an empty value clears the list.
+const UNTRUSTED_CONFIG: [(&str, &str); 4] = [
+    ("core.hooksPath", "/dev/null"),
+    ("core.fsmonitor", "false"),
+    ("core.sshCommand", "ssh"),
+    ("credential.helper", ""),
+];
+
+/// Makes every git command grpr starts from now on, including its own
+/// queries, ignore the programs configured in the repository's own config.
+pub fn distrust_local_config() {
+    DISTRUST_LOCAL_CONFIG.store(true, Ordering::Relaxed);
+}
+
+/// Returns the value that overrides `key` in a repository's own config when
+/// the key names a program for git to run, or lets git run one, such as a
+/// filter, the pager, an editor, an alias, or the `ext::` transport. Keys
+/// are lowercase but for their subsection, as `git config --name-only`
+/// prints them. An empty program makes git fail the command that needs it
+/// rather than run anything.
+fn untrusted_value(key: &str) -> Option<&'static str> {
+    let (section, rest) = key.split_once('.')?;
+    let (subsection, name) = match rest.rsplit_once('.') {
+        Some((subsection, name)) => (Some(subsection), name),
+        None => (None, rest),
+    };
+    match (section, subsection, name) {
+        (
+            "filter" | "diff" | "merge",
+            Some(_),
+            "clean" | "smudge" | "process" | "textconv" | "command" | "driver",
+        )
+        | ("diff", None, "external")
+        | ("core", None, "askpass" | "alternaterefscommand")
+        | ("credential", Some(_), "helper")
+        | ("alias", None, _) => Some(""),
+        ("core", None, "pager") | ("interactive", None, "difffilter") => Some("cat"),
+        ("pager", None, _)
+        | ("core" | "sequence", None, "editor")
+        | ("gpg", _, "program")
+        | ("gpg", Some("ssh"), "defaultkeycommand")
+        | ("difftool" | "mergetool", Some(_), "cmd") => Some("false"),
+        ("protocol", Some("ext"), "allow") => Some("never"),
+        ("protocol", _, "allow") => Some("user"),
+        ("submodule", Some(_), "update") => Some("checkout"),
+        _ => None,
+    }
+}
+
+/// Returns the environment that overrides the programs `repo_path`'s own
+/// config may run, through git's `GIT_CONFIG_COUNT` variables, which take
+/// precedence over config files. The keys are read with the files the
+/// config includes, so `include.path` cannot bring in settings that are
+/// left alone. The first value wins for `core.gitProxy` and for the
+/// `uploadpack` and `receivepack` programs of remotes, so the first is
+/// overridden by an empty `GIT_PROXY_COMMAND` instead, and the others by
+/// refusing the local transport, the only one to run them on this host.
+fn untrusted_config_env(repo_path: &Path) -> Vec<(String, String)> {
+    let mut process = Command::new("git");
+    clear_local_repo_env(&mut process);
+    let listed = process
+        .args([
+            "config",
+            "--includes",
+            "--show-scope",
+            "--name-only",
+            "--list",
+        ])
+        .current_dir(repo_path)
+        .stdin(Stdio::null())
+        .stderr(Stdio::null())
+        .output()
+        .map(|output| String::from_utf8_lossy(&output.stdout).into_owned())
+        .unwrap_or_default();
+    let local_keys: BTreeSet<&str> = listed
+        .lines()
+        .filter_map(|line| line.split_once('\t'))
+        .filter(|(scope, _)| matches!(*scope, "local" | "worktree"))
+        .map(|(_, key)| key)
+        .collect();
+    let mut overrides: Vec<(&str, &str)> = UNTRUSTED_CONFIG
+        .into_iter()
+        .chain(
+            local_keys
+                .iter()
+                .filter_map(|key| untrusted_value(key).map(|value| (*key, value))),
+        )
+        .collect();
+    let remote_programs = local_keys.iter().any(|key| {
+        key.starts_with("remote.")
+            && (key.ends_with(".uploadpack") || key.ends_with(".receivepack"))
+    });
+    if remote_programs {
+        overrides.push(("protocol.file.allow", "never"));
+    }
+
+    let mut env = vec![("GIT_CONFIG_COUNT".to_string(), overrides.len().to_string())];
+    for (index, (key, value)) in overrides.into_iter().enumerate() {
+        env.push((format!("GIT_CONFIG_KEY_{index}"), key.to_string()));
+        env.push((format!("GIT_CONFIG_VALUE_{index}"), value.to_string()));
+    }
+    if local_keys.contains("core.gitproxy") {
+        env.push(("GIT_PROXY_COMMAND".to_string(), String::new()));
+    }
+    env
+}
+
+/// Overrides the programs configured in `repo_path`'s own config for
+/// `process` after [`distrust_local_config`].
+fn guard_local_config(process: &mut Command, repo_path: &Path) {
+    if DISTRUST_LOCAL_CONFIG.load(Ordering::Relaxed) {
+        process.envs(untrusted_config_env(repo_path));
+    }
+}
+
+/// Environment variable holding the path of the repository a command runs in.
+pub const REPO_PATH_ENV: &str = "GRPR_REPO_PATH";
+/// Environment variable holding the directory name of the repository.
+pub const REPO_NAME_ENV: &str = "GRPR_REPO_NAME";
+/// Environment variable holding the checked-out branch of the repository,
+/// set for scripts unless `HEAD` is detached.
+pub const REPO_BRANCH_ENV: &str = "GRPR_REPO_BRANCH";
+/// Environment variable holding the `origin` URL of the repository, set for
+/// scripts when the remote exists.
+pub const REPO_REMOTE_ENV: &str = "GRPR_REPO_REMOTE";
+
+/// Returns true when `path` is a file its owner may execute.
+fn is_executable(path: &Path) -> bool {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        fs::metadata(path).is_ok_and(|metadata| metadata.permissions().mode() & 0o100 != 0)
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = path;
+        false
+    }
+}
+
+/// Parses a `KEY=VALUE` environment variable, as given to `--env`.
+pub fn parse_env_var(var: &str) -> Result<(String, String), String> {
+    match var.split_once('=') {
+        Some((key, value)) if !key.is_empty() && !key.contains('\0') => {
+            Ok((key.to_string(), value.to_string()))
+        }
+        _ => Err(format!("invalid variable `{var}`: expected KEY=VALUE")),
+    }
+}
+
+/// Environment variable that tells a post hook how the main command exited.
+pub const EXIT_STATUS_ENV: &str = "GRPR_EXIT_STATUS";
+
+/// Shell commands run in each repository around the main command.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Hooks {
+    /// Runs first; when it fails, the main command and post hook are skipped.
+    pub pre: Option<String>,
+    /// Runs after the main command, whether or not it succeeded, with its
+    /// exit code in [`EXIT_STATUS_ENV`].
+    pub post: Option<String>,
+    /// Stash local changes, including untracked files, before the main
+    /// command in a dirty repository, and restore them afterwards.
+    pub autostash: bool,
+    /// Run the command and its hooks in a temporary linked worktree with
+    /// this revision checked out, leaving the repository's own checkout
+    /// alone.
+    pub worktree: Option<String>,
+    /// Keep only the start of long captured output in memory, and the whole
+    /// of it in a file.
+    pub max_output: Option<OutputLimit>,
+}
+
+/// How much of a repository's captured stdout, and of its stderr, is kept in
+/// memory with `--max-output`. A stream that goes past the limit is written
+/// in full to a file in `dir`, which is created when first needed. A
+/// `private` directory is one made for this run alone in a shared place,
+/// such as the temporary directory, so only the user may read it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OutputLimit {
+    pub bytes: usize,
+    pub dir: PathBuf,
+    pub private: bool,
+}
+
+impl OutputLimit {
+    /// Creates `dir`, if it does not exist yet. A private one is created
+    /// readable only by the user and, when it exists already, must be the
+    /// user's own, so that nobody else can create it first to read the
+    /// output spilled into it.
+    fn create_dir(&self) -> io::Result<()> {
+        if !self.private {
+            return fs::create_dir_all(&self.dir);
+        }
+        let mut builder = fs::DirBuilder::new();
+        #[cfg(unix)]
+        std::os::unix::fs::DirBuilderExt::mode(&mut builder, 0o700);
+        match builder.create(&self.dir) {
+            Err(err) if err.kind() == io::ErrorKind::AlreadyExists => {}
+            result => return result,
+        }
+        // Another repository's output spilled first, or someone else got
+        // here before this run.
+        let metadata = fs::symlink_metadata(&self.dir)?;
+        #[cfg(unix)]
+        let owned = {
+            use std::os::unix::fs::MetadataExt;
+            // SAFETY: getuid cannot fail.
+            metadata.uid() == unsafe { libc::getuid() } && metadata.mode() & 0o077 == 0
+        };
+        #[cfg(not(unix))]
+        let owned = true;
+        if metadata.is_dir() && owned {
+            Ok(())
+        } else {
+            Err(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                format!("{} already exists and is not private", self.dir.display()),
+            ))
+        }
+    }
+
+    /// Creates a new file in `dir` for the `stream`, `stdout` or `stderr`,
+    /// of the command in `repo_path`, named after the repository so that
+    /// it is easy to find, with a number added when the name is taken.
+    fn spill_file(&self, repo_path: &Path, stream: &str) -> io::Result<(PathBuf, fs::File)> {
+        self.create_dir()?;
+        let name = repo_path
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_else(|| "repository".to_string());
+        for count in 1.. {
+            let file_name = match count {
+                1 => format!("{name}.{stream}"),
+                count => format!("{name}-{count}.{stream}"),
+            };
+            let path = self.dir.join(file_name);
+            match fs::File::create_new(&path) {
+                Ok(file) => return Ok((path, file)),
+                Err(err) if err.kind() == io::ErrorKind::AlreadyExists => continue,
+                Err(err) => return Err(err),
+            }
+        }
+        unreachable!("some file name is free")
+    }
+}
+
+/// The message of stashes made by [`Hooks::autostash`].
+const AUTOSTASH_MESSAGE: &str = "grpr autostash";
+
+impl Hooks {
+    fn pre_command(&self, command: &RepoCommand) -> Option<RepoCommand> {
+        self.pre
+            .as_deref()
+            .map(|script| command.hook("pre", script))
+    }
+
+    /// The command stashing the local changes in `repo_path` before the main
+    /// command, when they are to be stashed.
+    fn stash_command(&self, command: &RepoCommand, repo_path: &Path) -> Option<RepoCommand> {
+        (self.autostash && is_dirty(repo_path) == Some(true)).then(|| {
+            command.helper(
+                "autostash",
+                &[
+                    "stash",
+                    "push",
+                    "--include-untracked",
+                    "--message",
+                    AUTOSTASH_MESSAGE,
+                ],
+            )
+        })
+    }
+
+    /// The post hook for a main command that exited with `code`, or -1 when
+    /// it could not be started or was ended by a signal.
+    fn post_command(&self, command: &RepoCommand, code: Option<i32>) -> Option<RepoCommand> {
+        self.post.as_deref().map(|script| {
+            command
+                .hook("post", script)
+                .with_env(EXIT_STATUS_ENV, code.unwrap_or(-1).to_string())
+        })
+    }
+}
+
+/// Numbers the temporary worktrees made by this process.
+static WORKTREES: AtomicUsize = AtomicUsize::new(0);
+
+/// Returns a fresh location for a temporary worktree of `repo_path`. The
+/// worktree directory has the repository's name, so commands see the usual
+/// [`REPO_NAME_ENV`].
+fn worktree_path(repo_path: &Path) -> PathBuf {
+    let name = repo_path.file_name().unwrap_or(OsStr::new("repository"));
+    let number = WORKTREES.fetch_add(1, Ordering::Relaxed);
+    std::env::temp_dir()
+        .join(format!("grpr-worktree-{}-{number}", std::process::id()))
+        .join(name)
+}
+
+/// Removes the directory [`worktree_path`] made around `worktree`, once git
+/// has removed the worktree itself.
+fn remove_worktree_dir(worktree: &Path) {
+    let Some(parent) = worktree.parent() else {
+        return;
+    };
+    match fs::remove_dir_all(parent) {
+        Ok(()) => {}
+        Err(err) if err.kind() == io::ErrorKind::NotFound => {}
+        Err(err) => tracing::warn!("cannot remove {}: {err}", parent.display()),
+    }
+}
+
+/// Restores the changes stashed before `command` ran in `repo_path`, using
+/// `run` to run `git stash pop`, which reports whether it succeeded. When
+/// the command failed and left the working tree changed, as in a conflicted
+/// rebase, the changes stay stashed. Returns what went wrong, if anything.
+fn restore_stash(
+    repo_path: &Path,
+    command: &RepoCommand,
+    succeeded: bool,
+    mode: RunMode,
+    run: impl FnOnce(&RepoCommand) -> bool,
+) -> Option<String> {
+    if !succeeded && mode == RunMode::Execute && is_dirty(repo_path) != Some(false) {
+        return Some(format!(
+            "autostash: local changes in {} stay stashed because the command failed; \
+             run `git stash pop` once it is resolved",
+            repo_path.display()
+        ));
+    }
+    if run(&command.helper("autostash", &["stash", "pop"])) {
+        return None;
+    }
+    Some(format!(
+        "autostash: restoring local changes in {} conflicted; resolve the conflicts, \
+         then remove the stash with `git stash drop`",
+        repo_path.display()
+    ))
+}
+
+/// Executes a command in the provided repository path and returns its exit
+/// status, or `None` in [`RunMode::DryRun`], where the command line is
+/// printed instead.
+fn run_status(
+    repo_path: &Path,
+    command: &RepoCommand,
+    mode: RunMode,
+) -> Result<Option<ExitStatus>, io::Error> {
+    if mode == RunMode::DryRun {
+        println!("{}", command.command_line());
+        return Ok(None);
+    }
+
+    tracing::debug!(
+        "running `{}` in {}",
+        command.command_line(),
+        repo_path.display()
+    );
+    let mut child = command
+        .process(repo_path)
+        .stdout(Stdio::inherit())
+        .stderr(Stdio::inherit())
+        .spawn()?;
+    let status = interrupt::wait(&mut child)?;
+    tracing::debug!(
+        "`{}` in {} finished: {status}",
+        command.command_line(),
+        repo_path.display()
+    );
+    Ok(Some(status))
+}
+
+/// One stream of a command's output, of which at most a limit is kept in
+/// memory.
+#[derive(Debug, Default)]
+struct Bounded {
+    kept: Vec<u8>,
+    /// How long the whole stream was.
+    total: u64,
+    /// The file holding the whole stream when it went past the limit, or
+    /// why it could not be written.
+    spilled: Option<io::Result<PathBuf>>,
+}
+
+impl Bounded {
+    /// Reads the `stream` of the command in `repo_path` from `pipe` to the
+    /// end, keeping all of it, or with a `limit` only as much as is left of
+    /// it once the `used` bytes are taken off. Once the stream goes past
+    /// that, the whole of it is written to a file instead.
+    fn read(
+        mut pipe: impl Read,
+        limit: Option<&OutputLimit>,
+        used: usize,
+        repo_path: &Path,
+        stream: &str,
+    ) -> Self {
+        let mut bounded = Self::default();
+        let Some(output_limit) = limit else {
+            let _ = pipe.read_to_end(&mut bounded.kept);
+            bounded.total = bounded.kept.len() as u64;
+            return bounded;
+        };
+        let limit = output_limit.bytes.saturating_sub(used);
+        let mut file = None;
+        let mut buffer = [0; 8192];
+        loop {
+            let read = match pipe.read(&mut buffer) {
+                Ok(0) => break,
+                Ok(read) => read,
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+                Err(_) => break,
+            };
+            let chunk = &buffer[..read];
+            bounded.total += read as u64;
+            let room = limit.saturating_sub(bounded.kept.len()).min(read);
+            if room == read {
+                bounded.kept.extend_from_slice(chunk);
+                continue;
+            }
+            if bounded.spilled.is_none() {
+                // The start of the stream goes to the file too.
+                let spilled = output_limit.spill_file(repo_path, stream);
+                bounded.spilled = Some(spilled.and_then(|(path, mut opened)| {
+                    opened.write_all(&bounded.kept)?;
+                    file = Some(opened);
+                    Ok(path)
+                }));
+            }
+            bounded.kept.extend_from_slice(&chunk[..room]);
+            if let Some(opened) = &mut file {
+                if let Err(err) = opened.write_all(chunk) {
+                    bounded.spilled = Some(Err(err));
+                    file = None;
+                }
+            }
+        }
+        bounded
+    }
+
+    /// Appends the kept output to `kept`, ending it with a note of where
+    /// the rest is when it was cut short. Returns the file holding the
+    /// whole stream, if any.
+    fn append_to(self, kept: &mut Vec<u8>) -> Option<PathBuf> {
+        let cut = self.kept.len() as u64;
+        kept.extend_from_slice(&self.kept);
+        let spilled = self.spilled?;
+        if !kept.is_empty() && !kept.ends_with(b"\n") {
+            kept.push(b'\n');
+        }
+        let (note, path) = match spilled {
+            Ok(path) => (
+                format!("the whole output is in {}", path.display()),
+                Some(path),
+            ),
+            Err(err) => (format!("cannot keep the rest: {err}"), None),
+        };
+        kept.extend_from_slice(
+            format!(
+                "grpr: output cut short after {cut} of {} bytes (--max-output); {note}\n",
+                self.total
+            )
+            .as_bytes(),
+        );
+        path
+    }
+}
+
+/// The output of a command run by [`collect_output`].
+struct Collected {
+    status: ExitStatus,
+    stdout: Bounded,
+    stderr: Bounded,
+}
+
+/// Runs the `process` of the command in `repo_path` to completion and
+/// collects its output, like [`Command::output`], but stops it when grpr is
+/// interrupted twice. With a `limit`, at most that much of each stream,
+/// less what is `used` of it already, is kept in memory.
+fn collect_output(
+    mut process: Command,
+    repo_path: &Path,
+    limit: Option<&OutputLimit>,
+    used: (usize, usize),
+) -> Result<Collected, io::Error> {
+    let mut child = process
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()?;
+    let stdout = child.stdout.take();
+    let stderr = child.stderr.take();
+
+    thread::scope(|scope| {
+        let stdout = scope.spawn(|| {
+            stdout
+                .map(|pipe| Bounded::read(pipe, limit, used.0, repo_path, "stdout"))
+                .unwrap_or_default()
+        });
+        let stderr = scope.spawn(|| {
+            stderr
+                .map(|pipe| Bounded::read(pipe, limit, used.1, repo_path, "stderr"))
+                .unwrap_or_default()
+        });
+        let status = interrupt::wait(&mut child)?;
+        Ok(Collected {
+            status,
+            stdout: stdout.join().unwrap_or_default(),
+            stderr: stderr.join().unwrap_or_default(),
+        })
+    })
+}
+
+/// Executes a command in the provided repository path. In
+/// [`RunMode::DryRun`] the command line is printed instead.
+pub fn run_command(
+    repo_path: &Path,
+    command: &RepoCommand,
+    mode: RunMode,
+) -> Result<(), io::Error> {
+    match run_status(repo_path, command, mode)? {
+        Some(status) => check_status(repo_path, command, status),
+        None => Ok(()),
+    }
+}
+
+/// The output of a command and its hooks in one repository, kept so it can
+/// be presented later.
+#[derive(Debug)]
+pub struct Captured {
+    pub stdout: Vec<u8>,
+    pub stderr: Vec<u8>,
+    /// The exit code of the command itself, or `None` when it did not run
+    /// or was killed by a signal.
+    pub exit_code: Option<i32>,
+    /// The first failure among the hooks and the command.
+    pub result: Result<(), io::Error>,
+    /// The files holding the whole of output cut short by `--max-output`.
+    pub spilled: Vec<PathBuf>,
+}
+
+impl Captured {
+    fn new() -> Self {
+        Self {
+            stdout: Vec::new(),
+            stderr: Vec::new(),
+            exit_code: None,
+            result: Ok(()),
+            spilled: Vec::new(),
+        }
+    }
+
+    /// Appends the output of `command` and returns its exit code, or records
+    /// the failure to start it. With a `limit`, the output kept across the
+    /// commands run in the repository stays within it.
+    fn run(
+        &mut self,
+        repo_path: &Path,
+        command: &RepoCommand,
+        limit: Option<&OutputLimit>,
+    ) -> Option<i32> {
+        tracing::debug!(
+            "running `{}` in {}",
+            command.command_line(),
+            repo_path.display()
+        );
+        let used = (self.stdout.len(), self.stderr.len());
+        match collect_output(command.process(repo_path), repo_path, limit, used) {
+            Ok(output) => {
+                tracing::debug!(
+                    "`{}` in {} finished: {}",
+                    command.command_line(),
+                    repo_path.display(),
+                    output.status
+                );
+                self.spilled
+                    .extend(output.stdout.append_to(&mut self.stdout));
+                self.spilled
+                    .extend(output.stderr.append_to(&mut self.stderr));
+                if self.result.is_ok() {
+                    self.result = check_status(repo_path, command, output.status);
+                }
+                output.status.code()
+            }
+            Err(err) => {
+                if self.result.is_ok() {
+                    self.result = Err(err);
+                }
+                None
+            }
+        }
+    }
+
+    /// Runs `command` between its hooks, stashing local changes around it
+    /// with `--autostash`. A failing pre hook skips the rest.
+    fn run_between_hooks(&mut self, repo_path: &Path, command: &RepoCommand, hooks: &Hooks) {
+        let limit = hooks.max_output.as_ref();
+        if let Some(pre) = hooks.pre_command(command) {
+            self.run(repo_path, &pre, limit);
+            if self.result.is_err() {
+                return;
+            }
+        }
+        let stash = hooks.stash_command(command, repo_path);
+        if let Some(stash) = &stash {
+            self.run(repo_path, stash, limit);
+            if self.result.is_err() {
+                return;
+            }
+        }
+        let code = self.run(repo_path, command, limit);
+        self.exit_code = code;
+        if stash.is_some() {
+            let succeeded = self.result.is_ok();
+            let problem = restore_stash(repo_path, command, succeeded, RunMode::Execute, |pop| {
+                let mut restored = Captured::new();
+                restored.run(repo_path, pop, limit);
+                self.stdout.append(&mut restored.stdout);
+                self.stderr.append(&mut restored.stderr);
+                self.spilled.append(&mut restored.spilled);
+                restored.result.is_ok()
+            });
+            match problem {
+                Some(problem) if succeeded => self.result = Err(io::Error::other(problem)),
+                Some(problem) => self
+                    .stderr
+                    .extend_from_slice(format!("grpr: {problem}\n").as_bytes()),
+                None => {}
+            }
+        }
+        if let Some(post) = hooks.post_command(command, code) {
+            self.run(repo_path, &post, limit);
+        }
+    }
+}
+
+/// Executes a command and its hooks in the provided repository path,
+/// capturing their output so it can be presented later.
+pub fn capture_command(repo_path: &Path, command: &RepoCommand, hooks: &Hooks) -> Captured {
+    let mut captured = Captured::new();
+    let Some(rev) = &hooks.worktree else {
+        captured.run_between_hooks(repo_path, command, hooks);
+        return captured;
+    };
+
+    let limit = hooks.max_output.as_ref();
+    let worktree = worktree_path(repo_path);
+    captured.run(repo_path, &command.add_worktree(&worktree, rev), limit);
+    if captured.result.is_err() {
+        remove_worktree_dir(&worktree);
+        return captured;
+    }
+    captured.run_between_hooks(&worktree, command, hooks);
+    captured.run(repo_path, &command.remove_worktree(&worktree), limit);
+    remove_worktree_dir(&worktree);
+    captured
+}
+
+/// Runs a git query in the repository and returns its trimmed stdout, or
+/// `None` when git cannot be run or exits unsuccessfully.
+pub fn git_output(repo_path: &Path, args: &[&str]) -> Option<String> {
+    let mut process = Command::new("git");
+    clear_local_repo_env(&mut process);
+    guard_local_config(&mut process, repo_path);
+    let output = process
+        .args(args)
+        .current_dir(repo_path)
+        .stdin(Stdio::null())
+        .output()
+        .ok()?;
+
+    output
+        .status
+        .success()
+        .then(|| String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+/// Returns the URL configured for `remote` in the repository, if any.
+pub fn remote_url(repo_path: &Path, remote: &str) -> Option<String> {
+    config_value(repo_path, &format!("remote.{remote}.url"))
+}
+
+/// Returns the host a remote URL points at, in lowercase and without a user
+/// or port, such as `github.com` for both `https://github.com/o/r.git` and
+/// `git@github.com:o/r.git`. Local paths and `file://` URLs have no host.
+pub fn remote_host(url: &str) -> Option<String> {
+    let authority = match url.split_once("://") {
+        Some(("file", _)) => return None,
+        Some((_, rest)) => rest.split('/').next()?,
+        None => {
+            // An scp-like `user@host:path`. A slash before the colon, or a
+            // single letter such as `C:`, marks a local path instead.
+            let (host, _) = url.split_once(':')?;
+            if host.contains(['/', '\\']) || host.len() == 1 {
+                return None;
+            }
+            host
+        }
+    };
+    let host_and_port = authority.rsplit('@').next()?;
+    let host = match host_and_port.strip_prefix('[') {
+        Some(bracketed) => bracketed.split(']').next()?,
+        None => host_and_port.split(':').next()?,
+    };
+    (!host.is_empty()).then(|| host.to_ascii_lowercase())
+}
+
+/// Returns the port a remote URL connects to: the one it names, or the
+/// default of its protocol, such as 443 for `https` and 22 for scp-like
+/// addresses. Local paths, and protocols run by a helper, have none.
+pub fn remote_port(url: &str) -> Option<u16> {
+    remote_host(url)?;
+    let Some((scheme, rest)) = url.split_once("://") else {
+        return Some(22);
+    };
+    let authority = rest.split('/').next()?;
+    let host_and_port = authority.rsplit('@').next()?;
+    let port = match host_and_port.strip_prefix('[') {
+        Some(bracketed) => bracketed.split_once("]:").map(|(_, port)| port),
+        None => host_and_port.split_once(':').map(|(_, port)| port),
+    };
+    match port {
+        Some(port) if !port.is_empty() => port.parse().ok(),
+        _ => match scheme.to_ascii_lowercase().as_str() {
+            "ssh" | "git+ssh" | "ssh+git" => Some(22),
+            "git" => Some(9418),
+            "http" => Some(80),
+            "https" => Some(443),
+            _ => None,
+        },
+    }
+}
+
+/// Returns the total size in bytes of the files in the repository's git
+/// directory, which holds its history and objects. Symbolic links are not
+/// followed, and unreadable entries are skipped.
+pub fn git_dir_size(repo_path: &Path) -> Option<u64> {
+    fn directory_size(dir: &Path) -> u64 {
+        let Ok(entries) = fs::read_dir(dir) else {
+            return 0;
+        };
+        entries
+            .flatten()
+            .map(|entry| match entry.file_type() {
+                Ok(file_type) if file_type.is_dir() => directory_size(&entry.path()),
+                Ok(file_type) if file_type.is_file() => {
+                    entry.metadata().map(|metadata| metadata.len()).unwrap_or(0)
+                }
+                _ => 0,
+            })
+            .sum()
+    }
+
+    let git_dir = git_dir(repo_path)?;
+    git_dir.is_dir().then(|| directory_size(&git_dir))
+}
+
+/// Returns true when the working tree declares submodules in `.gitmodules`.
+pub fn has_submodules(repo_path: &Path) -> bool {
+    repo_path.join(GITMODULES_FILE_NAME).is_file()
+}
+
+/// Returns the effective value of the git config `key` in the repository,
+/// including global and system settings, or the last one when the key has
+/// several.
+pub fn config_value(repo_path: &Path, key: &str) -> Option<String> {
+    git_output(repo_path, &["config", "--get", key])
+}
+
+/// Returns the URLs of every remote configured in the repository, in
+/// configuration order.
+pub fn remote_urls(repo_path: &Path) -> Vec<String> {
+    git_output(repo_path, &["config", "--get-regexp", r"^remote\..*\.url$"])
+        .map(|urls| {
+            urls.lines()
+                .filter_map(|line| line.split_once(' '))
+                .map(|(_, url)| url.to_string())
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Returns the names of the remotes configured in the repository, sorted by
+/// name, or `None` when they cannot be read.
+pub fn remote_names(repo_path: &Path) -> Option<Vec<String>> {
+    let names = git_output(repo_path, &["remote"])?;
+    Some(names.lines().map(str::to_string).collect())
+}
+
+/// The kinds of uncommitted change in a working tree.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Changes {
+    /// Changes recorded in the index but not committed.
+    pub staged: bool,
+    /// Changes to tracked files that are not in the index.
+    pub unstaged: bool,
+    /// Files git does not track and does not ignore.
+    pub untracked: bool,
+}
+
+impl Changes {
+    /// Returns true when there is a change of any kind.
+    pub fn any(self) -> bool {
+        self.staged || self.unstaged || self.untracked
+    }
+}
+
+/// Reads the uncommitted changes in the working tree, or `None` when the
+/// status cannot be read, as in bare repositories.
+pub fn working_tree_changes(repo_path: &Path) -> Option<Changes> {
+    let status = git_output(
+        repo_path,
+        &["status", "--porcelain=v2", "--untracked-files=normal"],
+    )?;
+
+    let mut changes = Changes::default();
+    for line in status.lines() {
+        let mut fields = line.split(' ');
+        match fields.next() {
+            Some("?") => changes.untracked = true,
+            Some("1" | "2" | "u") => {
+                let code = fields.next().unwrap_or_default().as_bytes();
+                changes.staged |= code.first().is_some_and(|x| *x != b'.');
+                changes.unstaged |= code.get(1).is_some_and(|y| *y != b'.');
+            }
+            _ => {}
+        }
+    }
+    Some(changes)
+}
+
+/// Returns true when the working tree has staged, unstaged, or untracked
+/// changes, or `None` when the status cannot be read, as in bare repositories.
+pub fn is_dirty(repo_path: &Path) -> Option<bool> {
+    working_tree_changes(repo_path).map(Changes::any)
+}
+
+/// How the current branch relates to its upstream tracking branch.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UpstreamState {
+    UpToDate,
+    /// Has local commits the upstream lacks.
+    Ahead,
+    /// Lacks commits the upstream has.
+    Behind,
+    /// Both has local commits and lacks upstream ones.
+    Diverged,
+}
+
+/// Compares the current branch with its upstream as last fetched. Returns
+/// `None` when there is no upstream, as on a detached `HEAD` or an untracked
+/// branch.
+pub fn upstream_state(repo_path: &Path) -> Option<UpstreamState> {
+    let (ahead, behind) = ahead_behind(repo_path)?;
+
+    Some(match (ahead > 0, behind > 0) {
+        (false, false) => UpstreamState::UpToDate,
+        (true, false) => UpstreamState::Ahead,
+        (false, true) => UpstreamState::Behind,
+        (true, true) => UpstreamState::Diverged,
+    })
+}
+
+/// Counts the commits on the current branch that its upstream lacks, and
+/// those on the upstream that the branch lacks, as of the last fetch.
+pub fn ahead_behind(repo_path: &Path) -> Option<(usize, usize)> {
+    let counts = git_output(
+        repo_path,
+        &["rev-list", "--left-right", "--count", "HEAD...@{upstream}"],
+    )?;
+    let mut counts = counts.split_whitespace().map(str::parse::<usize>);
+    let ahead = counts.next()?.ok()?;
+    let behind = counts.next()?.ok()?;
+    Some((ahead, behind))
+}
+
+/// Returns the name of the checked-out branch, or `None` on a detached
+/// `HEAD`.
+pub fn current_branch(repo_path: &Path) -> Option<String> {
+    let head = fs::read_to_string(git_dir(repo_path)?.join(HEAD_FILE_NAME)).ok()?;
+    let branch = head.trim().strip_prefix("ref:")?.trim();
+    Some(
+        branch
+            .strip_prefix("refs/heads/")
+            .unwrap_or(branch)
+            .to_string(),
+    )
+}
+
+/// Returns the tags pointing at `HEAD`, sorted by name.
+pub fn head_tags(repo_path: &Path) -> Vec<String> {
+    git_output(repo_path, &["tag", "--points-at", "HEAD"])
+        .map(|tags| tags.lines().map(str::to_string).collect())
+        .unwrap_or_default()
+}
+
+/// Returns true when the current branch tracks an upstream branch that no
+/// longer exists, as after the branch was merged and deleted on the remote
+/// and pruned locally.
+pub fn upstream_gone(repo_path: &Path) -> bool {
+    let Some(branch) = current_branch(repo_path) else {
+        return false;
+    };
+
+    git_output(
+        repo_path,
+        &[
+            "for-each-ref",
+            "--format=%(upstream:track)",
+            &format!("refs/heads/{branch}"),
+        ],
+    )
+    .is_some_and(|track| track == "[gone]")
+}
+
+/// Returns true when the repository has stashed changes.
+pub fn has_stash(repo_path: &Path) -> bool {
+    git_output(
+        repo_path,
+        &["rev-parse", "--verify", "--quiet", "refs/stash"],
+    )
+    .is_some()
+}
+
+/// Returns true when `HEAD` points directly at a commit rather than at a
+/// branch, as after checking out a tag or a commit, or `None` when `HEAD`
+/// cannot be read.
+pub fn is_detached(repo_path: &Path) -> Option<bool> {
+    let head = fs::read_to_string(git_dir(repo_path)?.join(HEAD_FILE_NAME)).ok()?;
+    Some(!head.starts_with("ref:"))
+}
+
+/// Returns the branch that `origin/HEAD` points at, such as `main`, if the
+/// repository has one recorded.
+pub fn default_branch(repo_path: &Path) -> Option<String> {
+    let remote_head = git_output(
+        repo_path,
+        &[
+            "symbolic-ref",
+            "--quiet",
+            "--short",
+            "refs/remotes/origin/HEAD",
+        ],
+    )?;
+
+    remote_head
+        .strip_prefix("origin/")
+        .map(str::to_string)
+        .filter(|branch| !branch.is_empty())
+}
+
+/// Prints the repository being processed and runs the command in it, between
+/// its hooks. A failing pre hook skips the command and the post hook.
+pub fn process_repository(
+    repo_path: &Path,
+    command: &RepoCommand,
+    hooks: &Hooks,
+    mode: RunMode,
+) -> Result<(), io::Error> {
+    println!("{}", output::header(repo_path, Outcome::Running));
+    let result = run_in_worktree(repo_path, command, hooks, mode);
+    if let Some(group_end) = output::group_end() {
+        println!("{group_end}");
+    }
+    result
+}
+
+/// Runs the command in `repo_path`, or in a temporary worktree of it with
+/// `--in-worktree`, between its hooks.
+fn run_in_worktree(
+    repo_path: &Path,
+    command: &RepoCommand,
+    hooks: &Hooks,
+    mode: RunMode,
+) -> Result<(), io::Error> {
+    let Some(rev) = &hooks.worktree else {
+        return run_between_hooks(repo_path, command, hooks, mode);
+    };
+
+    let worktree = worktree_path(repo_path);
+    if let Err(err) = run_command(repo_path, &command.add_worktree(&worktree, rev), mode) {
+        remove_worktree_dir(&worktree);
+        return Err(err);
+    }
+    let result = run_between_hooks(&worktree, command, hooks, mode);
+    let removed = run_command(repo_path, &command.remove_worktree(&worktree), mode);
+    remove_worktree_dir(&worktree);
+    result.and(removed)
+}
+
+/// Runs `command` in `repo_path` between its hooks, stashing local changes
+/// around it with `--autostash`. A failing pre hook skips the rest.
+fn run_between_hooks(
+    repo_path: &Path,
+    command: &RepoCommand,
+    hooks: &Hooks,
+    mode: RunMode,
+) -> Result<(), io::Error> {
+    if let Some(pre) = hooks.pre_command(command) {
+        run_command(repo_path, &pre, mode)?;
+    }
+
+    let stash = hooks.stash_command(command, repo_path);
+    if let Some(stash) = &stash {
+        run_command(repo_path, stash, mode)?;
+    }
+
+    let (mut result, code) = match run_status(repo_path, command, mode) {
+        Ok(Some(status)) => (check_status(repo_path, command, status), status.code()),
+        Ok(None) => (Ok(()), Some(0)),
+        Err(err) => (Err(err), None),
+    };
+    if stash.is_some() {
+        let problem = restore_stash(repo_path, command, result.is_ok(), mode, |pop| {
+            run_command(repo_path, pop, mode).is_ok()
+        });
+        match problem {
+            Some(problem) if result.is_ok() => result = Err(io::Error::other(problem)),
+            Some(problem) => tracing::warn!("{problem}"),
+            None => {}
+        }
+    }
+    match hooks.post_command(command, code) {
+        Some(post) => result.and(run_command(repo_path, &post, mode)),
+        None => result,
+    }
+}
+
+/// Presents a captured result the way [`process_repository`] presents a live
+/// run: the repository header, colored by the outcome, and the command's
+/// stdout on stdout, and its stderr on stderr. Returns the failure, if any,
+/// for the caller to report.
+pub fn print_captured_result(repo_path: &Path, captured: Captured) -> Result<(), io::Error> {
+    let outcome = if captured.result.is_ok() {
+        Outcome::Succeeded
+    } else {
+        Outcome::Failed
+    };
+    let mut stdout = io::stdout().lock();
+    let header = format!("{}\n", output::header(repo_path, outcome));
+    output::write_stamped(&mut stdout, header.as_bytes())?;
+    output::write_stamped(&mut stdout, &captured.stdout)?;
+    stdout.flush()?;
+    output::write_stamped(io::stderr().lock(), &captured.stderr)?;
+    if let Some(group_end) = output::group_end() {
+        writeln!(stdout, "{group_end}")?;
+    }
+    captured.result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{git, git_with_env};
+    use std::fs;
+    use tempfile::tempdir;
+
+    fn create_regular_repo(path: &Path) {
+        let git_dir = path.join(".git");
+        fs::create_dir_all(&git_dir).unwrap();
+        fs::write(git_dir.join("config"), "[core]\n").unwrap();
+    }
+
+    /// Runs git in `dir` with a fixed identity, failing the test on error.
+    #[test]
+    fn detect_repository_identifies_valid_regular_repo() {
+        let dir = tempdir().unwrap();
+        let repo_dir = dir.path().join("repo");
+        fs::create_dir_all(&repo_dir).unwrap();
+        create_regular_repo(&repo_dir);
+
+        assert_eq!(detect_repository(&repo_dir), Some(RepositoryKind::Regular));
+    }
+
+    #[test]
+    fn detect_repository_rejects_missing_config() {
+        let dir = tempdir().unwrap();
+        let repo_dir = dir.path().join("repo");
+        fs::create_dir_all(repo_dir.join(".git")).unwrap();
+
+        assert_eq!(detect_repository(&repo_dir), None);
+    }
+
+    #[test]
+    fn detect_repository_identifies_valid_worktree() {
+        let dir = tempdir().unwrap();
+        let repo_dir = dir.path().join("worktree");
+        fs::create_dir_all(&repo_dir).unwrap();
+        fs::write(
+            repo_dir.join(".git"),
+            "gitdir: /path/to/repo/.git/worktrees/topic\n",
+        )
+        .unwrap();
+
+        assert_eq!(detect_repository(&repo_dir), Some(RepositoryKind::Worktree));
+    }
+
+    #[test]
+    fn detect_repository_identifies_submodule_checkouts() {
+        let dir = tempdir().unwrap();
+        let repo_dir = dir.path().join("submodule");
+        fs::create_dir_all(&repo_dir).unwrap();
+        fs::write(repo_dir.join(".git"), "gitdir: ../.git/modules/submodule\n").unwrap();
+
+        assert_eq!(
+            detect_repository(&repo_dir),
+            Some(RepositoryKind::Submodule)
+        );
+    }
+
+    #[test]
+    fn detect_repository_treats_submodule_worktrees_as_worktrees() {
+        let dir = tempdir().unwrap();
+        let repo_dir = dir.path().join("worktree");
+        fs::create_dir_all(&repo_dir).unwrap();
+        fs::write(
+            repo_dir.join(".git"),
+            "gitdir: /src/app/.git/modules/lib/worktrees/topic\n",
+        )
+        .unwrap();
+
+        assert_eq!(detect_repository(&repo_dir), Some(RepositoryKind::Worktree));
+    }
+
+    #[test]
+    fn detect_repository_rejects_invalid_worktree_file() {
+        let dir = tempdir().unwrap();
+        let repo_dir = dir.path().join("worktree");
+        fs::create_dir_all(&repo_dir).unwrap();
+        fs::write(repo_dir.join(".git"), "not a gitdir reference\n").unwrap();
+
+        assert_eq!(detect_repository(&repo_dir), None);
+    }
+
+    #[test]
+    fn detect_repository_rejects_empty_worktree_file() {
+        let dir = tempdir().unwrap();
+        let repo_dir = dir.path().join("worktree");
+        fs::create_dir_all(&repo_dir).unwrap();
+        fs::write(repo_dir.join(".git"), "").unwrap();
+
+        assert_eq!(detect_repository(&repo_dir), None);
+    }
+
+    #[test]
+    fn detect_repository_identifies_bare_repositories() {
+        let dir = tempdir().unwrap();
+        let repo_dir = dir.path().join("mirror.git");
+        git(dir.path(), &["init", "--bare", "--quiet", "mirror.git"]);
+
+        assert_eq!(detect_repository(&repo_dir), Some(RepositoryKind::Bare));
+    }
+
+    #[test]
+    fn detect_repository_rejects_incomplete_bare_layout() {
+        let dir = tempdir().unwrap();
+        let repo_dir = dir.path().join("mirror.git");
+        fs::create_dir_all(repo_dir.join("refs")).unwrap();
+        fs::write(repo_dir.join("HEAD"), "ref: refs/heads/main\n").unwrap();
+
+        assert_eq!(detect_repository(&repo_dir), None);
+    }
+
+    #[test]
+    fn detect_repository_rejects_file_paths() {
+        let dir = tempdir().unwrap();
+        let file_path = dir.path().join("not-a-dir");
+        fs::write(&file_path, "test").unwrap();
+
+        assert_eq!(detect_repository(&file_path), None);
+    }
+
+    #[test]
+    fn detect_repository_rejects_missing_paths() {
+        let dir = tempdir().unwrap();
+        let missing_path = dir.path().join("missing");
+
+        assert_eq!(detect_repository(&missing_path), None);
+    }
+
+    #[test]
+    fn discover_repositories_skips_descendants_of_found_repositories() {
+        let dir = tempdir().unwrap();
+        let parent_repo = dir.path().join("parent");
+        let nested_repo = parent_repo.join("nested");
+        let sibling_repo = dir.path().join("sibling");
+
+        fs::create_dir_all(&nested_repo).unwrap();
+        fs::create_dir_all(&sibling_repo).unwrap();
+        create_regular_repo(&parent_repo);
+        create_regular_repo(&nested_repo);
+        create_regular_repo(&sibling_repo);
+
+        let mut discovered =
+            discover_repositories(dir.path(), &DiscoveryOptions::default()).repositories;
+        discovered.sort();
+
+        assert_eq!(discovered, vec![parent_repo, sibling_repo]);
+    }
+
+    #[test]
+    fn discover_repositories_handles_root_repository() {
+        let dir = tempdir().unwrap();
+        create_regular_repo(dir.path());
+        let nested_repo = dir.path().join("nested");
+        fs::create_dir_all(&nested_repo).unwrap();
+        create_regular_repo(&nested_repo);
+
+        let discovered =
+            discover_repositories(dir.path(), &DiscoveryOptions::default()).repositories;
+
+        assert_eq!(discovered, vec![dir.path().to_path_buf()]);
+    }
+
+    #[test]
+    fn discover_repositories_includes_nested_repositories_when_requested() {
+        let dir = tempdir().unwrap();
+        let parent_repo = dir.path().join("parent");
+        let nested_repo = parent_repo.join("vendor").join("nested");
+
+        fs::create_dir_all(&nested_repo).unwrap();
+        create_regular_repo(&parent_repo);
+        create_regular_repo(&nested_repo);
+        fs::create_dir_all(parent_repo.join(".git").join("modules").join("sub")).unwrap();
+        create_regular_repo(&parent_repo.join(".git").join("modules").join("sub"));
+
+        let options = DiscoveryOptions {
+            nested: NestedPolicy::Include,
+            include_hidden: true,
+            ..DiscoveryOptions::default()
+        };
+        let mut discovered = discover_repositories(dir.path(), &options).repositories;
+        discovered.sort();
+
+        assert_eq!(discovered, vec![parent_repo, nested_repo]);
+    }
+
+    #[test]
+    fn discover_repositories_reports_only_nested_repositories_when_requested() {
+        let dir = tempdir().unwrap();
+        let parent_repo = dir.path().join("parent");
+        let nested_repo = parent_repo.join("vendor").join("nested");
+        let inner_repo = nested_repo.join("deps").join("inner");
+        let standalone_repo = dir.path().join("standalone");
+
+        for repo in [&parent_repo, &nested_repo, &inner_repo, &standalone_repo] {
+            fs::create_dir_all(repo).unwrap();
+            create_regular_repo(repo);
+        }
+
+        let options = DiscoveryOptions {
+            nested: NestedPolicy::Only,
+            ..DiscoveryOptions::default()
+        };
+
+        assert_eq!(
+            discover_repositories(dir.path(), &options).repositories,
+            vec![nested_repo, inner_repo]
+        );
+    }
+
+    #[test]
+    fn discover_repositories_can_skip_worktrees_and_submodules() {
+        let dir = tempdir().unwrap();
+        let regular_repo = dir.path().join("regular");
+        let worktree = dir.path().join("worktree");
+        let submodule = dir.path().join("submodule");
+
+        fs::create_dir_all(&regular_repo).unwrap();
+        fs::create_dir_all(worktree.join("inner")).unwrap();
+        fs::create_dir_all(&submodule).unwrap();
+        create_regular_repo(&regular_repo);
+        create_regular_repo(&worktree.join("inner"));
+        fs::write(worktree.join(".git"), "gitdir: /src/.git/worktrees/topic\n").unwrap();
+        fs::write(submodule.join(".git"), "gitdir: ../.git/modules/sub\n").unwrap();
+
+        let mut discovered =
+            discover_repositories(dir.path(), &DiscoveryOptions::default()).repositories;
+        discovered.sort();
+        assert_eq!(
+            discovered,
+            vec![regular_repo.clone(), submodule.clone(), worktree.clone()]
+        );
+
+        let options = DiscoveryOptions {
+            include_worktrees: false,
+            include_submodules: false,
+            ..DiscoveryOptions::default()
+        };
+        assert_eq!(
+            discover_repositories(dir.path(), &options).repositories,
+            vec![regular_repo]
+        );
+    }
+
+    #[test]
+    fn discover_repositories_includes_bare_repositories_only_when_requested() {
+        let dir = tempdir().unwrap();
+        let bare_repo = dir.path().join("mirrors").join("app.git");
+        git(
+            dir.path(),
+            &["init", "--bare", "--quiet", "mirrors/app.git"],
+        );
+
+        assert!(
+            discover_repositories(dir.path(), &DiscoveryOptions::default())
+                .repositories
+                .is_empty()
+        );
+
+        let options = DiscoveryOptions {
+            include_bare: true,
+            ..DiscoveryOptions::default()
+        };
+        assert_eq!(
+            discover_repositories(dir.path(), &options).repositories,
+            vec![bare_repo]
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn discover_repositories_follows_symlinks_once_per_repository() {
+        use std::os::unix::fs::symlink;
+
+        let dir = tempdir().unwrap();
+        let store = dir.path().join("store");
+        let repo = store.join("repo");
+        let farm = dir.path().join("farm");
+
+        fs::create_dir_all(&repo).unwrap();
+        fs::create_dir_all(&farm).unwrap();
+        create_regular_repo(&repo);
+        symlink(&repo, farm.join("a-link")).unwrap();
+        symlink(&repo, farm.join("b-link")).unwrap();
+        symlink(dir.path(), farm.join("loop")).unwrap();
+
+        assert!(
+            discover_repositories(&farm, &DiscoveryOptions::default())
+                .repositories
+                .is_empty()
+        );
+
+        let options = DiscoveryOptions {
+            follow_symlinks: true,
+            ..DiscoveryOptions::default()
+        };
+        let discovered = discover_repositories(&farm, &options).repositories;
+
+        assert_eq!(discovered.len(), 1);
+        assert_eq!(
+            fs::canonicalize(&discovered[0]).unwrap(),
+            fs::canonicalize(&repo).unwrap()
+        );
+    }
+
+    #[test]
+    fn discover_repositories_stays_on_the_same_file_system() {
+        let dir = tempdir().unwrap();
+        let repo = dir.path().join("repo");
+        fs::create_dir_all(&repo).unwrap();
+        create_regular_repo(&repo);
+
+        let options = DiscoveryOptions {
+            same_file_system: true,
+            ..DiscoveryOptions::default()
+        };
+
+        assert_eq!(
+            discover_repositories(dir.path(), &options).repositories,
+            vec![repo]
+        );
+    }
+
+    #[test]
+    fn discover_repositories_until_returns_complete_results_in_time() {
+        let dir = tempdir().unwrap();
+        let repo = dir.path().join("group").join("repo");
+        fs::create_dir_all(&repo).unwrap();
+        create_regular_repo(&repo);
+
+        let deadline = Instant::now() + std::time::Duration::from_secs(30);
+        let (found, unscanned) =
+            discover_repositories_until(dir.path(), &DiscoveryOptions::default(), deadline);
+
+        assert_eq!(found.repositories, vec![repo]);
+        assert!(unscanned.is_empty());
+    }
+
+    #[test]
+    fn discover_repositories_until_reports_expired_deadlines() {
+        let dir = tempdir().unwrap();
+
+        let (found, unscanned) =
+            discover_repositories_until(dir.path(), &DiscoveryOptions::default(), Instant::now());
+
+        assert!(found.repositories.is_empty());
+        assert_eq!(unscanned, vec![dir.path().to_path_buf()]);
+    }
+
+    #[test]
+    fn progress_visitor_tracks_unfinished_top_level_directories() {
+        let progress = Arc::new(Mutex::new(ScanProgress {
+            unscanned: BTreeSet::from([PathBuf::from("/r")]),
+            ..ScanProgress::default()
+        }));
+        let visitor = ProgressVisitor {
+            progress: Arc::clone(&progress),
+            cancelled: Arc::new(AtomicBool::new(false)),
+        };
+
+        visitor.listed(
+            Path::new("/r"),
+            0,
+            &[PathBuf::from("/r/a"), PathBuf::from("/r/b")],
+        );
+        visitor.repository(Path::new("/r/a/deep"));
+        visitor.finished(Path::new("/r/a/deep"), 2);
+        visitor.finished(Path::new("/r/a"), 1);
+
+        let progress = progress.lock().unwrap();
+        assert_eq!(progress.repositories, vec![PathBuf::from("/r/a/deep")]);
+        assert_eq!(
+            progress.unscanned.iter().collect::<Vec<_>>(),
+            vec![Path::new("/r/b")]
+        );
+    }
+
+    #[test]
+    fn discover_repositories_orders_results_independently_of_threads() {
+        let dir = tempdir().unwrap();
+        let mut repos = Vec::new();
+        for group in ["b", "a", "c"] {
+            for name in ["two", "one"] {
+                let repo = dir.path().join(group).join(name);
+                fs::create_dir_all(&repo).unwrap();
+                create_regular_repo(&repo);
+                repos.push(repo);
+            }
+        }
+        repos.sort();
+
+        for threads in [1, 4] {
+            let options = DiscoveryOptions {
+                threads,
+                ..DiscoveryOptions::default()
+            };
+
+            assert_eq!(
+                discover_repositories(dir.path(), &options).repositories,
+                repos
+            );
+        }
+    }
+
+    #[test]
+    fn discover_repositories_walks_breadth_first_on_request() {
+        let dir = tempdir().unwrap();
+        let deep_repo = dir.path().join("a").join("group").join("deep");
+        let shallow_repo = dir.path().join("b");
+        let middle_repo = dir.path().join("c").join("middle");
+        for repo in [&deep_repo, &shallow_repo, &middle_repo] {
+            fs::create_dir_all(repo).unwrap();
+            create_regular_repo(repo);
+        }
+
+        let dfs = discover_repositories(dir.path(), &DiscoveryOptions::default()).repositories;
+        let bfs = discover_repositories(
+            dir.path(),
+            &DiscoveryOptions {
+                traversal: Traversal::Bfs,
+                ..DiscoveryOptions::default()
+            },
+        )
+        .repositories;
+
+        assert_eq!(
+            dfs,
+            vec![deep_repo.clone(), shallow_repo.clone(), middle_repo.clone()]
+        );
+        assert_eq!(bfs, vec![shallow_repo, middle_repo, deep_repo]);
+    }
+
+    #[test]
+    fn breadth_first_walks_report_finished_top_level_directories() {
+        let dir = tempdir().unwrap();
+        let repo = dir.path().join("a").join("group").join("repo");
+        fs::create_dir_all(&repo).unwrap();
+        fs::create_dir_all(dir.path().join("b")).unwrap();
+        create_regular_repo(&repo);
+        let progress = Arc::new(Mutex::new(ScanProgress {
+            unscanned: BTreeSet::from([dir.path().to_path_buf()]),
+            ..ScanProgress::default()
+        }));
+        let visitor = ProgressVisitor {
+            progress: Arc::clone(&progress),
+            cancelled: Arc::new(AtomicBool::new(false)),
+        };
+        let options = DiscoveryOptions {
+            traversal: Traversal::Bfs,
+            ..DiscoveryOptions::default()
+        };
+
+        assert_eq!(
+            walk_repositories(dir.path(), &options, &visitor),
+            vec![repo.clone()]
+        );
+
+        let progress = progress.lock().unwrap();
+        assert_eq!(progress.repositories, vec![repo]);
+        assert!(progress.unscanned.is_empty());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn discover_repositories_collects_unreadable_directories() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = tempdir().unwrap();
+        let locked = dir.path().join("locked");
+        let repo = dir.path().join("repo");
+        fs::create_dir_all(locked.join("hidden-repo")).unwrap();
+        create_regular_repo(&repo);
+        fs::set_permissions(&locked, fs::Permissions::from_mode(0o000)).unwrap();
+        // Permissions do not restrict privileged users, so there is nothing to
+        // observe when running as root.
+        let readable = fs::read_dir(&locked).is_ok();
+
+        let scan = discover_repositories(dir.path(), &DiscoveryOptions::default());
+        fs::set_permissions(&locked, fs::Permissions::from_mode(0o755)).unwrap();
+
+        assert_eq!(scan.repositories, vec![repo]);
+        if !readable {
+            assert_eq!(scan.errors.len(), 1);
+            assert_eq!(scan.errors[0].path, locked);
+        }
+    }
+
+    #[test]
+    fn discover_repositories_reports_missing_roots() {
+        let dir = tempdir().unwrap();
+        let missing = dir.path().join("missing");
+
+        let scan = discover_repositories(&missing, &DiscoveryOptions::default());
+
+        assert!(scan.repositories.is_empty());
+        assert_eq!(scan.errors.len(), 1);
+        assert_eq!(scan.errors[0].path, missing);
+    }
+
+    #[test]
+    fn discover_repositories_respects_max_depth() {
+        let dir = tempdir().unwrap();
+        let shallow_repo = dir.path().join("shallow");
+        let deep_repo = dir.path().join("group").join("deep");
+
+        fs::create_dir_all(&shallow_repo).unwrap();
+        fs::create_dir_all(&deep_repo).unwrap();
+        create_regular_repo(&shallow_repo);
+        create_regular_repo(&deep_repo);
+
+        let options = DiscoveryOptions {
+            max_depth: Some(1),
+            ..DiscoveryOptions::default()
+        };
+        let discovered = discover_repositories(dir.path(), &options).repositories;
+
+        assert_eq!(discovered, vec![shallow_repo]);
+    }
+
+    #[test]
+    fn discover_repositories_prunes_excluded_directories() {
+        let dir = tempdir().unwrap();
+        let kept_repo = dir.path().join("kept");
+        let vendored_repo = dir.path().join("node_modules").join("dep");
+        let nested_target_repo = dir.path().join("app").join("target").join("out");
+
+        fs::create_dir_all(&kept_repo).unwrap();
+        fs::create_dir_all(&vendored_repo).unwrap();
+        fs::create_dir_all(&nested_target_repo).unwrap();
+        create_regular_repo(&kept_repo);
+        create_regular_repo(&vendored_repo);
+        create_regular_repo(&nested_target_repo);
+
+        let patterns = [
+            Glob::new("node_modules").unwrap(),
+            Glob::new("target").unwrap(),
+        ];
+        let options = DiscoveryOptions {
+            exclude: build_exclude_set(&patterns).unwrap(),
+            ..DiscoveryOptions::default()
+        };
+        let discovered = discover_repositories(dir.path(), &options).repositories;
+
+        assert_eq!(discovered, vec![kept_repo]);
+    }
+
+    #[test]
+    fn discover_repositories_matches_exclusions_against_relative_paths() {
+        let dir = tempdir().unwrap();
+        let kept_repo = dir.path().join("clients").join("web");
+        let excluded_repo = dir.path().join("vendor").join("lib");
+
+        fs::create_dir_all(&kept_repo).unwrap();
+        fs::create_dir_all(&excluded_repo).unwrap();
+        create_regular_repo(&kept_repo);
+        create_regular_repo(&excluded_repo);
+
+        let options = DiscoveryOptions {
+            exclude: build_exclude_set(&[Glob::new("vendor/*").unwrap()]).unwrap(),
+            ..DiscoveryOptions::default()
+        };
+        let discovered = discover_repositories(dir.path(), &options).repositories;
+
+        assert_eq!(discovered, vec![kept_repo]);
+    }
+
+    #[test]
+    fn discover_repositories_skips_hidden_directories_by_default() {
+        let dir = tempdir().unwrap();
+        let visible_repo = dir.path().join("visible");
+        let hidden_repo = dir.path().join(".hidden").join("repo");
+
+        fs::create_dir_all(&visible_repo).unwrap();
+        fs::create_dir_all(&hidden_repo).unwrap();
+        create_regular_repo(&visible_repo);
+        create_regular_repo(&hidden_repo);
+
+        let discovered =
+            discover_repositories(dir.path(), &DiscoveryOptions::default()).repositories;
+        assert_eq!(discovered, vec![visible_repo.clone()]);
+
+        let options = DiscoveryOptions {
+            include_hidden: true,
+            ..DiscoveryOptions::default()
+        };
+        let mut discovered = discover_repositories(dir.path(), &options).repositories;
+        discovered.sort();
+        assert_eq!(discovered, vec![hidden_repo, visible_repo]);
+    }
+
+    #[test]
+    fn discover_repositories_honors_stacked_ignore_files() {
+        let dir = tempdir().unwrap();
+        let kept_repo = dir.path().join("src").join("kept");
+        let root_ignored_repo = dir.path().join("build").join("repo");
+        let nested_ignored_repo = dir.path().join("src").join("scratch").join("repo");
+
+        fs::create_dir_all(&kept_repo).unwrap();
+        fs::create_dir_all(&root_ignored_repo).unwrap();
+        fs::create_dir_all(&nested_ignored_repo).unwrap();
+        create_regular_repo(&kept_repo);
+        create_regular_repo(&root_ignored_repo);
+        create_regular_repo(&nested_ignored_repo);
+        fs::write(dir.path().join(".gitignore"), "build/\n").unwrap();
+        fs::write(dir.path().join("src").join(".ignore"), "scratch\n").unwrap();
+
+        let discovered =
+            discover_repositories(dir.path(), &DiscoveryOptions::default()).repositories;
+        assert_eq!(discovered, vec![kept_repo]);
+
+        let options = DiscoveryOptions {
+            ignore_files: false,
+            ..DiscoveryOptions::default()
+        };
+        assert_eq!(
+            discover_repositories(dir.path(), &options)
+                .repositories
+                .len(),
+            3
+        );
+    }
+
+    #[test]
+    fn discover_repositories_stacks_grprignore_files() {
+        let dir = tempdir().unwrap();
+        let kept_repo = dir.path().join("work").join("build").join("kept");
+        let snapshot_repo = dir.path().join("work").join("snapshots").join("old");
+        let backup_repo = dir.path().join("backups").join("repo");
+
+        fs::create_dir_all(&kept_repo).unwrap();
+        fs::create_dir_all(&snapshot_repo).unwrap();
+        fs::create_dir_all(&backup_repo).unwrap();
+        create_regular_repo(&kept_repo);
+        create_regular_repo(&snapshot_repo);
+        create_regular_repo(&backup_repo);
+        fs::write(dir.path().join(".grprignore"), "backups/\n").unwrap();
+        fs::write(dir.path().join("work").join(".gitignore"), "build/\n").unwrap();
+        fs::write(
+            dir.path().join("work").join(".grprignore"),
+            "snapshots\n!build/\n",
+        )
+        .unwrap();
+
+        let discovered =
+            discover_repositories(dir.path(), &DiscoveryOptions::default()).repositories;
+
+        assert_eq!(discovered, vec![kept_repo]);
+    }
+
+    #[test]
+    fn discover_repositories_prunes_directories_with_stop_markers() {
+        let dir = tempdir().unwrap();
+        let kept_repo = dir.path().join("src").join("app");
+        let fenced_repo = dir.path().join("snapshots").join("daily").join("app");
+        let marked_repo = dir.path().join("src").join("vendored");
+
+        fs::create_dir_all(&kept_repo).unwrap();
+        fs::create_dir_all(&fenced_repo).unwrap();
+        fs::create_dir_all(&marked_repo).unwrap();
+        create_regular_repo(&kept_repo);
+        create_regular_repo(&fenced_repo);
+        create_regular_repo(&marked_repo);
+        fs::write(dir.path().join("snapshots").join(".grprstop"), "").unwrap();
+        fs::write(marked_repo.join(".grprstop"), "").unwrap();
+
+        let options = DiscoveryOptions {
+            ignore_files: false,
+            ..DiscoveryOptions::default()
+        };
+
+        assert_eq!(
+            discover_repositories(dir.path(), &options).repositories,
+            vec![kept_repo]
+        );
+    }
+
+    #[test]
+    fn discover_repositories_does_not_leak_ignore_rules_to_siblings() {
+        let dir = tempdir().unwrap();
+        let ignored_repo = dir.path().join("one").join("repo");
+        let sibling_repo = dir.path().join("two").join("repo");
+
+        fs::create_dir_all(&ignored_repo).unwrap();
+        fs::create_dir_all(&sibling_repo).unwrap();
+        create_regular_repo(&ignored_repo);
+        create_regular_repo(&sibling_repo);
+        fs::write(dir.path().join("one").join(".gitignore"), "repo\n").unwrap();
+
+        let discovered =
+            discover_repositories(dir.path(), &DiscoveryOptions::default()).repositories;
+
+        assert_eq!(discovered, vec![sibling_repo]);
+    }
+
+    #[test]
+    fn bounded_output_spills_the_whole_stream_past_the_limit() {
+        let dir = tempdir().unwrap();
+        let limit = OutputLimit {
+            bytes: 10,
+            dir: dir.path().join("spill"),
+            private: false,
+        };
+        let repo_path = Path::new("/src/app");
+        let output = b"0123456789abcdefghij\n".repeat(1000);
+
+        let bounded = Bounded::read(&output[..], Some(&limit), 4, repo_path, "stdout");
+        assert_eq!(bounded.kept, b"012345");
+        assert_eq!(bounded.total, output.len() as u64);
+        let mut kept = b"pre\n".to_vec();
+        let path = bounded.append_to(&mut kept).unwrap();
+        assert_eq!(path, dir.path().join("spill/app.stdout"));
+        assert_eq!(fs::read(&path).unwrap(), output);
+        assert_eq!(
+            String::from_utf8(kept).unwrap(),
+            format!(
+                "pre\n012345\ngrpr: output cut short after 6 of 21000 bytes (--max-output); \
+                 the whole output is in {}\n",
+                path.display()
+            )
+        );
+
+        let bounded = Bounded::read(&output[..], Some(&limit), 0, repo_path, "stdout");
+        let path = bounded.append_to(&mut Vec::new()).unwrap();
+        assert_eq!(path, dir.path().join("spill/app-2.stdout"));
+
+        let bounded = Bounded::read(&b"short\n"[..], Some(&limit), 0, repo_path, "stderr");
+        let mut kept = Vec::new();
+        assert_eq!(bounded.append_to(&mut kept), None);
+        assert_eq!(kept, b"short\n");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn private_spill_directory_is_only_the_users() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = tempdir().unwrap();
+        let limit = OutputLimit {
+            bytes: 0,
+            dir: dir.path().join("spill"),
+            private: true,
+        };
+        let repo_path = Path::new("/src/app");
+        limit.spill_file(repo_path, "stdout").unwrap();
+        limit.spill_file(repo_path, "stdout").unwrap();
+        let mode = fs::metadata(&limit.dir).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o700);
+
+        // A directory that others can get into was not made by this run.
+        fs::set_permissions(&limit.dir, fs::Permissions::from_mode(0o777)).unwrap();
+        let err = limit.spill_file(repo_path, "stdout").unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::AlreadyExists);
+    }
+
+    #[test]
+    fn capture_command_collects_output_and_status() {
+        let dir = tempdir().unwrap();
+        git(dir.path(), &["init", "--quiet"]);
+
+        let command = RepoCommand::git(vec![
+            "rev-parse".to_string(),
+            "--is-inside-work-tree".to_string(),
+        ]);
+        let captured = capture_command(dir.path(), &command, &Hooks::default());
+
+        assert!(captured.result.is_ok());
+        assert_eq!(String::from_utf8_lossy(&captured.stdout).trim(), "true");
+
+        let failing = RepoCommand::git(vec!["rev-parse".to_string(), "HEAD".to_string()]);
+        let captured = capture_command(dir.path(), &failing, &Hooks::default());
+        assert!(!captured.stderr.is_empty());
+        assert!(
+            captured
+                .result
+                .unwrap_err()
+                .to_string()
+                .starts_with("git command failed in ")
+        );
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn hooks_run_around_the_command() {
+        let dir = tempdir().unwrap();
+        let command = RepoCommand::exec(vec!["echo main; exit 3".to_string()]);
+        let hooks = Hooks {
+            pre: Some("echo pre".to_string()),
+            post: Some(format!("echo post ${EXIT_STATUS_ENV}")),
+            ..Hooks::default()
+        };
+
+        let captured = capture_command(dir.path(), &command, &hooks);
+        assert_eq!(
+            String::from_utf8_lossy(&captured.stdout),
+            "pre\nmain\npost 3\n"
+        );
+        assert!(
+            captured
+                .result
+                .unwrap_err()
+                .to_string()
+                .starts_with("command failed in ")
+        );
+
+        let failing_pre = Hooks {
+            pre: Some("echo pre; false".to_string()),
+            ..hooks.clone()
+        };
+        let captured = capture_command(dir.path(), &command, &failing_pre);
+        assert_eq!(String::from_utf8_lossy(&captured.stdout), "pre\n");
+        assert!(
+            captured
+                .result
+                .unwrap_err()
+                .to_string()
+                .starts_with("pre hook failed in ")
+        );
+
+        let marker = dir.path().join("post-ran");
+        let live = Hooks {
+            pre: None,
+            post: Some(format!("echo ${EXIT_STATUS_ENV} > post-ran")),
+            ..Hooks::default()
+        };
+        let ok = RepoCommand::exec(vec!["true".to_string()]);
+        assert!(process_repository(dir.path(), &ok, &live, RunMode::Execute).is_ok());
+        assert_eq!(fs::read_to_string(&marker).unwrap(), "0\n");
+        assert!(process_repository(dir.path(), &command, &live, RunMode::Execute).is_err());
+        assert_eq!(fs::read_to_string(&marker).unwrap(), "3\n");
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn commands_and_hooks_see_the_repository_and_extra_environment() {
+        let dir = tempdir().unwrap();
+        let repo_dir = dir.path().join("app");
+        fs::create_dir_all(&repo_dir).unwrap();
+        let script = format!("echo ${REPO_NAME_ENV} $STAGE; echo ${REPO_PATH_ENV}");
+        let command = RepoCommand::exec(vec![script]).with_env("STAGE", "main");
+        let hooks = Hooks {
+            pre: Some("echo $STAGE".to_string()),
+            post: None,
+            ..Hooks::default()
+        };
 
-    if git_metadata.is_dir() {
-        let config_path = git_path.join(GIT_CONFIG_NAME);
-        return config_path.is_file().then_some(RepositoryKind::Regular);
+        let captured = capture_command(&repo_dir, &command, &hooks);
+
+        assert_eq!(
+            String::from_utf8_lossy(&captured.stdout),
+            format!("main\napp main\n{}\n", repo_dir.display())
+        );
     }
 
-    if git_metadata.is_file() {
-        let contents = fs::read_to_string(&git_path).ok()?;
-        return contents
-            .trim_start()
-            .starts_with(GITDIR_PREFIX)
-            .then_some(RepositoryKind::Worktree);
+    #[test]
+    fn commands_do_not_inherit_git_repository_variables() {
+        let dir = tempdir().unwrap();
+        let removed = |command: &RepoCommand, name: &str| {
+            command
+                .process(dir.path())
+                .get_envs()
+                .any(|(key, value)| key == name && value.is_none())
+        };
+        let command = RepoCommand::git(vec!["status".to_string()]);
+
+        assert!(removed(&command, "GIT_DIR"));
+        assert!(removed(&command.hook("pre", "true"), "GIT_WORK_TREE"));
+        let kept = command.clone().keeping_git_env();
+        assert!(!removed(&kept, "GIT_DIR"));
+        assert!(!removed(&kept.hook("pre", "true"), "GIT_INDEX_FILE"));
+        // An explicit --env value still applies.
+        let explicit = command.with_env("GIT_DIR", "/elsewhere");
+        assert!(
+            explicit
+                .process(dir.path())
+                .get_envs()
+                .any(|(key, value)| key == "GIT_DIR" && value.is_some())
+        );
     }
 
-    None
-}
+    #[test]
+    #[cfg(unix)]
+    fn scripts_see_the_branch_and_remote() {
+        let dir = tempdir().unwrap();
+        let repo_dir = dir.path().join("app");
+        fs::create_dir_all(&repo_dir).unwrap();
+        git(&repo_dir, &["init", "--quiet", "--initial-branch", "trunk"]);
+        git(
+            &repo_dir,
+            &["remote", "add", "origin", "https://example.com/app.git"],
+        );
+        let script = dir.path().join("show.sh");
+        fs::write(
+            &script,
+            format!("echo ${REPO_BRANCH_ENV} ${REPO_REMOTE_ENV} \"$@\"\n"),
+        )
+        .unwrap();
+        let command = RepoCommand::script(&script, vec!["-n".to_string()]);
 
-/// Discovers git repositories under `root`, skipping descendants of any
-/// repository that is found.
-pub fn discover_repositories(root: &Path) -> Vec<PathBuf> {
-    let mut repositories = Vec::new();
-    let mut walker = WalkDir::new(root).into_iter();
-
-    while let Some(entry_result) = walker.next() {
-        let entry = match entry_result {
-            Ok(entry) => entry,
-            Err(err) => {
-                eprintln!("Error walking directory tree: {err}");
-                continue;
-            }
+        let captured = capture_command(&repo_dir, &command, &Hooks::default());
+
+        assert!(captured.result.is_ok());
+        assert_eq!(
+            String::from_utf8_lossy(&captured.stdout),
+            "trunk https://example.com/app.git -n\n"
+        );
+    }
+
+    /// A repository with one committed file, `notes`, that is then edited,
+    /// plus an untracked file.
+    #[test]
+    #[cfg(unix)]
+    fn untrusted_config_env_keeps_hooks_and_filters_from_running() {
+        use std::os::unix::fs::PermissionsExt;
+        let dir = tempdir().unwrap();
+        let repo = dir.path().join("repo");
+        fs::create_dir(&repo).unwrap();
+        git(&repo, &["init", "--quiet"]);
+        git(&repo, &["config", "user.name", "grpr"]);
+        git(&repo, &["config", "user.email", "grpr@example.com"]);
+        let marker = |name: &str| dir.path().join(name).display().to_string();
+        let hook = repo.join(".git/hooks/pre-commit");
+        fs::write(&hook, format!("#!/bin/sh\ntouch {}\n", marker("hook"))).unwrap();
+        fs::set_permissions(&hook, fs::Permissions::from_mode(0o755)).unwrap();
+        let filter = format!("touch {}; cat", marker("filter"));
+        git(&repo, &["config", "filter.evil.clean", &filter]);
+        fs::write(repo.join(".gitattributes"), "*.txt filter=evil\n").unwrap();
+        fs::write(repo.join("notes.txt"), "one\n").unwrap();
+
+        let status = Command::new("sh")
+            .args(["-c", "git add . && git commit --quiet -m one"])
+            .envs(untrusted_config_env(&repo))
+            .current_dir(&repo)
+            .status()
+            .unwrap();
+
+        assert!(status.success());
+        assert!(!dir.path().join("hook").exists());
+        assert!(!dir.path().join("filter").exists());
+    }
+
+    /// Runs `git args` in `repo` with its own config overridden, without the
+    /// editors and pager the environment may set.
+    #[cfg(unix)]
+    fn distrusted_git(repo: &Path, args: &[&str]) -> bool {
+        Command::new("git")
+            .args(["-c", "user.name=grpr", "-c", "user.email=grpr@example.com"])
+            .args(args)
+            .env_remove("GIT_EDITOR")
+            .env_remove("GIT_SEQUENCE_EDITOR")
+            .env_remove("GIT_PAGER")
+            .envs(untrusted_config_env(repo))
+            .current_dir(repo)
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .status()
+            .unwrap()
+            .success()
+    }
+
+    /// Returns the value git sees for `key` in `repo` with its own config
+    /// overridden.
+    fn distrusted_value(repo: &Path, key: &str) -> String {
+        let output = Command::new("git")
+            .args(["config", "--get", key])
+            .envs(untrusted_config_env(repo))
+            .current_dir(repo)
+            .output()
+            .unwrap();
+        String::from_utf8_lossy(&output.stdout).trim().to_string()
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn untrusted_config_env_keeps_configured_programs_from_running() {
+        use std::os::unix::fs::PermissionsExt;
+        let dir = tempdir().unwrap();
+        let repo = dir.path().join("repo");
+        let other = dir.path().join("other");
+        fs::create_dir(&repo).unwrap();
+        fs::create_dir(&other).unwrap();
+        git(&other, &["init", "--quiet"]);
+        git(&other, &["commit", "--quiet", "--allow-empty", "-m", "one"]);
+        git(&repo, &["init", "--quiet"]);
+        fs::write(repo.join("notes"), "one\n").unwrap();
+        git(&repo, &["add", "notes"]);
+        git(&repo, &["commit", "--quiet", "-m", "one"]);
+        // A program for each route, leaving a marker of its name when run.
+        let program = |name: &str| {
+            let path = dir.path().join(format!("run-{name}"));
+            let marker = dir.path().join(name);
+            fs::write(&path, format!("#!/bin/sh\ntouch {}\n", marker.display())).unwrap();
+            fs::set_permissions(&path, fs::Permissions::from_mode(0o755)).unwrap();
+            path.display().to_string()
         };
+        let routes = [
+            ("diff.external", "diff-external"),
+            ("core.editor", "editor"),
+            ("sequence.editor", "sequence-editor"),
+            ("gpg.program", "gpg"),
+            ("core.gitProxy", "proxy"),
+            ("remote.other.uploadpack", "uploadpack"),
+        ];
+        for (key, name) in routes {
+            git(&repo, &["config", key, &program(name)]);
+        }
+        git(
+            &repo,
+            &["config", "remote.other.url", &other.display().to_string()],
+        );
+        git(&repo, &["config", "protocol.ext.allow", "always"]);
+        let included = dir.path().join("included");
+        fs::write(
+            &included,
+            format!("[alias]\n\tincluded = !{}\n", program("include")),
+        )
+        .unwrap();
+        git(
+            &repo,
+            &["config", "include.path", &included.display().to_string()],
+        );
+
+        distrusted_git(&repo, &["rebase", "-i", "HEAD"]);
+        fs::write(repo.join("notes"), "two\n").unwrap();
+        distrusted_git(&repo, &["diff"]);
+        distrusted_git(&repo, &["included"]);
+        distrusted_git(&repo, &["commit", "--allow-empty"]);
+        distrusted_git(&repo, &["commit", "--allow-empty", "-S", "-m", "signed"]);
+        distrusted_git(&repo, &["fetch", "git://127.0.0.1:9/app.git"]);
+        distrusted_git(&repo, &["fetch", "other"]);
+        let ext = format!("ext::sh -c touch% {}", dir.path().join("ext").display());
+        distrusted_git(&repo, &["fetch", &ext]);
 
-        if !entry.file_type().is_dir() {
-            continue;
+        for name in [
+            "diff-external",
+            "include",
+            "editor",
+            "sequence-editor",
+            "gpg",
+            "proxy",
+            "uploadpack",
+            "ext",
+        ] {
+            assert!(!dir.path().join(name).exists(), "{name} ran");
         }
+        assert_eq!(distrusted_value(&repo, "protocol.file.allow"), "never");
+    }
 
-        if detect_repository(entry.path()).is_some() {
-            repositories.push(entry.into_path());
-            walker.skip_current_dir();
+    #[test]
+    fn untrusted_config_env_overrides_programs_git_runs_elsewhere() {
+        let dir = tempdir().unwrap();
+        git(dir.path(), &["init", "--quiet"]);
+        for (key, value) in [
+            ("core.pager", "evil"),
+            ("pager.log", "evil"),
+            ("core.askPass", "evil"),
+            ("core.alternateRefsCommand", "evil"),
+            ("credential.https://example.com.helper", "evil"),
+            ("protocol.file.allow", "always"),
+            ("submodule.lib.update", "!evil"),
+            ("alias.st", "!evil"),
+            ("interactive.diffFilter", "evil"),
+            ("difftool.mine.cmd", "evil"),
+            ("user.name", "grpr"),
+        ] {
+            git(dir.path(), &["config", key, value]);
         }
+
+        assert_eq!(distrusted_value(dir.path(), "core.pager"), "cat");
+        assert_eq!(distrusted_value(dir.path(), "pager.log"), "false");
+        assert_eq!(distrusted_value(dir.path(), "core.askPass"), "");
+        assert_eq!(
+            distrusted_value(dir.path(), "core.alternateRefsCommand"),
+            ""
+        );
+        assert_eq!(
+            distrusted_value(dir.path(), "credential.https://example.com.helper"),
+            ""
+        );
+        assert_eq!(distrusted_value(dir.path(), "protocol.file.allow"), "user");
+        assert_eq!(
+            distrusted_value(dir.path(), "submodule.lib.update"),
+            "checkout"
+        );
+        assert_eq!(distrusted_value(dir.path(), "alias.st"), "");
+        assert_eq!(
+            distrusted_value(dir.path(), "interactive.diffFilter"),
+            "cat"
+        );
+        assert_eq!(distrusted_value(dir.path(), "difftool.mine.cmd"), "false");
+        assert_eq!(distrusted_value(dir.path(), "user.name"), "grpr");
     }
 
-    repositories
-}
+    fn repository_with_local_edits(dir: &Path) {
+        git(dir, &["init", "--quiet"]);
+        git(dir, &["config", "user.name", "grpr"]);
+        git(dir, &["config", "user.email", "grpr@example.com"]);
+        fs::write(dir.join("notes"), "one\n").unwrap();
+        git(dir, &["add", "notes"]);
+        git(dir, &["commit", "--quiet", "-m", "one"]);
+        fs::write(dir.join("notes"), "local\n").unwrap();
+        fs::write(dir.join("scratch"), "untracked\n").unwrap();
+    }
 
-/// Executes a git command in the provided repository path.
-pub fn run_git_command(repo_path: &Path, args: &[String]) -> Result<(), io::Error> {
-    let status = Command::new("git")
-        .args(args.iter().map(OsStr::new))
-        .current_dir(repo_path)
-        .stdout(Stdio::inherit())
-        .stderr(Stdio::inherit())
-        .status()?;
+    #[test]
+    #[cfg(unix)]
+    fn autostash_runs_the_command_on_a_clean_tree_and_restores_changes() {
+        let dir = tempdir().unwrap();
+        repository_with_local_edits(dir.path());
+        let command = RepoCommand::exec(vec!["test -z \"$(git status --porcelain)\"".to_string()]);
+        let hooks = Hooks {
+            autostash: true,
+            ..Hooks::default()
+        };
 
-    if status.success() {
-        Ok(())
-    } else {
-        Err(io::Error::other(format!(
-            "git command failed in {} with status {status}",
-            repo_path.display()
-        )))
+        assert!(
+            process_repository(dir.path(), &command, &Hooks::default(), RunMode::Execute).is_err()
+        );
+        assert!(process_repository(dir.path(), &command, &hooks, RunMode::Execute).is_ok());
+        assert!(capture_command(dir.path(), &command, &hooks).result.is_ok());
+
+        assert_eq!(
+            fs::read_to_string(dir.path().join("notes")).unwrap(),
+            "local\n"
+        );
+        assert!(dir.path().join("scratch").exists());
+        assert_eq!(
+            git_output(dir.path(), &["stash", "list"]).as_deref(),
+            Some("")
+        );
     }
-}
 
-/// Prints the repository being processed and runs the git command in it.
-pub fn process_repository(repo_path: &Path, args: &[String]) -> Result<(), io::Error> {
-    println!("Inside git repo: {}", repo_path.display());
-    run_git_command(repo_path, args)
-}
+    #[test]
+    #[cfg(unix)]
+    fn autostash_reports_conflicts_restoring_changes() {
+        let dir = tempdir().unwrap();
+        repository_with_local_edits(dir.path());
+        let command = RepoCommand::exec(vec![
+            "echo upstream > notes && git commit --quiet -am upstream".to_string(),
+        ]);
+        let hooks = Hooks {
+            autostash: true,
+            ..Hooks::default()
+        };
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use std::fs;
-    use tempfile::tempdir;
+        let err = capture_command(dir.path(), &command, &hooks)
+            .result
+            .unwrap_err();
 
-    fn create_regular_repo(path: &Path) {
-        let git_dir = path.join(".git");
-        fs::create_dir_all(&git_dir).unwrap();
-        fs::write(git_dir.join("config"), "[core]\n").unwrap();
+        assert!(err.to_string().contains("restoring local changes"));
+        assert!(
+            git_output(dir.path(), &["stash", "list"])
+                .is_some_and(|list| list.contains(AUTOSTASH_MESSAGE))
+        );
     }
 
     #[test]
-    fn detect_repository_identifies_valid_regular_repo() {
+    #[cfg(unix)]
+    fn commands_run_in_a_temporary_worktree_of_the_revision() {
         let dir = tempdir().unwrap();
-        let repo_dir = dir.path().join("repo");
-        fs::create_dir_all(&repo_dir).unwrap();
-        create_regular_repo(&repo_dir);
+        repository_with_local_edits(dir.path());
+        git(dir.path(), &["branch", "feature"]);
+        git(dir.path(), &["checkout", "--quiet", "-b", "other"]);
+        let command = RepoCommand::exec(vec![
+            "test \"$(cat notes)\" = one && test ! -e scratch && touch made-here".to_string(),
+        ]);
+        let hooks = Hooks {
+            worktree: Some("feature".to_string()),
+            ..Hooks::default()
+        };
 
-        assert_eq!(detect_repository(&repo_dir), Some(RepositoryKind::Regular));
+        assert!(process_repository(dir.path(), &command, &hooks, RunMode::Execute).is_ok());
+        assert!(capture_command(dir.path(), &command, &hooks).result.is_ok());
+
+        assert_eq!(
+            fs::read_to_string(dir.path().join("notes")).unwrap(),
+            "local\n"
+        );
+        assert!(!dir.path().join("made-here").exists());
+        let worktrees = git_output(dir.path(), &["worktree", "list"]).unwrap();
+        assert_eq!(worktrees.lines().count(), 1);
     }
 
     #[test]
-    fn detect_repository_rejects_missing_config() {
+    fn parse_env_var_splits_at_the_first_equals_sign() {
+        assert_eq!(
+            parse_env_var("GIT_TRACE=1"),
+            Ok(("GIT_TRACE".to_string(), "1".to_string()))
+        );
+        assert_eq!(
+            parse_env_var("OPTS=a=b"),
+            Ok(("OPTS".to_string(), "a=b".to_string()))
+        );
+        assert_eq!(
+            parse_env_var("EMPTY="),
+            Ok(("EMPTY".to_string(), String::new()))
+        );
+        assert!(parse_env_var("=value").is_err());
+        assert!(parse_env_var("NOVALUE").is_err());
+    }
+
+    #[test]
+    fn remote_host_reads_urls_and_scp_like_addresses() {
+        assert_eq!(
+            remote_host("https://github.com/evolve75/grpr.git").as_deref(),
+            Some("github.com")
+        );
+        assert_eq!(
+            remote_host("ssh://git@GitLab.example.com:2222/team/app.git").as_deref(),
+            Some("gitlab.example.com")
+        );
+        assert_eq!(
+            remote_host("git@github.com:evolve75/grpr.git").as_deref(),
+            Some("github.com")
+        );
+        assert_eq!(
+            remote_host("https://user:token@[::1]:8443/app.git").as_deref(),
+            Some("::1")
+        );
+        assert_eq!(remote_host("file:///srv/git/app.git"), None);
+        assert_eq!(remote_host("/srv/git/app.git"), None);
+        assert_eq!(remote_host("../app.git"), None);
+        assert_eq!(remote_host("C:/git/app.git"), None);
+    }
+
+    #[test]
+    fn remote_port_reads_the_port_or_the_protocol_default() {
+        assert_eq!(
+            remote_port("https://github.com/evolve75/grpr.git"),
+            Some(443)
+        );
+        assert_eq!(remote_port("http://example.com/app.git"), Some(80));
+        assert_eq!(remote_port("git://example.com/app.git"), Some(9418));
+        assert_eq!(
+            remote_port("ssh://git@gitlab.example.com:2222/team/app.git"),
+            Some(2222)
+        );
+        assert_eq!(
+            remote_port("ssh://git@gitlab.example.com/app.git"),
+            Some(22)
+        );
+        assert_eq!(remote_port("git@github.com:evolve75/grpr.git"), Some(22));
+        assert_eq!(
+            remote_port("https://user:token@[::1]:8443/app.git"),
+            Some(8443)
+        );
+        assert_eq!(remote_port("https://[::1]/app.git"), Some(443));
+        assert_eq!(remote_port("persistent-https://example.com/app.git"), None);
+        assert_eq!(remote_port("/srv/git/app.git"), None);
+        assert_eq!(remote_port("file:///srv/git/app.git"), None);
+    }
+
+    #[test]
+    fn remote_url_reads_configured_remotes() {
         let dir = tempdir().unwrap();
-        let repo_dir = dir.path().join("repo");
-        fs::create_dir_all(repo_dir.join(".git")).unwrap();
+        git(dir.path(), &["init", "--quiet"]);
+        git(
+            dir.path(),
+            &["remote", "add", "origin", "git@github.com:org/app.git"],
+        );
 
-        assert_eq!(detect_repository(&repo_dir), None);
+        assert_eq!(
+            remote_url(dir.path(), "origin").as_deref(),
+            Some("git@github.com:org/app.git")
+        );
+        assert_eq!(remote_url(dir.path(), "upstream"), None);
+        assert_eq!(
+            config_value(dir.path(), "remote.origin.fetch").as_deref(),
+            Some("+refs/heads/*:refs/remotes/origin/*")
+        );
+
+        git(
+            dir.path(),
+            &["remote", "add", "fork", "https://example.com/me/app.git"],
+        );
+
+        assert_eq!(
+            remote_urls(dir.path()),
+            vec![
+                "git@github.com:org/app.git".to_string(),
+                "https://example.com/me/app.git".to_string(),
+            ]
+        );
+        assert!(remote_urls(&dir.path().join("missing")).is_empty());
+        assert_eq!(
+            remote_names(dir.path()),
+            Some(vec!["fork".to_string(), "origin".to_string()])
+        );
+        assert_eq!(remote_names(&dir.path().join("missing")), None);
     }
 
     #[test]
-    fn detect_repository_identifies_valid_worktree() {
+    fn is_dirty_detects_untracked_and_modified_files() {
         let dir = tempdir().unwrap();
-        let repo_dir = dir.path().join("worktree");
-        fs::create_dir_all(&repo_dir).unwrap();
+        git(dir.path(), &["init", "--quiet"]);
+
+        assert_eq!(is_dirty(dir.path()), Some(false));
+        fs::write(dir.path().join("notes.txt"), "draft\n").unwrap();
+        assert_eq!(is_dirty(dir.path()), Some(true));
+        assert_eq!(is_dirty(&dir.path().join("missing")), None);
+    }
+
+    #[test]
+    fn working_tree_changes_distinguishes_staged_unstaged_and_untracked() {
+        let dir = tempdir().unwrap();
+        git(dir.path(), &["init", "--quiet"]);
+        fs::write(dir.path().join("tracked.txt"), "first\n").unwrap();
+        git(dir.path(), &["add", "tracked.txt"]);
+        git(dir.path(), &["commit", "--quiet", "-m", "first"]);
+        let changes = |staged, unstaged, untracked| {
+            Some(Changes {
+                staged,
+                unstaged,
+                untracked,
+            })
+        };
+
+        assert_eq!(
+            working_tree_changes(dir.path()),
+            changes(false, false, false)
+        );
+        fs::write(dir.path().join("tracked.txt"), "second\n").unwrap();
+        assert_eq!(
+            working_tree_changes(dir.path()),
+            changes(false, true, false)
+        );
+        git(dir.path(), &["add", "tracked.txt"]);
+        assert_eq!(
+            working_tree_changes(dir.path()),
+            changes(true, false, false)
+        );
+        fs::write(dir.path().join("stray.txt"), "stray\n").unwrap();
+        assert_eq!(working_tree_changes(dir.path()), changes(true, false, true));
+        assert_eq!(working_tree_changes(&dir.path().join("missing")), None);
+    }
+
+    #[test]
+    fn upstream_state_compares_the_branch_with_its_upstream() {
+        let dir = tempdir().unwrap();
+        let origin = dir.path().join("origin");
+        let clone = dir.path().join("clone");
+        fs::create_dir_all(&origin).unwrap();
+        git(&origin, &["init", "--quiet"]);
+        git(&origin, &["commit", "--allow-empty", "-m", "first"]);
+        git(dir.path(), &["clone", "--quiet", "origin", "clone"]);
+
+        assert_eq!(upstream_state(&clone), Some(UpstreamState::UpToDate));
+        git(&clone, &["commit", "--allow-empty", "-m", "local"]);
+        assert_eq!(upstream_state(&clone), Some(UpstreamState::Ahead));
+        git(&origin, &["commit", "--allow-empty", "-m", "remote"]);
+        git(&clone, &["fetch", "--quiet"]);
+        assert_eq!(upstream_state(&clone), Some(UpstreamState::Diverged));
+        git(&clone, &["reset", "--quiet", "--hard", "HEAD~1"]);
+        assert_eq!(upstream_state(&clone), Some(UpstreamState::Behind));
+        git(&clone, &["checkout", "--quiet", "--detach"]);
+        assert_eq!(upstream_state(&clone), None);
+    }
+
+    #[test]
+    fn head_commit_time_reads_the_committer_date() {
+        let dir = tempdir().unwrap();
+        git(dir.path(), &["init", "--quiet"]);
+        assert_eq!(head_commit_time(dir.path()), None);
+
+        git_with_env(
+            dir.path(),
+            &[("GIT_COMMITTER_DATE", "@1700000000 +0000")],
+            &["commit", "--quiet", "--allow-empty", "-m", "first"],
+        );
+
+        assert_eq!(
+            head_commit_time(dir.path()),
+            Some(SystemTime::UNIX_EPOCH + std::time::Duration::from_secs(1_700_000_000))
+        );
+    }
+
+    #[test]
+    fn head_queries_read_branch_detachment_and_tags() {
+        let dir = tempdir().unwrap();
+        git(dir.path(), &["init", "--quiet"]);
+        git(
+            dir.path(),
+            &["commit", "--quiet", "--allow-empty", "-m", "first"],
+        );
+
+        assert_eq!(is_detached(dir.path()), Some(false));
+        git(dir.path(), &["checkout", "--quiet", "-b", "feature/x"]);
+        assert_eq!(current_branch(dir.path()).as_deref(), Some("feature/x"));
+        git(dir.path(), &["tag", "v1.0"]);
+        git(dir.path(), &["checkout", "--quiet", "--detach"]);
+        assert_eq!(is_detached(dir.path()), Some(true));
+        assert_eq!(current_branch(dir.path()), None);
+        assert_eq!(head_tags(dir.path()), vec!["v1.0".to_string()]);
+        assert_eq!(is_detached(&dir.path().join("missing")), None);
+    }
+
+    #[test]
+    fn upstream_gone_detects_pruned_upstream_branches() {
+        let dir = tempdir().unwrap();
+        let origin = dir.path().join("origin");
+        let clone = dir.path().join("clone");
+        fs::create_dir_all(&origin).unwrap();
+        git(&origin, &["init", "--quiet"]);
+        git(
+            &origin,
+            &["commit", "--quiet", "--allow-empty", "-m", "first"],
+        );
+        git(&origin, &["branch", "topic"]);
+        git(dir.path(), &["clone", "--quiet", "origin", "clone"]);
+        git(&clone, &["checkout", "--quiet", "topic"]);
+        assert!(!upstream_gone(&clone));
+
+        git(&origin, &["branch", "--quiet", "-D", "topic"]);
+        git(&clone, &["fetch", "--quiet", "--prune"]);
+        assert!(upstream_gone(&clone));
+    }
+
+    #[test]
+    fn has_stash_detects_stashed_changes() {
+        let dir = tempdir().unwrap();
+        git(dir.path(), &["init", "--quiet"]);
+        fs::write(dir.path().join("notes.txt"), "first\n").unwrap();
+        git(dir.path(), &["add", "notes.txt"]);
+        git(dir.path(), &["commit", "--quiet", "-m", "first"]);
+        assert!(!has_stash(dir.path()));
+
+        fs::write(dir.path().join("notes.txt"), "second\n").unwrap();
+        git(dir.path(), &["stash", "--quiet"]);
+        assert!(has_stash(dir.path()));
+    }
+
+    #[test]
+    fn default_branch_follows_origin_head() {
+        let dir = tempdir().unwrap();
+        git(dir.path(), &["init", "--quiet"]);
+        assert_eq!(default_branch(dir.path()), None);
+
+        git(
+            dir.path(),
+            &[
+                "symbolic-ref",
+                "refs/remotes/origin/HEAD",
+                "refs/remotes/origin/trunk",
+            ],
+        );
+
+        assert_eq!(default_branch(dir.path()).as_deref(), Some("trunk"));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn dedupe_repositories_recognizes_linked_paths() {
+        let dir = tempdir().unwrap();
+        let repo = dir.path().join("repo");
+        let other = dir.path().join("other");
+        let link = dir.path().join("link");
+        create_regular_repo(&repo);
+        create_regular_repo(&other);
+        std::os::unix::fs::symlink(&repo, &link).unwrap();
+
+        let deduped = dedupe_repositories(vec![
+            link.clone(),
+            other.clone(),
+            repo.clone(),
+            dir.path().join(".").join("repo"),
+        ]);
+
+        assert_eq!(deduped, vec![link, other]);
+    }
+
+    #[test]
+    fn git_dir_resolves_each_repository_layout() {
+        let dir = tempdir().unwrap();
+        let regular = dir.path().join("regular");
+        let worktree = dir.path().join("worktree");
+        let bare = dir.path().join("bare.git");
+        create_regular_repo(&regular);
+        fs::create_dir_all(&worktree).unwrap();
         fs::write(
-            repo_dir.join(".git"),
-            "gitdir: /path/to/repo/.git/worktrees/topic\n",
+            worktree.join(".git"),
+            "gitdir: ../regular/.git/worktrees/worktree\n",
         )
         .unwrap();
+        fs::create_dir_all(bare.join("objects")).unwrap();
+        fs::create_dir_all(bare.join("refs")).unwrap();
+        fs::write(bare.join("HEAD"), "ref: refs/heads/main\n").unwrap();
 
-        assert_eq!(detect_repository(&repo_dir), Some(RepositoryKind::Worktree));
+        assert_eq!(git_dir(&regular), Some(regular.join(".git")));
+        assert_eq!(
+            git_dir(&worktree),
+            Some(worktree.join("../regular/.git/worktrees/worktree"))
+        );
+        assert_eq!(git_dir(&bare), Some(bare.clone()));
+        assert_eq!(git_dir(dir.path()), None);
     }
 
     #[test]
-    fn detect_repository_rejects_invalid_worktree_file() {
+    fn git_dir_size_sums_files_in_the_git_directory() {
         let dir = tempdir().unwrap();
-        let repo_dir = dir.path().join("worktree");
-        fs::create_dir_all(&repo_dir).unwrap();
-        fs::write(repo_dir.join(".git"), "not a gitdir reference\n").unwrap();
+        create_regular_repo(dir.path());
+        let objects = dir.path().join(".git").join("objects").join("ab");
+        fs::create_dir_all(&objects).unwrap();
+        fs::write(objects.join("cdef"), vec![0; 1000]).unwrap();
+        fs::write(dir.path().join("large.bin"), vec![0; 5000]).unwrap();
 
-        assert_eq!(detect_repository(&repo_dir), None);
+        assert_eq!(
+            git_dir_size(dir.path()),
+            Some(1000 + "[core]\n".len() as u64)
+        );
+        assert_eq!(git_dir_size(&dir.path().join("missing")), None);
     }
 
     #[test]
-    fn detect_repository_rejects_empty_worktree_file() {
+    fn has_submodules_checks_for_gitmodules() {
         let dir = tempdir().unwrap();
-        let repo_dir = dir.path().join("worktree");
-        fs::create_dir_all(&repo_dir).unwrap();
-        fs::write(repo_dir.join(".git"), "").unwrap();
+        create_regular_repo(dir.path());
+        assert!(!has_submodules(dir.path()));
 
-        assert_eq!(detect_repository(&repo_dir), None);
+        fs::write(
+            dir.path().join(".gitmodules"),
+            "[submodule \"lib\"]\n\tpath = lib\n",
+        )
+        .unwrap();
+        assert!(has_submodules(dir.path()));
     }
 
     #[test]
-    fn detect_repository_rejects_file_paths() {
+    fn last_activity_uses_head_and_index_times() {
         let dir = tempdir().unwrap();
-        let file_path = dir.path().join("not-a-dir");
-        fs::write(&file_path, "test").unwrap();
+        create_regular_repo(dir.path());
+        let head = dir.path().join(".git").join("HEAD");
+        fs::write(&head, "ref: refs/heads/main\n").unwrap();
 
-        assert_eq!(detect_repository(&file_path), None);
+        assert_eq!(
+            last_activity(dir.path()),
+            Some(fs::metadata(&head).unwrap().modified().unwrap())
+        );
     }
 
     #[test]
-    fn detect_repository_rejects_missing_paths() {
+    fn repository_modified_prefers_git_entry() {
         let dir = tempdir().unwrap();
-        let missing_path = dir.path().join("missing");
+        create_regular_repo(dir.path());
 
-        assert_eq!(detect_repository(&missing_path), None);
+        assert_eq!(
+            repository_modified(dir.path()),
+            fs::metadata(dir.path().join(".git"))
+                .unwrap()
+                .modified()
+                .ok()
+        );
     }
 
     #[test]
-    fn discover_repositories_skips_descendants_of_found_repositories() {
+    fn run_command_accepts_multi_argument_commands() {
         let dir = tempdir().unwrap();
-        let parent_repo = dir.path().join("parent");
-        let nested_repo = parent_repo.join("nested");
-        let sibling_repo = dir.path().join("sibling");
+        git(dir.path(), &["init"]);
 
-        fs::create_dir_all(&nested_repo).unwrap();
-        fs::create_dir_all(&sibling_repo).unwrap();
-        create_regular_repo(&parent_repo);
-        create_regular_repo(&nested_repo);
-        create_regular_repo(&sibling_repo);
+        let command = RepoCommand::git(vec!["status".to_string(), "--short".to_string()]);
+        assert!(run_command(dir.path(), &command, RunMode::Execute).is_ok());
+    }
 
-        let mut discovered = discover_repositories(dir.path());
-        discovered.sort();
+    #[test]
+    fn needs_terminal_recognizes_interactive_git_commands() {
+        let needs_terminal = |args: &[&str]| {
+            RepoCommand::git(args.iter().map(|arg| arg.to_string()).collect()).needs_terminal()
+        };
 
-        assert_eq!(discovered, vec![parent_repo, sibling_repo]);
+        assert!(needs_terminal(&["add", "-p"]));
+        assert!(needs_terminal(&["add", "--patch", "src"]));
+        assert!(needs_terminal(&[
+            "-c",
+            "core.pager=cat",
+            "rebase",
+            "-i",
+            "main"
+        ]));
+        assert!(needs_terminal(&[
+            "rebase",
+            "--interactive=no-rebase-cousins"
+        ]));
+        assert!(needs_terminal(&["stash", "-p"]));
+        assert!(needs_terminal(&["commit"]));
+        assert!(needs_terminal(&["commit", "-a"]));
+        assert!(needs_terminal(&["commit", "-m", "msg", "--edit"]));
+        assert!(needs_terminal(&["mergetool"]));
+
+        assert!(!needs_terminal(&[]));
+        assert!(!needs_terminal(&["status"]));
+        assert!(!needs_terminal(&["add", "-A"]));
+        assert!(!needs_terminal(&["add", "--", "-p"]));
+        assert!(!needs_terminal(&["rebase", "main"]));
+        assert!(!needs_terminal(&["commit", "-am", "Fix"]));
+        assert!(!needs_terminal(&["commit", "--message=Fix"]));
+        assert!(!needs_terminal(&["commit", "--amend", "--no-edit"]));
+        assert!(!needs_terminal(&["log", "-p"]));
+        assert!(!RepoCommand::exec(vec!["vi".to_string()]).needs_terminal());
     }
 
     #[test]
-    fn discover_repositories_handles_root_repository() {
+    fn matches_invocation_compares_subcommand_and_options() {
+        let command =
+            |args: &[&str]| RepoCommand::git(args.iter().map(|arg| arg.to_string()).collect());
+
+        assert!(command(&["reset", "--hard", "HEAD~1"]).matches_invocation("reset --hard"));
+        assert!(command(&["clean", "-fdx"]).matches_invocation("clean -fd"));
+        assert!(command(&["clean", "-d", "-x", "-f"]).matches_invocation("clean -fd"));
+        assert!(command(&["push", "--force=true"]).matches_invocation("push --force"));
+        assert!(command(&["-C", "sub", "checkout", "."]).matches_invocation("checkout ."));
+        assert!(command(&["checkout", "--", "."]).matches_invocation("checkout ."));
+        assert!(command(&["restore", "--", "."]).matches_invocation("restore ."));
+
+        assert!(!command(&["reset", "--soft"]).matches_invocation("reset --hard"));
+        assert!(!command(&["clean", "-n"]).matches_invocation("clean -f"));
+        assert!(!command(&["push", "--force-with-lease"]).matches_invocation("push --force"));
+        assert!(!command(&["checkout", "main"]).matches_invocation("checkout ."));
+        assert!(!command(&["checkout", "--", "main.rs"]).matches_invocation("checkout ."));
+        assert!(!command(&["clean", "--", "-f"]).matches_invocation("clean -f"));
+        assert!(
+            !RepoCommand::exec(vec!["make".into(), "clean".into()]).matches_invocation("clean -f")
+        );
+    }
+
+    #[test]
+    fn uses_network_recognizes_remote_git_commands() {
+        let uses_network = |args: &[&str]| {
+            RepoCommand::git(args.iter().map(|arg| arg.to_string()).collect()).uses_network()
+        };
+
+        assert!(uses_network(&["fetch", "--all"]));
+        assert!(uses_network(&["-c", "http.lowSpeedLimit=1000", "pull"]));
+        assert!(uses_network(&["push", "origin", "main"]));
+        assert!(uses_network(&["remote", "update"]));
+        assert!(uses_network(&["submodule", "update", "--init"]));
+
+        assert!(!uses_network(&[]));
+        assert!(!uses_network(&["status"]));
+        assert!(!uses_network(&["remote", "-v"]));
+        assert!(!uses_network(&["log", "--", "fetch"]));
+        assert!(!RepoCommand::exec(vec!["git fetch".to_string()]).uses_network());
+    }
+
+    #[test]
+    fn change_kind_recognizes_status_and_diff() {
+        let kind = |args: &[&str]| {
+            RepoCommand::git(args.iter().map(ToString::to_string).collect()).change_kind()
+        };
+        assert_eq!(kind(&["status", "--short"]), Some(ChangeKind::Status));
+        assert_eq!(
+            kind(&["-c", "x=y", "diff", "HEAD~1"]),
+            Some(ChangeKind::Diff)
+        );
+        assert_eq!(kind(&["diff", "--color=never"]), Some(ChangeKind::Diff));
+        assert_eq!(kind(&["diff", "--color"]), None);
+        assert_eq!(kind(&["status", "-z"]), None);
+        assert_eq!(kind(&["status", "--porcelain=v2"]), None);
+        assert_eq!(kind(&["log", "-p"]), None);
+    }
+
+    #[test]
+    fn is_routine_output_recognizes_commands_with_nothing_to_do() {
+        let routine = |args: &[&str], output: &str| {
+            RepoCommand::git(args.iter().map(|arg| arg.to_string()).collect())
+                .is_routine_output(output.as_bytes())
+        };
+
+        assert!(routine(&["pull"], "Already up to date.\n"));
+        assert!(routine(&["push"], "Everything up-to-date\n"));
+        assert!(routine(
+            &["status"],
+            "On branch main\nYour branch is up to date with 'origin/main'.\n\n\
+             nothing to commit, working tree clean\n"
+        ));
+        assert!(routine(&["log"], "\n"));
+
+        assert!(!routine(
+            &["pull"],
+            "Updating 1a2b3c..4d5e6f\nFast-forward\n"
+        ));
+        assert!(!routine(
+            &["status"],
+            "On branch main\nChanges not staged for commit:\n"
+        ));
+        assert!(!routine(&["log"], "commit 1a2b3c\n"));
+        assert!(
+            !RepoCommand::exec(vec!["echo".into(), "Already up to date.".into()])
+                .is_routine_output(b"Already up to date.\n")
+        );
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn commands_without_terminal_get_empty_stdin() {
         let dir = tempdir().unwrap();
-        create_regular_repo(dir.path());
-        let nested_repo = dir.path().join("nested");
-        fs::create_dir_all(&nested_repo).unwrap();
-        create_regular_repo(&nested_repo);
+        let command = RepoCommand::exec(vec!["cat; echo $GIT_TERMINAL_PROMPT".to_string()])
+            .without_terminal();
 
-        let discovered = discover_repositories(dir.path());
+        let captured = capture_command(dir.path(), &command, &Hooks::default());
 
-        assert_eq!(discovered, vec![dir.path().to_path_buf()]);
+        assert_eq!(String::from_utf8_lossy(&captured.stdout), "0\n");
+    }
+
+    #[test]
+    fn commands_are_rebuilt_from_their_invocation() {
+        let git = RepoCommand::git(vec!["log".to_string(), "--format=%an <%ae>".to_string()]);
+        let program = RepoCommand::exec(vec!["make".to_string(), "test".to_string()]);
+        for command in [
+            git.clone(),
+            program.clone(),
+            RepoCommand::exec(vec!["cargo fmt && cargo test".to_string()]),
+            RepoCommand::script(Path::new("/opt/my scripts/fix; all.sh"), Vec::new()),
+        ] {
+            assert_eq!(
+                RepoCommand::from_invocation(command.invocation().clone()),
+                command
+            );
+        }
+        for command in [git, program] {
+            assert_eq!(RepoCommand::from_words(command.words()), command);
+        }
     }
 
     #[test]
-    fn run_git_command_accepts_multi_argument_commands() {
+    fn run_command_keeps_arguments_with_spaces_intact() {
         let dir = tempdir().unwrap();
-        let status = Command::new("git")
-            .arg("init")
-            .current_dir(dir.path())
-            .status()
-            .unwrap();
-        assert!(status.success());
+        git(dir.path(), &["init", "--quiet"]);
+        let args = ["config", "user.name", "Ada  Lovelace, Jr."];
+        let command = RepoCommand::git(args.map(String::from).into());
+
+        assert!(run_command(dir.path(), &command, RunMode::Execute).is_ok());
+        assert_eq!(
+            config_value(dir.path(), "user.name").as_deref(),
+            Some("Ada  Lovelace, Jr.")
+        );
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn exec_runs_programs_and_shell_scripts_in_the_repository() {
+        let dir = tempdir().unwrap();
+        let words = |words: &[&str]| words.iter().map(|word| word.to_string()).collect();
+        let program = RepoCommand::exec(words(&["pwd"]));
+        let script = RepoCommand::exec(words(&["echo $0 && test -d . | cat"]));
+        let argv = RepoCommand::exec(words(&["printf", "%s|", "a b", "c"]));
+
+        assert_eq!(program.command_line(), "sh -c pwd");
+        assert_eq!(script.command_line(), "sh -c 'echo $0 && test -d . | cat'");
+        assert_eq!(argv.command_line(), "printf '%s|' 'a b' c");
+        let captured = capture_command(dir.path(), &argv, &Hooks::default());
+        assert_eq!(String::from_utf8_lossy(&captured.stdout), "a b|c|");
+        assert!(run_command(dir.path(), &script, RunMode::Execute).is_ok());
+
+        let failing = RepoCommand::exec(words(&["false", "--ignored"]));
+        let err = run_command(dir.path(), &failing, RunMode::Execute).unwrap_err();
+        assert!(err.to_string().starts_with("command failed in "));
+    }
+
+    #[test]
+    fn command_line_quotes_arguments_for_the_shell() {
+        let args: Vec<String> = ["commit", "-m", "Fix the user's bug", "--", "a b.txt", ""]
+            .map(String::from)
+            .into();
+
+        assert_eq!(
+            RepoCommand::git(args).command_line(),
+            r"git commit -m 'Fix the user'\''s bug' -- 'a b.txt' ''"
+        );
+        assert_eq!(
+            RepoCommand::git(vec!["log".to_string(), "--format=%h@{u}".to_string()]).command_line(),
+            "git log '--format=%h@{u}'"
+        );
+    }
+
+    #[test]
+    fn dry_run_does_not_spawn_git() {
+        let dir = tempdir().unwrap();
+        let missing = dir.path().join("missing");
+        let command = RepoCommand::git(vec!["status".to_string()]);
 
-        let args = vec!["status".to_string(), "--short".to_string()];
-        assert!(run_git_command(dir.path(), &args).is_ok());
+        assert!(run_command(&missing, &command, RunMode::DryRun).is_ok());
+        assert!(run_command(&missing, &command, RunMode::Execute).is_err());
     }
 }
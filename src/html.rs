@@ -0,0 +1,291 @@
+/*
+ * grpr - A CLI tool for recursively executing git commands.
+ *
+ * Copyright (c) 2025 Anupam Sengupta
+ *
+ * This source code is licensed under the MIT license found in the LICENSE file
+ * in the root directory of this source tree.
+ */
+
+use crate::report::{RepositoryReport, Summary};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+/// Styles the page, keeping it readable when printed or mailed.
+const STYLE: &str = r#"
+body { font-family: system-ui, sans-serif; margin: 2em; color: #222; }
+h1 { font-size: 1.4em; }
+.summary span { margin-right: 1.5em; }
+.controls { margin: 1em 0; }
+.controls input { width: 24em; padding: 0.3em; }
+table { border-collapse: collapse; width: 100%; }
+th, td { border-bottom: 1px solid #ddd; padding: 0.4em 0.6em; text-align: left; vertical-align: top; }
+th { cursor: pointer; user-select: none; background: #f4f4f4; }
+th.sorted-asc::after { content: " \25B2"; }
+th.sorted-desc::after { content: " \25BC"; }
+td.number { text-align: right; }
+tr.failed .outcome { color: #b00020; font-weight: bold; }
+tr.succeeded .outcome { color: #1b7f3b; }
+tr.skipped .outcome { color: #8a6d00; }
+pre { background: #f8f8f8; padding: 0.5em; overflow-x: auto; max-height: 30em; }
+"#;
+
+/// Sorts the table by a clicked column and filters its rows by the search
+/// box and the outcome menu.
+const SCRIPT: &str = r#"
+const table = document.getElementById("repositories");
+const rows = () => Array.from(table.tBodies[0].rows);
+function filter() {
+  const text = document.getElementById("search").value.toLowerCase();
+  const outcome = document.getElementById("outcome").value;
+  for (const row of rows()) {
+    const shown = row.textContent.toLowerCase().includes(text)
+      && (outcome === "all" || row.classList.contains(outcome));
+    row.hidden = !shown;
+  }
+}
+table.tHead.addEventListener("click", (event) => {
+  const th = event.target.closest("th");
+  if (!th) return;
+  const column = th.cellIndex;
+  const ascending = !th.classList.contains("sorted-asc");
+  for (const other of table.tHead.rows[0].cells) other.classList.remove("sorted-asc", "sorted-desc");
+  th.classList.add(ascending ? "sorted-asc" : "sorted-desc");
+  const key = (row) => row.cells[column].dataset.sort ?? row.cells[column].textContent;
+  const numeric = th.dataset.type === "number";
+  const sorted = rows().sort((a, b) => {
+    const order = numeric ? Number(key(a)) - Number(key(b)) : key(a).localeCompare(key(b));
+    return ascending ? order : -order;
+  });
+  table.tBodies[0].append(...sorted);
+});
+document.getElementById("search").addEventListener("input", filter);
+document.getElementById("outcome").addEventListener("change", filter);
+"#;
+
+/// Escapes `text` for use in HTML content and attribute values.
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Writes one table row for `report`, with its output folded away.
+fn write_row(out: &mut impl Write, report: &RepositoryReport) -> io::Result<()> {
+    let outcome = if report.success {
+        "succeeded"
+    } else {
+        "failed"
+    };
+    let exit_code = report
+        .exit_code
+        .map_or_else(String::new, |code| code.to_string());
+    writeln!(out, "<tr class=\"{outcome}\">")?;
+    writeln!(out, "<td>{}</td>", escape(&report.path.to_string_lossy()))?;
+    writeln!(out, "<td class=\"outcome\">{outcome}</td>")?;
+    writeln!(
+        out,
+        "<td class=\"number\" data-sort=\"{}\">{exit_code}</td>",
+        report.exit_code.unwrap_or(-1)
+    )?;
+    writeln!(
+        out,
+        "<td class=\"number\" data-sort=\"{}\">{:.2}s</td>",
+        report.duration_secs, report.duration_secs
+    )?;
+    write!(out, "<td><details><summary>")?;
+    match &report.error {
+        Some(error) => write!(out, "{}", escape(error))?,
+        None => write!(out, "output")?,
+    }
+    write!(out, "</summary>")?;
+    write!(
+        out,
+        "<p><code>{}</code></p>",
+        escape(&report.command.join(" "))
+    )?;
+    if !report.stdout.is_empty() {
+        write!(out, "<pre>{}</pre>", escape(&report.stdout))?;
+    }
+    if !report.stderr.is_empty() {
+        write!(
+            out,
+            "<pre class=\"stderr\">{}</pre>",
+            escape(&report.stderr)
+        )?;
+    }
+    writeln!(out, "</details></td>")?;
+    writeln!(out, "</tr>")
+}
+
+/// Writes one table row for a repository the command was not run in, with
+/// the `reason` it was skipped.
+fn write_skipped_row(out: &mut impl Write, repo_path: &Path, reason: &str) -> io::Result<()> {
+    writeln!(out, "<tr class=\"skipped\">")?;
+    writeln!(out, "<td>{}</td>", escape(&repo_path.to_string_lossy()))?;
+    writeln!(out, "<td class=\"outcome\">skipped</td>")?;
+    writeln!(out, "<td class=\"number\" data-sort=\"-1\"></td>")?;
+    writeln!(out, "<td class=\"number\" data-sort=\"0\"></td>")?;
+    writeln!(out, "<td>{}</td>", escape(reason))?;
+    writeln!(out, "</tr>")
+}
+
+/// Writes a standalone HTML page with a sortable, searchable table of the
+/// repositories in `reports`, and of the `skipped` ones with the reason, and
+/// the `summary` of the run.
+pub fn write_report(
+    mut out: impl Write,
+    reports: &[RepositoryReport],
+    skipped: &[(PathBuf, String)],
+    summary: &Summary,
+) -> io::Result<()> {
+    writeln!(out, "<!DOCTYPE html>")?;
+    writeln!(out, "<html lang=\"en\">")?;
+    writeln!(out, "<head>")?;
+    writeln!(out, "<meta charset=\"utf-8\">")?;
+    writeln!(out, "<title>grpr report</title>")?;
+    writeln!(out, "<style>{STYLE}</style>")?;
+    writeln!(out, "</head>")?;
+    writeln!(out, "<body>")?;
+    writeln!(out, "<h1>grpr report</h1>")?;
+    writeln!(out, "<p class=\"summary\">")?;
+    writeln!(out, "<span>{} repositories</span>", summary.repositories)?;
+    writeln!(out, "<span>{} succeeded</span>", summary.succeeded)?;
+    writeln!(out, "<span>{} failed</span>", summary.failed)?;
+    // Counted from the rows, as in the JUnit report, so that repositories
+    // left out by a guard are included.
+    writeln!(out, "<span>{} skipped</span>", skipped.len())?;
+    writeln!(out, "<span>{:.1}s</span>", summary.duration_secs)?;
+    writeln!(out, "<span>run {}</span>", escape(&summary.run_id))?;
+    if summary.interrupted {
+        writeln!(out, "<span>interrupted</span>")?;
+    }
+    writeln!(out, "</p>")?;
+    writeln!(out, "<div class=\"controls\">")?;
+    writeln!(
+        out,
+        "<input id=\"search\" type=\"search\" placeholder=\"Search repositories and output\">"
+    )?;
+    writeln!(out, "<select id=\"outcome\">")?;
+    writeln!(out, "<option value=\"all\">All outcomes</option>")?;
+    writeln!(out, "<option value=\"failed\">Failed</option>")?;
+    writeln!(out, "<option value=\"succeeded\">Succeeded</option>")?;
+    writeln!(out, "<option value=\"skipped\">Skipped</option>")?;
+    writeln!(out, "</select>")?;
+    writeln!(out, "</div>")?;
+    writeln!(out, "<table id=\"repositories\">")?;
+    writeln!(
+        out,
+        "<thead><tr><th>Repository</th><th>Outcome</th>\
+         <th data-type=\"number\">Exit code</th><th data-type=\"number\">Duration</th>\
+         <th>Output</th></tr></thead>"
+    )?;
+    writeln!(out, "<tbody>")?;
+    for report in reports {
+        write_row(&mut out, report)?;
+    }
+    for (repo_path, reason) in skipped {
+        write_skipped_row(&mut out, repo_path, reason)?;
+    }
+    writeln!(out, "</tbody>")?;
+    writeln!(out, "</table>")?;
+    writeln!(out, "<script>{SCRIPT}</script>")?;
+    writeln!(out, "</body>")?;
+    writeln!(out, "</html>")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn escape_replaces_markup() {
+        assert_eq!(
+            escape("<a href=\"x\">&'</a>"),
+            "&lt;a href=&quot;x&quot;&gt;&amp;&#39;&lt;/a&gt;"
+        );
+    }
+
+    #[test]
+    fn report_has_a_row_per_repository_with_escaped_output() {
+        let reports = [RepositoryReport {
+            path: PathBuf::from("/src/app"),
+            command: vec!["git".to_string(), "log".to_string()],
+            exit_code: Some(1),
+            success: false,
+            duration_secs: 1.5,
+            stdout: "<script>alert(1)</script>\n".to_string(),
+            stderr: String::new(),
+            error: Some("git command failed".to_string()),
+        }];
+        let summary = Summary {
+            repositories: 1,
+            failed: 1,
+            ..Summary::default()
+        };
+
+        let mut out = Vec::new();
+        write_report(&mut out, &reports, &[], &summary).unwrap();
+        let html = String::from_utf8(out).unwrap();
+
+        assert!(html.starts_with("<!DOCTYPE html>"));
+        assert!(html.contains("<tr class=\"failed\">"));
+        assert!(html.contains("<td>/src/app</td>"));
+        assert!(html.contains("data-sort=\"1.5\">1.50s</td>"));
+        assert!(html.contains("<summary>git command failed</summary>"));
+        assert!(html.contains("<pre>&lt;script&gt;alert(1)&lt;/script&gt;\n</pre>"));
+        assert!(html.contains("<span>1 failed</span>"));
+        assert!(html.contains("<span>0 skipped</span>"));
+    }
+
+    #[test]
+    fn report_has_a_row_with_the_reason_for_each_skipped_repository() {
+        let reports = [RepositoryReport {
+            path: PathBuf::from("/src/app"),
+            command: vec!["git".to_string(), "pull".to_string()],
+            exit_code: Some(0),
+            success: true,
+            duration_secs: 0.5,
+            stdout: String::new(),
+            stderr: String::new(),
+            error: None,
+        }];
+        let skipped = [
+            (PathBuf::from("/src/web"), "dirty".to_string()),
+            (PathBuf::from("/src/api"), "offline".to_string()),
+            (PathBuf::from("/src/<lib>"), "on branch <main>".to_string()),
+        ];
+        let summary = Summary {
+            repositories: 1,
+            succeeded: 1,
+            // Guards are left out of the summary's count.
+            skipped: 2,
+            ..Summary::default()
+        };
+
+        let mut out = Vec::new();
+        write_report(&mut out, &reports, &skipped, &summary).unwrap();
+        let html = String::from_utf8(out).unwrap();
+
+        assert_eq!(html.matches("<tr class=\"skipped\">").count(), 3);
+        assert!(html.contains(
+            "<td>/src/web</td>\n<td class=\"outcome\">skipped</td>\n\
+             <td class=\"number\" data-sort=\"-1\"></td>\n\
+             <td class=\"number\" data-sort=\"0\"></td>\n<td>dirty</td>"
+        ));
+        assert!(html.contains("<td>offline</td>"));
+        assert!(html.contains("<td>/src/&lt;lib&gt;</td>"));
+        assert!(html.contains("<td>on branch &lt;main&gt;</td>"));
+        assert!(html.contains("<span>3 skipped</span>"));
+        assert!(html.contains("<option value=\"skipped\">Skipped</option>"));
+    }
+}
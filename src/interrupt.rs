@@ -0,0 +1,237 @@
+/*
+ * grpr - A CLI tool for recursively executing git commands.
+ *
+ * Copyright (c) 2025 Anupam Sengupta
+ *
+ * This source code is licensed under the MIT license found in the LICENSE file
+ * in the root directory of this source tree.
+ */
+
+use std::env;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, ExitStatus};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::thread;
+use std::time::{Duration, SystemTime};
+
+/// How often a running command is checked for having finished, or for
+/// having to be stopped.
+const POLL_INTERVAL: Duration = Duration::from_millis(20);
+
+/// How long grpr waits for stopped commands to exit after a second Ctrl-C
+/// before it exits anyway.
+const GRACE_PERIOD: Duration = Duration::from_secs(3);
+
+/// Exit status after Ctrl-C, following the shell convention of 128 + SIGINT.
+pub const EXIT_INTERRUPTED: u8 = 130;
+
+/// How often the stop file and SIGUSR1 are checked for.
+const STOP_POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Number of times Ctrl-C has been pressed, counting a stop request as the
+/// first.
+static INTERRUPTS: AtomicUsize = AtomicUsize::new(0);
+
+/// Set by the SIGUSR1 handler.
+static STOP_SIGNALED: AtomicBool = AtomicBool::new(false);
+
+/// Installs the Ctrl-C handler. The first Ctrl-C asks grpr to start no
+/// further repositories and let running commands finish. The second one
+/// stops the running commands, and exits grpr if it has not finished within
+/// a grace period.
+///
+/// It also starts watching for a stop request from outside: SIGUSR1, or
+/// touching the [`stop_file`]. Either acts like the first Ctrl-C.
+pub fn install() -> Result<(), io::Error> {
+    watch_stop_requests();
+    ctrlc::set_handler(|| {
+        if INTERRUPTS.fetch_add(1, Ordering::Relaxed) == 0 {
+            crate::pager::release();
+            tracing::info!(
+                "interrupted; waiting for running commands to finish (Ctrl-C again to stop them)"
+            );
+        } else {
+            tracing::info!("stopping running commands");
+            thread::sleep(GRACE_PERIOD);
+            std::process::exit(EXIT_INTERRUPTED.into());
+        }
+    })
+    .map_err(io::Error::other)
+}
+
+/// Returns the file that stops running grpr commands when touched,
+/// `$XDG_RUNTIME_DIR/grpr.stop`, or `grpr-<uid>.stop` in the temporary
+/// directory, which other users share, when `XDG_RUNTIME_DIR` is unset.
+pub fn stop_file() -> PathBuf {
+    match env::var_os("XDG_RUNTIME_DIR").filter(|value| !value.is_empty()) {
+        Some(dir) => PathBuf::from(dir).join("grpr.stop"),
+        #[cfg(unix)]
+        // SAFETY: getuid cannot fail.
+        None => env::temp_dir().join(format!("grpr-{}.stop", unsafe { libc::getuid() })),
+        #[cfg(not(unix))]
+        None => env::temp_dir().join("grpr.stop"),
+    }
+}
+
+/// Returns when `path` was last modified, or `None` when it does not exist.
+/// A symlink, or a file of another user, is taken not to exist, so that no
+/// one else can stop the run through a shared temporary directory.
+fn modified(path: &Path) -> Option<SystemTime> {
+    let metadata = fs::symlink_metadata(path).ok()?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::MetadataExt;
+        // SAFETY: getuid cannot fail.
+        if metadata.uid() != unsafe { libc::getuid() } {
+            return None;
+        }
+    }
+    if metadata.is_symlink() {
+        return None;
+    }
+    metadata.modified().ok()
+}
+
+/// Returns true when `path` has been created or touched since its
+/// modification time was `initial`, so a stop file left over from an
+/// earlier run is ignored.
+fn touched_since(path: &Path, initial: Option<SystemTime>) -> bool {
+    modified(path).is_some_and(|modified| Some(modified) != initial)
+}
+
+/// Handles SIGUSR1. Only sets a flag, which is all a signal handler can
+/// safely do.
+#[cfg(unix)]
+extern "C" fn on_stop_signal(_: libc::c_int) {
+    STOP_SIGNALED.store(true, Ordering::Relaxed);
+}
+
+/// Installs the SIGUSR1 handler and starts a background thread that turns
+/// the signal, or a touch of the stop file, into an interrupt.
+fn watch_stop_requests() {
+    #[cfg(unix)]
+    // SAFETY: the handler only stores to an atomic.
+    unsafe {
+        libc::signal(
+            libc::SIGUSR1,
+            on_stop_signal as extern "C" fn(libc::c_int) as libc::sighandler_t,
+        );
+    }
+    let stop_file = stop_file();
+    let initial = modified(&stop_file);
+    thread::spawn(move || {
+        loop {
+            let reason = if STOP_SIGNALED.load(Ordering::Relaxed) {
+                "received SIGUSR1".to_string()
+            } else if touched_since(&stop_file, initial) {
+                format!("{} was touched", stop_file.display())
+            } else {
+                thread::sleep(STOP_POLL_INTERVAL);
+                continue;
+            };
+            if INTERRUPTS
+                .compare_exchange(0, 1, Ordering::Relaxed, Ordering::Relaxed)
+                .is_ok()
+            {
+                crate::pager::release();
+                tracing::info!("stopping: {reason}; waiting for running commands to finish");
+            }
+            return;
+        }
+    });
+}
+
+/// Asks the run to stop as Ctrl-C does, for the `--tui` dashboard, which
+/// reads Ctrl-C as a key: the first request starts no further repositories,
+/// and the second stops the running commands.
+pub fn request() {
+    INTERRUPTS.fetch_add(1, Ordering::Relaxed);
+}
+
+/// Returns true once Ctrl-C has been pressed or a stop was requested.
+pub fn interrupted() -> bool {
+    INTERRUPTS.load(Ordering::Relaxed) > 0
+}
+
+/// Returns true once Ctrl-C has been pressed a second time, and running
+/// commands should be stopped.
+fn forced() -> bool {
+    INTERRUPTS.load(Ordering::Relaxed) > 1
+}
+
+/// Keeps Ctrl-C on the terminal from reaching `process`, so the command can
+/// finish after grpr is interrupted. Commands attached to the terminal are
+/// left alone, as they read from it and are stopped by Ctrl-C themselves.
+pub fn shield(process: &mut Command) {
+    #[cfg(unix)]
+    {
+        use std::os::unix::process::CommandExt;
+        process.process_group(0);
+    }
+    #[cfg(not(unix))]
+    let _ = process;
+}
+
+/// Waits for `child` to exit, killing it once Ctrl-C has been pressed twice.
+pub fn wait(child: &mut Child) -> Result<ExitStatus, io::Error> {
+    loop {
+        if let Some(status) = child.try_wait()? {
+            return Ok(status);
+        }
+        if forced() {
+            // The child may exit between the check and the kill.
+            let _ = child.kill();
+            return child.wait();
+        }
+        thread::sleep(POLL_INTERVAL);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn wait_returns_the_exit_status() {
+        let mut child = Command::new("git")
+            .arg("--version")
+            .stdout(std::process::Stdio::null())
+            .spawn()
+            .unwrap();
+
+        assert!(wait(&mut child).unwrap().success());
+    }
+
+    #[test]
+    fn stop_file_counts_only_when_touched_after_the_start() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("grpr.stop");
+
+        assert!(!touched_since(&path, None));
+        fs::write(&path, "").unwrap();
+        assert!(touched_since(&path, None));
+        let initial = modified(&path);
+        assert!(!touched_since(&path, initial));
+        fs::File::options()
+            .write(true)
+            .open(&path)
+            .unwrap()
+            .set_modified(SystemTime::now() + Duration::from_secs(60))
+            .unwrap();
+        assert!(touched_since(&path, initial));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn stop_file_ignores_symlinks() {
+        let dir = tempfile::tempdir().unwrap();
+        let target = dir.path().join("busy.log");
+        let path = dir.path().join("grpr.stop");
+        std::os::unix::fs::symlink(&target, &path).unwrap();
+
+        fs::write(&target, "").unwrap();
+        assert!(!touched_since(&path, None));
+    }
+}
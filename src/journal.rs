@@ -0,0 +1,207 @@
+/*
+ * grpr - A CLI tool for recursively executing git commands.
+ *
+ * Copyright (c) 2025 Anupam Sengupta
+ *
+ * This source code is licensed under the MIT license found in the LICENSE file
+ * in the root directory of this source tree.
+ */
+
+use crate::grpgit::Invocation;
+use crate::paths;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+const JOURNAL_FILE_NAME: &str = "last-run.toml";
+
+/// How the command went in one repository of a run.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Outcome {
+    Succeeded,
+    Failed,
+    /// The run stopped, or the repository was declined, before the command
+    /// started in it.
+    NotStarted,
+}
+
+/// A repository selected for a run and how the command went in it.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct JournalEntry {
+    pub path: PathBuf,
+    pub outcome: Outcome,
+}
+
+/// The repositories selected for the last run and their outcomes, kept so
+/// `--resume` and `--retry-failed` can pick up where it left off.
+#[derive(Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Journal {
+    /// The program that was run, followed by its arguments.
+    pub command: Vec<String>,
+    /// How the command was made, to make it again for `--resume`. Missing
+    /// from journals of older versions.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub invocation: Option<Invocation>,
+    #[serde(default, rename = "repository")]
+    pub repositories: Vec<JournalEntry>,
+}
+
+/// Returns the journal location, `$XDG_STATE_HOME/grpr/last-run.toml`,
+/// falling back to `~/.local/state/grpr/last-run.toml`.
+pub fn default_journal_path() -> Option<PathBuf> {
+    paths::state_dir().map(|dir| dir.join(JOURNAL_FILE_NAME))
+}
+
+impl Journal {
+    /// Loads the journal at `path`, or `None` when no run has been recorded.
+    pub fn load(path: &Path) -> Result<Option<Self>, io::Error> {
+        let contents = match fs::read_to_string(path) {
+            Ok(contents) => contents,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(err) => return Err(err),
+        };
+
+        toml::from_str(&contents).map(Some).map_err(|err| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("invalid run journal {}: {err}", path.display()),
+            )
+        })
+    }
+
+    /// Writes the journal to `path`, creating parent directories as needed.
+    pub fn save(&self, path: &Path) -> Result<(), io::Error> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        let contents = toml::to_string(self).map_err(io::Error::other)?;
+        fs::write(path, contents)
+    }
+
+    /// Folds a resumed run into this journal. Repositories the resumed run
+    /// started take its outcome; the rest keep their earlier one.
+    pub fn update(&mut self, resumed: Journal) {
+        self.command = resumed.command;
+        self.invocation = resumed.invocation;
+        for entry in resumed.repositories {
+            match self
+                .repositories
+                .iter_mut()
+                .find(|existing| existing.path == entry.path)
+            {
+                Some(_) if entry.outcome == Outcome::NotStarted => {}
+                Some(existing) => existing.outcome = entry.outcome,
+                None => self.repositories.push(entry),
+            }
+        }
+    }
+
+    /// Returns the repositories whose outcome is one of `outcomes`, in the
+    /// order they were selected.
+    pub fn paths_with(&self, outcomes: &[Outcome]) -> Vec<PathBuf> {
+        self.repositories
+            .iter()
+            .filter(|entry| outcomes.contains(&entry.outcome))
+            .map(|entry| entry.path.clone())
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn load_returns_none_without_a_recorded_run() {
+        let dir = tempdir().unwrap();
+
+        assert_eq!(
+            Journal::load(&dir.path().join("last-run.toml")).unwrap(),
+            None
+        );
+    }
+
+    #[test]
+    fn update_keeps_earlier_outcomes_of_repositories_not_started_again() {
+        let entry = |path: &str, outcome| JournalEntry {
+            path: PathBuf::from(path),
+            outcome,
+        };
+        let mut journal = Journal {
+            command: vec!["git".to_string(), "pull".to_string()],
+            invocation: None,
+            repositories: vec![
+                entry("/src/app", Outcome::Succeeded),
+                entry("/src/lib", Outcome::Failed),
+                entry("/src/web", Outcome::Failed),
+                entry("/src/cli", Outcome::NotStarted),
+            ],
+        };
+
+        journal.update(Journal {
+            command: vec!["git".to_string(), "pull".to_string()],
+            invocation: None,
+            repositories: vec![
+                entry("/src/lib", Outcome::Succeeded),
+                entry("/src/web", Outcome::NotStarted),
+                entry("/src/cli", Outcome::Failed),
+            ],
+        });
+
+        assert_eq!(
+            journal.repositories,
+            vec![
+                entry("/src/app", Outcome::Succeeded),
+                entry("/src/lib", Outcome::Succeeded),
+                entry("/src/web", Outcome::Failed),
+                entry("/src/cli", Outcome::Failed),
+            ]
+        );
+    }
+
+    #[test]
+    fn save_and_load_round_trip_outcomes() {
+        let dir = tempdir().unwrap();
+        let journal_path = dir.path().join("state").join("last-run.toml");
+        let journal = Journal {
+            command: vec!["git".to_string(), "pull".to_string()],
+            invocation: None,
+            repositories: vec![
+                JournalEntry {
+                    path: PathBuf::from("/src/app"),
+                    outcome: Outcome::Succeeded,
+                },
+                JournalEntry {
+                    path: PathBuf::from("/src/lib"),
+                    outcome: Outcome::Failed,
+                },
+                JournalEntry {
+                    path: PathBuf::from("/src/web"),
+                    outcome: Outcome::NotStarted,
+                },
+            ],
+        };
+
+        journal.save(&journal_path).unwrap();
+        let loaded = Journal::load(&journal_path).unwrap().unwrap();
+
+        assert_eq!(loaded, journal);
+        assert!(
+            fs::read_to_string(&journal_path)
+                .unwrap()
+                .contains("outcome = \"not-started\"")
+        );
+        assert_eq!(
+            loaded.paths_with(&[Outcome::Failed, Outcome::NotStarted]),
+            vec![PathBuf::from("/src/lib"), PathBuf::from("/src/web")]
+        );
+        assert_eq!(
+            loaded.paths_with(&[Outcome::Failed]),
+            vec![PathBuf::from("/src/lib")]
+        );
+    }
+}
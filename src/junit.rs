@@ -0,0 +1,172 @@
+/*
+ * grpr - A CLI tool for recursively executing git commands.
+ *
+ * Copyright (c) 2025 Anupam Sengupta
+ *
+ * This source code is licensed under the MIT license found in the LICENSE file
+ * in the root directory of this source tree.
+ */
+
+use crate::report::{RepositoryReport, Summary};
+use std::io::{self, Write};
+use std::path::PathBuf;
+
+/// Escapes `text` for XML content and attribute values. Control characters
+/// that XML 1.0 does not allow, such as the escape sequences of colored
+/// output, are replaced.
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            '\t' | '\n' | '\r' => escaped.push(c),
+            c if c.is_control() => escaped.push(char::REPLACEMENT_CHARACTER),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Writes one test case for the repository in `report`, with its output.
+fn write_case(out: &mut impl Write, report: &RepositoryReport) -> io::Result<()> {
+    writeln!(
+        out,
+        "    <testcase name=\"{}\" classname=\"{}\" time=\"{:.3}\">",
+        escape(&report.path.to_string_lossy()),
+        escape(&report.command.join(" ")),
+        report.duration_secs
+    )?;
+    if !report.success {
+        let exit_code = report
+            .exit_code
+            .map_or_else(|| "none".to_string(), |code| code.to_string());
+        writeln!(
+            out,
+            "      <failure message=\"{}\" type=\"exit code {exit_code}\"/>",
+            escape(report.error.as_deref().unwrap_or("failed"))
+        )?;
+    }
+    if !report.stdout.is_empty() {
+        writeln!(
+            out,
+            "      <system-out>{}</system-out>",
+            escape(&report.stdout)
+        )?;
+    }
+    if !report.stderr.is_empty() {
+        writeln!(
+            out,
+            "      <system-err>{}</system-err>",
+            escape(&report.stderr)
+        )?;
+    }
+    writeln!(out, "    </testcase>")
+}
+
+/// Writes a JUnit XML report of the run, with a test case for each of the
+/// `reports`, passed or failed, and for each of the `skipped` repositories,
+/// with the reason, in one test suite for the `summary` of the run.
+pub fn write_report(
+    mut out: impl Write,
+    reports: &[RepositoryReport],
+    skipped: &[(PathBuf, String)],
+    summary: &Summary,
+) -> io::Result<()> {
+    let tests = reports.len() + skipped.len();
+    let failures = reports.iter().filter(|report| !report.success).count();
+    let counts = format!(
+        "tests=\"{tests}\" failures=\"{failures}\" errors=\"0\" skipped=\"{}\" time=\"{:.3}\"",
+        skipped.len(),
+        summary.duration_secs
+    );
+    writeln!(out, "<?xml version=\"1.0\" encoding=\"UTF-8\"?>")?;
+    writeln!(out, "<testsuites name=\"grpr\" {counts}>")?;
+    writeln!(
+        out,
+        "  <testsuite name=\"grpr\" id=\"{}\" {counts}>",
+        escape(&summary.run_id)
+    )?;
+    for report in reports {
+        write_case(&mut out, report)?;
+    }
+    for (repo_path, reason) in skipped {
+        writeln!(
+            out,
+            "    <testcase name=\"{}\" classname=\"grpr\" time=\"0\">",
+            escape(&repo_path.to_string_lossy())
+        )?;
+        writeln!(out, "      <skipped message=\"{}\"/>", escape(reason))?;
+        writeln!(out, "    </testcase>")?;
+    }
+    writeln!(out, "  </testsuite>")?;
+    writeln!(out, "</testsuites>")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn escape_replaces_markup_and_control_characters() {
+        assert_eq!(
+            escape("<a x=\"1\">&'\x1b[31m\n"),
+            "&lt;a x=&quot;1&quot;&gt;&amp;&apos;\u{FFFD}[31m\n"
+        );
+    }
+
+    #[test]
+    fn report_has_a_test_case_per_repository() {
+        let reports = [
+            RepositoryReport {
+                path: PathBuf::from("/src/app"),
+                command: vec!["git".to_string(), "fetch".to_string()],
+                exit_code: Some(0),
+                success: true,
+                duration_secs: 1.5,
+                stdout: "fetched\n".to_string(),
+                stderr: String::new(),
+                error: None,
+            },
+            RepositoryReport {
+                path: PathBuf::from("/src/api"),
+                command: vec!["git".to_string(), "fetch".to_string()],
+                exit_code: Some(128),
+                success: false,
+                duration_secs: 0.25,
+                stdout: String::new(),
+                stderr: "fatal: <remote> hung up\n".to_string(),
+                error: Some("git command failed".to_string()),
+            },
+        ];
+        let skipped = [(PathBuf::from("/src/web"), "dirty".to_string())];
+        let summary = Summary {
+            run_id: "6710e2bf-1f3a-9c04e1d2".to_string(),
+            duration_secs: 2.0,
+            ..Summary::default()
+        };
+
+        let mut out = Vec::new();
+        write_report(&mut out, &reports, &skipped, &summary).unwrap();
+        let xml = String::from_utf8(out).unwrap();
+
+        assert!(xml.starts_with("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n"));
+        assert!(xml.contains(
+            "<testsuite name=\"grpr\" id=\"6710e2bf-1f3a-9c04e1d2\" tests=\"3\" failures=\"1\" \
+             errors=\"0\" skipped=\"1\" time=\"2.000\">"
+        ));
+        assert!(
+            xml.contains("<testcase name=\"/src/app\" classname=\"git fetch\" time=\"1.500\">")
+        );
+        assert!(xml.contains("<system-out>fetched\n</system-out>"));
+        assert!(xml.contains(
+            "<failure message=\"git command failed\" type=\"exit code 128\"/>\n      \
+             <system-err>fatal: &lt;remote&gt; hung up\n</system-err>"
+        ));
+        assert!(xml.contains("<skipped message=\"dirty\"/>"));
+        assert!(xml.ends_with("</testsuites>\n"));
+    }
+}
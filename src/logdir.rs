@@ -0,0 +1,217 @@
+/*
+ * grpr - A CLI tool for recursively executing git commands.
+ *
+ * Copyright (c) 2025 Anupam Sengupta
+ *
+ * This source code is licensed under the MIT license found in the LICENSE file
+ * in the root directory of this source tree.
+ */
+
+use crate::grpgit::{Captured, RepoCommand};
+use crate::report::Summary;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// The name of the file listing the logs of a run.
+pub const MANIFEST_FILE: &str = "manifest.json";
+
+/// One repository's entry in the manifest.
+#[derive(Debug, Serialize)]
+pub struct LogEntry {
+    pub path: PathBuf,
+    /// The log file, relative to the log directory.
+    pub log: String,
+    pub command: Vec<String>,
+    pub exit_code: Option<i32>,
+    pub success: bool,
+    pub duration_secs: f64,
+    pub error: Option<String>,
+}
+
+/// The manifest of a run, written once it is over.
+#[derive(Debug, Serialize)]
+struct Manifest<'a> {
+    repositories: &'a [LogEntry],
+    summary: Summary,
+}
+
+/// A directory holding the captured output of each repository in a run, in
+/// `<repo-name>.log`, and a manifest of the run.
+#[derive(Debug)]
+pub struct LogDir {
+    dir: PathBuf,
+    /// How many repositories of each name have a log, so that repositories
+    /// sharing a name get `<name>-2.log` and so on.
+    names: Mutex<HashMap<String, usize>>,
+    entries: Mutex<Vec<LogEntry>>,
+}
+
+impl LogDir {
+    /// Creates `dir`, and any missing parents, to hold the logs.
+    pub fn create(dir: &Path) -> io::Result<Self> {
+        fs::create_dir_all(dir)?;
+        Ok(Self {
+            dir: dir.to_path_buf(),
+            names: Mutex::new(HashMap::new()),
+            entries: Mutex::new(Vec::new()),
+        })
+    }
+
+    /// Returns a log file name for `repo_path` that no other repository in
+    /// the run has.
+    fn file_name(&self, repo_path: &Path) -> String {
+        let name = repo_path
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_else(|| "repository".to_string());
+        let mut names = self.names.lock().unwrap_or_else(|err| err.into_inner());
+        let count = names.entry(name.clone()).or_default();
+        *count += 1;
+        match *count {
+            1 => format!("{name}.log"),
+            count => format!("{name}-{count}.log"),
+        }
+    }
+
+    /// Writes the captured run of `command` in `repo_path` to its log file
+    /// and adds it to the manifest.
+    pub fn write(
+        &self,
+        repo_path: &Path,
+        command: &RepoCommand,
+        captured: &Captured,
+        elapsed: Duration,
+    ) -> io::Result<()> {
+        let log = self.file_name(repo_path);
+        let mut file = File::create(self.dir.join(&log))?;
+        writeln!(file, "repository: {}", repo_path.display())?;
+        writeln!(file, "command: {}", command.command_line())?;
+        match captured.exit_code {
+            Some(code) => writeln!(file, "exit code: {code}")?,
+            None => writeln!(file, "exit code: none")?,
+        }
+        if let Err(err) = &captured.result {
+            writeln!(file, "error: {err}")?;
+        }
+        writeln!(file, "duration: {:.2}s", elapsed.as_secs_f64())?;
+        writeln!(file, "\n[stdout]")?;
+        file.write_all(&captured.stdout)?;
+        writeln!(file, "\n[stderr]")?;
+        file.write_all(&captured.stderr)?;
+
+        self.entries
+            .lock()
+            .unwrap_or_else(|err| err.into_inner())
+            .push(LogEntry {
+                path: repo_path.to_path_buf(),
+                log,
+                command: command.words(),
+                exit_code: captured.exit_code,
+                success: captured.result.is_ok(),
+                duration_secs: elapsed.as_secs_f64(),
+                error: captured.result.as_ref().err().map(ToString::to_string),
+            });
+        Ok(())
+    }
+
+    /// Writes the manifest, listing each repository's log in the order they
+    /// finished and the `summary` of the run. Returns its path.
+    pub fn write_manifest(&self, summary: Summary) -> io::Result<PathBuf> {
+        let entries = self.entries.lock().unwrap_or_else(|err| err.into_inner());
+        let manifest = Manifest {
+            repositories: &entries,
+            summary,
+        };
+        let manifest_path = self.dir.join(MANIFEST_FILE);
+        let mut file = File::create(&manifest_path)?;
+        serde_json::to_writer_pretty(&mut file, &manifest)?;
+        writeln!(file)?;
+        Ok(manifest_path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    fn captured(stdout: &str, failed: bool) -> Captured {
+        Captured {
+            stdout: stdout.as_bytes().to_vec(),
+            stderr: b"warning\n".to_vec(),
+            exit_code: Some(i32::from(failed)),
+            result: if failed {
+                Err(io::Error::other("git command failed"))
+            } else {
+                Ok(())
+            },
+            spilled: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn logs_hold_each_repositorys_output_under_a_unique_name() {
+        let dir = tempdir().unwrap();
+        let logs = LogDir::create(&dir.path().join("logs")).unwrap();
+        let command = RepoCommand::git(vec!["fetch".to_string()]);
+
+        logs.write(
+            Path::new("/src/app"),
+            &command,
+            &captured("fetched\n", false),
+            Duration::from_millis(1500),
+        )
+        .unwrap();
+        logs.write(
+            Path::new("/vendor/app"),
+            &command,
+            &captured("", true),
+            Duration::ZERO,
+        )
+        .unwrap();
+
+        let log = fs::read_to_string(dir.path().join("logs/app.log")).unwrap();
+        assert!(log.starts_with("repository: /src/app\ncommand: git fetch\nexit code: 0\n"));
+        assert!(log.contains("duration: 1.50s\n"));
+        assert!(log.contains("[stdout]\nfetched\n"));
+        assert!(log.contains("[stderr]\nwarning\n"));
+        let log = fs::read_to_string(dir.path().join("logs/app-2.log")).unwrap();
+        assert!(log.contains("error: git command failed\n"));
+    }
+
+    #[test]
+    fn manifest_lists_the_logs_and_the_summary() {
+        let dir = tempdir().unwrap();
+        let logs = LogDir::create(dir.path()).unwrap();
+        let command = RepoCommand::git(vec!["fetch".to_string()]);
+        logs.write(
+            Path::new("/src/app"),
+            &command,
+            &captured("", true),
+            Duration::ZERO,
+        )
+        .unwrap();
+
+        let manifest_path = logs
+            .write_manifest(Summary {
+                repositories: 1,
+                failed: 1,
+                ..Summary::default()
+            })
+            .unwrap();
+
+        let json: serde_json::Value =
+            serde_json::from_str(&fs::read_to_string(manifest_path).unwrap()).unwrap();
+        let repository = &json["repositories"][0];
+        assert_eq!(repository["path"], "/src/app");
+        assert_eq!(repository["log"], "app.log");
+        assert_eq!(repository["exit_code"], 1);
+        assert_eq!(repository["success"], false);
+        assert_eq!(json["summary"]["failed"], 1);
+    }
+}
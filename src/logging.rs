@@ -0,0 +1,165 @@
+/*
+ * grpr - A CLI tool for recursively executing git commands.
+ *
+ * Copyright (c) 2025 Anupam Sengupta
+ *
+ * This source code is licensed under the MIT license found in the LICENSE file
+ * in the root directory of this source tree.
+ */
+
+use clap::ValueEnum;
+use std::error::Error;
+use std::fmt;
+use std::fs::OpenOptions;
+use std::io;
+use std::path::Path;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicBool, Ordering};
+use tracing::{Event, Level, Subscriber};
+use tracing_subscriber::filter::{EnvFilter, LevelFilter};
+use tracing_subscriber::fmt::format::Writer;
+use tracing_subscriber::fmt::{FmtContext, FormatEvent, FormatFields};
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::registry::LookupSpan;
+use tracing_subscriber::util::SubscriberInitExt;
+
+/// How much grpr reports about what it is doing, from `--log-level`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum LogLevel {
+    Error,
+    Warn,
+    /// Notes about the run, such as repositories skipped. The default.
+    Info,
+    /// Also what discovery finds and each command started and finished.
+    Debug,
+    /// Also every directory discovery looks at.
+    Trace,
+}
+
+impl From<LogLevel> for LevelFilter {
+    fn from(level: LogLevel) -> Self {
+        match level {
+            LogLevel::Error => LevelFilter::ERROR,
+            LogLevel::Warn => LevelFilter::WARN,
+            LogLevel::Info => LevelFilter::INFO,
+            LogLevel::Debug => LevelFilter::DEBUG,
+            LogLevel::Trace => LevelFilter::TRACE,
+        }
+    }
+}
+
+/// Set while the `--tui` dashboard has the terminal, which events on stderr
+/// would draw over.
+static STDERR_HELD: AtomicBool = AtomicBool::new(false);
+
+/// Keeps events off stderr while `held`, still writing them to the log
+/// file.
+pub fn hold_stderr(held: bool) {
+    STDERR_HELD.store(held, Ordering::Relaxed);
+}
+
+/// Returns where an event on stderr goes: nowhere while it is held.
+fn stderr_writer() -> Box<dyn io::Write> {
+    if STDERR_HELD.load(Ordering::Relaxed) {
+        Box::new(io::sink())
+    } else {
+        Box::new(io::stderr())
+    }
+}
+
+/// Formats events on stderr as grpr's notes have always looked, as
+/// `grpr: message`, naming the level of anything but notes.
+struct NoteFormat;
+
+/// Returns the word that introduces an event at `level` on stderr.
+fn level_label(level: Level) -> Option<&'static str> {
+    match level {
+        Level::INFO => None,
+        Level::ERROR => Some("error"),
+        Level::WARN => Some("warning"),
+        Level::DEBUG => Some("debug"),
+        Level::TRACE => Some("trace"),
+    }
+}
+
+impl<S, N> FormatEvent<S, N> for NoteFormat
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+    N: for<'a> FormatFields<'a> + 'static,
+{
+    fn format_event(
+        &self,
+        ctx: &FmtContext<'_, S, N>,
+        mut writer: Writer<'_>,
+        event: &Event<'_>,
+    ) -> fmt::Result {
+        if let Some(timestamp) = crate::output::timestamp() {
+            write!(writer, "{timestamp}")?;
+        }
+        write!(writer, "grpr: ")?;
+        if let Some(label) = level_label(*event.metadata().level()) {
+            write!(writer, "{label}: ")?;
+        }
+        ctx.field_format().format_fields(writer.by_ref(), event)?;
+        writeln!(writer)
+    }
+}
+
+/// Returns the filter for the run: `level` when given, otherwise the
+/// `RUST_LOG` environment variable, otherwise notes and above.
+fn filter(level: Option<LogLevel>) -> EnvFilter {
+    match level {
+        Some(level) => EnvFilter::default().add_directive(LevelFilter::from(level).into()),
+        None => EnvFilter::builder()
+            .with_default_directive(LevelFilter::INFO.into())
+            .from_env_lossy(),
+    }
+}
+
+/// Starts reporting grpr's events on stderr at `level`, and also to the end
+/// of `log_file`, with timestamps, when given.
+pub fn init(level: Option<LogLevel>, log_file: Option<&Path>) -> Result<(), Box<dyn Error>> {
+    let file_layer = match log_file {
+        Some(path) => {
+            let file = OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(path)
+                .map_err(|err| format!("cannot open the log file {}: {err}", path.display()))?;
+            Some(
+                tracing_subscriber::fmt::layer()
+                    .with_ansi(false)
+                    .with_writer(Mutex::new(file)),
+            )
+        }
+        None => None,
+    };
+    tracing_subscriber::registry()
+        .with(filter(level))
+        .with(
+            tracing_subscriber::fmt::layer()
+                .event_format(NoteFormat)
+                .with_writer(stderr_writer),
+        )
+        .with(file_layer)
+        .try_init()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_notes_go_without_a_label() {
+        assert_eq!(level_label(Level::INFO), None);
+        assert_eq!(level_label(Level::WARN), Some("warning"));
+        assert_eq!(level_label(Level::DEBUG), Some("debug"));
+    }
+
+    #[test]
+    fn log_level_flag_takes_precedence() {
+        assert_eq!(filter(Some(LogLevel::Debug)).to_string(), "debug");
+        assert_eq!(filter(Some(LogLevel::Error)).to_string(), "error");
+    }
+}
@@ -7,169 +7,5812 @@
  * LICENSE file in the root directory of this source tree.
  */
 
-use clap::Parser;
+use cache::DiscoveryCache;
+use clap::{Parser, Subcommand, ValueEnum};
+use config::Config;
+use confirm::{Confirmation, Decision};
+use dashboard::Dashboard;
+use dependency::Dependencies;
+use diffstat::DiffStat;
+use globset::Glob;
+use journal::{Journal, JournalEntry, Outcome};
+use logdir::LogDir;
+use logging::LogLevel;
+use output::ColorChoice;
+use priority::{IoClass, Priority};
+use progress::{Progress, ProgressMode};
 use rayon::prelude::*;
+use regex::Regex;
+use registry::{Recorded, RegisteredRepository, Registry};
+use report::{
+    OutputFormat, PorcelainVersion, ReportTarget, RepositoryReport, RunReport, Summary,
+    SummaryLine, TableRow,
+};
+use schedule::{HostPermit, HostSlots};
+use selector::{Criterion, Selector};
+use semaphore::{Permit, Semaphore};
+use std::cmp::{self, Reverse};
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::env;
 use std::error::Error;
+use std::ffi::OsString;
+use std::fmt;
+use std::fs;
+use std::io;
 use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::{Duration, Instant, SystemTime};
+use table::TableEntry;
+use task::Task;
+use throttle::Throttle;
 
+mod cache;
+mod config;
+mod confirm;
+mod dashboard;
+mod dependency;
+mod diffstat;
+mod duration;
+mod expr;
 mod grpgit;
+mod html;
+mod interrupt;
+mod journal;
+mod junit;
+mod logdir;
+mod logging;
+mod manifest;
+mod network;
+mod output;
+mod pager;
+mod paths;
+mod pick;
+mod pipeline;
+mod priority;
+mod progress;
+mod registry;
+mod report;
+mod schedule;
+mod selector;
+mod semaphore;
+mod size;
+mod table;
+mod task;
+#[cfg(test)]
+pub(crate) mod test_support;
+mod throttle;
+mod watch;
+
+const VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/// CLI represents the command-line arguments for grpr.
+#[derive(Parser, Debug)]
+#[command(
+    author,
+    version = VERSION,
+    about,
+    long_about = None,
+    allow_external_subcommands = true,
+    args_override_self = true
+)]
+struct Cli {
+    /// The number of threads to use for concurrent processing. When omitted,
+    /// grpr processes repositories sequentially for predictable output and
+    /// compatibility with grp.
+    #[arg(
+        short,
+        long,
+        help = "Opt in to parallel execution with the given number of worker threads"
+    )]
+    threads: Option<usize>,
+
+    /// Process repositories strictly one at a time, in path order unless
+    /// `--sort` says otherwise, with git attached directly to the terminal so
+    /// interactive commands such as `rebase -i` or `add -p` work.
+    #[arg(
+        long,
+        conflicts_with = "threads",
+        help = "Process repositories one at a time in a deterministic order"
+    )]
+    serial: bool,
+
+    /// Run each repository only after the repositories it depends on, as
+    /// declared in the `[dependencies]` table of the config file, have
+    /// succeeded. Independent repositories still run in parallel.
+    #[arg(
+        long,
+        help = "Honor the ordering constraints between repositories in the config file"
+    )]
+    dependency_order: bool,
+
+    /// The most repositories to run a network git command such as `fetch`,
+    /// `pull`, or `push` in at once, so a shared server is not flooded while
+    /// `--threads` stays high for local commands.
+    #[arg(
+        long,
+        value_name = "N",
+        value_parser = clap::value_parser!(u16).range(1..),
+        help = "Run network git commands in at most N repositories at once"
+    )]
+    net_jobs: Option<u16>,
+
+    /// The most repositories on one remote host, taken from the `origin`
+    /// URL, to run a network git command in at once. Repositories are started
+    /// taking each host in turn, so the load is spread across hosts.
+    #[arg(
+        long,
+        value_name = "N",
+        value_parser = clap::value_parser!(u16).range(1..),
+        help = "Run network git commands in at most N repositories per remote host at once"
+    )]
+    per_host: Option<u16>,
+
+    /// The most commands to start per second, however many threads are
+    /// running, for servers whose hooks or APIs enforce quotas. Fractions
+    /// such as `0.5` start one command every two seconds.
+    #[arg(
+        long,
+        value_name = "OPS_PER_SEC",
+        value_parser = throttle::parse_rate,
+        help = "Start at most OPS_PER_SEC commands per second"
+    )]
+    throttle: Option<f64>,
+
+    /// The number of threads used to search for repositories. Discovery
+    /// results are reported in the same order whatever the thread count.
+    #[arg(
+        global = true,
+        long,
+        value_name = "N",
+        default_value_t = 0,
+        help = "Search for repositories with N threads (0 uses one per CPU)"
+    )]
+    scan_threads: usize,
+
+    /// Walk the tree depth-first (the default) or breadth-first. Repositories
+    /// are processed in walk order unless `--sort` is given, so `bfs` with
+    /// `--limit` picks the repositories closest to the roots.
+    #[arg(
+        global = true,
+        long,
+        value_enum,
+        value_name = "ORDER",
+        default_value = "dfs",
+        help = "Search the tree depth-first or breadth-first"
+    )]
+    traversal: grpgit::Traversal,
+
+    /// The maximum number of directory levels below the current directory to
+    /// search for repositories. When omitted, the entire tree is searched.
+    #[arg(
+        global = true,
+        long,
+        value_name = "N",
+        help = "Limit repository discovery to N directory levels below the current directory"
+    )]
+    max_depth: Option<usize>,
+
+    /// Glob patterns for directories that should never be descended into,
+    /// such as `node_modules` or `target`. May be given multiple times.
+    #[arg(
+        global = true,
+        long,
+        value_name = "GLOB",
+        value_parser = Glob::new,
+        help = "Skip directories matching GLOB during discovery (repeatable)"
+    )]
+    exclude: Vec<Glob>,
+
+    /// Search hidden directories (names starting with `.`), which are skipped
+    /// by default.
+    #[arg(
+        global = true,
+        long,
+        help = "Search hidden directories during discovery"
+    )]
+    hidden: bool,
+
+    /// Ignore `.gitignore` and `.ignore` files, which are honored by default
+    /// to keep discovery out of ignored trees.
+    #[arg(
+        global = true,
+        long,
+        help = "Do not honor .gitignore and .ignore files during discovery"
+    )]
+    no_ignore: bool,
+
+    /// What to do with repositories nested inside other repositories, such as
+    /// vendored checkouts. By default the search stops at the outermost
+    /// repository (`skip`). `--nested` alone means `include`; `only`
+    /// processes just the nested repositories.
+    #[arg(
+        global = true,
+        long,
+        value_enum,
+        value_name = "POLICY",
+        num_args = 0..=1,
+        require_equals = true,
+        default_value = "skip",
+        default_missing_value = "include",
+        help = "Skip, include, or only process repositories nested inside other repositories"
+    )]
+    nested: grpgit::NestedPolicy,
+
+    /// Skip linked worktrees, whose `.git` file points into another
+    /// repository's `worktrees` directory.
+    #[arg(global = true, long, help = "Do not process linked git worktrees")]
+    skip_worktrees: bool,
+
+    /// Skip submodule checkouts, whose `.git` file points into another
+    /// repository's `modules` directory.
+    #[arg(global = true, long, help = "Do not process git submodule checkouts")]
+    skip_submodules: bool,
+
+    /// Also process bare repositories, recognized by a `HEAD` file alongside
+    /// `objects` and `refs` directories.
+    #[arg(
+        global = true,
+        long,
+        help = "Also process bare repositories such as mirrors"
+    )]
+    bare: bool,
+
+    /// Follow symbolic links to directories while searching. Repositories
+    /// reachable through several links are processed only once.
+    #[arg(global = true, long, help = "Follow symbolic links during discovery")]
+    follow_symlinks: bool,
+
+    /// Stay on the filesystem of each root, like `du -x`, so network mounts,
+    /// external drives, and snapshot directories are never scanned.
+    #[arg(
+        global = true,
+        short = 'x',
+        long,
+        help = "Do not cross filesystem boundaries during discovery"
+    )]
+    one_file_system: bool,
+
+    /// Directories to scan for repositories. May be given multiple times to
+    /// process several disjoint trees in one run. Defaults to the current
+    /// directory.
+    #[arg(
+        global = true,
+        short = 'C',
+        long = "root",
+        value_name = "PATH",
+        help = "Scan PATH instead of the current directory (repeatable)"
+    )]
+    roots: Vec<PathBuf>,
+
+    /// Scan the roots configured under NAME in the `[roots]` table of the
+    /// config file. May be given multiple times, and combined with `--root`.
+    #[arg(
+        global = true,
+        short = 'w',
+        long,
+        value_name = "NAME",
+        help = "Scan the roots configured for workspace NAME (repeatable)"
+    )]
+    workspace: Vec<String>,
+
+    /// Apply the flags of profile NAME from the `[profiles]` table of the
+    /// config file, as if given first on the command line. Flags given on
+    /// the command line override those of the profile.
+    #[arg(
+        long,
+        value_name = "NAME",
+        help = "Apply the flags of profile NAME from the config file"
+    )]
+    profile: Option<String>,
+
+    /// Stop discovery after SECS seconds and run the command in the
+    /// repositories found so far. Directories that were not fully scanned are
+    /// reported.
+    #[arg(
+        global = true,
+        long,
+        value_name = "SECS",
+        help = "Stop discovery after SECS seconds and use what was found"
+    )]
+    scan_timeout: Option<u64>,
+
+    /// Fail the run when any directory cannot be read during discovery. Such
+    /// directories are always listed after the command has run.
+    #[arg(
+        global = true,
+        long,
+        help = "Exit with an error if any directory could not be scanned"
+    )]
+    strict_scan: bool,
+
+    /// Skip repositories whose `origin` remote URL contains PATTERN, such as
+    /// third-party mirrors. May be given multiple times.
+    #[arg(
+        global = true,
+        long,
+        value_name = "PATTERN",
+        help = "Skip repositories whose origin URL contains PATTERN (repeatable)"
+    )]
+    exclude_remote: Vec<String>,
+
+    /// Only process repositories with a remote, under any name, whose URL
+    /// contains PATTERN, such as `github.com/myorg/`. May be given multiple
+    /// times; a repository matching any of them is kept.
+    #[arg(
+        global = true,
+        long,
+        value_name = "PATTERN",
+        help = "Only process repositories with a remote URL containing PATTERN (repeatable)"
+    )]
+    remote_matches: Vec<String>,
+
+    /// Only process local-only repositories, which have no remotes
+    /// configured.
+    #[arg(
+        global = true,
+        long,
+        conflicts_with = "has_remote",
+        help = "Only process repositories without any remote"
+    )]
+    no_remote: bool,
+
+    /// Only process repositories with a remote called NAME, such as
+    /// `upstream`, so bulk fetches and pushes skip repositories that would
+    /// fail. May be given multiple times; a repository with any of them is
+    /// kept.
+    #[arg(
+        global = true,
+        long,
+        value_name = "NAME",
+        help = "Only process repositories with a remote named NAME (repeatable)"
+    )]
+    has_remote: Vec<String>,
+
+    /// Skip repositories whose `HEAD` and index have not changed within
+    /// DURATION, such as `30d` or `2w`, so routine runs leave archived
+    /// projects alone.
+    #[arg(
+        global = true,
+        long,
+        value_name = "DURATION",
+        value_parser = duration::parse_duration,
+        help = "Skip repositories not used within DURATION (e.g. 12h, 30d, 2w)"
+    )]
+    active_within: Option<Duration>,
+
+    /// Only process repositories whose `HEAD` commit was made at or after
+    /// WHEN, given as a `YYYY-MM-DD` date (midnight UTC) or a duration before
+    /// now such as `30d`.
+    #[arg(
+        global = true,
+        long,
+        value_name = "WHEN",
+        value_parser = duration::parse_time,
+        help = "Only process repositories with a HEAD commit since WHEN (date or duration)"
+    )]
+    committed_since: Option<SystemTime>,
+
+    /// Only process repositories whose `HEAD` commit was made before WHEN,
+    /// such as stale projects to archive.
+    #[arg(
+        global = true,
+        long,
+        value_name = "WHEN",
+        value_parser = duration::parse_time,
+        help = "Only process repositories with a HEAD commit before WHEN (date or duration)"
+    )]
+    committed_before: Option<SystemTime>,
+
+    /// Only process repositories with staged, unstaged, or untracked changes.
+    #[arg(
+        global = true,
+        long,
+        conflicts_with = "clean",
+        help = "Only process repositories with uncommitted changes"
+    )]
+    dirty: bool,
+
+    /// Only process repositories with changes in the index that are not
+    /// committed yet.
+    #[arg(
+        global = true,
+        long,
+        conflicts_with = "clean",
+        help = "Only process repositories with staged changes"
+    )]
+    staged: bool,
+
+    /// Only process repositories with changes to tracked files that are not
+    /// staged.
+    #[arg(
+        global = true,
+        long,
+        conflicts_with = "clean",
+        help = "Only process repositories with unstaged changes"
+    )]
+    unstaged: bool,
+
+    /// Only process repositories with files git neither tracks nor ignores.
+    #[arg(
+        global = true,
+        long,
+        conflicts_with = "clean",
+        help = "Only process repositories with untracked files"
+    )]
+    untracked: bool,
+
+    /// Only process repositories whose working tree has no changes.
+    #[arg(
+        global = true,
+        long,
+        help = "Only process repositories without uncommitted changes"
+    )]
+    clean: bool,
+
+    /// Only process repositories whose path, relative to the root it was
+    /// found under, matches REGEX. Paths use `/` separators, so
+    /// `^clients/[^/]+/backend` selects every client's backend. May be given
+    /// multiple times; a repository matching any of them is kept.
+    #[arg(
+        global = true,
+        long = "match",
+        value_name = "REGEX",
+        help = "Only process repositories whose relative path matches REGEX (repeatable)"
+    )]
+    match_path: Vec<Regex>,
+
+    /// Skip repositories whose path, relative to the root it was found under,
+    /// matches REGEX. May be given multiple times.
+    #[arg(
+        global = true,
+        long = "no-match",
+        value_name = "REGEX",
+        help = "Skip repositories whose relative path matches REGEX (repeatable)"
+    )]
+    no_match_path: Vec<Regex>,
+
+    /// Skip repositories on a detached `HEAD`, such as checkouts pinned to a
+    /// tag or commit, so branch operations do not fail on them.
+    #[arg(
+        global = true,
+        long,
+        conflicts_with = "only_detached",
+        help = "Skip repositories with a detached HEAD"
+    )]
+    skip_detached: bool,
+
+    /// Only process repositories on a detached `HEAD`.
+    #[arg(
+        global = true,
+        long,
+        help = "Only process repositories with a detached HEAD"
+    )]
+    only_detached: bool,
+
+    /// Only process repositories with stashed changes, such as work left
+    /// behind with `git stash`.
+    #[arg(
+        global = true,
+        long,
+        help = "Only process repositories with stashed changes"
+    )]
+    has_stash: bool,
+
+    /// Only process repositories whose current branch tracks an upstream
+    /// branch that has been deleted and pruned, so they can be switched back
+    /// to the default branch. Run `git fetch --prune` first to notice
+    /// recent deletions.
+    #[arg(
+        global = true,
+        long,
+        help = "Only process repositories whose branch's upstream is gone"
+    )]
+    upstream_gone: bool,
+
+    /// Only process repositories whose current branch has commits its
+    /// upstream lacks, and none the other way. Combines with `--behind` and
+    /// `--diverged` to select any of the given states.
+    #[arg(
+        global = true,
+        long,
+        help = "Only process repositories ahead of their upstream"
+    )]
+    ahead: bool,
+
+    /// Only process repositories whose current branch lacks commits its
+    /// upstream has, and has none the upstream lacks.
+    #[arg(
+        global = true,
+        long,
+        help = "Only process repositories behind their upstream"
+    )]
+    behind: bool,
+
+    /// Only process repositories whose current branch and upstream each have
+    /// commits the other lacks.
+    #[arg(
+        global = true,
+        long,
+        help = "Only process repositories diverged from their upstream"
+    )]
+    diverged: bool,
+
+    /// Only process repositories that declare submodules in `.gitmodules`.
+    #[arg(
+        global = true,
+        long,
+        conflicts_with = "no_submodules",
+        help = "Only process repositories with submodules"
+    )]
+    has_submodules: bool,
+
+    /// Only process repositories without a `.gitmodules` file.
+    #[arg(
+        global = true,
+        long,
+        help = "Only process repositories without submodules"
+    )]
+    no_submodules: bool,
+
+    /// Only process repositories whose git directory holds at least SIZE,
+    /// such as `500M` or `2G`, to target heavyweight maintenance.
+    #[arg(
+        global = true,
+        long,
+        value_name = "SIZE",
+        value_parser = size::parse_size,
+        help = "Only process repositories whose .git is at least SIZE (e.g. 500M, 2G)"
+    )]
+    min_size: Option<u64>,
+
+    /// Only process repositories whose git directory holds at most SIZE.
+    #[arg(
+        global = true,
+        long,
+        value_name = "SIZE",
+        value_parser = size::parse_size,
+        help = "Only process repositories whose .git is at most SIZE"
+    )]
+    max_size: Option<u64>,
+
+    /// Only process repositories whose `origin/HEAD` points at the branch
+    /// NAME, such as those still defaulting to `master`. Repositories without
+    /// a recorded `origin/HEAD` are skipped; `git remote set-head origin
+    /// --auto` records it. May be given multiple times.
+    #[arg(
+        global = true,
+        long,
+        value_name = "NAME",
+        help = "Only process repositories whose default branch is NAME (repeatable)"
+    )]
+    default_branch: Vec<String>,
+
+    /// Only process repositories where the git config KEY has VALUE, such
+    /// as `user.email=me@work.com`. Global and system settings count. May be
+    /// given multiple times; a repository matching any of them is kept.
+    #[arg(
+        global = true,
+        long,
+        value_name = "KEY=VALUE",
+        value_parser = selector::parse_config_setting,
+        help = "Only process repositories whose git config KEY equals VALUE (repeatable)"
+    )]
+    config_equals: Vec<(String, String)>,
+
+    /// Only process repositories for which EXPR holds, such as
+    /// `dirty && branch == "main"`. Expressions test the attributes
+    /// `branch`, `dirty`, `detached`, `ahead`, `behind`, `remote`, `path`,
+    /// and `tags` with `==`, `!=`, `<`, `<=`, `>`, `>=`, `=~` (regex), and
+    /// `in`, combined with `!`, `&&`, `||`, and parentheses.
+    #[arg(
+        global = true,
+        long = "where",
+        value_name = "EXPR",
+        value_parser = expr::parse,
+        help = "Only process repositories matching EXPR, e.g. 'dirty && branch == \"main\"'"
+    )]
+    where_expr: Option<expr::Expr>,
+
+    /// How the selection filters above combine. With `all`, a repository
+    /// must pass every filter; with `any`, one is enough. Repeating a filter,
+    /// as in `--match a --match b`, always selects repositories matching any
+    /// of its values, while repeated exclusions skip repositories matching
+    /// any of theirs.
+    #[arg(
+        global = true,
+        long,
+        value_enum,
+        value_name = "MODE",
+        default_value = "all",
+        help = "Select repositories passing all filters or any filter"
+    )]
+    combine: selector::Combine,
+
+    /// Process the repositories the selection filters reject instead of
+    /// those they select. Discovery options such as `--exclude` still apply.
+    #[arg(
+        global = true,
+        long,
+        help = "Process the repositories the filters do not select"
+    )]
+    invert_selection: bool,
+
+    /// Sort the repositories before processing them. Parallel runs still
+    /// present each repository's output in the sorted order.
+    #[arg(
+        global = true,
+        long,
+        value_enum,
+        value_name = "ORDER",
+        help = "Process repositories in a deterministic order"
+    )]
+    sort: Option<RepositoryOrder>,
+
+    /// Skip the first N selected repositories, after sorting. Combine with
+    /// `--limit` to process a large tree in batches.
+    #[arg(
+        global = true,
+        long,
+        value_name = "N",
+        default_value_t = 0,
+        help = "Skip the first N selected repositories"
+    )]
+    skip: usize,
+
+    /// Process at most N selected repositories, after sorting and `--skip`.
+    #[arg(
+        global = true,
+        long,
+        value_name = "N",
+        help = "Process at most N selected repositories"
+    )]
+    limit: Option<usize>,
+
+    /// After selection, sorting, and batching, choose the repositories to
+    /// process from a fuzzy-searchable list on the terminal.
+    #[arg(
+        long,
+        conflicts_with = "stdin",
+        help = "Pick the repositories to process from an interactive list"
+    )]
+    pick: bool,
+
+    /// After the initial run, keep watching the repositories and re-run the
+    /// command in each repository whose working tree, index, HEAD, or refs
+    /// change.
+    #[arg(long, help = "Re-run the command in repositories as they change")]
+    watch: bool,
+
+    /// Print the git command line that would run in each selected
+    /// repository, quoted for a shell, without running it.
+    #[arg(
+        long,
+        conflicts_with = "watch",
+        help = "Show the git command for each repository without running it"
+    )]
+    dry_run: bool,
+
+    /// Stop starting new repositories after the first failure. Commands
+    /// already running are left to finish, and the exit status is non-zero.
+    #[arg(
+        long,
+        overrides_with = "keep_going",
+        help = "Stop after the first repository whose command fails"
+    )]
+    fail_fast: bool,
+
+    /// Process every repository even when the command fails in some, then
+    /// exit with a non-zero status. This is the default.
+    #[arg(
+        long,
+        overrides_with = "fail_fast",
+        help = "Process every repository despite failures (default)"
+    )]
+    keep_going: bool,
+
+    /// Retry a failed command up to N more times in the same repository,
+    /// waiting longer before each retry, so transient network failures
+    /// during `fetch`, `pull`, or `push` do not fail the run.
+    #[arg(
+        long,
+        value_name = "N",
+        default_value_t = 0,
+        help = "Retry a failed command up to N times per repository"
+    )]
+    retries: u32,
+
+    /// The pause before the first retry, such as `5s` or `1m`, or a number
+    /// of seconds. Each further retry waits twice as long as the one before.
+    #[arg(
+        long,
+        value_name = "DURATION",
+        default_value = "1s",
+        value_parser = duration::parse_delay,
+        help = "Wait DURATION before the first retry, doubling each time"
+    )]
+    retry_delay: Duration,
+
+    /// Ask before running the command in each repository: `y` runs it, `n`
+    /// (the default) skips the repository, `a` runs it here and everywhere
+    /// left, and `q` stops. Repositories are processed one at a time.
+    #[arg(
+        long,
+        conflicts_with_all = ["stdin", "watch"],
+        help = "Ask before running the command in each repository"
+    )]
+    confirm: bool,
+
+    /// Check each repository just before running the command in it, and skip
+    /// it if it has staged, unstaged, or untracked changes, so commands such
+    /// as `rebase` or `checkout` never touch work in progress.
+    #[arg(
+        long,
+        conflicts_with = "dirty",
+        help = "Skip repositories with local modifications instead of running the command"
+    )]
+    require_clean: bool,
+
+    /// Stash local changes, including untracked files, in each dirty
+    /// repository before the command, and restore them afterwards, so
+    /// commands such as `pull --rebase` work on trees with local edits.
+    /// Changes that cannot be restored cleanly are reported.
+    #[arg(
+        long,
+        conflicts_with = "require_clean",
+        help = "Stash local changes before the command and restore them afterwards"
+    )]
+    autostash: bool,
+
+    /// Run the command and its hooks in a temporary linked worktree of each
+    /// repository with REV checked out, removed afterwards, so builds or
+    /// tests against a branch leave the working trees untouched.
+    #[arg(
+        long,
+        value_name = "REV",
+        help = "Run the command in a temporary worktree with REV checked out"
+    )]
+    in_worktree: Option<String>,
+
+    /// Run a destructive git command, such as `reset --hard` or `clean -f`,
+    /// in more than one repository. Without it, grpr lists the repositories
+    /// that would be affected and refuses.
+    #[arg(
+        long,
+        help = "Allow destructive git commands in more than one repository"
+    )]
+    force: bool,
+
+    /// Skip network git commands such as `fetch`, `pull`, and `push`,
+    /// reporting each repository as skipped. Without a network connection,
+    /// grpr also does this on its own in repositories whose remote hosts
+    /// cannot be reached.
+    #[arg(
+        long,
+        conflicts_with = "online",
+        help = "Skip network git commands instead of running them"
+    )]
+    offline: bool,
+
+    /// Run network git commands without first checking for a network
+    /// connection, for networks that are only reachable through a proxy.
+    #[arg(
+        long,
+        help = "Run network git commands without checking for a network connection"
+    )]
+    online: bool,
+
+    /// Print how long the command took in every repository, slowest first,
+    /// after the run. Without it only the ten slowest are listed, and only
+    /// for runs across more than ten repositories.
+    #[arg(long, help = "Print how long the command took in each repository")]
+    stats: bool,
+
+    /// Color repository headers, green or red by outcome, and failure
+    /// messages. `auto` colors output that goes to a terminal unless the
+    /// `NO_COLOR` environment variable is set.
+    #[arg(
+        long,
+        value_enum,
+        value_name = "WHEN",
+        default_value_t = ColorChoice::Auto,
+        help = "Color the output: auto, always, or never"
+    )]
+    color: ColorChoice,
+
+    /// Print straight to the terminal instead of through `$PAGER`, or
+    /// `less`, which output that does not fit on one screen otherwise goes
+    /// through when stdout is a terminal.
+    #[arg(long, help = "Do not send the output through the pager")]
+    no_pager: bool,
+
+    /// How much grpr reports on stderr about what it is doing: `error`,
+    /// `warn`, `info` (the default), `debug` to also follow discovery and
+    /// each command started and finished, or `trace` to see every directory
+    /// looked at. Without it, the `RUST_LOG` environment variable is used,
+    /// as in `RUST_LOG=grpr=debug`.
+    #[arg(
+        long,
+        value_enum,
+        value_name = "LEVEL",
+        help = "Report at this level: error, warn, info, debug, or trace"
+    )]
+    log_level: Option<LogLevel>,
+
+    /// Also append grpr's reports, with timestamps and levels, to FILE.
+    #[arg(long, value_name = "FILE", help = "Also append grpr's reports to FILE")]
+    log_file: Option<PathBuf>,
+
+    /// Print nothing for repositories where the command succeeded without
+    /// output worth reporting, such as a clean `status` or an up-to-date
+    /// `pull`, leaving only the repositories that need attention. Also
+    /// spelled `--changes-only`.
+    #[arg(
+        short,
+        long,
+        visible_alias = "changes-only",
+        help = "Only show repositories that failed or printed something of note"
+    )]
+    quiet: bool,
+
+    /// Show a progress bar on stderr with the repositories done, the
+    /// failures so far, and the time left. `--progress=spinners` adds a
+    /// spinner naming each repository being processed. Output is captured
+    /// and printed above the bar as each repository finishes.
+    #[arg(
+        long,
+        value_enum,
+        value_name = "STYLE",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "bar",
+        conflicts_with_all = ["dry_run", "confirm"],
+        help = "Show a progress bar, optionally with a spinner per running repository"
+    )]
+    progress: Option<ProgressMode>,
+
+    /// Write each repository's captured output to `DIR/<repo-name>.log`,
+    /// and a `manifest.json` listing every repository with its log file,
+    /// exit code, and duration, so a long run can be gone through
+    /// afterwards. Repositories sharing a name get `<repo-name>-2.log` and
+    /// so on.
+    #[arg(
+        long,
+        value_name = "DIR",
+        conflicts_with_all = ["dry_run", "watch"],
+        help = "Write each repository's output to a log file in DIR"
+    )]
+    log_dir: Option<PathBuf>,
+
+    /// Keep at most SIZE of each repository's captured stdout, and as much
+    /// of its stderr, in memory, such as `1M`, so that commands printing a
+    /// lot, like `log --all`, cannot exhaust memory across many threads. A
+    /// stream going past it is cut short with a note, and written in full
+    /// to `<repo-name>.stdout` or `.stderr` in the `--log-dir`, or in a
+    /// `grpr-<run-id>` directory in the temporary directory. The files are
+    /// listed at the end of the run.
+    #[arg(
+        long,
+        value_name = "SIZE",
+        value_parser = size::parse_size,
+        help = "Keep at most SIZE of each repository's captured output in memory, the rest in a file"
+    )]
+    max_output: Option<u64>,
+
+    /// Hold back each repository's output and present it in sections once
+    /// the run is over, instead of as each repository finishes: `outcome`
+    /// puts the repositories that failed first, then those that were
+    /// skipped, then those that succeeded.
+    #[arg(
+        long,
+        value_enum,
+        value_name = "KEY",
+        conflicts_with_all = ["dry_run", "watch", "output"],
+        help = "Present the output in sections by outcome once the run is over"
+    )]
+    group_by: Option<GroupBy>,
+
+    /// The order of the results once the run is over: `duration` puts the
+    /// slowest repositories first, `name` sorts them by path, and `status`
+    /// puts the failures first. Text output is held back and printed in
+    /// that order at the end, as are `--output json`, `--table`, and
+    /// `--report`. Within `--group-by` sections, the repositories follow
+    /// this order.
+    #[arg(
+        long,
+        value_enum,
+        value_name = "KEY",
+        conflicts_with_all = ["dry_run", "watch", "porcelain", "tui"],
+        help = "Order the results at the end by duration, name, or status"
+    )]
+    sort_results: Option<ResultOrder>,
+
+    /// Write a report of the run to FILE once it is over, in addition to the
+    /// usual output. `html:FILE` writes a standalone page with a table of
+    /// the repositories, their outcomes, exit codes, and durations, which
+    /// can be sorted and searched, and each repository's output folded
+    /// away. `junit:FILE` writes JUnit XML with a test case per repository,
+    /// passed, failed, or skipped, for CI systems. Can be repeated.
+    #[arg(
+        long,
+        value_name = "FORMAT:FILE",
+        value_parser = report::parse_report_target,
+        conflicts_with_all = ["dry_run", "watch"],
+        help = "Write a report of the run to a file, such as html:report.html or junit:results.xml"
+    )]
+    report: Vec<ReportTarget>,
+
+    /// Show a live dashboard in the terminal instead of printing output: a
+    /// table of the repositories with their state, queued, running, done,
+    /// or failed, beside the output of the selected one. `r` runs the
+    /// command again in a failed repository, and `q` stops the run, as
+    /// Ctrl-C does, or leaves the dashboard once the run is over.
+    #[arg(
+        long,
+        conflicts_with_all = ["dry_run", "watch", "output", "confirm", "progress", "group_by"],
+        help = "Show a live dashboard of the run in the terminal"
+    )]
+    tui: bool,
+
+    /// Print one line per repository in a stable format for scripts, which
+    /// never changes within a version: `v1`, the default, prints
+    /// `path<TAB>status<TAB>code<TAB>message`, where the status is
+    /// `succeeded`, `failed`, or `skipped`, the code is the exit code or
+    /// `-`, and the message is the first line of output or the reason for a
+    /// skip. Skipped repositories are listed after the others.
+    #[arg(
+        long,
+        value_enum,
+        value_name = "VERSION",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "v1",
+        conflicts_with_all = ["dry_run", "watch", "output", "group_by", "tui"],
+        help = "Print a stable, versioned line per repository for scripts"
+    )]
+    porcelain: Option<PorcelainVersion>,
+
+    /// Collect each repository's output and print it at the end as a table,
+    /// with the repository's path relative to its root in the first column,
+    /// colored by outcome on a terminal, and its output aligned beside it.
+    /// Meant for commands that print a line per repository, such as
+    /// `branch --show-current`, `describe`, or `rev-parse HEAD`.
+    #[arg(
+        long,
+        conflicts_with_all = ["dry_run", "watch", "output", "porcelain", "group_by", "tui"],
+        help = "Print the output as a table with a row per repository"
+    )]
+    table: bool,
+
+    /// Prefix each line of output, and each note on stderr, with the time
+    /// since the run started. Output is captured, so its lines are stamped
+    /// when the repository finishes. The run ID, printed as the run starts,
+    /// is stamped like any other note.
+    #[arg(
+        long,
+        conflicts_with_all = ["tui"],
+        help = "Prefix lines with the time since the start"
+    )]
+    timestamps: bool,
+
+    /// How to present the outcome on stdout: `text` prints each
+    /// repository's output as it finishes, `json` prints one document at the
+    /// end with each repository's command, exit code, duration, and output,
+    /// and a summary of the run, and `jsonl` prints the same as one JSON
+    /// object per line, each repository's as soon as it finishes. `csv` and
+    /// `tsv` print a header, then a row per repository as it finishes, with
+    /// its path, branch, outcome, exit code, duration, and the first line of
+    /// its output.
+    #[arg(
+        long,
+        value_enum,
+        value_name = "FORMAT",
+        default_value_t = OutputFormat::Text,
+        conflicts_with_all = ["dry_run", "watch"],
+        help = "Present the outcome as text, a JSON document, JSON Lines, CSV, or TSV"
+    )]
+    output: OutputFormat,
+
+    /// A shell command to run in each repository before the main command,
+    /// such as `git stash`. When it fails, the repository is reported as
+    /// failed and the main command is not run.
+    #[arg(
+        long,
+        value_name = "CMD",
+        help = "Run the shell command CMD in each repository first"
+    )]
+    pre: Option<String>,
+
+    /// A shell command to run in each repository after the main command,
+    /// whether or not it succeeded. Its exit code is in `GRPR_EXIT_STATUS`.
+    #[arg(
+        long,
+        value_name = "CMD",
+        help = "Run the shell command CMD in each repository afterwards"
+    )]
+    post: Option<String>,
+
+    /// Set the environment variable KEY to VALUE for the command and hooks in
+    /// every repository. May be given multiple times. `GRPR_REPO_PATH` and
+    /// `GRPR_REPO_NAME` are always set to the repository's path and name.
+    #[arg(
+        long = "env",
+        value_name = "KEY=VALUE",
+        value_parser = grpgit::parse_env_var,
+        help = "Set an environment variable for each command (repeatable)"
+    )]
+    env_vars: Vec<(String, String)>,
+
+    /// Pass git's repository variables, such as GIT_DIR and GIT_WORK_TREE,
+    /// on to the command and hooks. grpr normally clears them, as when it
+    /// runs from a git hook they point every command at the same repository.
+    #[arg(
+        long,
+        help = "Let commands inherit GIT_DIR, GIT_WORK_TREE, and similar variables"
+    )]
+    keep_git_env: bool,
+
+    /// Keep each repository's own config, and the files it includes, from
+    /// running programs: hooks, the file system monitor, its SSH command,
+    /// credential helpers, filter, diff, and merge drivers, the pager,
+    /// editors, aliases, and the like, and from allowing the `ext::`
+    /// transport. For running over trees of untrusted repositories.
+    #[arg(
+        long,
+        help = "Ignore hooks and other programs set in each repository's own config"
+    )]
+    no_local_config: bool,
+
+    /// The niceness to run the command and hooks at, from -20 to 19, so a
+    /// long run such as `gc` across many repositories leaves the machine
+    /// usable. Levels below grpr's own need privileges.
+    #[arg(
+        long,
+        value_name = "LEVEL",
+        allow_negative_numbers = true,
+        value_parser = clap::value_parser!(i8).range(-20..=19),
+        help = "Run commands at niceness LEVEL (-20 to 19)"
+    )]
+    nice: Option<i8>,
+
+    /// The I/O scheduling class to run the command and hooks in. Only
+    /// Linux supports it; elsewhere it is ignored.
+    #[arg(
+        long,
+        value_enum,
+        value_name = "CLASS",
+        help = "Run commands in the I/O scheduling CLASS (Linux only)"
+    )]
+    ionice: Option<IoClass>,
+
+    /// Reuse repositories recorded in the discovery cache for roots that have
+    /// not changed since they were last scanned.
+    #[arg(
+        global = true,
+        long,
+        help = "Reuse cached discovery results for unchanged roots"
+    )]
+    cached: bool,
+
+    /// Rescan every root and rewrite its discovery cache entry.
+    #[arg(global = true, long, help = "Rescan and rebuild the discovery cache")]
+    refresh: bool,
+
+    /// Run against the repositories recorded in the registry instead of
+    /// scanning the filesystem.
+    #[arg(
+        global = true,
+        long,
+        help = "Run against registered repositories instead of scanning"
+    )]
+    registered: bool,
+
+    /// Run against the repositories listed in a manifest file instead of
+    /// scanning the filesystem. Plain files list one path per line; `.toml`
+    /// files use the registry format. Relative paths are resolved against the
+    /// manifest's directory.
+    #[arg(
+        global = true,
+        long,
+        value_name = "FILE",
+        conflicts_with = "registered",
+        help = "Run against the repositories listed in FILE instead of scanning"
+    )]
+    manifest: Option<PathBuf>,
+
+    /// Read the repositories to process from stdin, one path per line, instead
+    /// of scanning the filesystem. Relative paths are resolved against the
+    /// current directory.
+    #[arg(
+        global = true,
+        long,
+        conflicts_with_all = ["registered", "manifest"],
+        help = "Read repository paths from stdin instead of scanning"
+    )]
+    stdin: bool,
+
+    /// Run again in the repositories where the last run's command failed or
+    /// never started, such as after a network outage or Ctrl-C. Give the
+    /// command again; selection filters still apply.
+    #[arg(
+        global = true,
+        long,
+        conflicts_with_all = ["registered", "manifest", "stdin"],
+        help = "Run in the repositories the last run failed in or did not reach"
+    )]
+    resume: bool,
+
+    /// Run again only in the repositories where the last run's command
+    /// failed.
+    #[arg(
+        global = true,
+        long,
+        conflicts_with_all = ["registered", "manifest", "stdin", "resume"],
+        help = "Run in the repositories the last run failed in"
+    )]
+    retry_failed: bool,
+
+    /// Expect NUL-separated paths on stdin, as produced by `find -print0` or
+    /// `grpr list --print0`.
+    #[arg(
+        short = '0',
+        long = "null",
+        requires = "stdin",
+        help = "Read NUL-separated paths with --stdin"
+    )]
+    null_separated: bool,
+
+    /// A grpr subcommand, or the git command and its arguments to execute
+    /// (e.g., "pull", "status", etc.). Defaults to "status" if not provided.
+    #[command(subcommand)]
+    command: Option<CliCommand>,
+}
+
+/// Orders in which repositories can be processed.
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+enum RepositoryOrder {
+    /// By repository directory name, then by full path.
+    Name,
+    /// By full repository path.
+    Path,
+    /// Most recently modified repository first.
+    Mtime,
+}
+
+/// How `--group-by` arranges the output of a run into sections.
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+enum GroupBy {
+    /// Failed repositories first, then skipped ones, then successful ones.
+    Outcome,
+}
+
+/// How `--sort-results` orders the results of a run.
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+enum ResultOrder {
+    /// The slowest repositories first.
+    Duration,
+    /// By path.
+    Name,
+    /// Failed repositories first, then successful ones.
+    Status,
+}
+
+impl ResultOrder {
+    /// Compares two results, each a repository's path, whether its command
+    /// failed, and how long it ran. Ties are broken by path.
+    fn compare(
+        self,
+        (a, a_failed, a_elapsed): (&Path, bool, Duration),
+        (b, b_failed, b_elapsed): (&Path, bool, Duration),
+    ) -> cmp::Ordering {
+        let order = match self {
+            ResultOrder::Duration => b_elapsed.cmp(&a_elapsed),
+            ResultOrder::Name => cmp::Ordering::Equal,
+            ResultOrder::Status => b_failed.cmp(&a_failed),
+        };
+        order.then_with(|| a.cmp(b))
+    }
+}
+
+/// Subcommands handled by grpr itself. Any other command is passed to git.
+#[derive(Subcommand, Debug)]
+enum CliCommand {
+    /// Add repositories to the registry. Defaults to the current directory.
+    Register {
+        /// Repository paths to register.
+        paths: Vec<PathBuf>,
+    },
+    /// Remove repositories from the registry. Defaults to the current
+    /// directory.
+    Unregister {
+        /// Repository paths to unregister.
+        paths: Vec<PathBuf>,
+    },
+    /// Scan the roots once and record every repository found, with its
+    /// `origin` URL and default branch, in the registry.
+    Adopt,
+    /// Print the repositories the selection options would target, and why,
+    /// without running anything. Takes the same discovery, filter, sort, and
+    /// batch options as a git command.
+    Which,
+    /// List the registered repositories.
+    List {
+        /// Separate paths with NUL bytes instead of newlines.
+        #[arg(short = '0', long)]
+        print0: bool,
+    },
+    /// Work with the git command aliases defined in the `[aliases]` table of
+    /// the config file.
+    Alias {
+        #[command(subcommand)]
+        action: AliasCommand,
+    },
+    /// Run any command, not just git, in each selected repository, such as
+    /// `grpr exec -- cargo update`. A single argument is run by the shell, so
+    /// `grpr exec -- 'make clean && make'` works too.
+    Exec {
+        /// The command and its arguments.
+        #[arg(required = true, trailing_var_arg = true, allow_hyphen_values = true)]
+        command: Vec<String>,
+    },
+    /// Run a script file in each selected repository, such as `grpr
+    /// run-script ./bump-deps.sh`. Besides `GRPR_REPO_PATH` and
+    /// `GRPR_REPO_NAME`, the script finds the branch in `GRPR_REPO_BRANCH`
+    /// and the `origin` URL in `GRPR_REPO_REMOTE`.
+    RunScript {
+        /// The script to run. Executable scripts run directly; others are
+        /// run by `sh`.
+        script: PathBuf,
+        /// Arguments passed to the script.
+        #[arg(trailing_var_arg = true, allow_hyphen_values = true)]
+        args: Vec<String>,
+    },
+    /// The git command and its arguments to execute.
+    #[command(external_subcommand)]
+    Git(Vec<String>),
+}
+
+/// Subcommands of `grpr alias`.
+#[derive(Subcommand, Debug)]
+enum AliasCommand {
+    /// List the configured aliases and what they expand to.
+    List,
+}
+
+/// Extracts the command to run in each repository, with any `--env`
+/// variables, from the CLI arguments.
+fn command_from_cli(cli: &Cli) -> grpgit::RepoCommand {
+    let command = match &cli.command {
+        Some(CliCommand::Exec { command }) => grpgit::RepoCommand::exec(command.clone()),
+        Some(CliCommand::RunScript { script, args }) => {
+            let script = std::path::absolute(script).unwrap_or_else(|_| script.clone());
+            grpgit::RepoCommand::script(&script, args.clone())
+        }
+        Some(CliCommand::Git(args)) => grpgit::RepoCommand::git(args.clone()),
+        _ => grpgit::RepoCommand::git(vec!["status".to_string()]),
+    };
+    with_settings_from_cli(cli, command)
+}
+
+/// Sets the `--env` variables, `--keep-git-env`, and the `--nice` and
+/// `--ionice` priority on `command`.
+fn with_settings_from_cli(cli: &Cli, command: grpgit::RepoCommand) -> grpgit::RepoCommand {
+    let priority = Priority {
+        nice: cli.nice,
+        io_class: cli.ionice,
+    };
+    let command = cli
+        .env_vars
+        .iter()
+        .fold(command, |command, (key, value)| {
+            command.with_env(key, value)
+        })
+        .with_priority(priority);
+    if cli.keep_git_env {
+        command.keeping_git_env()
+    } else {
+        command
+    }
+}
+
+/// Returns true when `command` matches one of the `destructive` invocations.
+fn is_destructive(command: &grpgit::RepoCommand, destructive: &[String]) -> bool {
+    destructive
+        .iter()
+        .any(|pattern| command.matches_invocation(pattern))
+}
+
+/// Refuses to run `command` in more than one repository without `--force`
+/// when it matches one of the configured destructive invocations, after
+/// listing the repositories it would affect.
+fn check_destructive(
+    command: &grpgit::RepoCommand,
+    repositories: &[PathBuf],
+    destructive: &[String],
+) -> Result<(), Box<dyn Error>> {
+    if repositories.len() <= 1 {
+        return Ok(());
+    }
+    let Some(pattern) = destructive
+        .iter()
+        .find(|pattern| command.matches_invocation(pattern))
+    else {
+        return Ok(());
+    };
+
+    eprintln!(
+        "grpr: `{}` is destructive (matches `{pattern}`) and would run in {}:",
+        command.command_line(),
+        count_repositories(repositories.len())
+    );
+    for repo_path in repositories {
+        eprintln!("  {}", repo_path.display());
+    }
+    Err(format!(
+        "refusing to run a destructive command in {} without --force",
+        count_repositories(repositories.len())
+    )
+    .into())
+}
+
+/// Returns the command to run: the one given on the command line or, when
+/// resuming without one, the command of the last run.
+fn command_to_run(cli: &Cli) -> Result<grpgit::RepoCommand, Box<dyn Error>> {
+    if let Some(CliCommand::Git(args)) = &cli.command {
+        if let Some(args) = load_config()?.expand_alias(args) {
+            return Ok(with_settings_from_cli(cli, grpgit::RepoCommand::git(args)));
+        }
+    }
+    if cli.command.is_some() || !(cli.resume || cli.retry_failed) {
+        return Ok(command_from_cli(cli));
+    }
+    let journal = Journal::load(&journal_path()?)?.ok_or(NO_PREVIOUS_RUN)?;
+    Ok(with_settings_from_cli(cli, resumed_command(journal)))
+}
+
+/// Makes the command of the run recorded in `journal` again, as it was
+/// first made.
+fn resumed_command(journal: Journal) -> grpgit::RepoCommand {
+    match journal.invocation {
+        Some(invocation) => grpgit::RepoCommand::from_invocation(invocation),
+        None => grpgit::RepoCommand::from_words(journal.command),
+    }
+}
+
+/// Returns the task `command` names in the config file, with each rule's
+/// command prepared as the command given on the command line would be.
+fn task_for(
+    cli: &Cli,
+    command: &grpgit::RepoCommand,
+    options: &ExecutionOptions,
+) -> Result<Option<Task>, Box<dyn Error>> {
+    let words = command.words();
+    let Some(("git", args)) = words
+        .split_first()
+        .map(|(program, args)| (program.as_str(), args))
+    else {
+        return Ok(None);
+    };
+    let config = load_config()?;
+    let Some((rules, rest)) = config.task(args) else {
+        return Ok(None);
+    };
+    let task = Task::new(&args[0], rules, rest, |args| {
+        attach_terminal(
+            with_settings_from_cli(cli, grpgit::RepoCommand::git(args)),
+            options,
+        )
+    })?;
+    Ok(Some(task))
+}
+
+/// Runs each rule of `task` in the repositories it selects, one rule after
+/// another, and returns the repositories some rule selected. Every rule's
+/// repositories are checked for destructive commands before anything runs.
+fn execute_task(
+    task: &Task,
+    repositories: &[PathBuf],
+    roots: &[PathBuf],
+    destructive: &[String],
+    options: &ExecutionOptions,
+    status: &RunStatus,
+) -> Result<Vec<PathBuf>, Box<dyn Error>> {
+    let assigned = task.assign(repositories, roots);
+    for (command, repositories) in &assigned {
+        check_destructive(command, repositories, destructive)?;
+    }
+    status.expect(
+        assigned
+            .iter()
+            .map(|(_, repositories)| repositories.len())
+            .sum(),
+    );
+    for (command, repositories) in &assigned {
+        if status.stopped() {
+            break;
+        }
+        execute_selection(repositories, roots, command, options, status)?;
+    }
+
+    let selected: HashSet<&PathBuf> = assigned
+        .iter()
+        .flat_map(|(_, repositories)| repositories)
+        .collect();
+    Ok(repositories
+        .iter()
+        .filter(|repo_path| selected.contains(repo_path))
+        .cloned()
+        .collect())
+}
+
+/// Builds the repository discovery options from the CLI arguments.
+fn discovery_options_from_cli(cli: &Cli) -> Result<grpgit::DiscoveryOptions, Box<dyn Error>> {
+    Ok(grpgit::DiscoveryOptions {
+        max_depth: cli.max_depth,
+        exclude: grpgit::build_exclude_set(&cli.exclude)?,
+        include_hidden: cli.hidden,
+        ignore_files: !cli.no_ignore,
+        nested: cli.nested,
+        include_worktrees: !cli.skip_worktrees,
+        include_submodules: !cli.skip_submodules,
+        include_bare: cli.bare,
+        follow_symlinks: cli.follow_symlinks,
+        same_file_system: cli.one_file_system,
+        threads: cli.scan_threads,
+        traversal: cli.traversal,
+    })
+}
+
+/// Builds the repository selector from the CLI arguments. `roots` are the
+/// directories the target repositories were found under. Criteria that only
+/// look at the path come first, and those that run git last.
+fn selector_from_cli(cli: &Cli, roots: &[PathBuf]) -> Selector {
+    let mut criteria = Vec::new();
+    if !cli.match_path.is_empty() {
+        criteria.push(Criterion::MatchPath(cli.match_path.clone()));
+    }
+    if !cli.no_match_path.is_empty() {
+        criteria.push(Criterion::NoMatchPath(cli.no_match_path.clone()));
+    }
+    if cli.skip_detached || cli.only_detached {
+        criteria.push(Criterion::Detached(cli.only_detached));
+    }
+    if let Some(since) = cli
+        .active_within
+        .and_then(|within| SystemTime::now().checked_sub(within))
+    {
+        criteria.push(Criterion::ActiveSince(since));
+    }
+    if !cli.exclude_remote.is_empty() {
+        criteria.push(Criterion::ExcludeRemote(cli.exclude_remote.clone()));
+    }
+    if !cli.remote_matches.is_empty() {
+        criteria.push(Criterion::RemoteMatches(cli.remote_matches.clone()));
+    }
+    if cli.no_remote {
+        criteria.push(Criterion::NoRemote);
+    }
+    if !cli.has_remote.is_empty() {
+        criteria.push(Criterion::HasRemote(cli.has_remote.clone()));
+    }
+    if let Some(since) = cli.committed_since {
+        criteria.push(Criterion::CommittedSince(since));
+    }
+    if let Some(before) = cli.committed_before {
+        criteria.push(Criterion::CommittedBefore(before));
+    }
+    if cli.dirty || cli.clean {
+        criteria.push(Criterion::Dirty(cli.dirty));
+    }
+    for (selected, criterion) in [
+        (cli.staged, Criterion::Staged),
+        (cli.unstaged, Criterion::Unstaged),
+        (cli.untracked, Criterion::Untracked),
+    ] {
+        if selected {
+            criteria.push(criterion);
+        }
+    }
+    if cli.has_stash {
+        criteria.push(Criterion::HasStash);
+    }
+    if cli.upstream_gone {
+        criteria.push(Criterion::UpstreamGone);
+    }
+    let upstream_states: Vec<_> = [
+        (cli.ahead, grpgit::UpstreamState::Ahead),
+        (cli.behind, grpgit::UpstreamState::Behind),
+        (cli.diverged, grpgit::UpstreamState::Diverged),
+    ]
+    .into_iter()
+    .filter_map(|(selected, state)| selected.then_some(state))
+    .collect();
+    if !upstream_states.is_empty() {
+        criteria.push(Criterion::Upstream(upstream_states));
+    }
+    if cli.has_submodules || cli.no_submodules {
+        criteria.push(Criterion::Submodules(cli.has_submodules));
+    }
+    if let Some(bytes) = cli.min_size {
+        criteria.push(Criterion::MinSize(bytes));
+    }
+    if let Some(bytes) = cli.max_size {
+        criteria.push(Criterion::MaxSize(bytes));
+    }
+    if !cli.default_branch.is_empty() {
+        criteria.push(Criterion::DefaultBranch(cli.default_branch.clone()));
+    }
+    if !cli.config_equals.is_empty() {
+        criteria.push(Criterion::ConfigEquals(cli.config_equals.clone()));
+    }
+    if let Some(expr) = &cli.where_expr {
+        criteria.push(Criterion::Where(expr.clone()));
+    }
+
+    Selector {
+        criteria,
+        combine: cli.combine,
+        invert: cli.invert_selection,
+        roots: roots.to_vec(),
+    }
+}
+
+/// The order to process repositories in. Serial runs default to path order so
+/// they are repeatable.
+fn repository_order(cli: &Cli) -> Option<RepositoryOrder> {
+    cli.sort.or(cli.serial.then_some(RepositoryOrder::Path))
+}
+
+/// Sorts repositories into the requested processing order.
+fn sort_repositories(repositories: &mut [PathBuf], order: RepositoryOrder) {
+    match order {
+        RepositoryOrder::Name => {
+            repositories.sort_by(|a, b| a.file_name().cmp(&b.file_name()).then_with(|| a.cmp(b)))
+        }
+        RepositoryOrder::Path => repositories.sort(),
+        RepositoryOrder::Mtime => repositories
+            .sort_by_cached_key(|repo_path| Reverse(grpgit::repository_modified(repo_path))),
+    }
+}
+
+/// Applies `--skip` and `--limit` to the sorted selection.
+fn select_batch(repositories: Vec<PathBuf>, skip: usize, limit: Option<usize>) -> Vec<PathBuf> {
+    repositories
+        .into_iter()
+        .skip(skip)
+        .take(limit.unwrap_or(usize::MAX))
+        .collect()
+}
+
+/// Controls how the git command is executed across repositories.
+#[derive(Debug, Clone, Default)]
+struct ExecutionOptions {
+    /// Worker threads for parallel execution. `None` or 1 runs sequentially.
+    threads: Option<usize>,
+    /// Present the captured output of parallel runs in repository order
+    /// instead of as each repository finishes.
+    ordered_output: bool,
+    /// How each repository's outcome is presented.
+    output: OutputFormat,
+    /// Leave out repositories with nothing to report.
+    quiet: bool,
+    /// Show the progress of the run on stderr.
+    progress: Option<ProgressMode>,
+    /// Write each repository's output to a log file.
+    log_dir: bool,
+    /// Present the output in sections once the run is over.
+    group_by: Option<GroupBy>,
+    /// Hold back text output to print it in this order at the end.
+    sort_results: Option<ResultOrder>,
+    /// Prefix each line of output with the time since the start.
+    timestamps: bool,
+    /// Keep a report of each repository for `--report`.
+    reports: bool,
+    /// Show the run on the `--tui` dashboard.
+    tui: bool,
+    /// Color the output of `status` and `diff` and count the changes.
+    render_changes: bool,
+    /// Hold back the output to page it once the run is over.
+    paged: bool,
+    /// Run the git command or only print it.
+    mode: grpgit::RunMode,
+    /// What to do with the remaining repositories after a failure.
+    on_failure: FailurePolicy,
+    /// How often a failed command is retried in the same repository.
+    retry: RetryPolicy,
+    /// Ask before running the command in each repository.
+    confirm: bool,
+    /// Skip repositories with local modifications.
+    require_clean: bool,
+    /// Skip network commands instead of running them.
+    offline: bool,
+    /// Skip network commands in repositories whose remote hosts cannot be
+    /// reached, once the machine looks offline.
+    check_remotes: bool,
+    /// Shell commands run before and after the command in each repository.
+    hooks: grpgit::Hooks,
+    /// How many repositories may run a network command at once.
+    net_jobs: Option<usize>,
+    /// How many repositories on each remote host may run a network command
+    /// at once.
+    host_slots: Option<Arc<HostSlots>>,
+    /// Spaces out the start of commands to a bounded rate.
+    throttle: Option<Arc<Throttle>>,
+    /// The ordering constraints between repositories to honor.
+    dependencies: Option<Arc<Dependencies>>,
+}
+
+impl ExecutionOptions {
+    /// Returns true when each repository's output is captured and presented
+    /// once its command finishes: in parallel runs, so that it is not
+    /// interleaved with the others, and to be filtered or turned into
+    /// machine-readable output.
+    fn buffers_output(&self) -> bool {
+        let parallel = self.threads.is_some_and(|threads| threads > 1);
+        (parallel || self.captures_output()) && self.mode == grpgit::RunMode::Execute
+    }
+
+    /// Returns true when the output of commands is captured for `--quiet`,
+    /// `--output`, `--progress`, `--log-dir`, `--group-by`,
+    /// `--sort-results`, `--report`, `--tui`, or `--timestamps`, or to
+    /// render the changes shown by `status` and `diff`, even in sequential
+    /// runs.
+    fn captures_output(&self) -> bool {
+        self.quiet
+            || self.output != OutputFormat::Text
+            || self.progress.is_some()
+            || self.log_dir
+            || self.group_by.is_some()
+            || self.sort_results.is_some()
+            || self.timestamps
+            || self.reports
+            || self.tui
+            || self.render_changes
+            || self.paged
+    }
+}
+
+/// Builds the execution options from the CLI arguments.
+fn execution_options_from_cli(cli: &Cli) -> ExecutionOptions {
+    let on_failure = if cli.fail_fast {
+        FailurePolicy::FailFast
+    } else {
+        FailurePolicy::KeepGoing
+    };
+
+    if cli.dry_run {
+        // Printing command lines is instant, so keep them in order.
+        return ExecutionOptions {
+            mode: grpgit::RunMode::DryRun,
+            on_failure,
+            confirm: cli.confirm,
+            require_clean: cli.require_clean,
+            hooks: hooks_from_cli(cli),
+            ..ExecutionOptions::default()
+        };
+    }
+
+    ExecutionOptions {
+        // Questions are asked one repository at a time.
+        threads: cli.threads.filter(|_| !cli.confirm),
+        ordered_output: cli.sort.is_some(),
+        // Only one version so far.
+        output: match cli.porcelain {
+            Some(PorcelainVersion::V1) => OutputFormat::Porcelain,
+            None if cli.table => OutputFormat::Table,
+            None => cli.output,
+        },
+        quiet: cli.quiet,
+        progress: cli.progress,
+        log_dir: cli.log_dir.is_some(),
+        group_by: cli.group_by,
+        sort_results: cli.sort_results,
+        timestamps: cli.timestamps,
+        reports: !cli.report.is_empty(),
+        tui: cli.tui,
+        // Needs the command; see `renders_changes`.
+        render_changes: false,
+        // Needs the command too; see `pages_output`.
+        paged: false,
+        mode: grpgit::RunMode::Execute,
+        on_failure,
+        retry: RetryPolicy {
+            retries: cli.retries,
+            delay: cli.retry_delay,
+        },
+        confirm: cli.confirm,
+        require_clean: cli.require_clean,
+        offline: cli.offline,
+        check_remotes: false,
+        hooks: hooks_from_cli(cli),
+        net_jobs: cli.net_jobs.map(usize::from),
+        host_slots: cli
+            .per_host
+            .map(|limit| Arc::new(HostSlots::new(limit.into()))),
+        throttle: cli.throttle.map(|rate| Arc::new(Throttle::new(rate))),
+        // Read from the config file by the caller with `--dependency-order`.
+        dependencies: None,
+    }
+}
+
+/// The limits on a network command for one run: `--net-jobs` overall and
+/// `--per-host` on each remote host. Commands that do not use the network are
+/// not limited.
+#[derive(Default)]
+struct NetworkLimits<'a> {
+    jobs: Option<Semaphore>,
+    hosts: Option<&'a HostSlots>,
+}
+
+impl<'a> NetworkLimits<'a> {
+    fn new(command: &grpgit::RepoCommand, options: &'a ExecutionOptions) -> Self {
+        if !command.uses_network() {
+            return Self::default();
+        }
+        Self {
+            jobs: options.net_jobs.map(Semaphore::new),
+            hosts: options.host_slots.as_deref(),
+        }
+    }
+
+    /// Waits until the command may start in `repo_path`. Its slots are held
+    /// until the returned permits are dropped.
+    fn acquire(&self, repo_path: &Path) -> (Option<HostPermit<'a>>, Option<Permit<'_>>) {
+        let host = self.hosts.and_then(|hosts| hosts.acquire(repo_path));
+        (host, self.jobs.as_ref().map(Semaphore::acquire))
+    }
+}
+
+/// Waits for the network slots of `repo_path` and then for `--throttle`,
+/// so the rate counts commands as they actually start.
+fn dispatch<'a, 'b>(
+    limits: &'b NetworkLimits<'a>,
+    options: &ExecutionOptions,
+    repo_path: &Path,
+) -> (Option<HostPermit<'a>>, Option<Permit<'b>>) {
+    let permits = limits.acquire(repo_path);
+    if let Some(throttle) = &options.throttle {
+        throttle.wait();
+    }
+    permits
+}
+
+/// Gives `command` the terminal when repositories are processed one at a
+/// time. Parallel runs detach it instead, and refuse commands that are known
+/// to need the terminal rather than let several of them fight over it. So
+/// do runs that capture the output of commands.
+fn attach_terminal(
+    command: grpgit::RepoCommand,
+    options: &ExecutionOptions,
+) -> Result<grpgit::RepoCommand, Box<dyn Error>> {
+    let parallel = options.threads.is_some_and(|count| count > 1);
+    if !parallel && !options.captures_output() {
+        return Ok(command);
+    }
+    if command.needs_terminal() {
+        let reason = if parallel {
+            "which parallel runs cannot share; use --serial instead of --threads"
+        } else {
+            "which --quiet, --output, --progress, --log-dir, --group-by, --sort-results, \
+             --report, --tui, and --timestamps capture"
+        };
+        return Err(format!(
+            "`{}` is interactive and needs the terminal, {reason}",
+            command.command_line()
+        )
+        .into());
+    }
+    Ok(command.without_terminal())
+}
+
+/// Builds the per-repository hooks from the CLI arguments.
+fn hooks_from_cli(cli: &Cli) -> grpgit::Hooks {
+    grpgit::Hooks {
+        pre: cli.pre.clone(),
+        post: cli.post.clone(),
+        autostash: cli.autostash,
+        worktree: cli.in_worktree.clone(),
+        // Needs the run ID; see `output_limit`.
+        max_output: None,
+    }
+}
+
+/// Returns the `--max-output` limit for the run `run_id`, spilling into the
+/// `--log-dir`, if any, or else a directory of its own.
+fn output_limit(cli: &Cli, run_id: &str) -> Option<grpgit::OutputLimit> {
+    let bytes = cli.max_output?;
+    let (dir, private) = match &cli.log_dir {
+        Some(dir) => (dir.clone(), false),
+        None => (env::temp_dir().join(format!("grpr-{run_id}")), true),
+    };
+    Some(grpgit::OutputLimit {
+        bytes: usize::try_from(bytes).unwrap_or(usize::MAX),
+        dir,
+        private,
+    })
+}
+
+/// Formats `count` with "repository" or "repositories" to match.
+fn count_repositories(count: usize) -> String {
+    let noun = if count == 1 {
+        "repository"
+    } else {
+        "repositories"
+    };
+    format!("{count} {noun}")
+}
+
+/// Exit status when the command failed in some, but not all, repositories.
+const EXIT_SOME_FAILED: u8 = 3;
+/// Exit status when the command failed in every repository it ran in.
+const EXIT_ALL_FAILED: u8 = 4;
+/// Exit status when no git repositories were found under the roots.
+const EXIT_NO_REPOSITORIES: u8 = 5;
+
+/// An error that ends grpr with a specific exit status, so scripts can tell
+/// a partly failed run from a completely failed one. An empty message has
+/// already been reported.
+#[derive(Debug)]
+struct ExitError {
+    code: u8,
+    message: String,
+}
+
+impl ExitError {
+    fn no_repositories() -> Self {
+        Self {
+            code: EXIT_NO_REPOSITORIES,
+            message: String::new(),
+        }
+    }
+}
+
+impl fmt::Display for ExitError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl Error for ExitError {}
+
+/// How often, and after what pause, a failed command is run again.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+struct RetryPolicy {
+    /// Attempts after the first one. Zero disables retrying.
+    retries: u32,
+    /// The pause before the first retry, doubled for each one after it.
+    delay: Duration,
+}
+
+impl RetryPolicy {
+    /// The pause before retry number `retry`, counting from 1.
+    fn backoff(&self, retry: u32) -> Duration {
+        self.delay
+            .saturating_mul(1 << retry.saturating_sub(1).min(16))
+    }
+}
+
+/// Runs `attempt` in `repo_path`, retrying with exponential backoff while
+/// `succeeded` rejects its result, up to the policy's limit or until the run
+/// is stopped. Returns the last result.
+fn run_with_retries<T>(
+    repo_path: &Path,
+    policy: &RetryPolicy,
+    status: &RunStatus,
+    mut attempt: impl FnMut() -> T,
+    succeeded: impl Fn(&T) -> bool,
+) -> T {
+    let mut result = attempt();
+    let mut retry = 0;
+    while !succeeded(&result) && retry < policy.retries && !status.stopped() {
+        retry += 1;
+        let pause = policy.backoff(retry);
+        status.note(&format!(
+            "command failed in {}; retrying in {pause:?} (retry {retry} of {})",
+            repo_path.display(),
+            policy.retries
+        ));
+        std::thread::sleep(pause);
+        result = attempt();
+    }
+
+    if retry > 0 {
+        status.record_retried(succeeded(&result));
+    }
+    result
+}
+
+/// How a run continues once the git command fails in a repository.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+enum FailurePolicy {
+    /// Process every repository and report the failures at the end.
+    #[default]
+    KeepGoing,
+    /// Start no new repositories after the first failure.
+    FailFast,
+}
+
+/// Counts the failures of a run and tells workers when to stop taking on
+/// repositories. Work already started is left to finish.
+#[derive(Debug, Default)]
+struct RunStatus {
+    /// The run's unique ID.
+    run_id: String,
+    on_failure: FailurePolicy,
+    /// Repositories selected for the run so far.
+    selected: AtomicUsize,
+    /// Repositories whose command has finished, successfully or not.
+    completed: AtomicUsize,
+    failures: AtomicUsize,
+    stopped: AtomicBool,
+    /// Repositories whose command was retried at least once.
+    retried: AtomicUsize,
+    /// Retried repositories whose command eventually succeeded.
+    recovered: AtomicUsize,
+    /// Repositories skipped by `--require-clean`.
+    skipped_dirty: AtomicUsize,
+    /// Repositories whose network command was skipped while offline.
+    skipped_offline: AtomicUsize,
+    /// Whether each remote host and port checked by `check_remotes` could
+    /// be reached.
+    reachable: Mutex<HashMap<(String, u16), bool>>,
+    /// The outcome in each repository the command ran in.
+    outcomes: Mutex<HashMap<PathBuf, Outcome>>,
+    /// How long the command ran in each repository, across any retries.
+    timings: Mutex<HashMap<PathBuf, Duration>>,
+    /// What happened in each repository, in the order they finished, for
+    /// `--output json` and `--report`.
+    reports: Mutex<Vec<RepositoryReport>>,
+    /// The progress shown with `--progress`.
+    progress: Option<Progress>,
+    /// Where each repository's output is logged with `--log-dir`.
+    logs: Option<LogDir>,
+    /// Output held back by `--group-by`, in the order the repositories
+    /// finished.
+    held: Mutex<Vec<(PathBuf, grpgit::Captured)>>,
+    /// Repositories the command was not run in, with the reason.
+    skipped: Mutex<Vec<(PathBuf, String)>>,
+    /// The files holding output cut short by `--max-output`.
+    spilled: Mutex<Vec<PathBuf>>,
+    /// The changes shown by `status` or `diff` in each repository.
+    changes: Mutex<Vec<(PathBuf, DiffStat)>>,
+    /// Whether the header of `--output csv` or `tsv` has been printed.
+    table_header: Mutex<bool>,
+    /// The `--tui` dashboard, once the repositories of the run are known.
+    dashboard: OnceLock<Dashboard>,
+}
+
+/// How many repositories the summary lists as the slowest without `--stats`.
+const SLOWEST_SHOWN: usize = 10;
+
+/// Formats a command's run time for the timing report.
+fn format_elapsed(elapsed: Duration) -> String {
+    let seconds = elapsed.as_secs_f64();
+    if seconds < 60.0 {
+        format!("{seconds:.2}s")
+    } else {
+        let seconds = elapsed.as_secs();
+        format!("{}m{:02}s", seconds / 60, seconds % 60)
+    }
+}
+
+impl RunStatus {
+    fn new(on_failure: FailurePolicy) -> Self {
+        Self {
+            run_id: report::new_run_id(),
+            on_failure,
+            ..Self::default()
+        }
+    }
+
+    /// Shows the progress of the run on stderr in the style of `mode`, if
+    /// any.
+    fn with_progress(self, mode: Option<ProgressMode>) -> Self {
+        Self {
+            progress: mode.map(Progress::new),
+            ..self
+        }
+    }
+
+    /// Logs each repository's output in `logs`, if any.
+    fn with_logs(self, logs: Option<LogDir>) -> Self {
+        Self { logs, ..self }
+    }
+
+    /// Shows the run in `repositories` on a dashboard instead of printing
+    /// its output.
+    fn show_dashboard(&self, repositories: &[PathBuf]) -> &Dashboard {
+        self.dashboard.get_or_init(|| Dashboard::new(repositories))
+    }
+
+    /// Reports `message` as a note, above the progress bar if there is one,
+    /// or on the dashboard.
+    fn note(&self, message: &str) {
+        if let Some(dashboard) = self.dashboard.get() {
+            dashboard.note(message);
+        }
+        self.print(|| tracing::info!("{message}"));
+    }
+
+    /// Runs `print` with the progress bar, if there is one, out of the way.
+    fn print<R>(&self, print: impl FnOnce() -> R) -> R {
+        match &self.progress {
+            Some(progress) => progress.suspend(print),
+            None => print(),
+        }
+    }
+
+    /// Adds `count` repositories to the progress of the run.
+    fn expect(&self, count: usize) {
+        self.selected.fetch_add(count, Ordering::Relaxed);
+        if let Some(progress) = &self.progress {
+            progress.add_repositories(count);
+        }
+    }
+
+    /// Shows that the command runs in `repo_path` until the returned value
+    /// is dropped, with `--progress=spinners`.
+    fn running(&self, repo_path: &Path) -> Option<progress::Spinner> {
+        if let Some(dashboard) = self.dashboard.get() {
+            dashboard.started(repo_path);
+        }
+        self.progress
+            .as_ref()
+            .and_then(|progress| progress.running(repo_path))
+    }
+
+    /// Counts a repository as done in the progress of the run, whether its
+    /// command ran or it was skipped.
+    fn advance(&self) {
+        if let Some(progress) = &self.progress {
+            progress.finish_repository(self.failures.load(Ordering::Relaxed));
+        }
+    }
+
+    /// Removes the progress bar once no more commands will run.
+    fn clear_progress(&self) {
+        if let Some(progress) = &self.progress {
+            progress.clear();
+        }
+    }
+
+    /// Records how the command went in `repo_path`, reporting a failure on
+    /// stderr, or as an annotation under GitHub Actions, unless the dashboard
+    /// shows it. Returns true when it succeeded.
+    fn record(&self, repo_path: &Path, result: Result<(), io::Error>) -> bool {
+        self.completed.fetch_add(1, Ordering::Relaxed);
+        let succeeded = result.is_ok();
+        let outcome = if succeeded {
+            Outcome::Succeeded
+        } else {
+            Outcome::Failed
+        };
+        self.outcomes
+            .lock()
+            .unwrap_or_else(|err| err.into_inner())
+            .insert(repo_path.to_path_buf(), outcome);
+        if let Err(err) = result {
+            if self.dashboard.get().is_none() {
+                let message = err.to_string();
+                match output::annotation(&message) {
+                    Some(annotation) => self.print(|| println!("{annotation}")),
+                    None => self.print(|| eprintln!("{}", output::failure(&message))),
+                }
+            }
+            self.failures.fetch_add(1, Ordering::Relaxed);
+            if self.on_failure == FailurePolicy::FailFast {
+                self.stopped.store(true, Ordering::Relaxed);
+            }
+        }
+        self.advance();
+        succeeded
+    }
+
+    /// Reports that the command is not run in `repo_path` because of
+    /// `reason`, and counts the repository as done.
+    fn skip(&self, repo_path: &Path, reason: &str) {
+        self.note(&format!("{}: skipped: {reason}", repo_path.display()));
+        self.skipped
+            .lock()
+            .unwrap_or_else(|err| err.into_inner())
+            .push((repo_path.to_path_buf(), reason.to_string()));
+        if let Some(dashboard) = self.dashboard.get() {
+            dashboard.skipped(repo_path, reason);
+        }
+        self.advance();
+    }
+
+    /// Returns true, after reporting it, when `--require-clean` skips
+    /// `repo_path` because it has local modifications. The command is left
+    /// unstarted there, so `--resume` picks it up again.
+    fn skip_dirty(&self, repo_path: &Path, options: &ExecutionOptions) -> bool {
+        let skip = options.require_clean && grpgit::is_dirty(repo_path) == Some(true);
+        if skip {
+            self.skipped_dirty.fetch_add(1, Ordering::Relaxed);
+            self.skip(repo_path, "dirty");
+        }
+        skip
+    }
+
+    /// Returns true, after reporting it, when `command` needs the network
+    /// and the run is offline, or none of the repository's remote hosts can
+    /// be reached while checking them. Like [`RunStatus::skip_dirty`], the
+    /// command is left unstarted for `--resume`.
+    fn skip_offline(
+        &self,
+        repo_path: &Path,
+        command: &grpgit::RepoCommand,
+        options: &ExecutionOptions,
+    ) -> bool {
+        let skip = command.uses_network()
+            && (options.offline || options.check_remotes && self.remotes_unreachable(repo_path));
+        if skip {
+            self.skipped_offline.fetch_add(1, Ordering::Relaxed);
+            self.skip(repo_path, "offline");
+        }
+        skip
+    }
+
+    /// Returns true when `repo_path` has remotes on other hosts and none of
+    /// them can be reached. Each host is only checked once in a run.
+    fn remotes_unreachable(&self, repo_path: &Path) -> bool {
+        let addresses: Vec<(String, u16)> = grpgit::remote_urls(repo_path)
+            .iter()
+            .filter_map(|url| Some((grpgit::remote_host(url)?, grpgit::remote_port(url)?)))
+            .collect();
+        !addresses.is_empty()
+            && !addresses.into_iter().any(|address| {
+                let known = self
+                    .reachable
+                    .lock()
+                    .unwrap_or_else(|err| err.into_inner())
+                    .get(&address)
+                    .copied();
+                // Checked without the lock, so other hosts are not held up.
+                known.unwrap_or_else(|| {
+                    let reachable = network::can_reach(&address.0, address.1);
+                    self.reachable
+                        .lock()
+                        .unwrap_or_else(|err| err.into_inner())
+                        .insert(address, reachable);
+                    reachable
+                })
+            })
+    }
+
+    /// Runs one attempt of the command in `repo_path` with `run`, adding
+    /// the time it takes to the repository's run time. Waiting for a
+    /// network slot or the throttle is left out.
+    fn timed<T>(&self, repo_path: &Path, run: impl FnOnce() -> T) -> T {
+        let started = Instant::now();
+        let result = run();
+        *self
+            .timings
+            .lock()
+            .unwrap_or_else(|err| err.into_inner())
+            .entry(repo_path.to_path_buf())
+            .or_default() += started.elapsed();
+        result
+    }
+
+    /// Returns how long the command has run in `repo_path`.
+    fn elapsed(&self, repo_path: &Path) -> Duration {
+        let timings = self.timings.lock().unwrap_or_else(|err| err.into_inner());
+        timings.get(repo_path).copied().unwrap_or_default()
+    }
+
+    /// Presents the captured run of `command` in `repo_path` in the output
+    /// format of `options`, then records how it went. Returns true when it
+    /// succeeded.
+    fn present(
+        &self,
+        repo_path: &Path,
+        command: &grpgit::RepoCommand,
+        captured: grpgit::Captured,
+        options: &ExecutionOptions,
+    ) -> bool {
+        self.spilled
+            .lock()
+            .unwrap_or_else(|err| err.into_inner())
+            .extend(captured.spilled.iter().cloned());
+        if let Some(logs) = &self.logs {
+            let elapsed = self.elapsed(repo_path);
+            if let Err(err) = logs.write(repo_path, command, &captured, elapsed) {
+                self.print(|| {
+                    tracing::warn!("cannot write the log for {}: {err}", repo_path.display())
+                });
+            }
+        }
+        if options.output == OutputFormat::Json || options.reports {
+            let elapsed = self.elapsed(repo_path);
+            let report = RepositoryReport::new(repo_path, command, &captured, elapsed);
+            self.reports
+                .lock()
+                .unwrap_or_else(|err| err.into_inner())
+                .push(report);
+        }
+        if let Some(dashboard) = self.dashboard.get() {
+            dashboard.finished(repo_path, command, &captured);
+            return self.record(repo_path, captured.result);
+        }
+        let result = match options.output {
+            OutputFormat::Text
+                if options.quiet
+                    && captured.result.is_ok()
+                    && command.is_routine_output(&captured.stdout)
+                    && command.is_routine_output(&captured.stderr) =>
+            {
+                captured.result
+            }
+            OutputFormat::Text if options.group_by.is_some() || options.sort_results.is_some() => {
+                let captured = self.render_changes(repo_path, command, captured, options);
+                self.hold(repo_path, captured)
+            }
+            OutputFormat::Table => self.hold(repo_path, captured),
+            OutputFormat::Text => {
+                let captured = self.render_changes(repo_path, command, captured, options);
+                self.print(|| grpgit::print_captured_result(repo_path, captured))
+            }
+            OutputFormat::Json => captured.result,
+            OutputFormat::Jsonl => {
+                let elapsed = self.elapsed(repo_path);
+                let report = RepositoryReport::new(repo_path, command, &captured, elapsed);
+                self.print(|| report.write_line(io::stdout().lock()))
+                    .and(captured.result)
+            }
+            OutputFormat::Porcelain => {
+                let outcome = if captured.result.is_ok() {
+                    "succeeded"
+                } else {
+                    "failed"
+                };
+                let message = report::message(&captured);
+                self.print(|| {
+                    report::write_porcelain(
+                        io::stdout().lock(),
+                        repo_path,
+                        outcome,
+                        captured.exit_code,
+                        &message,
+                    )
+                })
+                .and(captured.result)
+            }
+            OutputFormat::Csv | OutputFormat::Tsv => {
+                let elapsed = self.elapsed(repo_path);
+                let branch = grpgit::current_branch(repo_path);
+                let row = TableRow::new(repo_path, branch, &captured, elapsed);
+                self.print(|| self.write_table_row(options.output, Some(&row)))
+                    .and(captured.result)
+            }
+        };
+        self.record(repo_path, result)
+    }
+
+    /// Colors the output of `status` or `diff` in `captured`, ending it with
+    /// the changes it shows in `repo_path`, which are kept for the total at
+    /// the end of the run.
+    fn render_changes(
+        &self,
+        repo_path: &Path,
+        command: &grpgit::RepoCommand,
+        mut captured: grpgit::Captured,
+        options: &ExecutionOptions,
+    ) -> grpgit::Captured {
+        let Some(kind) = command.change_kind().filter(|_| options.render_changes) else {
+            return captured;
+        };
+        let mut stat = diffstat::count(kind, &captured.stdout);
+        if kind == diffstat::ChangeKind::Status && !stat.is_empty() {
+            if let Some(lines) = diffstat::status_lines(repo_path) {
+                stat.insertions = lines.insertions;
+                stat.deletions = lines.deletions;
+            }
+        }
+        captured.stdout = diffstat::render(kind, &captured.stdout);
+        if !stat.is_empty() {
+            captured
+                .stdout
+                .extend_from_slice(format!(" {stat}\n").as_bytes());
+        }
+        self.changes
+            .lock()
+            .unwrap_or_else(|err| err.into_inner())
+            .push((repo_path.to_path_buf(), stat));
+        captured
+    }
+
+    /// Prints the changes shown by `status` or `diff` in each repository,
+    /// named relative to `roots`, and their total across the run.
+    fn print_changes(&self, roots: &[PathBuf]) -> Result<(), io::Error> {
+        let changes = self.changes.lock().unwrap_or_else(|err| err.into_inner());
+        let mut repositories: Vec<(String, DiffStat)> = changes
+            .iter()
+            .map(|(repo_path, stat)| (selector::relative_path(repo_path, roots), *stat))
+            .collect();
+        repositories.sort_by(|a, b| a.0.cmp(&b.0));
+        diffstat::write_fleet(io::stdout().lock(), &repositories)
+    }
+
+    /// Holds back the captured run in `repo_path` to be printed once the run
+    /// is over, and returns its result.
+    fn hold(&self, repo_path: &Path, captured: grpgit::Captured) -> Result<(), io::Error> {
+        let result = match &captured.result {
+            Ok(()) => Ok(()),
+            Err(err) => Err(io::Error::new(err.kind(), err.to_string())),
+        };
+        self.held
+            .lock()
+            .unwrap_or_else(|err| err.into_inner())
+            .push((repo_path.to_path_buf(), captured));
+        result
+    }
+
+    /// Sorts the output held back and the reports kept for the end of the
+    /// run in `order`.
+    fn sort_results(&self, order: ResultOrder) {
+        let mut held = self.held.lock().unwrap_or_else(|err| err.into_inner());
+        held.sort_by(|(a, a_captured), (b, b_captured)| {
+            order.compare(
+                (a, a_captured.result.is_err(), self.elapsed(a)),
+                (b, b_captured.result.is_err(), self.elapsed(b)),
+            )
+        });
+        let mut reports = self.reports.lock().unwrap_or_else(|err| err.into_inner());
+        reports.sort_by(|a, b| {
+            order.compare(
+                (&a.path, !a.success, self.elapsed(&a.path)),
+                (&b.path, !b.success, self.elapsed(&b.path)),
+            )
+        });
+    }
+
+    /// Prints the output held back by `--sort-results` in its order.
+    fn print_held(&self) {
+        let held = std::mem::take(&mut *self.held.lock().unwrap_or_else(|err| err.into_inner()));
+        for (repo_path, captured) in held {
+            // Failures were already reported as the run went.
+            let _ = grpgit::print_captured_result(&repo_path, captured);
+        }
+    }
+
+    /// Prints the output held back by `--table` as a table of the
+    /// repositories, named relative to `roots`. They are in order of their
+    /// names, unless `--sort-results` gives another `order`, which puts
+    /// skipped repositories last. Skipped repositories are listed with the
+    /// reason.
+    fn print_table(&self, roots: &[PathBuf], order: Option<ResultOrder>) -> Result<(), io::Error> {
+        let held = self.held.lock().unwrap_or_else(|err| err.into_inner());
+        let skipped = self.skipped.lock().unwrap_or_else(|err| err.into_inner());
+        let mut entries: Vec<TableEntry> = held
+            .iter()
+            .map(|(repo_path, captured)| {
+                TableEntry::new(selector::relative_path(repo_path, roots), captured)
+            })
+            .chain(skipped.iter().map(|(repo_path, reason)| {
+                TableEntry::skipped(selector::relative_path(repo_path, roots), reason)
+            }))
+            .collect();
+        if matches!(order, None | Some(ResultOrder::Name)) {
+            entries.sort_by(|a, b| a.name.cmp(&b.name));
+        }
+        table::write_table(io::stdout().lock(), &entries)
+    }
+
+    /// Prints the output held back by `--group-by outcome` in sections: the
+    /// repositories that failed, then those skipped, then those that
+    /// succeeded. Empty sections are left out.
+    fn print_groups(&self) {
+        let held = std::mem::take(&mut *self.held.lock().unwrap_or_else(|err| err.into_inner()));
+        let (failed, succeeded): (Vec<_>, Vec<_>) = held
+            .into_iter()
+            .partition(|(_, captured)| captured.result.is_err());
+        let skipped = self.skipped.lock().unwrap_or_else(|err| err.into_inner());
+
+        let print_section = |outcome, results: Vec<(PathBuf, grpgit::Captured)>| {
+            if !results.is_empty() {
+                println!("{}", output::section(outcome, results.len()));
+            }
+            for (repo_path, captured) in results {
+                // Failures were already reported as the run went.
+                let _ = grpgit::print_captured_result(&repo_path, captured);
+            }
+        };
+        print_section(output::Outcome::Failed, failed);
+        if !skipped.is_empty() {
+            println!(
+                "{}",
+                output::section(output::Outcome::Skipped, skipped.len())
+            );
+        }
+        for (repo_path, reason) in skipped.iter() {
+            println!("{}: {reason}", repo_path.display());
+        }
+        print_section(output::Outcome::Succeeded, succeeded);
+    }
+
+    /// Returns the counts for the run, which started at `started`.
+    fn summary(&self, started: Instant) -> Summary {
+        let failed = self.failures.load(Ordering::Relaxed);
+        let repositories = self.completed.load(Ordering::Relaxed);
+        Summary {
+            run_id: self.run_id.clone(),
+            repositories,
+            succeeded: repositories - failed,
+            failed,
+            skipped: self.skipped_dirty.load(Ordering::Relaxed)
+                + self.skipped_offline.load(Ordering::Relaxed),
+            interrupted: interrupt::interrupted(),
+            duration_secs: started.elapsed().as_secs_f64(),
+            spilled: self
+                .spilled
+                .lock()
+                .unwrap_or_else(|err| err.into_inner())
+                .clone(),
+        }
+    }
+
+    /// Writes each of the `--report` `targets` for the run, which started
+    /// at `started`.
+    fn write_reports(&self, targets: &[ReportTarget], started: Instant) -> Result<(), String> {
+        let reports = self.reports.lock().unwrap_or_else(|err| err.into_inner());
+        let skipped = self.skipped.lock().unwrap_or_else(|err| err.into_inner());
+        let summary = self.summary(started);
+        for target in targets {
+            target.write(&reports, &skipped, &summary).map_err(|err| {
+                format!("cannot write the report {}: {err}", target.path.display())
+            })?;
+        }
+        Ok(())
+    }
+
+    /// Writes the manifest of the logs of the run, which started at
+    /// `started`, with `--log-dir`.
+    fn write_log_manifest(&self, started: Instant) {
+        let Some(logs) = &self.logs else {
+            return;
+        };
+        match logs.write_manifest(self.summary(started)) {
+            Ok(manifest_path) => {
+                tracing::info!("wrote logs, listed in {}", manifest_path.display())
+            }
+            Err(err) => tracing::warn!("cannot write the log manifest: {err}"),
+        }
+    }
+
+    /// Prints `row` in the tabular `format`, after the header if it has not
+    /// been printed yet.
+    fn write_table_row(&self, format: OutputFormat, row: Option<&TableRow>) -> io::Result<()> {
+        let mut header_written = self
+            .table_header
+            .lock()
+            .unwrap_or_else(|err| err.into_inner());
+        let mut stdout = io::stdout().lock();
+        if !*header_written {
+            format.write_header(&mut stdout)?;
+            *header_written = true;
+        }
+        match row {
+            Some(row) => row.write(format, stdout),
+            None => Ok(()),
+        }
+    }
+
+    /// Prints the end of the machine-readable `output` for the run, which
+    /// started at `started`: the whole `--output json` document, the
+    /// summary line of `--output jsonl`, the header of `--output csv` when
+    /// no repository printed a row, or the skipped repositories of
+    /// `--porcelain`.
+    fn print_report(&self, output: OutputFormat, started: Instant) -> Result<(), io::Error> {
+        let summary = self.summary(started);
+        match output {
+            OutputFormat::Text => Ok(()),
+            OutputFormat::Json => {
+                let reports = self.reports.lock().unwrap_or_else(|err| err.into_inner());
+                let report = RunReport {
+                    repositories: &reports,
+                    summary,
+                };
+                report.write(io::stdout().lock())
+            }
+            OutputFormat::Jsonl => SummaryLine { summary }.write(io::stdout().lock()),
+            // Only the header when no repository was run.
+            OutputFormat::Csv | OutputFormat::Tsv => self.write_table_row(output, None),
+            // Printed with the roots the repositories are named after.
+            OutputFormat::Table => Ok(()),
+            OutputFormat::Porcelain => {
+                let skipped = self.skipped.lock().unwrap_or_else(|err| err.into_inner());
+                let mut stdout = io::stdout().lock();
+                for (repo_path, reason) in skipped.iter() {
+                    report::write_porcelain(&mut stdout, repo_path, "skipped", None, reason)?;
+                }
+                Ok(())
+            }
+        }
+    }
+
+    /// Returns the repositories the command ran in with their run times,
+    /// slowest first.
+    fn slowest(&self) -> Vec<(PathBuf, Duration)> {
+        let timings = self.timings.lock().unwrap_or_else(|err| err.into_inner());
+        let mut slowest: Vec<(PathBuf, Duration)> = timings
+            .iter()
+            .map(|(repo_path, elapsed)| (repo_path.clone(), *elapsed))
+            .collect();
+        slowest.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        slowest
+    }
+
+    /// Prints how long the command took in each repository, slowest first:
+    /// every repository with `all`, and otherwise the ten slowest when more
+    /// than ten ran, unless `quiet`.
+    fn report_timings(&self, all: bool, quiet: bool) {
+        let mut slowest = self.slowest();
+        if all {
+            eprintln!("grpr: time per repository:");
+        } else if !quiet && slowest.len() > SLOWEST_SHOWN {
+            slowest.truncate(SLOWEST_SHOWN);
+            eprintln!("grpr: slowest {SLOWEST_SHOWN} repositories (--stats for all):");
+        } else {
+            return;
+        }
+        for (repo_path, elapsed) in slowest {
+            eprintln!("{:>10}  {}", format_elapsed(elapsed), repo_path.display());
+        }
+    }
+
+    /// Counts a repository whose command was retried, and whether a retry
+    /// succeeded.
+    fn record_retried(&self, recovered: bool) {
+        self.retried.fetch_add(1, Ordering::Relaxed);
+        if recovered {
+            self.recovered.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// Records the command run again from the dashboard in `repo_path`,
+    /// where it had failed, no longer counting the failure once it
+    /// succeeds.
+    fn record_rerun(&self, repo_path: &Path, succeeded: bool) {
+        self.record_retried(succeeded);
+        if !succeeded {
+            return;
+        }
+        let mut outcomes = self.outcomes.lock().unwrap_or_else(|err| err.into_inner());
+        if outcomes.insert(repo_path.to_path_buf(), Outcome::Succeeded) == Some(Outcome::Failed) {
+            self.failures.fetch_sub(1, Ordering::Relaxed);
+        }
+    }
+
+    /// Records the outcome of the run in each of the `selected` repositories,
+    /// for `--resume` and `--retry-failed`.
+    fn journal(&self, command: &grpgit::RepoCommand, selected: &[PathBuf]) -> Journal {
+        let outcomes = self.outcomes.lock().unwrap_or_else(|err| err.into_inner());
+        let repositories = selected
+            .iter()
+            .map(|repo_path| JournalEntry {
+                path: repo_path.clone(),
+                outcome: outcomes
+                    .get(repo_path)
+                    .copied()
+                    .unwrap_or(Outcome::NotStarted),
+            })
+            .collect();
+        Journal {
+            command: command.words(),
+            invocation: Some(command.invocation().clone()),
+            repositories,
+        }
+    }
+
+    /// Starts no further repositories, without counting a failure.
+    fn stop(&self) {
+        self.stopped.store(true, Ordering::Relaxed);
+    }
+
+    /// Returns true once no further repositories should be started, after a
+    /// failure under `--fail-fast` or when grpr is interrupted.
+    fn stopped(&self) -> bool {
+        self.stopped.load(Ordering::Relaxed) || interrupt::interrupted()
+    }
+
+    /// Reports any retries, then turns the failures of the run into the
+    /// error that sets the exit code: one for a run where some repositories
+    /// failed, and another for a run where all of them did.
+    fn finish(&self) -> Result<(), Box<dyn Error>> {
+        let skipped_dirty = self.skipped_dirty.load(Ordering::Relaxed);
+        if skipped_dirty > 0 {
+            tracing::info!(
+                "skipped {} with local modifications (--require-clean)",
+                count_repositories(skipped_dirty)
+            );
+        }
+        let skipped_offline = self.skipped_offline.load(Ordering::Relaxed);
+        if skipped_offline > 0 {
+            tracing::info!(
+                "skipped the network command in {} while offline",
+                count_repositories(skipped_offline)
+            );
+        }
+        let spilled = self.spilled.lock().unwrap_or_else(|err| err.into_inner());
+        if !spilled.is_empty() {
+            tracing::info!("output cut short by --max-output is kept in full in:");
+            for path in spilled.iter() {
+                tracing::info!("  {}", path.display());
+            }
+        }
+        drop(spilled);
+        let retried = self.retried.load(Ordering::Relaxed);
+        if retried > 0 {
+            let recovered = self.recovered.load(Ordering::Relaxed);
+            tracing::info!(
+                "retried the command in {}; {recovered} succeeded on retry",
+                count_repositories(retried)
+            );
+        }
+
+        let failures = self.failures.load(Ordering::Relaxed);
+        let completed = self.completed.load(Ordering::Relaxed);
+        if interrupt::interrupted() {
+            let mut message = format!(
+                "interrupted after running the command in {}",
+                count_repositories(completed)
+            );
+            if failures > 0 {
+                message.push_str(&format!("; {failures} failed"));
+            }
+            return Err(ExitError {
+                code: interrupt::EXIT_INTERRUPTED,
+                message,
+            }
+            .into());
+        }
+        if failures == 0 {
+            return Ok(());
+        }
+
+        let mut message = format!(
+            "command failed in {failures} of {}",
+            count_repositories(completed)
+        );
+        let skipped = self
+            .skipped
+            .lock()
+            .unwrap_or_else(|err| err.into_inner())
+            .len();
+        let unstarted = self
+            .selected
+            .load(Ordering::Relaxed)
+            .saturating_sub(completed + skipped);
+        if unstarted > 0 && self.on_failure == FailurePolicy::FailFast {
+            message.push_str(&format!(
+                "; stopped early with {} not started (--fail-fast)",
+                count_repositories(unstarted)
+            ));
+        }
+        let code = if failures == completed && unstarted == 0 {
+            EXIT_ALL_FAILED
+        } else {
+            EXIT_SOME_FAILED
+        };
+        Err(ExitError { code, message }.into())
+    }
+}
+
+/// Results waiting for every earlier repository to be printed.
+#[derive(Default)]
+struct PendingResults {
+    next: usize,
+    /// Results by index; `None` marks a repository skipped by `--fail-fast`
+    /// or `--require-clean`.
+    completed: BTreeMap<usize, Option<grpgit::Captured>>,
+}
+
+/// Buffers results that complete out of order and prints each one as soon as
+/// every result before it has been printed.
+struct OrderedPrinter<'a> {
+    repositories: &'a [PathBuf],
+    pending: Mutex<PendingResults>,
+    command: &'a grpgit::RepoCommand,
+    options: &'a ExecutionOptions,
+    status: &'a RunStatus,
+}
+
+impl<'a> OrderedPrinter<'a> {
+    fn new(
+        repositories: &'a [PathBuf],
+        command: &'a grpgit::RepoCommand,
+        options: &'a ExecutionOptions,
+        status: &'a RunStatus,
+    ) -> Self {
+        Self {
+            repositories,
+            pending: Mutex::new(PendingResults::default()),
+            command,
+            options,
+            status,
+        }
+    }
+
+    /// Records the result for the repository at `index` and prints every
+    /// result that is now ready.
+    fn complete(&self, index: usize, result: Option<grpgit::Captured>) {
+        let mut pending = self.pending.lock().unwrap_or_else(|err| err.into_inner());
+        pending.completed.insert(index, result);
+
+        loop {
+            let next = pending.next;
+            let Some(result) = pending.completed.remove(&next) else {
+                break;
+            };
+            if let Some(result) = result {
+                let repo_path = &self.repositories[next];
+                self.status
+                    .present(repo_path, self.command, result, self.options);
+            }
+            pending.next += 1;
+        }
+    }
+}
+
+/// Executes a git command across the discovered repositories. Processing is
+/// sequential by default and becomes parallel only when a thread count is
+/// provided.
+fn execute_repositories(
+    repositories: &[PathBuf],
+    command: &grpgit::RepoCommand,
+    options: &ExecutionOptions,
+    status: &RunStatus,
+) -> Result<(), Box<dyn Error>> {
+    if let Some(thread_count) = options.threads.filter(|count| *count > 1) {
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(thread_count)
+            .build()?;
+
+        let limits = NetworkLimits::new(command, options);
+        // Start repositories taking each remote host in turn.
+        let order: Vec<usize> = match limits.hosts {
+            Some(hosts) => hosts.interleave(repositories),
+            None => (0..repositories.len()).collect(),
+        };
+
+        if options.ordered_output {
+            let printer = OrderedPrinter::new(repositories, command, options, status);
+            pool.install(|| {
+                order.into_iter().par_bridge().for_each(|index| {
+                    let repo_path = &repositories[index];
+                    let skipped = status.stopped()
+                        || status.skip_offline(repo_path, command, options)
+                        || status.skip_dirty(repo_path, options);
+                    let result = (!skipped).then(|| {
+                        let _spinner = status.running(repo_path);
+                        run_with_retries(
+                            repo_path,
+                            &options.retry,
+                            status,
+                            || {
+                                let _permits = dispatch(&limits, options, repo_path);
+                                status.timed(repo_path, || {
+                                    grpgit::capture_command(repo_path, command, &options.hooks)
+                                })
+                            },
+                            |captured| captured.result.is_ok(),
+                        )
+                    });
+                    printer.complete(index, result);
+                });
+            });
+            return Ok(());
+        }
+
+        let ordered = order.into_iter().map(|index| repositories[index].clone());
+        execute_stream(ordered, command, options, status)?;
+        return Ok(());
+    }
+
+    execute_stream(repositories.iter().cloned(), command, options, status)?;
+    Ok(())
+}
+
+/// What one worker needs to run the command in a repository.
+struct Worker<'a> {
+    command: &'a grpgit::RepoCommand,
+    options: &'a ExecutionOptions,
+    status: &'a RunStatus,
+    confirmation: Option<&'a Confirmation>,
+    limits: &'a NetworkLimits<'a>,
+}
+
+impl Worker<'_> {
+    /// Runs the command in `repo_path` and records the outcome, returning
+    /// whether it succeeded. Returns `None` when the repository is skipped
+    /// because it is dirty, the user declined it, or the run was stopped.
+    fn process(&self, repo_path: &Path) -> Option<bool> {
+        let (options, status) = (self.options, self.status);
+        if status.skip_offline(repo_path, self.command, options)
+            || status.skip_dirty(repo_path, options)
+        {
+            return None;
+        }
+        if let Some(confirmation) = self.confirmation {
+            match confirmation.ask(repo_path) {
+                // Ctrl-C while the question was open.
+                Ok(Decision::Run) if status.stopped() => return None,
+                Ok(Decision::Run) => {}
+                Ok(Decision::Skip) => return None,
+                Ok(Decision::Quit) => {
+                    status.stop();
+                    return None;
+                }
+                Err(err) => {
+                    tracing::warn!("cannot ask for confirmation: {err}");
+                    status.stop();
+                    return None;
+                }
+            }
+        }
+        if options.buffers_output() {
+            let spinner = status.running(repo_path);
+            let captured = run_with_retries(
+                repo_path,
+                &options.retry,
+                status,
+                || {
+                    let _permits = dispatch(self.limits, options, repo_path);
+                    status.timed(repo_path, || {
+                        grpgit::capture_command(repo_path, self.command, &options.hooks)
+                    })
+                },
+                |captured| captured.result.is_ok(),
+            );
+            drop(spinner);
+            return Some(status.present(repo_path, self.command, captured, options));
+        }
+        let result = run_with_retries(
+            repo_path,
+            &options.retry,
+            status,
+            || {
+                let _permits = dispatch(self.limits, options, repo_path);
+                status.timed(repo_path, || {
+                    grpgit::process_repository(
+                        repo_path,
+                        self.command,
+                        &options.hooks,
+                        options.mode,
+                    )
+                })
+            },
+            Result::is_ok,
+        );
+        Some(status.record(repo_path, result))
+    }
+}
+
+/// Executes a git command across the selected repositories, honoring the
+/// ordering constraints between them with `--dependency-order`. Paths in
+/// the constraints are relative to `roots`.
+fn execute_selection(
+    repositories: &[PathBuf],
+    roots: &[PathBuf],
+    command: &grpgit::RepoCommand,
+    options: &ExecutionOptions,
+    status: &RunStatus,
+) -> Result<(), Box<dyn Error>> {
+    let Some(dependencies) = &options.dependencies else {
+        return execute_repositories(repositories, command, options, status);
+    };
+
+    let graph = dependencies.graph(repositories, roots)?;
+    let confirmation = options
+        .confirm
+        .then(|| Confirmation::new(command.command_line()));
+    let limits = NetworkLimits::new(command, options);
+    let worker = Worker {
+        command,
+        options,
+        status,
+        confirmation: confirmation.as_ref(),
+        limits: &limits,
+    };
+    graph.execute(
+        options.threads.unwrap_or(1),
+        |index| !status.stopped() && worker.process(&repositories[index]) == Some(true),
+        |index, prerequisite| {
+            if !status.stopped() {
+                let reason = format!("{} did not succeed", repositories[prerequisite].display());
+                status.skip(&repositories[index], &reason);
+            }
+        },
+    );
+    Ok(())
+}
+
+/// Executes a git command in each repository as `repositories` yields it,
+/// returning the repositories that were processed. Output is not reordered.
+/// Once `status` is stopped, no further repositories are taken. With
+/// `--confirm`, repositories the user declines are not processed.
+fn execute_stream(
+    repositories: impl Iterator<Item = PathBuf> + Send,
+    command: &grpgit::RepoCommand,
+    options: &ExecutionOptions,
+    status: &RunStatus,
+) -> Result<Vec<PathBuf>, Box<dyn Error>> {
+    let confirmation = options
+        .confirm
+        .then(|| Confirmation::new(command.command_line()));
+    let limits = NetworkLimits::new(command, options);
+    let process = |repo_path: &PathBuf| {
+        let worker = Worker {
+            command,
+            options,
+            status,
+            confirmation: confirmation.as_ref(),
+            limits: &limits,
+        };
+        worker.process(repo_path).is_some()
+    };
+    let repositories = repositories.take_while(|_| !status.stopped());
+
+    let Some(thread_count) = options.threads.filter(|count| *count > 1) else {
+        return Ok(repositories.filter(process).collect());
+    };
+
+    let pool = rayon::ThreadPoolBuilder::new()
+        .num_threads(thread_count)
+        .build()?;
+    let processed = Mutex::new(Vec::new());
+    pool.install(|| {
+        repositories.par_bridge().for_each(|repo_path| {
+            if process(&repo_path) {
+                processed
+                    .lock()
+                    .unwrap_or_else(|err| err.into_inner())
+                    .push(repo_path);
+            }
+        });
+    });
+
+    Ok(processed
+        .into_inner()
+        .unwrap_or_else(|err| err.into_inner()))
+}
+
+/// Describes every discovery-affecting flag so cached results are only reused
+/// for an identical search.
+fn discovery_fingerprint(cli: &Cli) -> String {
+    let exclude: Vec<&str> = cli.exclude.iter().map(Glob::glob).collect();
+
+    format!(
+        "max_depth={:?};exclude={exclude:?};hidden={};no_ignore={};nested={:?};\
+         skip_worktrees={};skip_submodules={};bare={};follow_symlinks={};\
+         one_file_system={};traversal={:?}",
+        cli.max_depth,
+        cli.hidden,
+        cli.no_ignore,
+        cli.nested,
+        cli.skip_worktrees,
+        cli.skip_submodules,
+        cli.bare,
+        cli.follow_symlinks,
+        cli.one_file_system,
+        cli.traversal,
+    )
+}
+
+/// How the discovery cache takes part in a run.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum CacheMode {
+    /// Always scan and leave the cache untouched.
+    Off,
+    /// Reuse fresh cache entries and record new scans.
+    Reuse,
+    /// Always scan and record the results.
+    Refresh,
+}
+
+/// Determines the cache mode. `--refresh` takes precedence over `--cached`.
+fn cache_mode_from_cli(cli: &Cli) -> CacheMode {
+    if cli.refresh {
+        CacheMode::Refresh
+    } else if cli.cached {
+        CacheMode::Reuse
+    } else {
+        CacheMode::Off
+    }
+}
+
+/// Loads the user's config file, if there is one.
+fn load_config() -> Result<Config, Box<dyn Error>> {
+    match config::default_config_path() {
+        Some(config_path) => {
+            tracing::trace!("reading the config file {}", config_path.display());
+            Ok(Config::load(&config_path)?)
+        }
+        None => Ok(Config::default()),
+    }
+}
+
+/// Inserts the flags of the profile `name` into the command line `args`,
+/// before the flags given there so those take precedence.
+fn with_profile(
+    mut args: Vec<OsString>,
+    name: &str,
+    config: &Config,
+) -> Result<Vec<OsString>, Box<dyn Error>> {
+    let flags = config.profile(name).ok_or_else(|| {
+        format!(
+            "unknown profile `{name}`; configured profiles: {}",
+            config.profile_names().join(", ")
+        )
+    })?;
+    let at = args.len().min(1);
+    args.splice(at..at, flags.into_iter().map(OsString::from));
+    Ok(args)
+}
+
+/// Resolves the directories to scan: the `--root` directories followed by the
+/// roots of each `--workspace`. Relative roots are resolved against the
+/// current directory, which is also the default root.
+fn roots_from_cli(
+    cli: &Cli,
+    current_dir: &Path,
+    config: &Config,
+) -> Result<Vec<PathBuf>, Box<dyn Error>> {
+    let mut roots: Vec<PathBuf> = cli
+        .roots
+        .iter()
+        .map(|root| current_dir.join(root))
+        .collect();
+
+    for name in &cli.workspace {
+        let workspace_roots = config.workspace_roots(name).ok_or_else(|| {
+            format!(
+                "unknown workspace `{name}`; configured workspaces: {}",
+                config.workspace_names().join(", ")
+            )
+        })?;
+        roots.extend(workspace_roots);
+    }
+
+    if roots.is_empty() {
+        roots.push(current_dir.to_path_buf());
+    }
+    Ok(roots)
+}
+
+/// Repositories found by discovery, along with the directories that could not
+/// be fully scanned before the scan timeout and those that could not be read.
+#[derive(Debug, Default, PartialEq, Eq)]
+struct Discovery {
+    /// The directories the repositories were found under. Listed
+    /// repositories are relative to the current directory.
+    roots: Vec<PathBuf>,
+    repositories: Vec<PathBuf>,
+    unscanned: Vec<PathBuf>,
+    errors: Vec<grpgit::ScanError>,
+}
+
+impl Discovery {
+    /// Target repositories listed without scanning the filesystem, taken
+    /// relative to `current_dir`.
+    fn listed(repositories: Vec<PathBuf>, current_dir: &Path) -> Self {
+        Self {
+            roots: vec![current_dir.to_path_buf()],
+            repositories,
+            ..Self::default()
+        }
+    }
+}
+
+/// Discovers repositories under `root`, giving up at `deadline` if one is set.
+fn discover_root(
+    root: &Path,
+    options: &grpgit::DiscoveryOptions,
+    deadline: Option<Instant>,
+) -> (grpgit::Scan, Vec<PathBuf>) {
+    match deadline {
+        Some(deadline) => grpgit::discover_repositories_until(root, options, deadline),
+        None => (grpgit::discover_repositories(root, options), Vec::new()),
+    }
+}
+
+/// Discovers repositories under each root in order, merging the results and
+/// dropping repositories that were already found under an earlier root.
+fn discover_repositories_from(
+    roots: &[PathBuf],
+    options: &grpgit::DiscoveryOptions,
+    deadline: Option<Instant>,
+) -> Discovery {
+    let mut discovery = Discovery::default();
+
+    for root in roots {
+        let (scan, unscanned) = discover_root(root, options, deadline);
+        merge_repositories(&mut discovery.repositories, scan.repositories);
+        discovery.unscanned.extend(unscanned);
+        discovery.errors.extend(scan.errors);
+    }
+
+    discovery
+}
+
+/// Appends `found` to `repositories`, skipping repositories already present.
+fn merge_repositories(repositories: &mut Vec<PathBuf>, found: Vec<PathBuf>) {
+    for repo_path in found {
+        if !repositories.contains(&repo_path) {
+            repositories.push(repo_path);
+        }
+    }
+}
+
+/// Discovers repositories under each root like [`discover_repositories_from`],
+/// consulting and updating the discovery cache at `cache_path`. An unreadable
+/// cache is reported and rebuilt. Roots whose scan timed out or hit unreadable
+/// directories are not cached.
+fn discover_repositories_cached(
+    roots: &[PathBuf],
+    options: &grpgit::DiscoveryOptions,
+    deadline: Option<Instant>,
+    fingerprint: &str,
+    mode: CacheMode,
+    cache_path: &Path,
+) -> Result<Discovery, Box<dyn Error>> {
+    let mut cache = DiscoveryCache::load(cache_path).unwrap_or_else(|err| {
+        tracing::warn!("ignoring discovery cache: {err}");
+        DiscoveryCache::default()
+    });
+    let mut discovery = Discovery::default();
+
+    for root in roots {
+        let cached = (mode == CacheMode::Reuse)
+            .then(|| cache.lookup(root, fingerprint))
+            .flatten();
+        let found = cached.unwrap_or_else(|| {
+            let (scan, unscanned) = discover_root(root, options, deadline);
+            if unscanned.is_empty() && scan.errors.is_empty() {
+                cache.store(root, fingerprint, &scan.repositories);
+            }
+            discovery.unscanned.extend(unscanned);
+            discovery.errors.extend(scan.errors);
+            scan.repositories
+        });
+        merge_repositories(&mut discovery.repositories, found);
+    }
+
+    cache.save(cache_path)?;
+    Ok(discovery)
+}
+
+/// Reports the directories that could not be read during discovery. With
+/// `--strict-scan`, any such directory fails the run.
+fn check_scan_errors(errors: &[grpgit::ScanError], strict: bool) -> Result<(), Box<dyn Error>> {
+    if errors.is_empty() {
+        return Ok(());
+    }
+
+    tracing::warn!("discovery could not read:");
+    for error in errors {
+        tracing::warn!("  {error}");
+    }
+
+    if strict {
+        Err("discovery skipped unreadable directories (--strict-scan)".into())
+    } else {
+        Ok(())
+    }
+}
+
+/// Reports the directories that discovery could not finish scanning within
+/// `--scan-timeout`.
+fn report_unscanned(unscanned: &[PathBuf], timeout_secs: u64) {
+    if unscanned.is_empty() {
+        return;
+    }
+
+    tracing::warn!("discovery timed out after {timeout_secs}s; not fully scanned:");
+    for dir in unscanned {
+        tracing::warn!("  {}", dir.display());
+    }
+}
+
+/// Formats the scanned roots for user-facing messages.
+fn describe_roots(roots: &[PathBuf]) -> String {
+    roots
+        .iter()
+        .map(|root| root.display().to_string())
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Returns the registry location, or an error when it cannot be determined.
+fn registry_path() -> Result<PathBuf, Box<dyn Error>> {
+    registry::default_registry_path()
+        .ok_or_else(|| "cannot locate the registry; set XDG_DATA_HOME or HOME".into())
+}
+
+const NO_PREVIOUS_RUN: &str = "no previous run to resume";
+
+/// Returns the location of the run journal, or an error when it cannot be
+/// determined.
+fn journal_path() -> Result<PathBuf, Box<dyn Error>> {
+    journal::default_journal_path()
+        .ok_or_else(|| "cannot locate the run journal; set XDG_STATE_HOME or HOME".into())
+}
+
+/// Saves the outcomes of a run to the journal. A resumed run is folded into
+/// the run it resumes, so repositories it did not revisit are remembered.
+fn record_run(cli: &Cli, journal: Journal) -> Result<(), Box<dyn Error>> {
+    let journal_path = journal_path()?;
+    let journal = match Journal::load(&journal_path)? {
+        Some(mut previous) if cli.resume || cli.retry_failed => {
+            previous.update(journal);
+            previous
+        }
+        _ => journal,
+    };
+    Ok(journal.save(&journal_path)?)
+}
+
+/// Loads the repositories of the last run whose outcome is one of
+/// `outcomes`, reporting and skipping any that no longer exist.
+fn journal_repositories(
+    journal_path: &Path,
+    outcomes: &[Outcome],
+) -> Result<Vec<PathBuf>, Box<dyn Error>> {
+    let journal = Journal::load(journal_path)?.ok_or(NO_PREVIOUS_RUN)?;
+    Ok(existing_repositories(
+        journal.paths_with(outcomes),
+        "last run",
+    ))
+}
+
+/// Resolves the paths given to a registry subcommand. Paths are made absolute
+/// and canonical where possible, and default to the current directory.
+fn registry_targets(paths: &[PathBuf], current_dir: &Path) -> Vec<PathBuf> {
+    if paths.is_empty() {
+        return vec![current_dir.to_path_buf()];
+    }
+
+    paths
+        .iter()
+        .map(|path| {
+            let path = current_dir.join(path);
+            fs::canonicalize(&path).unwrap_or(path)
+        })
+        .collect()
+}
+
+/// Adds repositories to the registry, skipping paths that are not
+/// repositories.
+fn register_repositories(
+    registry_path: &Path,
+    paths: &[PathBuf],
+    current_dir: &Path,
+) -> Result<(), Box<dyn Error>> {
+    let mut registry = Registry::load(registry_path)?;
+
+    for repo_path in registry_targets(paths, current_dir) {
+        if grpgit::detect_repository(&repo_path).is_none() {
+            tracing::warn!("not a git repository: {}", repo_path.display());
+        } else if registry.register(repo_path.clone()) {
+            println!("Registered {}", repo_path.display());
+        } else {
+            println!("Already registered: {}", repo_path.display());
+        }
+    }
+
+    Ok(registry.save(registry_path)?)
+}
+
+/// Removes repositories from the registry.
+fn unregister_repositories(
+    registry_path: &Path,
+    paths: &[PathBuf],
+    current_dir: &Path,
+) -> Result<(), Box<dyn Error>> {
+    let mut registry = Registry::load(registry_path)?;
+
+    for repo_path in registry_targets(paths, current_dir) {
+        if registry.unregister(&repo_path) {
+            println!("Unregistered {}", repo_path.display());
+        } else {
+            tracing::warn!("not registered: {}", repo_path.display());
+        }
+    }
+
+    Ok(registry.save(registry_path)?)
+}
+
+/// Scans the roots and records every repository found in the registry,
+/// printing a summary of the changes.
+fn adopt_repositories(
+    cli: &Cli,
+    registry_path: &Path,
+    current_dir: &Path,
+) -> Result<(), Box<dyn Error>> {
+    let roots = roots_from_cli(cli, current_dir, &load_config()?)?;
+    let deadline = cli
+        .scan_timeout
+        .map(|secs| Instant::now() + Duration::from_secs(secs));
+    let discovery = discover_repositories_from(&roots, &discovery_options_from_cli(cli)?, deadline);
+    report_unscanned(&discovery.unscanned, cli.scan_timeout.unwrap_or_default());
+
+    check_scan_errors(&discovery.errors, cli.strict_scan)?;
+
+    let mut registry = Registry::load(registry_path)?;
+    let (mut added, mut updated) = (0, 0);
+    for repo_path in &discovery.repositories {
+        let repository = RegisteredRepository {
+            path: fs::canonicalize(repo_path).unwrap_or_else(|_| repo_path.clone()),
+            remote: grpgit::remote_url(repo_path, "origin"),
+            default_branch: grpgit::default_branch(repo_path),
+        };
+        match registry.record(repository) {
+            Recorded::Added => added += 1,
+            Recorded::Updated => updated += 1,
+            Recorded::Unchanged => {}
+        }
+    }
+    registry.save(registry_path)?;
+
+    println!(
+        "Adopted {} repositories under {}: {added} new, {updated} updated",
+        discovery.repositories.len(),
+        describe_roots(&roots)
+    );
+    Ok(())
+}
+
+/// Prints the registered repositories, one per line or NUL-terminated.
+fn list_registered_repositories(registry_path: &Path, print0: bool) -> Result<(), Box<dyn Error>> {
+    let terminator = if print0 { '\0' } else { '\n' };
+    for repo_path in Registry::load(registry_path)?.paths() {
+        print!("{}{terminator}", repo_path.display());
+    }
+
+    Ok(())
+}
+
+/// Prints each configured alias as `name = expansion`, sorted by name.
+fn list_aliases(config: &Config) {
+    for (name, expansion) in &config.aliases {
+        println!("{name} = {expansion}");
+    }
+}
+
+/// Selects, sorts, and batches the target repositories as a git command
+/// would, pairing each with the reasons it was selected.
+fn explain_selection(cli: &Cli, discovery: &Discovery) -> Vec<(PathBuf, Vec<String>)> {
+    let selector = selector_from_cli(cli, &discovery.roots);
+    let explained: Vec<(PathBuf, Vec<String>)> =
+        grpgit::dedupe_repositories(discovery.repositories.clone())
+            .into_par_iter()
+            .filter_map(|repo_path| {
+                let why = selector.reasons(&repo_path)?;
+                Some((repo_path, why))
+            })
+            .collect();
+
+    let mut repositories: Vec<PathBuf> = explained
+        .iter()
+        .map(|(repo_path, _)| repo_path.clone())
+        .collect();
+    if let Some(order) = repository_order(cli) {
+        sort_repositories(&mut repositories, order);
+    }
+    let mut reasons: HashMap<PathBuf, Vec<String>> = explained.into_iter().collect();
+    select_batch(repositories, cli.skip, cli.limit)
+        .into_iter()
+        .filter_map(|repo_path| {
+            let why = reasons.remove(&repo_path)?;
+            Some((repo_path, why))
+        })
+        .collect()
+}
+
+/// Prints the repositories a git command would run in, each with the
+/// selection options it satisfied, then how many of the candidates that is.
+fn which_repositories(cli: &Cli, current_dir: &Path) -> Result<(), Box<dyn Error>> {
+    let discovery = target_repositories(cli, current_dir)?;
+    let candidates = grpgit::dedupe_repositories(discovery.repositories.clone()).len();
+    let selected = explain_selection(cli, &discovery);
+
+    for (repo_path, reasons) in &selected {
+        if reasons.is_empty() {
+            println!("{}", repo_path.display());
+        } else {
+            println!("{}  [{}]", repo_path.display(), reasons.join(", "));
+        }
+    }
+    tracing::info!("{} of {candidates} repositories selected", selected.len());
+
+    check_scan_errors(&discovery.errors, cli.strict_scan)
+}
+
+/// Keeps the listed repositories that still exist, reporting the rest with
+/// `source` describing where they were listed.
+fn existing_repositories(repositories: Vec<PathBuf>, source: &str) -> Vec<PathBuf> {
+    repositories
+        .into_iter()
+        .filter(|repo_path| {
+            let exists = grpgit::detect_repository(repo_path).is_some();
+            if !exists {
+                tracing::warn!("{source} repository is missing: {}", repo_path.display());
+            }
+            exists
+        })
+        .collect()
+}
+
+/// Loads the registered repositories, reporting and skipping any that no
+/// longer exist.
+fn registered_repositories(registry_path: &Path) -> Result<Vec<PathBuf>, Box<dyn Error>> {
+    let listed = Registry::load(registry_path)?.paths();
+    Ok(existing_repositories(listed, "registered"))
+}
+
+/// Loads the repositories listed in a manifest, reporting and skipping any
+/// that do not exist.
+fn manifest_repositories(
+    manifest_path: &Path,
+    current_dir: &Path,
+) -> Result<Vec<PathBuf>, Box<dyn Error>> {
+    let listed = manifest::load_manifest(&current_dir.join(manifest_path))?;
+    Ok(existing_repositories(listed, "manifest"))
+}
+
+/// Collects the target repositories from the registry, a manifest, or by
+/// scanning the roots, reporting when none are found.
+fn target_repositories(cli: &Cli, current_dir: &Path) -> Result<Discovery, Box<dyn Error>> {
+    if cli.registered {
+        let repositories = registered_repositories(&registry_path()?)?;
+        if repositories.is_empty() {
+            tracing::info!("no registered repositories found");
+        }
+        return Ok(Discovery::listed(repositories, current_dir));
+    }
+
+    if cli.resume || cli.retry_failed {
+        let outcomes: &[Outcome] = if cli.resume {
+            &[Outcome::Failed, Outcome::NotStarted]
+        } else {
+            &[Outcome::Failed]
+        };
+        let repositories = journal_repositories(&journal_path()?, outcomes)?;
+        if repositories.is_empty() {
+            tracing::info!("nothing left to run from the last run");
+        }
+        return Ok(Discovery::listed(repositories, current_dir));
+    }
+
+    if cli.stdin {
+        let listed = manifest::read_repository_list(io::stdin().lock(), cli.null_separated)?;
+        let listed = listed
+            .into_iter()
+            .map(|repo_path| current_dir.join(repo_path))
+            .collect();
+        let repositories = existing_repositories(listed, "stdin");
+        if repositories.is_empty() {
+            tracing::info!("no git repositories read from stdin");
+        }
+        return Ok(Discovery::listed(repositories, current_dir));
+    }
+
+    if let Some(manifest_path) = &cli.manifest {
+        let repositories = manifest_repositories(manifest_path, current_dir)?;
+        if repositories.is_empty() {
+            tracing::info!("no git repositories listed in {}", manifest_path.display());
+        }
+        return Ok(Discovery::listed(repositories, current_dir));
+    }
+
+    let discovery_options = discovery_options_from_cli(cli)?;
+    let roots = roots_from_cli(cli, current_dir, &load_config()?)?;
+    let deadline = cli
+        .scan_timeout
+        .map(|secs| Instant::now() + Duration::from_secs(secs));
+    let discovery = match cache_mode_from_cli(cli) {
+        CacheMode::Off => discover_repositories_from(&roots, &discovery_options, deadline),
+        mode => {
+            let cache_path = cache::default_cache_path()
+                .ok_or("cannot locate the discovery cache; set XDG_CACHE_HOME or HOME")?;
+            discover_repositories_cached(
+                &roots,
+                &discovery_options,
+                deadline,
+                &discovery_fingerprint(cli),
+                mode,
+                &cache_path,
+            )?
+        }
+    };
+    report_unscanned(&discovery.unscanned, cli.scan_timeout.unwrap_or_default());
+    if discovery.repositories.is_empty() {
+        tracing::info!("no git repositories found under {}", describe_roots(&roots));
+    }
+
+    Ok(Discovery { roots, ..discovery })
+}
+
+/// Returns true when the command can start in each repository as soon as it is
+/// found. This applies to parallel runs that scan the filesystem without a
+/// cache or deadline, and whose selection does not depend on the complete list
+/// of repositories.
+fn streams_discovery(cli: &Cli) -> bool {
+    cli.threads.is_some_and(|count| count > 1)
+        && !cli.registered
+        && !cli.stdin
+        && !cli.resume
+        && !cli.retry_failed
+        && cli.manifest.is_none()
+        && cli.per_host.is_none()
+        && !cli.dependency_order
+        && cache_mode_from_cli(cli) == CacheMode::Off
+        && cli.scan_timeout.is_none()
+        && cli.sort.is_none()
+        && cli.skip == 0
+        && cli.limit.is_none()
+        && !cli.pick
+        && !cli.dry_run
+        && !cli.confirm
+        && !cli.tui
+}
+
+/// Scans the roots and executes the git command in each repository while the
+/// rest of the tree is still being scanned. Returns the selected repositories,
+/// including any left unstarted when the run stopped early, and the
+/// directories that could not be read, along with the number of repositories
+/// found before selection.
+fn execute_while_discovering(
+    cli: &Cli,
+    current_dir: &Path,
+    command: &grpgit::RepoCommand,
+    options: &ExecutionOptions,
+    status: &RunStatus,
+) -> Result<(Discovery, usize), Box<dyn Error>> {
+    let discovery_options = discovery_options_from_cli(cli)?;
+    let roots = roots_from_cli(cli, current_dir, &load_config()?)?;
+
+    let mut found_count = 0;
+    let (processed, errors) = pipeline::stream_repositories(&roots, &discovery_options, |found| {
+        let selector = selector_from_cli(cli, &roots);
+        let mut selected = Vec::new();
+        let mut remaining = found
+            .inspect(|_| found_count += 1)
+            .filter(|repo_path| selector.matches(repo_path))
+            .inspect(|repo_path| selected.push(repo_path.clone()))
+            .inspect(|_| status.expect(1));
+        let started = std::iter::from_fn(|| {
+            if status.stopped() {
+                None
+            } else {
+                remaining.next()
+            }
+        });
+        execute_stream(started, command, options, status)?;
+        // Finish the scan so the journal lists the repositories never started.
+        if status.stopped() {
+            remaining.for_each(drop);
+        }
+        Ok::<_, Box<dyn Error>>(selected)
+    });
+    let repositories = processed?;
+    if found_count == 0 {
+        tracing::info!("no git repositories found under {}", describe_roots(&roots));
+    }
+
+    let discovery = Discovery {
+        roots,
+        repositories,
+        errors,
+        ..Discovery::default()
+    };
+    Ok((discovery, found_count))
+}
+
+/// Runs `execute` while showing its progress on the `--tui` dashboard of
+/// `repositories`, and returns what it returns once the dashboard is left.
+/// Failed repositories run again from the dashboard run their command
+/// alongside the rest of the run.
+fn run_dashboard(
+    repositories: &[PathBuf],
+    options: &ExecutionOptions,
+    status: &RunStatus,
+    execute: impl FnOnce() -> Result<Vec<PathBuf>, Box<dyn Error>> + Send,
+) -> Result<Vec<PathBuf>, Box<dyn Error>> {
+    let dashboard = status.show_dashboard(repositories);
+    let title = env::args().collect::<Vec<_>>().join(" ");
+    logging::hold_stderr(true);
+    let (shown, executed) = std::thread::scope(|scope| {
+        let runner = scope.spawn(|| execute().map_err(|err| err.to_string()));
+        let shown = dashboard::show(
+            dashboard,
+            &title,
+            || runner.is_finished() && dashboard.counts().running == 0,
+            |repo_path| {
+                let Some(command) = dashboard.failed_command(repo_path) else {
+                    return;
+                };
+                dashboard.started(repo_path);
+                let repo_path = repo_path.to_path_buf();
+                scope.spawn(move || {
+                    let captured = status.timed(&repo_path, || {
+                        grpgit::capture_command(&repo_path, &command, &options.hooks)
+                    });
+                    dashboard.finished(&repo_path, &command, &captured);
+                    status.record_rerun(&repo_path, captured.result.is_ok());
+                });
+            },
+        );
+        let executed = runner.join().expect("the run does not panic");
+        (shown, executed)
+    });
+    logging::hold_stderr(false);
+    shown.map_err(|err| format!("cannot show the dashboard: {err}"))?;
+    Ok(executed?)
+}
+
+/// Returns true when the output of the run may be held back for the pager:
+/// unless `--no-pager` is given, a command or hook may prompt on the
+/// terminal while it runs, or the run uses the terminal for something
+/// else, such as questions or a dashboard.
+fn pages_output(cli: &Cli, may_prompt: bool) -> bool {
+    !cli.no_pager
+        && !may_prompt
+        && cli.pre.is_none()
+        && cli.post.is_none()
+        && !cli.watch
+        && !cli.tui
+        && !cli.pick
+        && !cli.confirm
+        && cli.progress.is_none()
+}
+
+/// Returns true when the output of `command` is rendered with colors and
+/// counted: when it is `status` or `diff` and its output is printed as text
+/// to a terminal. Piped output is left as git prints it, so that a diff can
+/// still be applied.
+fn renders_changes(command: &grpgit::RepoCommand, options: &ExecutionOptions) -> bool {
+    command.change_kind().is_some()
+        && options.output == OutputFormat::Text
+        && options.mode == grpgit::RunMode::Execute
+        && !options.tui
+        && output::stdout_on_terminal()
+}
+
+/// Selects the target repositories and runs the command in each one.
+fn run_command_across(cli: &Cli, current_dir: &Path) -> Result<(), Box<dyn Error>> {
+    let started = Instant::now();
+    let mut execution_options = execution_options_from_cli(cli);
+    if cli.dependency_order {
+        let dependencies = Dependencies::new(&load_config()?.dependencies)?;
+        execution_options.dependencies = Some(Arc::new(dependencies));
+    }
+    if execution_options.sort_results.is_some()
+        && matches!(
+            execution_options.output,
+            OutputFormat::Jsonl | OutputFormat::Csv | OutputFormat::Tsv
+        )
+    {
+        return Err(format!(
+            "--sort-results cannot order --output {}, which prints each repository as it finishes",
+            execution_options
+                .output
+                .to_possible_value()
+                .expect("the format is a value of --output")
+                .get_name()
+        )
+        .into());
+    }
+    let command = command_to_run(cli)?;
+    execution_options.render_changes = renders_changes(&command, &execution_options);
+    let command = attach_terminal(command, &execution_options)?;
+    let task = task_for(cli, &command, &execution_options)?;
+    let uses_network = match &task {
+        Some(task) => task.commands().any(grpgit::RepoCommand::uses_network),
+        None => command.uses_network(),
+    };
+    let may_prompt = match &task {
+        Some(task) => task.commands().any(grpgit::RepoCommand::may_prompt),
+        None => command.may_prompt(),
+    };
+    // Output is captured while it is held back, so no command writes to the
+    // terminal behind the pager's back.
+    let pager = pages_output(cli, may_prompt).then(pager::start).flatten();
+    execution_options.paged = pager.is_some();
+    if uses_network
+        && execution_options.mode == grpgit::RunMode::Execute
+        && !execution_options.offline
+        && !cli.online
+        && !network::is_online()
+    {
+        // The route check alone misses networks that reach their remotes
+        // some other way, so only unreachable remotes are skipped.
+        tracing::warn!(
+            "no route to the internet; skipping network commands where the remote cannot be reached (--online to run them anyway)"
+        );
+        execution_options.check_remotes = true;
+    }
+    let logs =
+        match &cli.log_dir {
+            Some(dir) => Some(LogDir::create(dir).map_err(|err| {
+                format!("cannot create the log directory {}: {err}", dir.display())
+            })?),
+            None => None,
+        };
+    let status = RunStatus::new(execution_options.on_failure)
+        .with_progress(execution_options.progress)
+        .with_logs(logs);
+    // Machine-readable output carries the run ID in its summary instead.
+    if execution_options.output == OutputFormat::Text {
+        tracing::info!("run {}", status.run_id);
+    }
+    execution_options.hooks.max_output = output_limit(cli, &status.run_id);
+    // Dry runs change nothing, so only real runs are guarded.
+    let destructive = if cli.force || execution_options.mode == grpgit::RunMode::DryRun {
+        Vec::new()
+    } else {
+        load_config()?.destructive_commands()
+    };
+    // A destructive command waits for the complete selection, to list it,
+    // and a task for it to assign each repository its command.
+    let guarded = match &task {
+        Some(task) => task
+            .commands()
+            .any(|command| is_destructive(command, &destructive)),
+        None => is_destructive(&command, &destructive),
+    };
+
+    let (repositories, roots, scan_errors, found_any) =
+        if streams_discovery(cli) && !guarded && task.is_none() {
+            let (discovery, found_count) =
+                execute_while_discovering(cli, current_dir, &command, &execution_options, &status)?;
+            (
+                discovery.repositories,
+                discovery.roots,
+                discovery.errors,
+                found_count > 0,
+            )
+        } else {
+            let discovery = target_repositories(cli, current_dir)?;
+            // Having nothing left to resume is not a failure.
+            let found_any = !discovery.repositories.is_empty() || cli.resume || cli.retry_failed;
+            let repositories = grpgit::dedupe_repositories(discovery.repositories);
+            let mut repositories = selector_from_cli(cli, &discovery.roots).apply(repositories);
+            if let Some(order) = repository_order(cli) {
+                sort_repositories(&mut repositories, order);
+            }
+            let mut repositories = select_batch(repositories, cli.skip, cli.limit);
+            if cli.pick && !repositories.is_empty() {
+                repositories = pick::pick_repositories(repositories, &discovery.roots)?;
+                if repositories.is_empty() {
+                    tracing::info!("no repositories picked");
+                }
+            }
+            // Refused before the dashboard is shown, so the listing is seen.
+            match &task {
+                Some(task) if cli.tui => {
+                    for (command, repositories) in task.assign(&repositories, &discovery.roots) {
+                        check_destructive(command, &repositories, &destructive)?;
+                    }
+                }
+                Some(_) => {}
+                None => check_destructive(&command, &repositories, &destructive)?,
+            }
+            let execute = || match &task {
+                Some(task) => execute_task(
+                    task,
+                    &repositories,
+                    &discovery.roots,
+                    &destructive,
+                    &execution_options,
+                    &status,
+                ),
+                None => {
+                    status.expect(repositories.len());
+                    execute_selection(
+                        &repositories,
+                        &discovery.roots,
+                        &command,
+                        &execution_options,
+                        &status,
+                    )
+                    .map(|()| repositories.clone())
+                }
+            };
+            let repositories = if cli.tui && !repositories.is_empty() {
+                run_dashboard(&repositories, &execution_options, &status, execute)?
+            } else {
+                execute()?
+            };
+            (repositories, discovery.roots, discovery.errors, found_any)
+        };
+    status.clear_progress();
+    if let Some(order) = execution_options.sort_results {
+        status.sort_results(order);
+    }
+    if execution_options.group_by.is_some() {
+        status.print_groups();
+    } else if execution_options.sort_results.is_some()
+        && execution_options.output == OutputFormat::Text
+    {
+        status.print_held();
+    }
+    if execution_options.output == OutputFormat::Table {
+        status.print_table(&roots, execution_options.sort_results)?;
+    }
+    if execution_options.render_changes {
+        status.print_changes(&roots)?;
+    }
+    if execution_options.mode == grpgit::RunMode::Execute && !repositories.is_empty() {
+        let journal = status.journal(&command, &repositories);
+        if let Err(err) = record_run(cli, journal) {
+            tracing::warn!("cannot record the run for --resume: {err}");
+        }
+    }
+    status.print_report(execution_options.output, started)?;
+    status.write_log_manifest(started);
+    status.write_reports(&cli.report, started)?;
+    check_scan_errors(&scan_errors, cli.strict_scan)?;
+    if !found_any {
+        return Err(ExitError::no_repositories().into());
+    }
+
+    if cli.watch && !repositories.is_empty() {
+        watch::watch_repositories(&repositories, |changed| {
+            let status = RunStatus::new(execution_options.on_failure)
+                .with_progress(execution_options.progress);
+            let result = match &task {
+                Some(task) => {
+                    execute_task(task, changed, &roots, &[], &execution_options, &status).map(drop)
+                }
+                None => {
+                    status.expect(changed.len());
+                    execute_selection(changed, &roots, &command, &execution_options, &status)
+                }
+            }
+            .and_then(|()| {
+                status.clear_progress();
+                if execution_options.mode == grpgit::RunMode::Execute {
+                    status.report_timings(cli.stats, cli.quiet);
+                }
+                status.finish()
+            });
+            if let Err(err) = result {
+                tracing::error!("{err}");
+            }
+        })?;
+    }
+
+    if execution_options.mode == grpgit::RunMode::Execute {
+        status.report_timings(cli.stats, cli.quiet);
+    }
+    status.finish()
+}
+
+/// Parses CLI arguments and either handles a grpr subcommand or runs the
+/// requested git or `exec` command in each target repository.
+fn run() -> Result<(), Box<dyn Error>> {
+    let mut cli = Cli::parse();
+    if let Some(name) = &cli.profile {
+        cli = Cli::parse_from(with_profile(
+            env::args_os().collect(),
+            name,
+            &load_config()?,
+        )?);
+    }
+    output::set_color(cli.color);
+    if cli.timestamps {
+        output::set_timestamps();
+    }
+    logging::init(cli.log_level, cli.log_file.as_deref())?;
+    if cli.no_local_config {
+        grpgit::distrust_local_config();
+    }
+    let current_dir = env::current_dir()?;
+
+    match &cli.command {
+        Some(CliCommand::Register { paths }) => {
+            register_repositories(&registry_path()?, paths, &current_dir)
+        }
+        Some(CliCommand::Unregister { paths }) => {
+            unregister_repositories(&registry_path()?, paths, &current_dir)
+        }
+        Some(CliCommand::Which) => which_repositories(&cli, &current_dir),
+        Some(CliCommand::Adopt) => adopt_repositories(&cli, &registry_path()?, &current_dir),
+        Some(CliCommand::List { print0 }) => {
+            list_registered_repositories(&registry_path()?, *print0)
+        }
+        Some(CliCommand::Alias {
+            action: AliasCommand::List,
+        }) => {
+            list_aliases(&load_config()?);
+            Ok(())
+        }
+        Some(CliCommand::RunScript { script, .. }) if !script.is_file() => {
+            Err(format!("cannot run script {}: no such file", script.display()).into())
+        }
+        Some(CliCommand::Exec { .. } | CliCommand::RunScript { .. } | CliCommand::Git(_))
+        | None => {
+            interrupt::install()?;
+            run_command_across(&cli, &current_dir)
+        }
+    }
+}
+
+/// Main function runs grpr and turns its outcome into the exit status:
+/// distinct codes for failures in some or all repositories and for finding
+/// no repositories, and 1 for any other error.
+fn main() -> ExitCode {
+    let Err(err) = run() else {
+        return ExitCode::SUCCESS;
+    };
+    match err.downcast_ref::<ExitError>() {
+        Some(exit) => {
+            if !exit.message.is_empty() {
+                eprintln!("grpr: {}", exit.message);
+            }
+            ExitCode::from(exit.code)
+        }
+        None => {
+            eprintln!("Error: {err:?}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{git as run_git, git_with_env};
+    use clap::{CommandFactory, Parser};
+    use tempfile::tempdir;
+
+    fn git(args: &[&str]) -> grpgit::RepoCommand {
+        grpgit::RepoCommand::git(args.iter().map(|arg| arg.to_string()).collect())
+    }
+
+    fn create_regular_repo(path: &Path) {
+        let git_dir = path.join(".git");
+        fs::create_dir_all(&git_dir).unwrap();
+        fs::write(git_dir.join("config"), "[core]\n").unwrap();
+    }
+
+    #[test]
+    fn adopt_records_discovered_repositories_once() {
+        let dir = tempdir().unwrap();
+        let registry_path = dir.path().join("data").join("repos.toml");
+        let root = dir.path().join("src");
+        for name in ["app", "lib"] {
+            let repo_dir = root.join(name);
+            fs::create_dir_all(&repo_dir).unwrap();
+            create_regular_repo(&repo_dir);
+        }
+        let cli = Cli::parse_from(["grpr", "adopt"]);
+
+        adopt_repositories(&cli, &registry_path, &root).unwrap();
+        adopt_repositories(&cli, &registry_path, &root).unwrap();
+
+        let registered = Registry::load(&registry_path).unwrap().paths();
+        assert_eq!(
+            registered,
+            vec![
+                fs::canonicalize(root.join("app")).unwrap(),
+                fs::canonicalize(root.join("lib")).unwrap(),
+            ]
+        );
+    }
+
+    #[test]
+    fn git_command_defaults_to_status() {
+        let cli = Cli::parse_from(["grpr"]);
+
+        assert_eq!(command_from_cli(&cli), git(&["status"]));
+    }
+
+    #[test]
+    fn git_command_preserves_multiple_arguments() {
+        let cli = Cli::parse_from(["grpr", "log", "--oneline", "--graph"]);
+
+        assert_eq!(
+            command_from_cli(&cli),
+            git(&["log", "--oneline", "--graph"])
+        );
+    }
+
+    #[test]
+    fn discovery_options_default_to_unbounded_depth() {
+        let cli = Cli::parse_from(["grpr", "status"]);
+
+        assert_eq!(discovery_options_from_cli(&cli).unwrap().max_depth, None);
+    }
+
+    #[test]
+    fn discovery_options_use_max_depth_flag() {
+        let cli = Cli::parse_from(["grpr", "--max-depth", "2", "pull"]);
+
+        assert_eq!(discovery_options_from_cli(&cli).unwrap().max_depth, Some(2));
+        assert_eq!(command_from_cli(&cli), git(&["pull"]));
+    }
+
+    #[test]
+    fn discovery_options_collect_repeated_exclusions() {
+        let cli = Cli::parse_from([
+            "grpr",
+            "--exclude",
+            "node_modules",
+            "--exclude",
+            "target",
+            "status",
+        ]);
+        let options = discovery_options_from_cli(&cli).unwrap();
+
+        assert_eq!(options.exclude.len(), 2);
+        assert!(options.exclude.is_match("target"));
+    }
+
+    #[test]
+    fn cli_rejects_invalid_exclusion_globs() {
+        assert!(Cli::try_parse_from(["grpr", "--exclude", "[", "status"]).is_err());
+    }
+
+    #[test]
+    fn discovery_options_toggle_hidden_and_ignore_files() {
+        let cli = Cli::parse_from(["grpr", "status"]);
+        let options = discovery_options_from_cli(&cli).unwrap();
+        assert!(!options.include_hidden);
+        assert!(options.ignore_files);
+
+        let cli = Cli::parse_from(["grpr", "--hidden", "--no-ignore", "status"]);
+        let options = discovery_options_from_cli(&cli).unwrap();
+        assert!(options.include_hidden);
+        assert!(!options.ignore_files);
+    }
+
+    #[test]
+    fn discovery_options_enable_nested_search() {
+        let nested = |args: &[&str]| {
+            let cli = Cli::parse_from(args);
+            assert_eq!(command_from_cli(&cli), git(&["status"]));
+            discovery_options_from_cli(&cli).unwrap().nested
+        };
+
+        assert_eq!(nested(&["grpr", "status"]), grpgit::NestedPolicy::Skip);
+        assert_eq!(
+            nested(&["grpr", "--nested", "status"]),
+            grpgit::NestedPolicy::Include
+        );
+        assert_eq!(
+            nested(&["grpr", "--nested=only", "status"]),
+            grpgit::NestedPolicy::Only
+        );
+    }
+
+    #[test]
+    fn discovery_options_skip_worktrees_and_submodules() {
+        let cli = Cli::parse_from(["grpr", "status"]);
+        let options = discovery_options_from_cli(&cli).unwrap();
+        assert!(options.include_worktrees);
+        assert!(options.include_submodules);
+
+        let cli = Cli::parse_from(["grpr", "--skip-worktrees", "--skip-submodules", "status"]);
+        let options = discovery_options_from_cli(&cli).unwrap();
+        assert!(!options.include_worktrees);
+        assert!(!options.include_submodules);
+    }
+
+    #[test]
+    fn discovery_options_include_bare_repositories_on_request() {
+        let cli = Cli::parse_from(["grpr", "status"]);
+        assert!(!discovery_options_from_cli(&cli).unwrap().include_bare);
+
+        let cli = Cli::parse_from(["grpr", "--bare", "fetch"]);
+        assert!(discovery_options_from_cli(&cli).unwrap().include_bare);
+    }
+
+    #[test]
+    fn discovery_options_follow_symlinks_on_request() {
+        let cli = Cli::parse_from(["grpr", "--follow-symlinks", "status"]);
+
+        assert!(discovery_options_from_cli(&cli).unwrap().follow_symlinks);
+    }
+
+    #[test]
+    fn git_command_passes_through_hyphenated_arguments() {
+        let cli = Cli::parse_from(["grpr", "-t", "4", "commit", "-m", "fix two bugs"]);
+
+        assert_eq!(cli.threads, Some(4));
+        assert_eq!(
+            command_from_cli(&cli),
+            git(&["commit", "-m", "fix two bugs"])
+        );
+    }
+
+    #[test]
+    fn cli_parses_exec_commands_with_their_own_flags() {
+        let cli = Cli::parse_from([
+            "grpr",
+            "exec",
+            "--dirty",
+            "--",
+            "cargo",
+            "update",
+            "--workspace",
+        ]);
+
+        assert!(cli.dirty);
+        assert_eq!(
+            command_from_cli(&cli),
+            grpgit::RepoCommand::exec(vec![
+                "cargo".to_string(),
+                "update".to_string(),
+                "--workspace".to_string(),
+            ])
+        );
+        assert_eq!(
+            command_from_cli(&cli).command_line(),
+            "cargo update --workspace"
+        );
+        assert!(Cli::try_parse_from(["grpr", "exec"]).is_err());
+    }
+
+    #[test]
+    fn profiles_supply_flags_that_the_command_line_overrides() {
+        let config: Config =
+            toml::from_str("[profiles]\nci = \"--fail-fast -t 2 --net-jobs 4 --exclude a\"\n")
+                .unwrap();
+        let args = |words: &[&str]| words.iter().map(OsString::from).collect::<Vec<_>>();
+
+        let cli = Cli::parse_from(
+            with_profile(
+                args(&[
+                    "grpr",
+                    "--profile",
+                    "ci",
+                    "-t",
+                    "8",
+                    "--exclude",
+                    "b",
+                    "fetch",
+                ]),
+                "ci",
+                &config,
+            )
+            .unwrap(),
+        );
+
+        assert!(cli.fail_fast);
+        assert_eq!(cli.threads, Some(8));
+        assert_eq!(cli.net_jobs, Some(4));
+        assert_eq!(cli.exclude.len(), 2);
+        let err = with_profile(args(&["grpr", "fetch"]), "local", &config).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "unknown profile `local`; configured profiles: ci"
+        );
+    }
+
+    #[test]
+    fn cli_parses_run_script_with_script_arguments() {
+        let cli = Cli::parse_from([
+            "grpr",
+            "run-script",
+            "--dirty",
+            "/opt/fix.sh",
+            "--all",
+            "v2",
+        ]);
+
+        assert!(cli.dirty);
+        assert_eq!(
+            command_from_cli(&cli),
+            grpgit::RepoCommand::script(
+                Path::new("/opt/fix.sh"),
+                vec!["--all".to_string(), "v2".to_string()]
+            )
+        );
+        assert!(Cli::try_parse_from(["grpr", "run-script"]).is_err());
+    }
+
+    #[test]
+    fn registry_subcommands_are_not_passed_to_git() {
+        let cli = Cli::parse_from(["grpr", "register", "app", "lib"]);
+
+        assert!(matches!(
+            cli.command,
+            Some(CliCommand::Register { ref paths }) if paths.len() == 2
+        ));
+        assert!(matches!(
+            Cli::parse_from(["grpr", "list", "--print0"]).command,
+            Some(CliCommand::List { print0: true })
+        ));
+        assert!(matches!(
+            Cli::parse_from(["grpr", "alias", "list"]).command,
+            Some(CliCommand::Alias {
+                action: AliasCommand::List
+            })
+        ));
+    }
+
+    #[test]
+    fn register_and_unregister_update_the_registry() {
+        let dir = tempdir().unwrap();
+        let registry_path = dir.path().join("data").join("repos.toml");
+        let repo_dir = dir.path().join("repo");
+        let plain_dir = dir.path().join("plain");
+        fs::create_dir_all(&repo_dir).unwrap();
+        fs::create_dir_all(&plain_dir).unwrap();
+        create_regular_repo(&repo_dir);
+        let repo_dir = fs::canonicalize(repo_dir).unwrap();
+
+        register_repositories(
+            &registry_path,
+            &[PathBuf::from("repo"), PathBuf::from("plain")],
+            dir.path(),
+        )
+        .unwrap();
+        assert_eq!(
+            registered_repositories(&registry_path).unwrap(),
+            vec![repo_dir.clone()]
+        );
+
+        unregister_repositories(&registry_path, &[], &repo_dir).unwrap();
+        assert!(registered_repositories(&registry_path).unwrap().is_empty());
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn resumed_script_runs_as_it_was_recorded() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = tempdir().unwrap();
+        let repo_dir = dir.path().join("repo");
+        fs::create_dir_all(&repo_dir).unwrap();
+        run_git(&repo_dir, &["init", "--quiet", "--initial-branch", "trunk"]);
+        let script = dir.path().join("my scripts").join("show branch.sh");
+        fs::create_dir_all(script.parent().unwrap()).unwrap();
+        fs::write(&script, "#!/bin/sh\necho \"$GRPR_REPO_BRANCH\"\n").unwrap();
+        fs::set_permissions(&script, fs::Permissions::from_mode(0o755)).unwrap();
+        let journal_path = dir.path().join("last-run.toml");
+        let command = grpgit::RepoCommand::script(&script, Vec::new());
+        RunStatus::default()
+            .journal(&command, std::slice::from_ref(&repo_dir))
+            .save(&journal_path)
+            .unwrap();
+
+        let resumed = resumed_command(Journal::load(&journal_path).unwrap().unwrap());
+
+        assert_eq!(resumed, command);
+        let captured = grpgit::capture_command(&repo_dir, &resumed, &grpgit::Hooks::default());
+        assert!(captured.result.is_ok());
+        assert_eq!(String::from_utf8_lossy(&captured.stdout), "trunk\n");
+    }
+
+    #[test]
+    fn journal_repositories_select_by_outcome_and_skip_missing_entries() {
+        let dir = tempdir().unwrap();
+        let journal_path = dir.path().join("last-run.toml");
+        let repo_dir = dir.path().join("repo");
+        fs::create_dir_all(&repo_dir).unwrap();
+        create_regular_repo(&repo_dir);
+        let entry = |path: PathBuf, outcome| JournalEntry { path, outcome };
+        let journal = Journal {
+            command: vec!["git".to_string(), "pull".to_string()],
+            invocation: None,
+            repositories: vec![
+                entry(dir.path().join("done"), Outcome::Succeeded),
+                entry(dir.path().join("gone"), Outcome::Failed),
+                entry(repo_dir.clone(), Outcome::NotStarted),
+            ],
+        };
+        journal.save(&journal_path).unwrap();
+
+        assert_eq!(
+            journal_repositories(&journal_path, &[Outcome::Failed, Outcome::NotStarted]).unwrap(),
+            vec![repo_dir]
+        );
+        assert!(
+            journal_repositories(&journal_path, &[Outcome::Failed])
+                .unwrap()
+                .is_empty()
+        );
+        assert_eq!(
+            journal_repositories(&dir.path().join("missing.toml"), &[Outcome::Failed])
+                .unwrap_err()
+                .to_string(),
+            NO_PREVIOUS_RUN
+        );
+    }
+
+    #[test]
+    fn registered_repositories_skip_missing_entries() {
+        let dir = tempdir().unwrap();
+        let registry_path = dir.path().join("repos.toml");
+        let repo_dir = dir.path().join("repo");
+        fs::create_dir_all(&repo_dir).unwrap();
+        create_regular_repo(&repo_dir);
+
+        let mut registry = Registry::default();
+        registry.register(dir.path().join("gone"));
+        registry.register(repo_dir.clone());
+        registry.save(&registry_path).unwrap();
+
+        assert_eq!(
+            registered_repositories(&registry_path).unwrap(),
+            vec![repo_dir]
+        );
+    }
+
+    #[test]
+    fn manifest_repositories_resolve_relative_to_manifest() {
+        let dir = tempdir().unwrap();
+        let repo_dir = dir.path().join("ci").join("app");
+        fs::create_dir_all(&repo_dir).unwrap();
+        create_regular_repo(&repo_dir);
+        fs::write(dir.path().join("ci").join("repos.txt"), "app\nmissing\n").unwrap();
+
+        let repositories = manifest_repositories(Path::new("ci/repos.txt"), dir.path()).unwrap();
+
+        assert_eq!(repositories, vec![repo_dir]);
+    }
+
+    #[test]
+    fn manifest_conflicts_with_registered() {
+        assert!(
+            Cli::try_parse_from(["grpr", "--registered", "--manifest", "repos.txt", "pull"])
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn cli_accepts_nul_separated_stdin() {
+        let cli = Cli::parse_from(["grpr", "--stdin", "-0", "pull"]);
+
+        assert!(cli.stdin);
+        assert!(cli.null_separated);
+        assert!(Cli::try_parse_from(["grpr", "-0", "pull"]).is_err());
+        assert!(Cli::try_parse_from(["grpr", "--stdin", "--registered", "pull"]).is_err());
+    }
+
+    #[test]
+    fn discovery_options_stay_on_one_file_system_on_request() {
+        let cli = Cli::parse_from(["grpr", "status"]);
+        assert!(!discovery_options_from_cli(&cli).unwrap().same_file_system);
+
+        let cli = Cli::parse_from(["grpr", "-x", "status"]);
+        assert!(discovery_options_from_cli(&cli).unwrap().same_file_system);
+
+        let cli = Cli::parse_from(["grpr", "--one-file-system", "status"]);
+        assert!(discovery_options_from_cli(&cli).unwrap().same_file_system);
+    }
+
+    #[test]
+    fn cli_version_matches_cargo_package_version() {
+        assert_eq!(VERSION, "2.0.2");
+        assert_eq!(VERSION, env!("CARGO_PKG_VERSION"));
+    }
+
+    #[test]
+    fn clap_renders_expected_version_string() {
+        let rendered = Cli::command().render_version().to_string();
+
+        assert_eq!(rendered.trim(), "grpr 2.0.2");
+    }
+
+    #[test]
+    fn discover_repositories_from_finds_root_level_repositories() {
+        let dir = tempdir().unwrap();
+        let repo_dir = dir.path().join("repo");
+        fs::create_dir_all(&repo_dir).unwrap();
+        create_regular_repo(&repo_dir);
+
+        let discovery = discover_repositories_from(
+            &[dir.path().to_path_buf()],
+            &grpgit::DiscoveryOptions::default(),
+            None,
+        );
+
+        assert_eq!(discovery.repositories, vec![repo_dir]);
+    }
+
+    #[test]
+    fn discover_repositories_from_merges_multiple_roots() {
+        let dir = tempdir().unwrap();
+        let work_repo = dir.path().join("work").join("app");
+        let oss_repo = dir.path().join("oss").join("lib");
+        fs::create_dir_all(&work_repo).unwrap();
+        fs::create_dir_all(&oss_repo).unwrap();
+        create_regular_repo(&work_repo);
+        create_regular_repo(&oss_repo);
+
+        let roots = vec![
+            dir.path().join("work"),
+            dir.path().join("oss"),
+            dir.path().join("work"),
+        ];
+        let discovery =
+            discover_repositories_from(&roots, &grpgit::DiscoveryOptions::default(), None);
+
+        assert_eq!(discovery.repositories, vec![work_repo, oss_repo]);
+    }
+
+    #[test]
+    fn discover_repositories_from_reports_roots_past_the_deadline() {
+        let dir = tempdir().unwrap();
+        let repo_dir = dir.path().join("repo");
+        fs::create_dir_all(&repo_dir).unwrap();
+        create_regular_repo(&repo_dir);
+        let roots = vec![dir.path().to_path_buf()];
+        let options = grpgit::DiscoveryOptions::default();
+
+        let in_time = discover_repositories_from(
+            &roots,
+            &options,
+            Some(Instant::now() + Duration::from_secs(30)),
+        );
+        let expired = discover_repositories_from(&roots, &options, Some(Instant::now()));
+
+        assert_eq!(in_time.repositories, vec![repo_dir]);
+        assert!(in_time.unscanned.is_empty());
+        assert!(expired.repositories.is_empty());
+        assert_eq!(expired.unscanned, roots);
+    }
+
+    #[test]
+    fn discover_repositories_from_collects_unreadable_roots() {
+        let dir = tempdir().unwrap();
+        let missing = dir.path().join("missing");
+
+        let discovery = discover_repositories_from(
+            std::slice::from_ref(&missing),
+            &grpgit::DiscoveryOptions::default(),
+            None,
+        );
+
+        assert!(discovery.repositories.is_empty());
+        assert_eq!(discovery.errors.len(), 1);
+        assert_eq!(discovery.errors[0].path, missing);
+    }
+
+    #[test]
+    fn check_scan_errors_fails_only_when_strict() {
+        let errors = vec![grpgit::ScanError {
+            path: PathBuf::from("/src/private"),
+            message: "Permission denied (os error 13)".to_string(),
+        }];
+
+        assert!(check_scan_errors(&[], true).is_ok());
+        assert!(check_scan_errors(&errors, false).is_ok());
+        assert!(check_scan_errors(&errors, true).is_err());
+    }
+
+    #[test]
+    fn discover_repositories_cached_reuses_entries_until_refreshed() {
+        let dir = tempdir().unwrap();
+        let cache_path = dir.path().join("cache").join("discovery.toml");
+        let root = dir.path().join("src");
+        let first_repo = root.join("first");
+        fs::create_dir_all(&first_repo).unwrap();
+        fs::create_dir_all(root.join("group")).unwrap();
+        create_regular_repo(&first_repo);
+        let roots = vec![root.clone()];
+        let options = grpgit::DiscoveryOptions::default();
+
+        let scanned = discover_repositories_cached(
+            &roots,
+            &options,
+            None,
+            "fp",
+            CacheMode::Reuse,
+            &cache_path,
+        )
+        .unwrap()
+        .repositories;
+        assert_eq!(scanned, vec![first_repo.clone()]);
+
+        // Adding a repository below an existing directory leaves the root's
+        // modification time untouched, so the cached result is reused.
+        let second_repo = root.join("group").join("second");
+        fs::create_dir_all(&second_repo).unwrap();
+        create_regular_repo(&second_repo);
+
+        let reused = discover_repositories_cached(
+            &roots,
+            &options,
+            None,
+            "fp",
+            CacheMode::Reuse,
+            &cache_path,
+        )
+        .unwrap()
+        .repositories;
+        assert_eq!(reused, vec![first_repo.clone()]);
+
+        let mut refreshed = discover_repositories_cached(
+            &roots,
+            &options,
+            None,
+            "fp",
+            CacheMode::Refresh,
+            &cache_path,
+        )
+        .unwrap()
+        .repositories;
+        refreshed.sort();
+        assert_eq!(refreshed, vec![first_repo.clone(), second_repo.clone()]);
+
+        let mut reused = discover_repositories_cached(
+            &roots,
+            &options,
+            None,
+            "fp",
+            CacheMode::Reuse,
+            &cache_path,
+        )
+        .unwrap()
+        .repositories;
+        reused.sort();
+        assert_eq!(reused, vec![first_repo, second_repo]);
+    }
+
+    #[test]
+    fn cache_mode_prefers_refresh_over_cached() {
+        assert_eq!(
+            cache_mode_from_cli(&Cli::parse_from(["grpr", "status"])),
+            CacheMode::Off
+        );
+        assert_eq!(
+            cache_mode_from_cli(&Cli::parse_from(["grpr", "--cached", "status"])),
+            CacheMode::Reuse
+        );
+        assert_eq!(
+            cache_mode_from_cli(&Cli::parse_from(["grpr", "--cached", "--refresh"])),
+            CacheMode::Refresh
+        );
+    }
+
+    #[test]
+    fn discovery_fingerprint_tracks_discovery_flags() {
+        let plain = discovery_fingerprint(&Cli::parse_from(["grpr", "status"]));
+        let same = discovery_fingerprint(&Cli::parse_from(["grpr", "--cached", "pull"]));
+        let nested = discovery_fingerprint(&Cli::parse_from(["grpr", "--nested", "status"]));
+
+        assert_eq!(plain, same);
+        assert_ne!(plain, nested);
+    }
+
+    #[test]
+    fn roots_default_to_current_directory() {
+        let cli = Cli::parse_from(["grpr", "status"]);
+
+        assert_eq!(
+            roots_from_cli(&cli, Path::new("/home/user"), &Config::default()).unwrap(),
+            vec![PathBuf::from("/home/user")]
+        );
+    }
+
+    #[test]
+    fn roots_include_configured_workspaces() {
+        let dir = tempdir().unwrap();
+        let config_path = dir.path().join("config.toml");
+        fs::write(
+            &config_path,
+            "[roots]\nwork = \"/src/work\"\noss = [\"/src/oss\", \"/srv/forks\"]\n",
+        )
+        .unwrap();
+        let config = Config::load(&config_path).unwrap();
+        let cli = Cli::parse_from(["grpr", "-C", "extra", "-w", "oss", "fetch"]);
+
+        assert_eq!(
+            roots_from_cli(&cli, Path::new("/home/user"), &config).unwrap(),
+            vec![
+                PathBuf::from("/home/user/extra"),
+                PathBuf::from("/src/oss"),
+                PathBuf::from("/srv/forks"),
+            ]
+        );
+
+        let cli = Cli::parse_from(["grpr", "--workspace", "home", "fetch"]);
+        let err = roots_from_cli(&cli, Path::new("/home/user"), &config).unwrap_err();
+        assert!(err.to_string().contains("oss, work"));
+    }
+
+    #[test]
+    fn roots_resolve_repeated_flags_against_current_directory() {
+        let cli = Cli::parse_from(["grpr", "-C", "work", "--root", "/srv/oss", "pull"]);
+
+        assert_eq!(
+            roots_from_cli(&cli, Path::new("/home/user"), &Config::default()).unwrap(),
+            vec![PathBuf::from("/home/user/work"), PathBuf::from("/srv/oss")]
+        );
+        assert_eq!(command_from_cli(&cli), git(&["pull"]));
+    }
+
+    #[test]
+    fn execute_repositories_succeeds_with_sequential_processing() {
+        let dir = tempdir().unwrap();
+        let repo_dir = dir.path().join("repo");
+        fs::create_dir_all(&repo_dir).unwrap();
+
+        run_git(&repo_dir, &["init"]);
+
+        let repositories = vec![repo_dir];
+        let command = grpgit::RepoCommand::git(vec!["status".to_string()]);
+
+        let run = RunStatus::default();
+
+        assert!(
+            execute_repositories(&repositories, &command, &ExecutionOptions::default(), &run)
+                .is_ok()
+        );
+        assert!(run.finish().is_ok());
+    }
+
+    #[test]
+    fn execute_repositories_succeeds_with_ordered_parallel_output() {
+        let dir = tempdir().unwrap();
+        let mut repositories = Vec::new();
+        for name in ["a", "b", "c"] {
+            let repo_dir = dir.path().join(name);
+            fs::create_dir_all(&repo_dir).unwrap();
+            run_git(&repo_dir, &["init", "--quiet"]);
+            repositories.push(repo_dir);
+        }
+
+        let options = ExecutionOptions {
+            threads: Some(2),
+            ordered_output: true,
+            ..ExecutionOptions::default()
+        };
+        let command = grpgit::RepoCommand::git(vec!["status".to_string(), "--short".to_string()]);
+
+        let run = RunStatus::default();
+
+        assert!(execute_repositories(&repositories, &command, &options, &run).is_ok());
+        assert!(run.finish().is_ok());
+    }
+
+    #[test]
+    fn offline_skips_only_network_commands() {
+        let dir = tempdir().unwrap();
+        let repositories = vec![dir.path().join("app")];
+        let options = execution_options_from_cli(&Cli::parse_from(["grpr", "--offline", "fetch"]));
+        let run = RunStatus::default();
+
+        let processed = execute_stream(
+            repositories.clone().into_iter(),
+            &git(&["fetch"]),
+            &options,
+            &run,
+        )
+        .unwrap();
+
+        assert!(processed.is_empty());
+        assert_eq!(run.skipped_offline.load(Ordering::Relaxed), 1);
+        assert_eq!(
+            run.journal(&git(&["fetch"]), &repositories)
+                .paths_with(&[Outcome::NotStarted]),
+            repositories
+        );
+        assert!(!run.skip_offline(&repositories[0], &git(&["log"]), &options));
+        assert!(Cli::try_parse_from(["grpr", "--offline", "--online", "fetch"]).is_err());
+    }
+
+    #[test]
+    fn checking_remotes_skips_only_repositories_whose_remote_cannot_be_reached() {
+        let dir = tempdir().unwrap();
+        let unreachable = dir.path().join("unreachable");
+        let local = dir.path().join("local");
+        for (repo, url) in [
+            // Names under `.invalid` never resolve.
+            (&unreachable, "https://git.grpr.invalid/app.git"),
+            (&local, "../unreachable"),
+        ] {
+            fs::create_dir_all(repo).unwrap();
+            run_git(repo, &["init", "--quiet"]);
+            run_git(repo, &["remote", "add", "origin", url]);
+        }
+        let mut options = execution_options_from_cli(&Cli::parse_from(["grpr", "fetch"]));
+        let run = RunStatus::default();
+
+        assert!(!run.skip_offline(&unreachable, &git(&["fetch"]), &options));
+
+        options.check_remotes = true;
+        assert!(run.skip_offline(&unreachable, &git(&["fetch"]), &options));
+        assert!(!run.skip_offline(&unreachable, &git(&["log"]), &options));
+        assert!(!run.skip_offline(&local, &git(&["fetch"]), &options));
+        assert_eq!(run.skipped_offline.load(Ordering::Relaxed), 1);
+        assert_eq!(
+            run.reachable
+                .lock()
+                .unwrap()
+                .get(&("git.grpr.invalid".to_string(), 443)),
+            Some(&false)
+        );
+    }
+
+    #[test]
+    fn run_status_adds_up_run_times_slowest_first() {
+        let run = RunStatus::default();
+        let (fast, slow) = (PathBuf::from("/src/fast"), PathBuf::from("/src/slow"));
+
+        run.timed(&fast, || std::thread::sleep(Duration::from_millis(5)));
+        run.timed(&slow, || std::thread::sleep(Duration::from_millis(20)));
+        // A retry adds to the first attempt.
+        run.timed(&fast, || std::thread::sleep(Duration::from_millis(5)));
+
+        let slowest = run.slowest();
+        assert_eq!(slowest[0].0, slow);
+        assert_eq!(slowest[1].0, fast);
+        assert!(slowest[1].1 >= Duration::from_millis(10));
+        assert_eq!(format_elapsed(Duration::from_millis(1234)), "1.23s");
+        assert_eq!(format_elapsed(Duration::from_secs(125)), "2m05s");
+    }
+
+    #[test]
+    fn require_clean_skips_repositories_with_local_changes() {
+        let dir = tempdir().unwrap();
+        let repositories: Vec<PathBuf> =
+            ["clean", "dirty"].map(|name| dir.path().join(name)).into();
+        for repo_path in &repositories {
+            fs::create_dir_all(repo_path).unwrap();
+            run_git(repo_path, &["init", "--quiet"]);
+        }
+        fs::write(repositories[1].join("notes.txt"), "draft").unwrap();
+        let options =
+            execution_options_from_cli(&Cli::parse_from(["grpr", "--require-clean", "status"]));
+        let command = git(&["status"]);
+        let run = RunStatus::default();
+
+        let processed =
+            execute_stream(repositories.clone().into_iter(), &command, &options, &run).unwrap();
+
+        assert_eq!(processed, repositories[..1]);
+        assert_eq!(run.skipped_dirty.load(Ordering::Relaxed), 1);
+        assert_eq!(
+            run.journal(&command, &repositories)
+                .paths_with(&[Outcome::NotStarted]),
+            vec![repositories[1].clone()]
+        );
+        assert!(run.finish().is_ok());
+    }
+
+    #[test]
+    fn keep_going_processes_every_repository_and_fails_the_run() {
+        let dir = tempdir().unwrap();
+        let repositories: Vec<PathBuf> = ["a", "b", "c"].map(|name| dir.path().join(name)).into();
+        let command = grpgit::RepoCommand::git(vec!["status".to_string()]);
+        let run = RunStatus::new(FailurePolicy::KeepGoing);
+
+        let processed = execute_stream(
+            repositories.clone().into_iter(),
+            &command,
+            &ExecutionOptions::default(),
+            &run,
+        )
+        .unwrap();
+
+        assert_eq!(processed, repositories);
+        let err = run.finish().unwrap_err();
+        assert_eq!(err.to_string(), "command failed in 3 of 3 repositories");
+        assert_eq!(
+            err.downcast_ref::<ExitError>().unwrap().code,
+            EXIT_ALL_FAILED
+        );
+    }
+
+    #[test]
+    fn run_without_repositories_exits_with_its_own_code() {
+        let dir = tempdir().unwrap();
+
+        for args in [&["grpr", "status"][..], &["grpr", "--serial", "status"]] {
+            let cli = Cli::parse_from(args);
+            let err = run_command_across(&cli, dir.path()).unwrap_err();
+            assert_eq!(
+                err.downcast_ref::<ExitError>().unwrap().code,
+                EXIT_NO_REPOSITORIES
+            );
+        }
+    }
+
+    #[test]
+    fn partly_failed_run_exits_with_its_own_code() {
+        let dir = tempdir().unwrap();
+        let repo_dir = dir.path().join("repo");
+        fs::create_dir_all(&repo_dir).unwrap();
+        run_git(&repo_dir, &["init", "--quiet"]);
+        let repositories = vec![repo_dir, dir.path().join("missing")];
+        let run = RunStatus::new(FailurePolicy::KeepGoing);
+
+        execute_repositories(
+            &repositories,
+            &git(&["status"]),
+            &ExecutionOptions::default(),
+            &run,
+        )
+        .unwrap();
+
+        let err = run.finish().unwrap_err();
+        assert_eq!(err.to_string(), "command failed in 1 of 2 repositories");
+        assert_eq!(
+            err.downcast_ref::<ExitError>().unwrap().code,
+            EXIT_SOME_FAILED
+        );
+    }
+
+    #[test]
+    fn run_status_journals_the_outcome_in_each_selected_repository() {
+        let dir = tempdir().unwrap();
+        let repo_dir = dir.path().join("repo");
+        fs::create_dir_all(&repo_dir).unwrap();
+        run_git(&repo_dir, &["init", "--quiet"]);
+        let repositories = vec![
+            repo_dir,
+            dir.path().join("missing"),
+            dir.path().join("later"),
+        ];
+        let command = git(&["status"]);
+        let run = RunStatus::new(FailurePolicy::FailFast);
+
+        execute_repositories(&repositories, &command, &ExecutionOptions::default(), &run).unwrap();
+
+        let outcomes: Vec<Outcome> = run
+            .journal(&command, &repositories)
+            .repositories
+            .into_iter()
+            .map(|entry| entry.outcome)
+            .collect();
+        assert_eq!(
+            outcomes,
+            vec![Outcome::Succeeded, Outcome::Failed, Outcome::NotStarted]
+        );
+    }
+
+    #[test]
+    fn fail_fast_stops_taking_repositories_after_a_failure() {
+        let dir = tempdir().unwrap();
+        let repositories: Vec<PathBuf> = ["a", "b", "c"].map(|name| dir.path().join(name)).into();
+        let command = grpgit::RepoCommand::git(vec!["status".to_string()]);
+        let run = RunStatus::new(FailurePolicy::FailFast);
+
+        let processed = execute_stream(
+            repositories.clone().into_iter(),
+            &command,
+            &ExecutionOptions::default(),
+            &run,
+        )
+        .unwrap();
+
+        assert_eq!(processed, repositories[..1]);
+        assert_eq!(
+            run.finish().unwrap_err().to_string(),
+            "command failed in 1 of 1 repository"
+        );
+
+        let run = RunStatus::new(FailurePolicy::FailFast);
+        run.expect(repositories.len());
+        execute_stream(
+            repositories.clone().into_iter(),
+            &command,
+            &ExecutionOptions::default(),
+            &run,
+        )
+        .unwrap();
+        let err = run.finish().unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "command failed in 1 of 1 repository; \
+             stopped early with 2 repositories not started (--fail-fast)"
+        );
+        assert_eq!(
+            err.downcast_ref::<ExitError>().unwrap().code,
+            EXIT_SOME_FAILED
+        );
+
+        let run = RunStatus::new(FailurePolicy::FailFast);
+        let options = ExecutionOptions {
+            threads: Some(2),
+            ordered_output: true,
+            ..ExecutionOptions::default()
+        };
+        assert!(execute_repositories(&repositories, &command, &options, &run).is_ok());
+        assert!(run.finish().is_err());
+    }
+
+    #[test]
+    fn retries_back_off_exponentially_until_success() {
+        let dir = tempdir().unwrap();
+        let policy = RetryPolicy {
+            retries: 3,
+            delay: Duration::from_millis(1),
+        };
+        let run = RunStatus::default();
+        let mut attempts = 0;
+
+        let result = run_with_retries(
+            dir.path(),
+            &policy,
+            &run,
+            || {
+                attempts += 1;
+                attempts
+            },
+            |attempt| *attempt == 3,
+        );
+
+        assert_eq!(result, 3);
+        assert_eq!(policy.backoff(1), Duration::from_millis(1));
+        assert_eq!(policy.backoff(3), Duration::from_millis(4));
+        assert_eq!(run.retried.load(Ordering::Relaxed), 1);
+        assert_eq!(run.recovered.load(Ordering::Relaxed), 1);
+
+        let result = run_with_retries(dir.path(), &policy, &run, || false, |ok| *ok);
+        assert!(!result);
+        assert_eq!(run.retried.load(Ordering::Relaxed), 2);
+        assert_eq!(run.recovered.load(Ordering::Relaxed), 1);
+    }
+
+    #[test]
+    fn retries_default_to_none() {
+        let options = execution_options_from_cli(&Cli::parse_from(["grpr", "fetch"]));
+        let retrying = execution_options_from_cli(&Cli::parse_from([
+            "grpr",
+            "--retries",
+            "2",
+            "--retry-delay",
+            "5s",
+            "fetch",
+        ]));
+
+        assert_eq!(options.retry.retries, 0);
+        assert_eq!(
+            retrying.retry,
+            RetryPolicy {
+                retries: 2,
+                delay: Duration::from_secs(5),
+            }
+        );
+
+        let bare = Cli::parse_from(["grpr", "--retry-delay", "5", "fetch"]);
+        assert_eq!(bare.retry_delay, Duration::from_secs(5));
+    }
+
+    #[test]
+    fn hooks_come_from_pre_and_post() {
+        let cli = Cli::parse_from([
+            "grpr",
+            "--pre",
+            "git stash",
+            "--post",
+            "git stash pop",
+            "pull",
+        ]);
+
+        assert_eq!(
+            execution_options_from_cli(&cli).hooks,
+            grpgit::Hooks {
+                pre: Some("git stash".to_string()),
+                post: Some("git stash pop".to_string()),
+                autostash: false,
+                worktree: None,
+                max_output: None,
+            }
+        );
+        assert_eq!(
+            execution_options_from_cli(&Cli::parse_from(["grpr", "pull"])).hooks,
+            grpgit::Hooks::default()
+        );
+    }
+
+    #[test]
+    fn env_variables_are_added_to_the_command() {
+        let cli = Cli::parse_from(["grpr", "--env", "GIT_TRACE=1", "--env", "A=b=c", "fetch"]);
+
+        assert_eq!(
+            command_from_cli(&cli),
+            git(&["fetch"])
+                .with_env("GIT_TRACE", "1")
+                .with_env("A", "b=c")
+        );
+        assert!(Cli::try_parse_from(["grpr", "--env", "MISSING", "fetch"]).is_err());
+        assert_eq!(
+            command_from_cli(&Cli::parse_from(["grpr", "--keep-git-env", "fetch"])),
+            git(&["fetch"]).keeping_git_env()
+        );
+    }
+
+    #[test]
+    fn nice_and_ionice_set_the_command_priority() {
+        let cli = Cli::parse_from(["grpr", "--nice", "10", "--ionice", "idle", "gc"]);
+
+        assert_eq!(
+            command_from_cli(&cli),
+            git(&["gc"]).with_priority(Priority {
+                nice: Some(10),
+                io_class: Some(IoClass::Idle),
+            })
+        );
+        assert_eq!(
+            Cli::parse_from(["grpr", "--nice", "-5", "gc"]).nice,
+            Some(-5)
+        );
+        assert!(Cli::try_parse_from(["grpr", "--nice", "20", "gc"]).is_err());
+    }
+
+    #[test]
+    fn parallel_runs_refuse_interactive_commands() {
+        let options = |args: &[&str]| execution_options_from_cli(&Cli::parse_from(args));
+        let interactive = || git(&["rebase", "-i", "main"]);
+
+        assert_eq!(
+            attach_terminal(interactive(), &options(&["grpr", "--serial", "rebase"])).unwrap(),
+            interactive()
+        );
+        assert_eq!(
+            attach_terminal(git(&["fetch"]), &options(&["grpr", "-t", "4", "fetch"])).unwrap(),
+            git(&["fetch"]).without_terminal()
+        );
+        let err = attach_terminal(interactive(), &options(&["grpr", "-t", "4", "rebase"]))
+            .unwrap_err()
+            .to_string();
+        assert!(err.starts_with("`git rebase -i main` is interactive"));
+    }
 
-const VERSION: &str = env!("CARGO_PKG_VERSION");
+    #[test]
+    fn destructive_commands_need_force_in_several_repositories() {
+        let destructive = vec!["reset --hard".to_string(), "clean -f".to_string()];
+        let repositories = vec![PathBuf::from("/src/app"), PathBuf::from("/src/lib")];
 
-/// CLI represents the command-line arguments for grpr.
-#[derive(Parser, Debug)]
-#[command(author, version = VERSION, about, long_about = None)]
-struct Cli {
-    /// The number of threads to use for concurrent processing. When omitted,
-    /// grpr scans and processes repositories sequentially for predictable
-    /// output and compatibility with grp.
-    #[arg(
-        short,
-        long,
-        help = "Opt in to parallel execution with the given number of worker threads"
-    )]
-    threads: Option<usize>,
+        let err =
+            check_destructive(&git(&["clean", "-fdx"]), &repositories, &destructive).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "refusing to run a destructive command in 2 repositories without --force"
+        );
+        assert!(check_destructive(&git(&["clean", "-n"]), &repositories, &destructive).is_ok());
+        assert!(
+            check_destructive(&git(&["reset", "--hard"]), &repositories[..1], &destructive).is_ok()
+        );
+        assert!(check_destructive(&git(&["reset", "--hard"]), &repositories, &[]).is_ok());
+    }
 
-    /// The git command and its arguments to execute (e.g., "pull", "status",
-    /// etc.). Defaults to "status" if not provided.
-    #[arg(required = false, num_args = 1.., trailing_var_arg = true, allow_hyphen_values = true)]
-    command: Vec<String>,
-}
+    #[test]
+    fn throttle_is_off_for_dry_runs() {
+        let options =
+            execution_options_from_cli(&Cli::parse_from(["grpr", "--throttle", "2.5", "push"]));
+        assert!(options.throttle.is_some());
 
-/// Extracts the git command from the CLI arguments.
-fn git_command_from_cli(cli: &Cli) -> Vec<String> {
-    if cli.command.is_empty() {
-        vec!["status".to_string()]
-    } else {
-        cli.command.clone()
+        let options = execution_options_from_cli(&Cli::parse_from([
+            "grpr",
+            "--throttle",
+            "2.5",
+            "--dry-run",
+            "push",
+        ]));
+        assert!(options.throttle.is_none());
+        assert!(Cli::try_parse_from(["grpr", "--throttle", "0", "push"]).is_err());
     }
-}
 
-/// Executes a git command across the discovered repositories. Processing is
-/// sequential by default and becomes parallel only when a thread count is
-/// provided.
-fn execute_repositories(
-    repositories: &[PathBuf],
-    git_args: &[String],
-    threads: Option<usize>,
-) -> Result<(), Box<dyn Error>> {
-    if let Some(thread_count) = threads.filter(|count| *count > 1) {
-        let pool = rayon::ThreadPoolBuilder::new()
-            .num_threads(thread_count)
-            .build()?;
+    #[test]
+    fn net_jobs_limits_only_network_commands() {
+        let options = execution_options_from_cli(&Cli::parse_from([
+            "grpr",
+            "-t",
+            "16",
+            "--net-jobs",
+            "4",
+            "fetch",
+        ]));
 
-        pool.install(|| {
-            repositories.par_iter().for_each(|repo_path| {
-                if let Err(err) = grpgit::process_repository(repo_path, git_args) {
-                    eprintln!("{err}");
-                }
-            });
-        });
-    } else {
-        for repo_path in repositories {
-            if let Err(err) = grpgit::process_repository(repo_path, git_args) {
-                eprintln!("{err}");
-            }
+        assert_eq!(options.net_jobs, Some(4));
+        assert!(
+            NetworkLimits::new(&git(&["fetch", "--all"]), &options)
+                .jobs
+                .is_some()
+        );
+        assert!(
+            NetworkLimits::new(&git(&["status"]), &options)
+                .jobs
+                .is_none()
+        );
+        assert!(
+            NetworkLimits::new(&git(&["fetch"]), &ExecutionOptions::default())
+                .jobs
+                .is_none()
+        );
+        assert!(Cli::try_parse_from(["grpr", "--net-jobs", "0", "fetch"]).is_err());
+    }
+
+    #[test]
+    fn failure_policy_defaults_to_keep_going() {
+        let policy = |args: &[&str]| execution_options_from_cli(&Cli::parse_from(args)).on_failure;
+
+        assert_eq!(policy(&["grpr", "fetch"]), FailurePolicy::KeepGoing);
+        assert_eq!(
+            policy(&["grpr", "--fail-fast", "fetch"]),
+            FailurePolicy::FailFast
+        );
+        assert_eq!(
+            policy(&["grpr", "--fail-fast", "--keep-going", "fetch"]),
+            FailurePolicy::KeepGoing
+        );
+    }
+
+    #[test]
+    fn execute_stream_returns_processed_repositories() {
+        let dir = tempdir().unwrap();
+        let mut repositories = Vec::new();
+        for name in ["a", "b", "c"] {
+            let repo_dir = dir.path().join(name);
+            fs::create_dir_all(&repo_dir).unwrap();
+            create_regular_repo(&repo_dir);
+            repositories.push(repo_dir);
         }
+        let options = ExecutionOptions {
+            threads: Some(2),
+            ..ExecutionOptions::default()
+        };
+        let command = grpgit::RepoCommand::git(vec!["--version".to_string()]);
+
+        let mut processed = execute_stream(
+            repositories.clone().into_iter(),
+            &command,
+            &options,
+            &RunStatus::default(),
+        )
+        .unwrap();
+        processed.sort();
+
+        assert_eq!(processed, repositories);
     }
 
-    Ok(())
-}
+    #[test]
+    fn streams_discovery_only_for_unordered_parallel_scans() {
+        let streams = |args: &[&str]| streams_discovery(&Cli::parse_from(args));
 
-fn discover_repositories_from(current_dir: &Path) -> Vec<PathBuf> {
-    grpgit::discover_repositories(current_dir)
-}
+        assert!(streams(&["grpr", "-t", "4", "fetch"]));
+        assert!(!streams(&["grpr", "fetch"]));
+        assert!(!streams(&["grpr", "-t", "4", "--sort", "path", "fetch"]));
+        assert!(!streams(&["grpr", "-t", "4", "--limit", "3", "fetch"]));
+        assert!(!streams(&["grpr", "-t", "4", "--cached", "fetch"]));
+        assert!(!streams(&["grpr", "-t", "4", "--registered", "fetch"]));
+        assert!(!streams(&["grpr", "-t", "4", "--pick", "fetch"]));
+        assert!(!streams(&["grpr", "-t", "4", "--dry-run", "fetch"]));
+    }
 
-/// Main function initializes the program, parses CLI arguments, discovers git
-/// repositories, and executes the requested git command in each one.
-fn main() -> Result<(), Box<dyn Error>> {
-    let cli = Cli::parse();
-    let git_args = git_command_from_cli(&cli);
-    let current_dir = env::current_dir()?;
-    let repositories = discover_repositories_from(current_dir.as_path());
+    #[test]
+    fn dry_run_prints_commands_sequentially() {
+        let cli = Cli::parse_from(["grpr", "-t", "4", "--sort", "path", "--dry-run", "push"]);
+        let options = execution_options_from_cli(&cli);
+
+        assert_eq!(options.mode, grpgit::RunMode::DryRun);
+        assert_eq!(options.threads, None);
+        assert!(!options.ordered_output);
+        assert_eq!(
+            execution_options_from_cli(&Cli::parse_from(["grpr", "push"])).mode,
+            grpgit::RunMode::Execute
+        );
+        assert!(Cli::try_parse_from(["grpr", "--dry-run", "--watch", "fetch"]).is_err());
 
-    if repositories.is_empty() {
-        eprintln!(
-            "grpr: no git repositories found under {}",
-            current_dir.display()
+        let dir = tempdir().unwrap();
+        let missing = vec![dir.path().join("missing")];
+        let command = grpgit::RepoCommand::git(vec!["push".to_string()]);
+        assert_eq!(
+            execute_stream(
+                missing.clone().into_iter(),
+                &command,
+                &options,
+                &RunStatus::default()
+            )
+            .unwrap(),
+            missing
         );
     }
 
-    execute_repositories(&repositories, &git_args, cli.threads)
-}
+    #[test]
+    fn confirm_processes_repositories_one_at_a_time() {
+        let cli = Cli::parse_from(["grpr", "-t", "8", "--confirm", "reset", "--hard"]);
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use clap::{CommandFactory, Parser};
-    use std::fs;
-    use tempfile::tempdir;
+        assert!(execution_options_from_cli(&cli).confirm);
+        assert_eq!(execution_options_from_cli(&cli).threads, None);
+        assert!(!streams_discovery(&cli));
+        assert!(Cli::try_parse_from(["grpr", "--confirm", "--stdin", "fetch"]).is_err());
+    }
 
-    fn create_regular_repo(path: &Path) {
-        let git_dir = path.join(".git");
-        fs::create_dir_all(&git_dir).unwrap();
-        fs::write(git_dir.join("config"), "[core]\n").unwrap();
+    #[test]
+    fn serial_runs_sequentially_in_path_order() {
+        let serial = Cli::parse_from(["grpr", "--serial", "rebase", "-i"]);
+        let sorted = Cli::parse_from(["grpr", "--serial", "--sort", "mtime", "fetch"]);
+
+        assert_eq!(repository_order(&serial), Some(RepositoryOrder::Path));
+        assert_eq!(repository_order(&sorted), Some(RepositoryOrder::Mtime));
+        assert_eq!(repository_order(&Cli::parse_from(["grpr", "fetch"])), None);
+        assert_eq!(execution_options_from_cli(&serial).threads, None);
+        assert!(!streams_discovery(&serial));
+        assert!(Cli::try_parse_from(["grpr", "--serial", "-t", "4", "fetch"]).is_err());
     }
 
     #[test]
-    fn git_command_defaults_to_status() {
-        let cli = Cli::parse_from(["grpr"]);
+    fn sort_repositories_orders_by_name_then_path() {
+        let mut repositories = vec![
+            PathBuf::from("/src/work/zeta"),
+            PathBuf::from("/src/oss/alpha"),
+            PathBuf::from("/src/work/alpha"),
+        ];
 
-        assert_eq!(git_command_from_cli(&cli), vec!["status"]);
+        sort_repositories(&mut repositories, RepositoryOrder::Name);
+
+        assert_eq!(
+            repositories,
+            vec![
+                PathBuf::from("/src/oss/alpha"),
+                PathBuf::from("/src/work/alpha"),
+                PathBuf::from("/src/work/zeta"),
+            ]
+        );
     }
 
     #[test]
-    fn git_command_preserves_multiple_arguments() {
-        let cli = Cli::parse_from(["grpr", "log", "--oneline", "--graph"]);
+    fn sort_repositories_orders_by_path() {
+        let mut repositories = vec![PathBuf::from("/b/a"), PathBuf::from("/a/z")];
+
+        sort_repositories(&mut repositories, RepositoryOrder::Path);
 
         assert_eq!(
-            git_command_from_cli(&cli),
-            vec!["log", "--oneline", "--graph"]
+            repositories,
+            vec![PathBuf::from("/a/z"), PathBuf::from("/b/a")]
         );
     }
 
     #[test]
-    fn cli_version_matches_cargo_package_version() {
-        assert_eq!(VERSION, "2.0.2");
-        assert_eq!(VERSION, env!("CARGO_PKG_VERSION"));
+    fn sort_repositories_orders_by_recent_modification_first() {
+        let dir = tempdir().unwrap();
+        let older = dir.path().join("older");
+        let newer = dir.path().join("newer");
+        for repo_dir in [&older, &newer] {
+            fs::create_dir_all(repo_dir).unwrap();
+            create_regular_repo(repo_dir);
+        }
+        let old_time = std::time::SystemTime::UNIX_EPOCH + std::time::Duration::from_secs(1);
+        fs::File::open(older.join(".git"))
+            .unwrap()
+            .set_modified(old_time)
+            .unwrap();
+
+        let mut repositories = vec![older.clone(), newer.clone()];
+        sort_repositories(&mut repositories, RepositoryOrder::Mtime);
+
+        assert_eq!(repositories, vec![newer, older]);
+    }
+
+    fn init_repo_with_origin(path: &Path, url: &str) {
+        fs::create_dir_all(path).unwrap();
+        run_git(path, &["init", "--quiet"]);
+        run_git(path, &["remote", "add", "origin", url]);
     }
 
     #[test]
-    fn clap_renders_expected_version_string() {
-        let rendered = Cli::command().render_version().to_string();
+    fn selector_skips_matching_origins() {
+        let dir = tempdir().unwrap();
+        let own = dir.path().join("own");
+        let mirror = dir.path().join("mirror");
+        let local = dir.path().join("local");
+        init_repo_with_origin(&own, "git@github.com:me/app.git");
+        init_repo_with_origin(&mirror, "https://github.com/rust-lang/rust.git");
+        fs::create_dir_all(&local).unwrap();
+        create_regular_repo(&local);
 
-        assert_eq!(rendered.trim(), "grpr 2.0.2");
+        let repositories = vec![own.clone(), mirror, local.clone()];
+        let filter = Selector {
+            criteria: vec![Criterion::ExcludeRemote(vec![
+                "github.com/rust-lang/".to_string(),
+            ])],
+            ..Selector::default()
+        };
+        let kept = filter.apply(repositories);
+
+        assert_eq!(kept, vec![own, local]);
     }
 
     #[test]
-    fn discover_repositories_from_finds_root_level_repositories() {
+    fn selector_skips_inactive_repositories() {
         let dir = tempdir().unwrap();
         let repo_dir = dir.path().join("repo");
         fs::create_dir_all(&repo_dir).unwrap();
         create_regular_repo(&repo_dir);
+        fs::write(repo_dir.join(".git").join("HEAD"), "ref: refs/heads/main\n").unwrap();
+        let used = grpgit::last_activity(&repo_dir).unwrap();
+        let filter_since = |since| Selector {
+            criteria: vec![Criterion::ActiveSince(since)],
+            ..Selector::default()
+        };
 
-        let repositories = discover_repositories_from(dir.path());
+        assert!(filter_since(used - Duration::from_secs(60)).matches(&repo_dir));
+        assert!(!filter_since(used + Duration::from_secs(60)).matches(&repo_dir));
+    }
 
-        assert_eq!(repositories, vec![repo_dir]);
+    #[test]
+    fn selector_selects_dirty_or_clean_repositories() {
+        let dir = tempdir().unwrap();
+        let clean = dir.path().join("clean");
+        let dirty = dir.path().join("dirty");
+        for repo_dir in [&clean, &dirty] {
+            fs::create_dir_all(repo_dir).unwrap();
+            run_git(repo_dir, &["init", "--quiet"]);
+        }
+        fs::write(dirty.join("notes.txt"), "draft\n").unwrap();
+        let repositories = vec![clean.clone(), dirty.clone()];
+        let filter_dirty = |dirty| Selector {
+            criteria: vec![Criterion::Dirty(dirty)],
+            ..Selector::default()
+        };
+
+        assert_eq!(filter_dirty(true).apply(repositories.clone()), vec![dirty]);
+        assert_eq!(filter_dirty(false).apply(repositories), vec![clean]);
+        assert!(Cli::try_parse_from(["grpr", "--dirty", "--clean", "status"]).is_err());
     }
 
     #[test]
-    fn execute_repositories_succeeds_with_sequential_processing() {
+    fn selector_matches_paths_relative_to_their_root() {
+        let roots = vec![PathBuf::from("/src"), PathBuf::from("/src/work")];
+        let repositories = vec![
+            PathBuf::from("/src/clients/acme/backend"),
+            PathBuf::from("/src/clients/acme/frontend"),
+            PathBuf::from("/src/work/clients/globex/backend"),
+            PathBuf::from("/src/clients/initech/backend-legacy"),
+        ];
+        let cli = Cli::try_parse_from([
+            "grpr",
+            "--match",
+            "^clients/[^/]+/backend",
+            "--no-match",
+            "legacy$",
+            "status",
+        ])
+        .unwrap();
+
+        let kept = selector_from_cli(&cli, &roots).apply(repositories.clone());
+
+        assert_eq!(kept, vec![repositories[0].clone(), repositories[2].clone()]);
+        assert!(Cli::try_parse_from(["grpr", "--match", "(", "status"]).is_err());
+    }
+
+    #[test]
+    fn selector_keeps_repositories_with_a_matching_remote() {
         let dir = tempdir().unwrap();
-        let repo_dir = dir.path().join("repo");
-        fs::create_dir_all(&repo_dir).unwrap();
+        let mine = dir.path().join("mine");
+        let upstream = dir.path().join("upstream");
+        let local = dir.path().join("local");
+        for (repo_dir, remotes) in [
+            (
+                &mine,
+                &[
+                    ("origin", "https://github.com/other/app.git"),
+                    ("fork", "git@github.com:myorg/app.git"),
+                ][..],
+            ),
+            (
+                &upstream,
+                &[("origin", "https://github.com/other/lib.git")][..],
+            ),
+            (&local, &[][..]),
+        ] {
+            fs::create_dir_all(repo_dir).unwrap();
+            run_git(repo_dir, &["init", "--quiet"]);
+            for (name, url) in remotes {
+                run_git(repo_dir, &["remote", "add", name, url]);
+            }
+        }
+        let filter = Selector {
+            criteria: vec![Criterion::RemoteMatches(vec![
+                "github.com/myorg/".to_string(),
+                "github.com:myorg/".to_string(),
+            ])],
+            ..Selector::default()
+        };
 
-        let status = std::process::Command::new("git")
-            .arg("init")
-            .current_dir(&repo_dir)
-            .status()
-            .unwrap();
-        assert!(status.success());
+        assert_eq!(
+            filter.apply(vec![mine.clone(), upstream, local]),
+            vec![mine]
+        );
+    }
 
-        let repositories = vec![repo_dir];
-        let git_args = vec!["status".to_string()];
+    #[test]
+    fn selector_bounds_head_commit_times() {
+        let dir = tempdir().unwrap();
+        let mut repositories = Vec::new();
+        for (name, date) in [("old", "@1500000000 +0000"), ("new", "@1700000000 +0000")] {
+            let repo_dir = dir.path().join(name);
+            fs::create_dir_all(&repo_dir).unwrap();
+            run_git(&repo_dir, &["init", "--quiet"]);
+            git_with_env(
+                &repo_dir,
+                &[("GIT_COMMITTER_DATE", date)],
+                &["commit", "--quiet", "--allow-empty", "-m", name],
+            );
+            repositories.push(repo_dir);
+        }
+        let empty = dir.path().join("empty");
+        fs::create_dir_all(&empty).unwrap();
+        run_git(&empty, &["init", "--quiet"]);
+        repositories.push(empty);
 
-        assert!(execute_repositories(&repositories, &git_args, None).is_ok());
+        let since =
+            Cli::try_parse_from(["grpr", "--committed-since", "2020-01-01", "log"]).unwrap();
+        let before =
+            Cli::try_parse_from(["grpr", "--committed-before", "2020-01-01", "log"]).unwrap();
+
+        assert_eq!(
+            selector_from_cli(&since, &[]).apply(repositories.clone()),
+            vec![repositories[1].clone()]
+        );
+        assert_eq!(
+            selector_from_cli(&before, &[]).apply(repositories.clone()),
+            vec![repositories[0].clone()]
+        );
+    }
+
+    #[test]
+    fn selector_selects_detached_or_attached_heads() {
+        let dir = tempdir().unwrap();
+        let attached = dir.path().join("attached");
+        let detached = dir.path().join("detached");
+        for repo_dir in [&attached, &detached] {
+            create_regular_repo(repo_dir);
+        }
+        fs::write(attached.join(".git").join("HEAD"), "ref: refs/heads/main\n").unwrap();
+        fs::write(
+            detached.join(".git").join("HEAD"),
+            "0123456789abcdef0123456789abcdef01234567\n",
+        )
+        .unwrap();
+        let repositories = vec![attached.clone(), detached.clone()];
+        let skip = Cli::try_parse_from(["grpr", "--skip-detached", "pull"]).unwrap();
+        let only = Cli::try_parse_from(["grpr", "--only-detached", "status"]).unwrap();
+
+        assert_eq!(
+            selector_from_cli(&skip, &[]).apply(repositories.clone()),
+            vec![attached]
+        );
+        assert_eq!(
+            selector_from_cli(&only, &[]).apply(repositories),
+            vec![detached]
+        );
+        assert!(
+            Cli::try_parse_from(["grpr", "--skip-detached", "--only-detached", "status"]).is_err()
+        );
+    }
+
+    #[test]
+    fn cli_rejects_invalid_where_expressions() {
+        assert!(Cli::try_parse_from(["grpr", "--where", "dirty && !detached", "status"]).is_ok());
+        assert!(Cli::try_parse_from(["grpr", "--where", "branch", "status"]).is_err());
+        assert!(Cli::try_parse_from(["grpr", "--where", "owner == \"me\"", "status"]).is_err());
+    }
+
+    #[test]
+    fn cli_selects_upstream_states() {
+        let cli = Cli::try_parse_from(["grpr", "--ahead", "--diverged", "push"]).unwrap();
+        let selector = selector_from_cli(&cli, &[]);
+
+        assert!(matches!(
+            selector.criteria.as_slice(),
+            [Criterion::Upstream(states)]
+                if states == &[grpgit::UpstreamState::Ahead, grpgit::UpstreamState::Diverged]
+        ));
+    }
+
+    #[test]
+    fn explain_selection_lists_selected_repositories_with_reasons() {
+        let dir = tempdir().unwrap();
+        let mut repositories = Vec::new();
+        for name in ["apps/web", "apps/api", "tools/cli"] {
+            let repo_dir = dir.path().join(name);
+            create_regular_repo(&repo_dir);
+            repositories.push(repo_dir);
+        }
+        let discovery = Discovery {
+            roots: vec![dir.path().to_path_buf()],
+            repositories: repositories.clone(),
+            ..Discovery::default()
+        };
+        let cli =
+            Cli::try_parse_from(["grpr", "which", "--match", "^apps/", "--sort", "path"]).unwrap();
+
+        assert!(matches!(cli.command, Some(CliCommand::Which)));
+        assert_eq!(
+            explain_selection(&cli, &discovery),
+            vec![
+                (repositories[1].clone(), vec!["--match ^apps/".to_string()]),
+                (repositories[0].clone(), vec!["--match ^apps/".to_string()]),
+            ]
+        );
+    }
+
+    #[test]
+    fn cli_selects_repositories_by_configured_remotes() {
+        let local = Cli::try_parse_from(["grpr", "--no-remote", "status"]).unwrap();
+        let named = Cli::try_parse_from([
+            "grpr",
+            "--has-remote",
+            "upstream",
+            "--has-remote",
+            "fork",
+            "fetch",
+        ])
+        .unwrap();
+
+        assert!(matches!(
+            selector_from_cli(&local, &[]).criteria.as_slice(),
+            [Criterion::NoRemote]
+        ));
+        assert!(matches!(
+            selector_from_cli(&named, &[]).criteria.as_slice(),
+            [Criterion::HasRemote(names)] if names == &["upstream", "fork"]
+        ));
+        assert!(
+            Cli::try_parse_from(["grpr", "--no-remote", "--has-remote", "origin", "status"])
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn cli_selects_repositories_by_submodule_usage() {
+        let has = Cli::try_parse_from(["grpr", "--has-submodules", "submodule", "sync"]).unwrap();
+        let without = Cli::try_parse_from(["grpr", "which", "--no-submodules"]).unwrap();
+
+        assert!(matches!(
+            selector_from_cli(&has, &[]).criteria.as_slice(),
+            [Criterion::Submodules(true)]
+        ));
+        assert!(matches!(
+            selector_from_cli(&without, &[]).criteria.as_slice(),
+            [Criterion::Submodules(false)]
+        ));
+        assert!(
+            Cli::try_parse_from(["grpr", "--has-submodules", "--no-submodules", "status"]).is_err()
+        );
+    }
+
+    #[test]
+    fn cli_parses_config_settings() {
+        let cli = Cli::try_parse_from([
+            "grpr",
+            "--config-equals",
+            "user.email=me@work.com",
+            "config",
+            "user.email",
+        ])
+        .unwrap();
+
+        assert_eq!(
+            cli.config_equals,
+            vec![("user.email".to_string(), "me@work.com".to_string())]
+        );
+        assert!(Cli::try_parse_from(["grpr", "--config-equals", "user.email", "status"]).is_err());
+    }
+
+    #[test]
+    fn cli_sets_selection_combination_and_inversion() {
+        let default = selector_from_cli(&Cli::parse_from(["grpr", "--dirty", "status"]), &[]);
+        let cli = Cli::try_parse_from([
+            "grpr",
+            "--dirty",
+            "--has-stash",
+            "--combine",
+            "any",
+            "--invert-selection",
+            "status",
+        ])
+        .unwrap();
+        let selector = selector_from_cli(&cli, &[]);
+
+        assert_eq!(default.combine, selector::Combine::All);
+        assert!(!default.invert);
+        assert_eq!(selector.combine, selector::Combine::Any);
+        assert!(selector.invert);
+        assert_eq!(selector.criteria.len(), 2);
+    }
+
+    #[test]
+    fn cli_parses_active_within_durations() {
+        let cli = Cli::parse_from(["grpr", "--active-within", "2w", "fetch"]);
+
+        assert_eq!(
+            cli.active_within,
+            Some(Duration::from_secs(14 * 24 * 60 * 60))
+        );
+        assert!(Cli::try_parse_from(["grpr", "--active-within", "soon", "fetch"]).is_err());
+    }
+
+    #[test]
+    fn select_batch_applies_skip_then_limit() {
+        let repositories: Vec<PathBuf> = ["a", "b", "c", "d"].iter().map(PathBuf::from).collect();
+
+        assert_eq!(select_batch(repositories.clone(), 0, None), repositories);
+        assert_eq!(
+            select_batch(repositories.clone(), 1, Some(2)),
+            vec![PathBuf::from("b"), PathBuf::from("c")]
+        );
+        assert!(select_batch(repositories, 5, Some(2)).is_empty());
+    }
+
+    #[test]
+    fn cli_parses_skip_and_limit() {
+        let cli = Cli::parse_from(["grpr", "--skip", "10", "--limit", "5", "pull"]);
+
+        assert_eq!(cli.skip, 10);
+        assert_eq!(cli.limit, Some(5));
+    }
+
+    #[test]
+    fn ordered_output_follows_sort_flag() {
+        let cli = Cli::parse_from(["grpr", "-t", "4", "status"]);
+        assert!(!execution_options_from_cli(&cli).ordered_output);
+
+        let cli = Cli::parse_from(["grpr", "-t", "4", "--sort", "name", "status"]);
+        assert!(execution_options_from_cli(&cli).ordered_output);
+        assert_eq!(cli.sort, Some(RepositoryOrder::Name));
+    }
+
+    #[test]
+    fn parallel_runs_buffer_output() {
+        let options = |args: &[&str]| execution_options_from_cli(&Cli::parse_from(args));
+
+        assert!(options(&["grpr", "-t", "4", "status"]).buffers_output());
+        assert!(!options(&["grpr", "status"]).buffers_output());
+        assert!(!options(&["grpr", "-t", "1", "status"]).buffers_output());
+        assert!(!options(&["grpr", "-t", "4", "--dry-run", "status"]).buffers_output());
+        assert!(options(&["grpr", "--output", "json", "status"]).buffers_output());
+        assert!(options(&["grpr", "--output", "jsonl", "status"]).buffers_output());
+        assert!(options(&["grpr", "-q", "status"]).buffers_output());
+        assert!(options(&["grpr", "--progress", "status"]).buffers_output());
+        assert!(options(&["grpr", "--log-dir", "logs", "status"]).buffers_output());
+    }
+
+    #[test]
+    fn changes_only_is_quiet() {
+        assert!(Cli::parse_from(["grpr", "--changes-only", "status"]).quiet);
+        assert!(execution_options_from_cli(&Cli::parse_from(["grpr", "-q", "status"])).quiet);
+    }
+
+    #[test]
+    fn progress_flag_defaults_to_a_bar() {
+        let progress = |args: &[&str]| Cli::parse_from(args).progress;
+
+        assert_eq!(progress(&["grpr", "status"]), None);
+        assert_eq!(
+            progress(&["grpr", "--progress", "status"]),
+            Some(ProgressMode::Bar)
+        );
+        assert_eq!(
+            progress(&["grpr", "--progress=spinners", "status"]),
+            Some(ProgressMode::Spinners)
+        );
+        assert!(Cli::try_parse_from(["grpr", "--progress", "--confirm", "status"]).is_err());
+    }
+
+    #[test]
+    fn result_order_puts_the_slowest_or_the_failures_first() {
+        let fast_failure = (Path::new("/src/b"), true, Duration::from_secs(1));
+        let slow_success = (Path::new("/src/a"), false, Duration::from_secs(5));
+
+        assert_eq!(
+            ResultOrder::Duration.compare(fast_failure, slow_success),
+            cmp::Ordering::Greater
+        );
+        assert_eq!(
+            ResultOrder::Status.compare(fast_failure, slow_success),
+            cmp::Ordering::Less
+        );
+        assert_eq!(
+            ResultOrder::Name.compare(fast_failure, slow_success),
+            cmp::Ordering::Greater
+        );
+    }
+
+    #[test]
+    fn porcelain_defaults_to_its_first_version() {
+        let output = |args: &[&str]| execution_options_from_cli(&Cli::parse_from(args)).output;
+
+        assert_eq!(
+            output(&["grpr", "--porcelain", "fetch"]),
+            OutputFormat::Porcelain
+        );
+        assert_eq!(
+            output(&["grpr", "--porcelain=v1", "fetch"]),
+            OutputFormat::Porcelain
+        );
+        assert!(Cli::try_parse_from(["grpr", "--porcelain=v2", "fetch"]).is_err());
+        assert!(Cli::try_parse_from(["grpr", "--porcelain", "--output", "csv", "fetch"]).is_err());
+    }
+
+    #[test]
+    fn pager_is_left_out_when_the_terminal_is_needed() {
+        let pages = |args: &[&str]| {
+            let cli = Cli::parse_from(args);
+            pages_output(&cli, command_to_run(&cli).unwrap().may_prompt())
+        };
+
+        assert!(pages(&["grpr", "log", "-5"]));
+        assert!(pages(&["grpr", "diff", "--stat"]));
+        assert!(!pages(&["grpr", "pull"]));
+        assert!(!pages(&["grpr", "fetch", "--all"]));
+        assert!(!pages(&["grpr", "push"]));
+        assert!(!pages(&["grpr", "rebase", "-i", "main"]));
+        assert!(!pages(&["grpr", "exec", "make"]));
+        assert!(!pages(&["grpr", "--pre", "make", "log", "-5"]));
+        assert!(!pages(&["grpr", "--no-pager", "log", "-5"]));
+        assert!(!pages(&["grpr", "--progress", "log", "-5"]));
+        assert!(!pages(&["grpr", "--tui", "log", "-5"]));
+    }
+
+    #[test]
+    fn tui_captures_output_and_waits_for_the_selection() {
+        let cli = Cli::parse_from(["grpr", "--tui", "-t", "4", "fetch"]);
+
+        assert!(execution_options_from_cli(&cli).captures_output());
+        assert!(!streams_discovery(&cli));
+        assert!(Cli::try_parse_from(["grpr", "--tui", "--output", "json", "status"]).is_err());
+        assert!(Cli::try_parse_from(["grpr", "--tui", "--progress", "status"]).is_err());
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn json_output_collects_a_report_per_repository() {
+        let dir = tempdir().unwrap();
+        let repositories: Vec<PathBuf> = ["a", "b"].map(|name| dir.path().join(name)).into();
+        for repo_path in &repositories {
+            fs::create_dir(repo_path).unwrap();
+        }
+        let options =
+            execution_options_from_cli(&Cli::parse_from(["grpr", "--output", "json", "status"]));
+        let command = grpgit::RepoCommand::exec(vec![
+            "echo \"$GRPR_REPO_NAME\"; test \"$GRPR_REPO_NAME\" = a".to_string(),
+        ]);
+        let run = RunStatus::default();
+
+        execute_stream(repositories.clone().into_iter(), &command, &options, &run).unwrap();
+
+        let reports = run.reports.into_inner().unwrap();
+        assert_eq!(reports.len(), 2);
+        assert_eq!(reports[0].path, repositories[0]);
+        assert_eq!(reports[0].stdout, "a\n");
+        assert_eq!(reports[0].exit_code, Some(0));
+        assert!(!reports[1].success);
+        assert_eq!(reports[1].exit_code, Some(1));
+        assert!(Cli::try_parse_from(["grpr", "--output", "json", "--dry-run", "status"]).is_err());
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn group_by_outcome_holds_output_until_the_end() {
+        let dir = tempdir().unwrap();
+        let repositories: Vec<PathBuf> = ["a", "b"].map(|name| dir.path().join(name)).into();
+        for repo_path in &repositories {
+            fs::create_dir(repo_path).unwrap();
+        }
+        let options = execution_options_from_cli(&Cli::parse_from([
+            "grpr",
+            "--group-by",
+            "outcome",
+            "status",
+        ]));
+        let command = grpgit::RepoCommand::exec(vec!["test \"$GRPR_REPO_NAME\" = a".to_string()]);
+        let run = RunStatus::default();
+
+        execute_stream(repositories.clone().into_iter(), &command, &options, &run).unwrap();
+        run.skip(&dir.path().join("c"), "dirty");
+
+        let held = run.held.into_inner().unwrap();
+        assert_eq!(held.len(), 2);
+        assert!(held[0].1.result.is_ok());
+        assert!(held[1].1.result.is_err());
+        assert_eq!(run.failures.load(Ordering::Relaxed), 1);
+        assert_eq!(
+            run.skipped.into_inner().unwrap(),
+            [(dir.path().join("c"), "dirty".to_string())]
+        );
     }
 }
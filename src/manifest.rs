@@ -0,0 +1,150 @@
+/*
+ * grpr - A CLI tool for recursively executing git commands.
+ *
+ * Copyright (c) 2025 Anupam Sengupta
+ *
+ * This source code is licensed under the MIT license found in the LICENSE file
+ * in the root directory of this source tree.
+ */
+
+use crate::registry::Registry;
+use std::fs;
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+
+const TOML_EXTENSION: &str = "toml";
+const COMMENT_PREFIX: char = '#';
+
+/// Parses a plain-text manifest: one repository path per line, ignoring blank
+/// lines and lines starting with `#`.
+fn parse_lines(contents: &str) -> Vec<PathBuf> {
+    contents
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with(COMMENT_PREFIX))
+        .map(PathBuf::from)
+        .collect()
+}
+
+/// Loads the repository paths listed in the manifest at `path`.
+///
+/// Files with a `.toml` extension use the registry format (`[[repository]]`
+/// tables with a `path` key); any other file lists one path per line. Relative
+/// paths are resolved against the manifest's directory.
+pub fn load_manifest(path: &Path) -> Result<Vec<PathBuf>, io::Error> {
+    let contents = fs::read_to_string(path)?;
+
+    let listed = if path.extension().is_some_and(|ext| ext == TOML_EXTENSION) {
+        let registry: Registry = toml::from_str(&contents).map_err(|err| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("invalid manifest {}: {err}", path.display()),
+            )
+        })?;
+        registry.paths()
+    } else {
+        parse_lines(&contents)
+    };
+
+    let base_dir = path.parent().unwrap_or(Path::new(""));
+    Ok(listed
+        .into_iter()
+        .map(|repo_path| base_dir.join(repo_path))
+        .collect())
+}
+
+/// Reads repository paths from `reader`, such as stdin. Paths are separated
+/// by newlines, or by NUL bytes when `nul_separated` is set, and empty entries
+/// are skipped.
+pub fn read_repository_list<R: Read>(
+    mut reader: R,
+    nul_separated: bool,
+) -> Result<Vec<PathBuf>, io::Error> {
+    let mut contents = String::new();
+    reader.read_to_string(&mut contents)?;
+
+    let separator = if nul_separated { '\0' } else { '\n' };
+    Ok(contents
+        .split(separator)
+        .map(|entry| entry.strip_suffix('\r').unwrap_or(entry))
+        .filter(|entry| !entry.is_empty())
+        .map(PathBuf::from)
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn load_manifest_reads_plain_text_lists() {
+        let dir = tempdir().unwrap();
+        let manifest_path = dir.path().join("repos.txt");
+        fs::write(
+            &manifest_path,
+            "# pinned repositories\n/src/app\n\n  libs/core  \n",
+        )
+        .unwrap();
+
+        let repositories = load_manifest(&manifest_path).unwrap();
+
+        assert_eq!(
+            repositories,
+            vec![PathBuf::from("/src/app"), dir.path().join("libs/core")]
+        );
+    }
+
+    #[test]
+    fn load_manifest_reads_registry_formatted_toml() {
+        let dir = tempdir().unwrap();
+        let manifest_path = dir.path().join("repos.toml");
+        fs::write(
+            &manifest_path,
+            "[[repository]]\npath = \"/src/app\"\n\n[[repository]]\npath = \"lib\"\n",
+        )
+        .unwrap();
+
+        let repositories = load_manifest(&manifest_path).unwrap();
+
+        assert_eq!(
+            repositories,
+            vec![PathBuf::from("/src/app"), dir.path().join("lib")]
+        );
+    }
+
+    #[test]
+    fn read_repository_list_splits_on_newlines() {
+        let input = "/src/app\r\n\n/src/with space\n";
+
+        let repositories = read_repository_list(input.as_bytes(), false).unwrap();
+
+        assert_eq!(
+            repositories,
+            vec![PathBuf::from("/src/app"), PathBuf::from("/src/with space")]
+        );
+    }
+
+    #[test]
+    fn read_repository_list_splits_on_nul_bytes() {
+        let input = "/src/app\0/src/line\nbreak\0";
+
+        let repositories = read_repository_list(input.as_bytes(), true).unwrap();
+
+        assert_eq!(
+            repositories,
+            vec![PathBuf::from("/src/app"), PathBuf::from("/src/line\nbreak")]
+        );
+    }
+
+    #[test]
+    fn load_manifest_rejects_malformed_toml() {
+        let dir = tempdir().unwrap();
+        let manifest_path = dir.path().join("repos.toml");
+        fs::write(&manifest_path, "[[repository]]\n").unwrap();
+
+        let err = load_manifest(&manifest_path).unwrap_err();
+
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+}
@@ -0,0 +1,70 @@
+/*
+ * grpr - A CLI tool for recursively executing git commands.
+ *
+ * Copyright (c) 2025 Anupam Sengupta
+ *
+ * This source code is licensed under the MIT license found in the LICENSE file
+ * in the root directory of this source tree.
+ */
+
+use std::io::ErrorKind;
+use std::net::{TcpStream, ToSocketAddrs, UdpSocket};
+use std::time::Duration;
+
+/// Public DNS servers, with the local address to reach each from. Only the
+/// route to them is looked up; nothing is sent.
+const PROBES: [(&str, &str); 2] = [
+    ("0.0.0.0:0", "8.8.8.8:53"),
+    ("[::]:0", "[2001:4860:4860::8888]:53"),
+];
+
+/// How long to wait for a remote host to accept a connection.
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// Returns true when the machine has a route to the internet over IPv4 or
+/// IPv6. Connecting a UDP socket only chooses a route, so this is quick and
+/// sends no traffic, and it fails at once when there is no network. Some
+/// networks reach their remotes without such a route, so a failure is only
+/// a hint, to be confirmed with [`can_reach`].
+pub fn is_online() -> bool {
+    PROBES.iter().any(|(local, remote)| {
+        UdpSocket::bind(local)
+            .and_then(|socket| socket.connect(remote))
+            .is_ok()
+    })
+}
+
+/// Returns false when `host` cannot be resolved, or when no address it
+/// resolves to can be reached on `port` because there is no route to it or
+/// it does not answer in time. A refused connection still shows that the
+/// network works, so it counts as reachable.
+pub fn can_reach(host: &str, port: u16) -> bool {
+    let Ok(addresses) = (host, port).to_socket_addrs() else {
+        return false;
+    };
+    addresses.into_iter().any(|address| {
+        match TcpStream::connect_timeout(&address, CONNECT_TIMEOUT) {
+            Ok(_) => true,
+            Err(err) => !matches!(
+                err.kind(),
+                ErrorKind::NetworkUnreachable | ErrorKind::HostUnreachable | ErrorKind::TimedOut
+            ),
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::TcpListener;
+
+    #[test]
+    fn hosts_are_reachable_only_when_they_resolve_and_answer() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+
+        assert!(can_reach("127.0.0.1", port));
+        // Names under `.invalid` never resolve.
+        assert!(!can_reach("grpr.invalid", 443));
+    }
+}
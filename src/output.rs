@@ -0,0 +1,312 @@
+/*
+ * grpr - A CLI tool for recursively executing git commands.
+ *
+ * Copyright (c) 2025 Anupam Sengupta
+ *
+ * This source code is licensed under the MIT license found in the LICENSE file
+ * in the root directory of this source tree.
+ */
+
+use clap::ValueEnum;
+use std::env;
+use std::io::Write;
+use std::io::{self, IsTerminal};
+use std::path::Path;
+use std::sync::OnceLock;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Instant;
+
+const BOLD: &str = "\x1b[1m";
+const GREEN: &str = "\x1b[1;32m";
+const RED: &str = "\x1b[1;31m";
+const YELLOW: &str = "\x1b[1;33m";
+const CYAN: &str = "\x1b[36m";
+const PLAIN_GREEN: &str = "\x1b[32m";
+const PLAIN_RED: &str = "\x1b[31m";
+const RESET: &str = "\x1b[0m";
+
+/// When grpr colors its output.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum ColorChoice {
+    /// Color output that goes to a terminal, unless `NO_COLOR` is set.
+    #[default]
+    Auto,
+    Always,
+    Never,
+}
+
+/// The `--color` choice for the run.
+static COLOR: OnceLock<ColorChoice> = OnceLock::new();
+
+/// When the run started, with `--timestamps`.
+static STARTED: OnceLock<Instant> = OnceLock::new();
+
+/// Prefixes each line of output, and each note, with the time since now,
+/// for `--timestamps`.
+pub fn set_timestamps() {
+    let _ = STARTED.set(Instant::now());
+}
+
+/// Returns the prefix for a line printed now, such as `[  12.345s] `, with
+/// `--timestamps`.
+pub fn timestamp() -> Option<String> {
+    STARTED
+        .get()
+        .map(|started| format!("[{:>8.3}s] ", started.elapsed().as_secs_f64()))
+}
+
+/// Writes `output` to `out`, prefixing each line with the timestamp with
+/// `--timestamps`.
+pub fn write_stamped(out: impl Write, output: &[u8]) -> io::Result<()> {
+    write_prefixed(out, output, timestamp().as_deref())
+}
+
+/// Writes `output` to `out` with `prefix`, if any, before each line.
+fn write_prefixed(mut out: impl Write, output: &[u8], prefix: Option<&str>) -> io::Result<()> {
+    let Some(prefix) = prefix else {
+        return out.write_all(output);
+    };
+    for line in output.split_inclusive(|&byte| byte == b'\n') {
+        out.write_all(prefix.as_bytes())?;
+        out.write_all(line)?;
+    }
+    Ok(())
+}
+
+/// Set once stdout goes through the pager, which shows colors on the
+/// terminal.
+static STDOUT_PAGED: AtomicBool = AtomicBool::new(false);
+/// Set once stderr goes through the pager as well.
+static STDERR_PAGED: AtomicBool = AtomicBool::new(false);
+
+/// Colors stdout, and stderr when `stderr` is paged too, as on a terminal
+/// now that they go through the pager.
+pub fn set_paged(stderr: bool) {
+    STDOUT_PAGED.store(true, Ordering::Relaxed);
+    STDERR_PAGED.store(stderr, Ordering::Relaxed);
+}
+
+/// Returns true when `stream` ends up on a terminal, directly or through the
+/// pager, as recorded in `paged`.
+fn on_terminal(stream: impl IsTerminal, paged: &AtomicBool) -> bool {
+    paged.load(Ordering::Relaxed) || stream.is_terminal()
+}
+
+/// Sets when grpr colors its output, once at startup.
+pub fn set_color(choice: ColorChoice) {
+    let _ = COLOR.set(choice);
+}
+
+/// How the command in a repository went, which sets the color of its header.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Outcome {
+    /// Still running, as when output is printed live.
+    Running,
+    Succeeded,
+    Failed,
+    /// Left out, as by `--require-clean`, without running the command.
+    Skipped,
+}
+
+/// Returns true when output to a stream that is, or is not, a `terminal`
+/// should be colored under `choice`.
+fn color_enabled(choice: ColorChoice, terminal: bool) -> bool {
+    match choice {
+        ColorChoice::Always => true,
+        ColorChoice::Never => false,
+        ColorChoice::Auto => {
+            terminal && env::var_os("NO_COLOR").is_none_or(|value| value.is_empty())
+        }
+    }
+}
+
+/// Wraps `text` in the escape sequence `code` when `enabled`.
+fn paint(text: &str, code: &str, enabled: bool) -> String {
+    if enabled {
+        format!("{code}{text}{RESET}")
+    } else {
+        text.to_string()
+    }
+}
+
+fn choice() -> ColorChoice {
+    COLOR.get().copied().unwrap_or_default()
+}
+
+fn outcome_code(outcome: Outcome) -> &'static str {
+    match outcome {
+        Outcome::Running => BOLD,
+        Outcome::Succeeded => GREEN,
+        Outcome::Failed => RED,
+        Outcome::Skipped => YELLOW,
+    }
+}
+
+/// Whether grpr runs under GitHub Actions, which reads workflow commands
+/// such as `::group::` from its output.
+static GITHUB_ACTIONS: OnceLock<bool> = OnceLock::new();
+
+/// Returns true when running under GitHub Actions, which sets
+/// `GITHUB_ACTIONS=true`.
+pub fn github_actions() -> bool {
+    *GITHUB_ACTIONS
+        .get_or_init(|| env::var_os("GITHUB_ACTIONS").is_some_and(|value| value == "true"))
+}
+
+/// Returns the GitHub Actions workflow command `name` with `message`, with
+/// the characters that would end or garble it escaped.
+fn workflow_command(name: &str, message: &str) -> String {
+    let message = message
+        .replace('%', "%25")
+        .replace('\r', "%0D")
+        .replace('\n', "%0A");
+    format!("::{name}::{message}")
+}
+
+/// Returns the line on stdout that ends a repository's output under GitHub
+/// Actions, closing the group its header opened.
+pub fn group_end() -> Option<String> {
+    github_actions().then(|| workflow_command("endgroup", ""))
+}
+
+/// Returns the annotation on stdout that highlights a failure under GitHub
+/// Actions, or `None` elsewhere.
+pub fn annotation(message: &str) -> Option<String> {
+    github_actions().then(|| workflow_command("error", message))
+}
+
+/// Returns the line on stdout that introduces a repository's output, in
+/// bold while its command runs, and green or red once it has finished.
+/// Under GitHub Actions, it opens a collapsible group instead.
+pub fn header(repo_path: &Path, outcome: Outcome) -> String {
+    let title = format!("Inside git repo: {}", repo_path.display());
+    if github_actions() {
+        return workflow_command("group", &title);
+    }
+    let enabled = color_enabled(choice(), on_terminal(io::stdout(), &STDOUT_PAGED));
+    paint(&title, outcome_code(outcome), enabled)
+}
+
+/// Returns the line on stdout that opens the section of repositories with
+/// `outcome` in `--group-by outcome`, such as `== Failed (3) ==`.
+pub fn section(outcome: Outcome, count: usize) -> String {
+    let title = match outcome {
+        Outcome::Running => "Running",
+        Outcome::Succeeded => "Succeeded",
+        Outcome::Failed => "Failed",
+        Outcome::Skipped => "Skipped",
+    };
+    let enabled = color_enabled(choice(), on_terminal(io::stdout(), &STDOUT_PAGED));
+    paint(
+        &format!("== {title} ({count}) =="),
+        outcome_code(outcome),
+        enabled,
+    )
+}
+
+/// Returns `text` for a cell of the `--table` on stdout, green, red, or
+/// yellow by the `outcome` of its repository.
+pub fn table_cell(text: &str, outcome: Outcome) -> String {
+    let enabled = color_enabled(choice(), on_terminal(io::stdout(), &STDOUT_PAGED));
+    paint(text, outcome_code(outcome), enabled)
+}
+
+/// Returns a heading of the `--table` on stdout, in bold.
+pub fn table_heading(text: &str) -> String {
+    let enabled = color_enabled(choice(), on_terminal(io::stdout(), &STDOUT_PAGED));
+    paint(text, BOLD, enabled)
+}
+
+/// Returns true when stdout ends up on a terminal, directly or through the
+/// pager.
+pub fn stdout_on_terminal() -> bool {
+    on_terminal(io::stdout(), &STDOUT_PAGED)
+}
+
+/// How a part of the output of `status` or `diff` is colored, as git
+/// colors it on a terminal.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Highlight {
+    Plain,
+    /// The headers of a file in a diff, in bold.
+    Meta,
+    /// The line starting a hunk, in cyan.
+    Hunk,
+    /// An added line, or a staged change, in green.
+    Added,
+    /// A removed line, or a change that is not staged, in red.
+    Removed,
+}
+
+/// Returns `text` from the output of `status` or `diff` for stdout, colored
+/// as `highlight` says.
+pub fn highlight(text: &str, highlight: Highlight) -> String {
+    let code = match highlight {
+        Highlight::Plain => return text.to_string(),
+        Highlight::Meta => BOLD,
+        Highlight::Hunk => CYAN,
+        Highlight::Added => PLAIN_GREEN,
+        Highlight::Removed => PLAIN_RED,
+    };
+    let enabled = color_enabled(choice(), on_terminal(io::stdout(), &STDOUT_PAGED));
+    paint(text, code, enabled)
+}
+
+/// Returns `message` about a failure for stderr, in red.
+pub fn failure(message: &str) -> String {
+    paint(
+        message,
+        RED,
+        color_enabled(choice(), on_terminal(io::stderr(), &STDERR_PAGED)),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn color_follows_the_choice_and_the_terminal() {
+        assert!(color_enabled(ColorChoice::Always, false));
+        assert!(!color_enabled(ColorChoice::Never, true));
+        assert!(!color_enabled(ColorChoice::Auto, false));
+    }
+
+    #[test]
+    fn paint_wraps_text_only_when_enabled() {
+        assert_eq!(paint("ok", GREEN, true), "\x1b[1;32mok\x1b[0m");
+        assert_eq!(paint("ok", GREEN, false), "ok");
+    }
+
+    #[test]
+    fn workflow_commands_escape_line_breaks() {
+        assert_eq!(
+            workflow_command("error", "100% failed\nin app\r"),
+            "::error::100%25 failed%0Ain app%0D"
+        );
+        assert_eq!(workflow_command("endgroup", ""), "::endgroup::");
+    }
+
+    #[test]
+    fn prefix_goes_before_each_line() {
+        let mut out = Vec::new();
+        write_prefixed(&mut out, b"one\ntwo\nthree", Some("[   1.500s] ")).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "[   1.500s] one\n[   1.500s] two\n[   1.500s] three"
+        );
+
+        let mut out = Vec::new();
+        write_prefixed(&mut out, b"one\n", None).unwrap();
+        assert_eq!(out, b"one\n");
+    }
+
+    #[test]
+    fn header_is_plain_without_a_terminal() {
+        assert_eq!(
+            header(Path::new("/src/app"), Outcome::Failed),
+            "Inside git repo: /src/app"
+        );
+        assert_eq!(section(Outcome::Skipped, 2), "== Skipped (2) ==");
+    }
+}
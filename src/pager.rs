@@ -0,0 +1,261 @@
+/*
+ * grpr - A CLI tool for recursively executing git commands.
+ *
+ * Copyright (c) 2025 Anupam Sengupta
+ *
+ * This source code is licensed under the MIT license found in the LICENSE file
+ * in the root directory of this source tree.
+ */
+
+use std::env;
+use std::ffi::OsString;
+use std::io::{self, IsTerminal, Write};
+
+/// The pager used when `PAGER` is unset.
+const DEFAULT_PAGER: &str = "less";
+
+/// The screen size assumed when the terminal does not tell its own.
+const DEFAULT_SCREEN: Screen = Screen { rows: 24, cols: 80 };
+
+/// Returns the pager command to run for the value of `PAGER`, or `None`
+/// when paging is turned off by setting it empty or to `cat`.
+fn pager_command(pager: Option<OsString>) -> Option<OsString> {
+    match pager {
+        None => Some(DEFAULT_PAGER.into()),
+        Some(pager) if pager.is_empty() || pager == "cat" => None,
+        Some(pager) => Some(pager),
+    }
+}
+
+/// The size of the terminal, in character cells.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Screen {
+    rows: usize,
+    cols: usize,
+}
+
+impl Screen {
+    /// Returns true when `output` takes up more rows than the screen has,
+    /// counting lines longer than the screen is wide as the rows they wrap
+    /// onto.
+    fn overflows(&self, output: &[u8]) -> bool {
+        let cols = self.cols.max(1);
+        let mut rows = 0;
+        for line in String::from_utf8_lossy(output).lines() {
+            rows += line.chars().count().div_ceil(cols).max(1);
+            if rows >= self.rows {
+                return true;
+            }
+        }
+        false
+    }
+}
+
+/// The output held back while the run goes on: stdout, and stderr when it
+/// is a terminal, write into a pipe that a thread reads to the end.
+#[cfg(unix)]
+struct Held {
+    command: OsString,
+    stdout: std::os::fd::OwnedFd,
+    stderr: Option<std::os::fd::OwnedFd>,
+    reader: std::thread::JoinHandle<Vec<u8>>,
+}
+
+#[cfg(unix)]
+static HELD: std::sync::Mutex<Option<Held>> = std::sync::Mutex::new(None);
+
+/// Holds back the output of the run until it is dropped, then prints it,
+/// through the pager when it does not fit on one screen.
+pub struct Pager(());
+
+impl Drop for Pager {
+    fn drop(&mut self) {
+        finish(true);
+    }
+}
+
+/// Starts holding back stdout, and stderr when it is a terminal, to send
+/// them through `$PAGER`, or `less`, once the run is over, as git does,
+/// when stdout is a terminal. `less` is run with `LESS=FRX` unless `LESS`
+/// is set, so colors are kept.
+pub fn start() -> Option<Pager> {
+    if !io::stdout().is_terminal() {
+        return None;
+    }
+    let command = pager_command(env::var_os("PAGER"))?;
+    match hold(command) {
+        Ok(()) => Some(Pager(())),
+        Err(err) => {
+            tracing::warn!("cannot hold back the output for the pager: {err}");
+            None
+        }
+    }
+}
+
+/// Gives stdout and stderr back to the terminal, printing what was held
+/// back without paging it, so that a run stopped by Ctrl-C shows its
+/// output, and the notes about stopping, as they come.
+pub fn release() {
+    finish(false);
+}
+
+#[cfg(unix)]
+fn hold(command: OsString) -> io::Result<()> {
+    use std::fs::File;
+    use std::io::Read;
+    use std::os::fd::{AsFd, AsRawFd, FromRawFd, OwnedFd};
+
+    let mut fds = [0; 2];
+    // SAFETY: pipe fills in two new file descriptors, owned from here on,
+    // and fcntl only keeps them from leaking into commands.
+    let (read, write) = unsafe {
+        if libc::pipe(fds.as_mut_ptr()) == -1 {
+            return Err(io::Error::last_os_error());
+        }
+        for fd in fds {
+            libc::fcntl(fd, libc::F_SETFD, libc::FD_CLOEXEC);
+        }
+        (OwnedFd::from_raw_fd(fds[0]), OwnedFd::from_raw_fd(fds[1]))
+    };
+    let reader = std::thread::spawn(move || {
+        let mut output = Vec::new();
+        let _ = File::from(read).read_to_end(&mut output);
+        output
+    });
+
+    io::stdout().flush()?;
+    let stdout = io::stdout().as_fd().try_clone_to_owned()?;
+    let stderr = if io::stderr().is_terminal() {
+        Some(io::stderr().as_fd().try_clone_to_owned()?)
+    } else {
+        None
+    };
+    // SAFETY: dup2 only replaces the file descriptors.
+    unsafe {
+        libc::dup2(write.as_raw_fd(), libc::STDOUT_FILENO);
+        if stderr.is_some() {
+            libc::dup2(write.as_raw_fd(), libc::STDERR_FILENO);
+        }
+    }
+    crate::output::set_paged(stderr.is_some());
+    *HELD.lock().unwrap_or_else(|err| err.into_inner()) = Some(Held {
+        command,
+        stdout,
+        stderr,
+        reader,
+    });
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn hold(_command: OsString) -> io::Result<()> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "paging is only supported on Unix",
+    ))
+}
+
+/// Gives stdout and stderr back to the terminal, if they are held back,
+/// and prints the output held back, through the pager when `page` is set
+/// and it does not fit on one screen.
+#[cfg(unix)]
+fn finish(page: bool) {
+    use std::os::fd::AsRawFd;
+
+    let Some(held) = HELD.lock().unwrap_or_else(|err| err.into_inner()).take() else {
+        return;
+    };
+    let _ = io::stdout().flush();
+    // SAFETY: dup2 only replaces the file descriptors. Closing the last
+    // copies of the pipe lets the reader see the end of the output.
+    unsafe {
+        libc::dup2(held.stdout.as_raw_fd(), libc::STDOUT_FILENO);
+        if let Some(stderr) = &held.stderr {
+            libc::dup2(stderr.as_raw_fd(), libc::STDERR_FILENO);
+        }
+    }
+    let output = held.reader.join().unwrap_or_default();
+    if page && screen(&held.stdout).overflows(&output) {
+        match show(&held.command, &output) {
+            Ok(()) => return,
+            Err(err) => tracing::warn!(
+                "cannot start the pager {}: {err}",
+                held.command.to_string_lossy()
+            ),
+        }
+    }
+    let _ = io::stdout().write_all(&output);
+    let _ = io::stdout().flush();
+}
+
+#[cfg(not(unix))]
+fn finish(_page: bool) {}
+
+/// Returns the size of the terminal `fd` is on.
+#[cfg(unix)]
+fn screen(fd: &impl std::os::fd::AsRawFd) -> Screen {
+    let mut size = libc::winsize {
+        ws_row: 0,
+        ws_col: 0,
+        ws_xpixel: 0,
+        ws_ypixel: 0,
+    };
+    // SAFETY: TIOCGWINSZ only fills in `size`.
+    let known = unsafe { libc::ioctl(fd.as_raw_fd(), libc::TIOCGWINSZ, &mut size) } == 0;
+    if !known || size.ws_row == 0 || size.ws_col == 0 {
+        return DEFAULT_SCREEN;
+    }
+    Screen {
+        rows: size.ws_row.into(),
+        cols: size.ws_col.into(),
+    }
+}
+
+/// Sends `output` through the pager `command` and waits for it to exit.
+#[cfg(unix)]
+fn show(command: &OsString, output: &[u8]) -> io::Result<()> {
+    use std::process::{Command, Stdio};
+
+    let mut process = Command::new("sh");
+    process.arg("-c").arg(command).stdin(Stdio::piped());
+    if env::var_os("LESS").is_none() {
+        process.env("LESS", "FRX");
+    }
+    if env::var_os("LV").is_none() {
+        process.env("LV", "-c");
+    }
+    let mut child = process.spawn()?;
+    let mut input = child.stdin.take().expect("the pager's stdin is piped");
+    // Quitting the pager early closes its input, which is not a failure.
+    let _ = input.write_all(output);
+    drop(input);
+    child.wait()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pager_defaults_to_less_and_can_be_turned_off() {
+        assert_eq!(pager_command(None), Some(OsString::from("less")));
+        assert_eq!(
+            pager_command(Some("most -s".into())),
+            Some(OsString::from("most -s"))
+        );
+        assert_eq!(pager_command(Some("".into())), None);
+        assert_eq!(pager_command(Some("cat".into())), None);
+    }
+
+    #[test]
+    fn output_overflows_a_screen_by_rows_including_wrapped_lines() {
+        let screen = Screen { rows: 3, cols: 10 };
+
+        assert!(!screen.overflows(b""));
+        assert!(!screen.overflows(b"one\ntwo\n"));
+        assert!(screen.overflows(b"one\ntwo\nthree\n"));
+        assert!(screen.overflows(b"one\n0123456789abc\n"));
+        assert!(!screen.overflows("\u{e9}\u{e9}\u{e9}\u{e9}\u{e9}\n".as_bytes()));
+    }
+}
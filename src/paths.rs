@@ -0,0 +1,51 @@
+/*
+ * grpr - A CLI tool for recursively executing git commands.
+ *
+ * Copyright (c) 2025 Anupam Sengupta
+ *
+ * This source code is licensed under the MIT license found in the LICENSE file
+ * in the root directory of this source tree.
+ */
+
+use std::env;
+use std::path::PathBuf;
+
+const APP_DIR_NAME: &str = "grpr";
+
+/// Resolves an XDG base directory from `var`, falling back to `fallback`
+/// below the home directory when the variable is unset or empty.
+fn xdg_base_dir(var: &str, fallback: &[&str]) -> Option<PathBuf> {
+    env::var_os(var)
+        .filter(|value| !value.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| {
+            env::var_os("HOME").map(|home| {
+                fallback
+                    .iter()
+                    .fold(PathBuf::from(home), |path, part| path.join(part))
+            })
+        })
+}
+
+/// Returns grpr's data directory, `$XDG_DATA_HOME/grpr` or
+/// `~/.local/share/grpr`.
+pub fn data_dir() -> Option<PathBuf> {
+    xdg_base_dir("XDG_DATA_HOME", &[".local", "share"]).map(|dir| dir.join(APP_DIR_NAME))
+}
+
+/// Returns grpr's cache directory, `$XDG_CACHE_HOME/grpr` or `~/.cache/grpr`.
+pub fn cache_dir() -> Option<PathBuf> {
+    xdg_base_dir("XDG_CACHE_HOME", &[".cache"]).map(|dir| dir.join(APP_DIR_NAME))
+}
+
+/// Returns grpr's configuration directory, `$XDG_CONFIG_HOME/grpr` or
+/// `~/.config/grpr`.
+pub fn config_dir() -> Option<PathBuf> {
+    xdg_base_dir("XDG_CONFIG_HOME", &[".config"]).map(|dir| dir.join(APP_DIR_NAME))
+}
+
+/// Returns grpr's state directory, `$XDG_STATE_HOME/grpr` or
+/// `~/.local/state/grpr`.
+pub fn state_dir() -> Option<PathBuf> {
+    xdg_base_dir("XDG_STATE_HOME", &[".local", "state"]).map(|dir| dir.join(APP_DIR_NAME))
+}
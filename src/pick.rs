@@ -0,0 +1,130 @@
+/*
+ * grpr - A CLI tool for recursively executing git commands.
+ *
+ * Copyright (c) 2025 Anupam Sengupta
+ *
+ * This source code is licensed under the MIT license found in the LICENSE file
+ * in the root directory of this source tree.
+ */
+
+use crate::selector;
+use dialoguer::FuzzySelect;
+use std::io::{self, IsTerminal};
+use std::path::PathBuf;
+
+/// Number of list rows shown at once.
+const VISIBLE_ROWS: usize = 15;
+
+/// The repositories offered for picking and which of them are picked.
+struct Picker {
+    labels: Vec<String>,
+    picked: Vec<bool>,
+}
+
+impl Picker {
+    fn new(labels: Vec<String>) -> Self {
+        let picked = vec![false; labels.len()];
+        Self { labels, picked }
+    }
+
+    /// The prompt items: an entry that finishes picking, followed by one
+    /// entry per repository showing whether it is picked.
+    fn items(&self) -> Vec<String> {
+        let count = self.picked.iter().filter(|picked| **picked).count();
+        let done = format!("> Done: run in {count} picked");
+        let entries = self.labels.iter().zip(&self.picked).map(|(label, picked)| {
+            let mark = if *picked { "[x]" } else { "[ ]" };
+            format!("{mark} {label}")
+        });
+        std::iter::once(done).chain(entries).collect()
+    }
+
+    /// Handles the choice of prompt item `item`. Returns true when picking
+    /// is finished; otherwise toggles the chosen repository.
+    fn choose(&mut self, item: usize) -> bool {
+        match item.checked_sub(1) {
+            None => true,
+            Some(index) => {
+                self.picked[index] = !self.picked[index];
+                false
+            }
+        }
+    }
+
+    /// The picked entries of `repositories`, in their original order.
+    fn picked(&self, repositories: Vec<PathBuf>) -> Vec<PathBuf> {
+        repositories
+            .into_iter()
+            .zip(&self.picked)
+            .filter_map(|(repo_path, picked)| picked.then_some(repo_path))
+            .collect()
+    }
+}
+
+/// Lets the user pick some of `repositories` from a fuzzy-searchable list on
+/// the terminal. Choosing a repository toggles it, and choosing the first
+/// entry finishes. Cancelling with Esc picks nothing. Repositories are shown
+/// relative to `roots` and returned in their original order.
+///
+/// Each toggle shows the prompt afresh, which clears the search text;
+/// `FuzzySelect` cannot hand it back to carry over.
+pub fn pick_repositories(
+    repositories: Vec<PathBuf>,
+    roots: &[PathBuf],
+) -> Result<Vec<PathBuf>, io::Error> {
+    if !io::stdin().is_terminal() || !io::stderr().is_terminal() {
+        return Err(io::Error::other("--pick needs an interactive terminal"));
+    }
+
+    let labels = repositories
+        .iter()
+        .map(|repo_path| selector::relative_path(repo_path, roots))
+        .collect();
+    let mut picker = Picker::new(labels);
+    let mut cursor = 0;
+
+    loop {
+        let chosen = FuzzySelect::new()
+            .with_prompt("Pick repositories (Enter toggles, Esc cancels)")
+            .items(picker.items())
+            .default(cursor)
+            .max_length(VISIBLE_ROWS)
+            .interact_opt()?;
+        let Some(item) = chosen else {
+            return Ok(Vec::new());
+        };
+        if picker.choose(item) {
+            return Ok(picker.picked(repositories));
+        }
+        cursor = item;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn picker_toggles_repositories_until_done() {
+        let repositories = vec![
+            PathBuf::from("/src/a"),
+            PathBuf::from("/src/b"),
+            PathBuf::from("/src/c"),
+        ];
+        let mut picker = Picker::new(vec!["a".into(), "b".into(), "c".into()]);
+
+        assert!(!picker.choose(3));
+        assert!(!picker.choose(1));
+        assert!(!picker.choose(2));
+        assert!(!picker.choose(2));
+        assert_eq!(
+            picker.items(),
+            vec!["> Done: run in 2 picked", "[x] a", "[ ] b", "[x] c"]
+        );
+        assert!(picker.choose(0));
+        assert_eq!(
+            picker.picked(repositories),
+            vec![PathBuf::from("/src/a"), PathBuf::from("/src/c")]
+        );
+    }
+}
@@ -0,0 +1,152 @@
+/*
+ * grpr - A CLI tool for recursively executing git commands.
+ *
+ * Copyright (c) 2025 Anupam Sengupta
+ *
+ * This source code is licensed under the MIT license found in the LICENSE file
+ * in the root directory of this source tree.
+ */
+
+use crate::grpgit::{self, DiscoveryOptions, DiscoveryVisitor, RepositoryIdentity, ScanError};
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, SyncSender};
+use std::thread;
+
+/// Number of discovered repositories that may wait for a worker. A full queue
+/// pauses the walk until the workers catch up.
+const QUEUE_CAPACITY: usize = 256;
+
+/// Feeds repositories into the queue as the walk finds them, and stops the
+/// walk once nobody is reading from the queue any more.
+struct QueueVisitor<'a> {
+    sender: SyncSender<PathBuf>,
+    closed: AtomicBool,
+    errors: &'a Mutex<Vec<ScanError>>,
+}
+
+impl DiscoveryVisitor for QueueVisitor<'_> {
+    fn repository(&self, repo_path: &Path) {
+        if self.sender.send(repo_path.to_path_buf()).is_err() {
+            self.closed.store(true, Ordering::Relaxed);
+        }
+    }
+
+    fn error(&self, error: ScanError) {
+        self.errors
+            .lock()
+            .unwrap_or_else(|err| err.into_inner())
+            .push(error);
+    }
+
+    fn cancelled(&self) -> bool {
+        self.closed.load(Ordering::Relaxed)
+    }
+}
+
+/// Repositories received from the discovery thread, in the order they were
+/// found. Repositories reachable under several roots or paths are yielded
+/// once.
+pub struct Discovered {
+    receiver: Receiver<PathBuf>,
+    seen: HashSet<RepositoryIdentity>,
+}
+
+impl Iterator for Discovered {
+    type Item = PathBuf;
+
+    fn next(&mut self) -> Option<PathBuf> {
+        loop {
+            let repo_path = self.receiver.recv().ok()?;
+            if self.seen.insert(grpgit::repository_identity(&repo_path)) {
+                return Some(repo_path);
+            }
+        }
+    }
+}
+
+/// Discovers repositories under each root on a background thread and hands
+/// them to `consume` as they are found, so work can start while the rest of
+/// the tree is still being scanned. If `consume` returns before the walk is
+/// over, the walk is cancelled. Returns the result of `consume` and the
+/// directories that could not be read.
+pub fn stream_repositories<T>(
+    roots: &[PathBuf],
+    options: &DiscoveryOptions,
+    consume: impl FnOnce(Discovered) -> T,
+) -> (T, Vec<ScanError>) {
+    let (sender, receiver) = mpsc::sync_channel(QUEUE_CAPACITY);
+    let errors = Mutex::new(Vec::new());
+
+    let consumed = thread::scope(|scope| {
+        scope.spawn(|| {
+            let visitor = QueueVisitor {
+                sender,
+                closed: AtomicBool::new(false),
+                errors: &errors,
+            };
+            for root in roots {
+                grpgit::walk_repositories(root, options, &visitor);
+                if visitor.cancelled() {
+                    break;
+                }
+            }
+        });
+
+        consume(Discovered {
+            receiver,
+            seen: HashSet::new(),
+        })
+    });
+
+    let errors = errors.into_inner().unwrap_or_else(|err| err.into_inner());
+    (consumed, errors)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::tempdir;
+
+    fn create_regular_repo(path: &Path) {
+        fs::create_dir_all(path.join(".git")).unwrap();
+        fs::write(path.join(".git").join("config"), "").unwrap();
+    }
+
+    #[test]
+    fn stream_repositories_yields_each_repository_once() {
+        let dir = tempdir().unwrap();
+        let mut repos = Vec::new();
+        for name in ["a", "b", "c"] {
+            let repo = dir.path().join(name);
+            create_regular_repo(&repo);
+            repos.push(repo);
+        }
+        let roots = vec![dir.path().to_path_buf(), dir.path().to_path_buf()];
+
+        let (mut found, errors): (Vec<PathBuf>, _) =
+            stream_repositories(&roots, &DiscoveryOptions::default(), Iterator::collect);
+        found.sort();
+
+        assert_eq!(found, repos);
+        assert!(errors.is_empty());
+    }
+
+    #[test]
+    fn stream_repositories_stops_when_the_consumer_is_done() {
+        let dir = tempdir().unwrap();
+        for index in 0..QUEUE_CAPACITY * 2 {
+            create_regular_repo(&dir.path().join(format!("repo{index}")));
+        }
+        let roots = vec![dir.path().to_path_buf()];
+
+        let (first, _) = stream_repositories(&roots, &DiscoveryOptions::default(), |mut found| {
+            found.next()
+        });
+
+        assert!(first.is_some());
+    }
+}
@@ -0,0 +1,132 @@
+/*
+ * grpr - A CLI tool for recursively executing git commands.
+ *
+ * Copyright (c) 2025 Anupam Sengupta
+ *
+ * This source code is licensed under the MIT license found in the LICENSE file
+ * in the root directory of this source tree.
+ */
+
+use std::process::Command;
+
+/// The I/O scheduling class of a command on Linux, as set by `ionice`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum IoClass {
+    /// Share disk time with other processes, at a level following the
+    /// command's niceness.
+    BestEffort,
+    /// Use the disk only when no other process needs it.
+    Idle,
+}
+
+/// How much of the machine a command may take from other processes, so a
+/// long run across many repositories leaves the workstation usable.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Priority {
+    /// The niceness to run at, from -20 (most favorable) to 19 (least).
+    pub nice: Option<i8>,
+    /// The I/O scheduling class to run in. Ignored outside Linux.
+    pub io_class: Option<IoClass>,
+}
+
+impl Priority {
+    /// Returns true when the command runs as grpr itself does.
+    pub fn is_default(&self) -> bool {
+        self.nice.is_none() && self.io_class.is_none()
+    }
+
+    /// Makes `process` run at this priority once spawned. Failing to set it,
+    /// such as lowering the niceness without privileges, fails the spawn.
+    pub fn apply(self, process: &mut Command) {
+        if self.is_default() {
+            return;
+        }
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::process::CommandExt;
+            // SAFETY: the closure runs in the forked child before exec and
+            // only makes async-signal-safe system calls.
+            unsafe {
+                process.pre_exec(move || self.set_current());
+            }
+        }
+        #[cfg(windows)]
+        {
+            use std::os::windows::process::CommandExt;
+            const IDLE_PRIORITY_CLASS: u32 = 0x40;
+            const BELOW_NORMAL_PRIORITY_CLASS: u32 = 0x4000;
+            match self.nice {
+                Some(10..) => process.creation_flags(IDLE_PRIORITY_CLASS),
+                Some(1..) => process.creation_flags(BELOW_NORMAL_PRIORITY_CLASS),
+                _ => process,
+            };
+        }
+        #[cfg(not(any(unix, windows)))]
+        let _ = process;
+    }
+
+    /// Sets this priority on the calling process.
+    #[cfg(unix)]
+    fn set_current(self) -> std::io::Result<()> {
+        if let Some(nice) = self.nice {
+            // SAFETY: setpriority only reads its integer arguments.
+            if unsafe { libc::setpriority(libc::PRIO_PROCESS, 0, nice.into()) } == -1 {
+                return Err(std::io::Error::last_os_error());
+            }
+        }
+        #[cfg(target_os = "linux")]
+        if let Some(io_class) = self.io_class {
+            const IOPRIO_WHO_PROCESS: libc::c_long = 1;
+            const IOPRIO_CLASS_SHIFT: u32 = 13;
+            let (class, level) = match io_class {
+                // The kernel derives the best-effort level from niceness the
+                // same way when none is given.
+                IoClass::BestEffort => (2, (i32::from(self.nice.unwrap_or(0)) + 20) / 5),
+                IoClass::Idle => (3, 0),
+            };
+            let ioprio = libc::c_long::from(class << IOPRIO_CLASS_SHIFT | level);
+            // SAFETY: ioprio_set only reads its integer arguments.
+            let result =
+                unsafe { libc::syscall(libc::SYS_ioprio_set, IOPRIO_WHO_PROCESS, 0, ioprio) };
+            if result == -1 {
+                return Err(std::io::Error::last_os_error());
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn default_priority_is_reported() {
+        assert!(Priority::default().is_default());
+        assert!(
+            !Priority {
+                nice: None,
+                io_class: Some(IoClass::Idle),
+            }
+            .is_default()
+        );
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn apply_sets_niceness_and_io_class_of_the_command() {
+        let mut process = Command::new("sh");
+        process.args(["-c", "cut -d ' ' -f 19 /proc/self/stat"]);
+        Priority {
+            nice: Some(19),
+            io_class: Some(IoClass::Idle),
+        }
+        .apply(&mut process);
+
+        let output = process.output().unwrap();
+
+        assert!(output.status.success());
+        assert_eq!(String::from_utf8(output.stdout).unwrap().trim(), "19");
+    }
+}
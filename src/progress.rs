@@ -0,0 +1,155 @@
+/*
+ * grpr - A CLI tool for recursively executing git commands.
+ *
+ * Copyright (c) 2025 Anupam Sengupta
+ *
+ * This source code is licensed under the MIT license found in the LICENSE file
+ * in the root directory of this source tree.
+ */
+
+use clap::ValueEnum;
+use indicatif::{MultiProgress, ProgressBar, ProgressDrawTarget, ProgressState, ProgressStyle};
+use std::fmt::Write;
+use std::path::Path;
+use std::time::Duration;
+
+/// What `--progress` shows on stderr while commands run.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum ProgressMode {
+    /// One bar for the run as a whole.
+    Bar,
+    /// The bar, with a spinner naming each repository being processed.
+    Spinners,
+}
+
+/// How often spinners turn while their command runs.
+const SPINNER_TICK: Duration = Duration::from_millis(120);
+
+/// Formats the estimated time left, as `45s` or `1m20s`.
+fn format_eta(eta: Duration) -> String {
+    let seconds = eta.as_secs();
+    if seconds < 60 {
+        format!("{seconds}s")
+    } else {
+        format!("{}m{:02}s", seconds / 60, seconds % 60)
+    }
+}
+
+/// The progress of a run on stderr: `37/214 repos, 3 failed, ETA 1m20s`.
+/// Anything else printed while it is shown goes through
+/// [`Progress::suspend`], so lines are not drawn over the bar.
+#[derive(Debug)]
+pub struct Progress {
+    multi: MultiProgress,
+    bar: ProgressBar,
+    spinners: bool,
+}
+
+impl Progress {
+    /// Shows progress on stderr, which is left alone when it is not a
+    /// terminal.
+    pub fn new(mode: ProgressMode) -> Self {
+        Self::with_draw_target(mode, ProgressDrawTarget::stderr())
+    }
+
+    fn with_draw_target(mode: ProgressMode, target: ProgressDrawTarget) -> Self {
+        let multi = MultiProgress::with_draw_target(target);
+        let style = ProgressStyle::with_template(
+            "{bar:30.cyan/blue} {pos}/{len} repos, {msg} failed, ETA {eta_short}",
+        )
+        .expect("the progress template is valid")
+        .with_key("eta_short", |state: &ProgressState, out: &mut dyn Write| {
+            let _ = out.write_str(&format_eta(state.eta()));
+        })
+        .progress_chars("=> ");
+        let bar = multi.add(ProgressBar::new(0).with_style(style).with_message("0"));
+        Self {
+            multi,
+            bar,
+            spinners: mode == ProgressMode::Spinners,
+        }
+    }
+
+    /// Adds `count` repositories to those the run goes through.
+    pub fn add_repositories(&self, count: usize) {
+        self.bar.inc_length(count as u64);
+    }
+
+    /// Counts a repository as done, with `failed` failures so far.
+    pub fn finish_repository(&self, failed: usize) {
+        self.bar.set_message(failed.to_string());
+        self.bar.inc(1);
+    }
+
+    /// Shows a spinner naming `repo_path` while its command runs, with
+    /// `--progress=spinners`. The spinner is removed when the returned value
+    /// is dropped.
+    pub fn running(&self, repo_path: &Path) -> Option<Spinner> {
+        if !self.spinners {
+            return None;
+        }
+        let spinner = self.multi.insert_before(
+            &self.bar,
+            ProgressBar::new_spinner().with_message(repo_path.display().to_string()),
+        );
+        spinner.enable_steady_tick(SPINNER_TICK);
+        Some(Spinner(spinner))
+    }
+
+    /// Hides the progress while `print` writes to the terminal, then draws it
+    /// again below what was printed.
+    pub fn suspend<R>(&self, print: impl FnOnce() -> R) -> R {
+        self.multi.suspend(print)
+    }
+
+    /// Removes the progress from the terminal once the run is over.
+    pub fn clear(&self) {
+        self.bar.finish_and_clear();
+        let _ = self.multi.clear();
+    }
+}
+
+/// A spinner for one repository, removed when dropped.
+pub struct Spinner(ProgressBar);
+
+impl Drop for Spinner {
+    fn drop(&mut self) {
+        self.0.finish_and_clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn eta_is_formatted_in_minutes_and_seconds() {
+        assert_eq!(format_eta(Duration::from_secs(45)), "45s");
+        assert_eq!(format_eta(Duration::from_secs(80)), "1m20s");
+    }
+
+    #[test]
+    fn progress_counts_repositories_and_failures() {
+        let progress = Progress::with_draw_target(ProgressMode::Bar, ProgressDrawTarget::hidden());
+        progress.add_repositories(3);
+        progress.add_repositories(1);
+        progress.finish_repository(0);
+        progress.finish_repository(1);
+
+        assert_eq!(progress.bar.length(), Some(4));
+        assert_eq!(progress.bar.position(), 2);
+        assert_eq!(progress.bar.message(), "1");
+        assert!(progress.running(Path::new("/src/app")).is_none());
+    }
+
+    #[test]
+    fn spinners_are_shown_only_while_running() {
+        let progress =
+            Progress::with_draw_target(ProgressMode::Spinners, ProgressDrawTarget::hidden());
+        let spinner = progress.running(Path::new("/src/app")).unwrap();
+        assert_eq!(spinner.0.message(), "/src/app");
+        let bar = spinner.0.clone();
+        drop(spinner);
+        assert!(bar.is_finished());
+    }
+}
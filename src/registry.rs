@@ -0,0 +1,222 @@
+/*
+ * grpr - A CLI tool for recursively executing git commands.
+ *
+ * Copyright (c) 2025 Anupam Sengupta
+ *
+ * This source code is licensed under the MIT license found in the LICENSE file
+ * in the root directory of this source tree.
+ */
+
+use crate::paths;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+const REGISTRY_FILE_NAME: &str = "repos.toml";
+
+/// A repository recorded in the persistent registry.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RegisteredRepository {
+    pub path: PathBuf,
+    /// The `origin` URL when the repository was adopted.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub remote: Option<String>,
+    /// The branch `origin/HEAD` pointed at when the repository was adopted.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub default_branch: Option<String>,
+}
+
+impl RegisteredRepository {
+    /// A registry entry for `path` without any recorded metadata.
+    pub fn new(path: PathBuf) -> Self {
+        Self {
+            path,
+            remote: None,
+            default_branch: None,
+        }
+    }
+}
+
+/// How [`Registry::record`] changed the registry.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Recorded {
+    Added,
+    Updated,
+    Unchanged,
+}
+
+/// The persistent list of repositories that can be targeted with
+/// `--registered` instead of walking the filesystem.
+#[derive(Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Registry {
+    #[serde(default, rename = "repository")]
+    pub repositories: Vec<RegisteredRepository>,
+}
+
+/// Returns the registry location, `$XDG_DATA_HOME/grpr/repos.toml`, falling
+/// back to `~/.local/share/grpr/repos.toml`.
+pub fn default_registry_path() -> Option<PathBuf> {
+    paths::data_dir().map(|dir| dir.join(REGISTRY_FILE_NAME))
+}
+
+impl Registry {
+    /// Loads the registry at `path`. A missing file is an empty registry.
+    pub fn load(path: &Path) -> Result<Self, io::Error> {
+        let contents = match fs::read_to_string(path) {
+            Ok(contents) => contents,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(Self::default()),
+            Err(err) => return Err(err),
+        };
+
+        toml::from_str(&contents).map_err(|err| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("invalid registry {}: {err}", path.display()),
+            )
+        })
+    }
+
+    /// Writes the registry to `path`, creating parent directories as needed.
+    pub fn save(&self, path: &Path) -> Result<(), io::Error> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        let contents = toml::to_string(self).map_err(io::Error::other)?;
+        fs::write(path, contents)
+    }
+
+    /// Adds `repo_path` to the registry. Returns false if it was already
+    /// registered.
+    pub fn register(&mut self, repo_path: PathBuf) -> bool {
+        if self.contains(&repo_path) {
+            return false;
+        }
+
+        self.repositories.push(RegisteredRepository::new(repo_path));
+        true
+    }
+
+    /// Adds `repository`, or replaces the entry with the same path when its
+    /// metadata differs.
+    pub fn record(&mut self, repository: RegisteredRepository) -> Recorded {
+        let existing = self
+            .repositories
+            .iter_mut()
+            .find(|repo| repo.path == repository.path);
+
+        match existing {
+            Some(repo) if *repo == repository => Recorded::Unchanged,
+            Some(repo) => {
+                *repo = repository;
+                Recorded::Updated
+            }
+            None => {
+                self.repositories.push(repository);
+                Recorded::Added
+            }
+        }
+    }
+
+    /// Removes `repo_path` from the registry. Returns false if it was not
+    /// registered.
+    pub fn unregister(&mut self, repo_path: &Path) -> bool {
+        let before = self.repositories.len();
+        self.repositories.retain(|repo| repo.path != repo_path);
+        self.repositories.len() != before
+    }
+
+    /// Returns true when `repo_path` is registered.
+    pub fn contains(&self, repo_path: &Path) -> bool {
+        self.repositories.iter().any(|repo| repo.path == repo_path)
+    }
+
+    /// Returns the registered repository paths in registration order.
+    pub fn paths(&self) -> Vec<PathBuf> {
+        self.repositories
+            .iter()
+            .map(|repo| repo.path.clone())
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn load_returns_empty_registry_for_missing_file() {
+        let dir = tempdir().unwrap();
+
+        let registry = Registry::load(&dir.path().join("repos.toml")).unwrap();
+
+        assert!(registry.repositories.is_empty());
+    }
+
+    #[test]
+    fn save_and_load_round_trip_registered_paths() {
+        let dir = tempdir().unwrap();
+        let registry_path = dir.path().join("nested").join("repos.toml");
+        let mut registry = Registry::default();
+
+        assert!(registry.register(PathBuf::from("/src/app")));
+        assert!(registry.register(PathBuf::from("/src/lib")));
+        registry.save(&registry_path).unwrap();
+
+        let loaded = Registry::load(&registry_path).unwrap();
+
+        assert_eq!(loaded, registry);
+        assert_eq!(
+            loaded.paths(),
+            vec![PathBuf::from("/src/app"), PathBuf::from("/src/lib")]
+        );
+    }
+
+    #[test]
+    fn register_and_unregister_report_changes() {
+        let mut registry = Registry::default();
+
+        assert!(registry.register(PathBuf::from("/src/app")));
+        assert!(!registry.register(PathBuf::from("/src/app")));
+        assert!(registry.unregister(Path::new("/src/app")));
+        assert!(!registry.unregister(Path::new("/src/app")));
+    }
+
+    #[test]
+    fn record_adds_and_updates_repository_metadata() {
+        let dir = tempdir().unwrap();
+        let registry_path = dir.path().join("repos.toml");
+        let mut registry = Registry::default();
+        registry.register(PathBuf::from("/src/app"));
+        let adopted = RegisteredRepository {
+            path: PathBuf::from("/src/app"),
+            remote: Some("git@example.com:org/app.git".to_string()),
+            default_branch: Some("main".to_string()),
+        };
+
+        assert_eq!(registry.record(adopted.clone()), Recorded::Updated);
+        assert_eq!(registry.record(adopted.clone()), Recorded::Unchanged);
+        assert_eq!(
+            registry.record(RegisteredRepository::new(PathBuf::from("/src/lib"))),
+            Recorded::Added
+        );
+        registry.save(&registry_path).unwrap();
+
+        let loaded = Registry::load(&registry_path).unwrap();
+        assert_eq!(loaded.repositories[0], adopted);
+        assert_eq!(loaded.repositories.len(), 2);
+    }
+
+    #[test]
+    fn load_rejects_malformed_registry() {
+        let dir = tempdir().unwrap();
+        let registry_path = dir.path().join("repos.toml");
+        fs::write(&registry_path, "repository = 3\n").unwrap();
+
+        let err = Registry::load(&registry_path).unwrap_err();
+
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+}
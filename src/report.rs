@@ -0,0 +1,570 @@
+/*
+ * grpr - A CLI tool for recursively executing git commands.
+ *
+ * Copyright (c) 2025 Anupam Sengupta
+ *
+ * This source code is licensed under the MIT license found in the LICENSE file
+ * in the root directory of this source tree.
+ */
+
+use crate::grpgit::{Captured, RepoCommand};
+use crate::html;
+use crate::junit;
+use clap::ValueEnum;
+use serde::Serialize;
+use std::collections::hash_map::RandomState;
+use std::fs::File;
+use std::hash::{BuildHasher, Hasher};
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, SystemTime};
+
+/// How the outcome of a run is presented on stdout.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum OutputFormat {
+    /// Each repository's output under an `Inside git repo:` header.
+    #[default]
+    Text,
+    /// One JSON document describing every repository, printed at the end.
+    Json,
+    /// One JSON object per repository, printed as soon as it finishes, then
+    /// one with the summary.
+    Jsonl,
+    /// A header and one comma-separated row per repository, printed as soon
+    /// as it finishes.
+    Csv,
+    /// Like `csv`, with tabs between the fields.
+    Tsv,
+    /// The stable lines of `--porcelain`, one per repository.
+    #[value(skip)]
+    Porcelain,
+    /// The aligned table of `--table`, printed at the end.
+    #[value(skip)]
+    Table,
+}
+
+/// The versions of the `--porcelain` format. A released version never
+/// changes; a new one is added instead.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum PorcelainVersion {
+    /// `path<TAB>status<TAB>code<TAB>message`, one line per repository.
+    V1,
+}
+
+/// Writes one line of `--porcelain` v1 for the repository at `path`: its
+/// path, its `status` (`succeeded`, `failed`, or `skipped`), the exit code,
+/// or `-` when there is none, and `message`, separated by tabs. Tabs and
+/// line breaks within the fields become spaces.
+pub fn write_porcelain(
+    mut out: impl Write,
+    path: &Path,
+    status: &str,
+    exit_code: Option<i32>,
+    message: &str,
+) -> io::Result<()> {
+    let exit_code = exit_code.map_or_else(|| "-".to_string(), |code| code.to_string());
+    let fields = [&*path.to_string_lossy(), status, &exit_code, message]
+        .map(|field| field.replace(['\t', '\n', '\r'], " "));
+    writeln!(out, "{}", fields.join("\t"))?;
+    out.flush()
+}
+
+impl OutputFormat {
+    /// Returns the field separator of a tabular format.
+    fn separator(self) -> char {
+        if self == OutputFormat::Tsv { '\t' } else { ',' }
+    }
+
+    /// Returns `field` as it is written in a row of this tabular format:
+    /// quoted when needed in CSV, and with tabs and line breaks turned into
+    /// spaces in TSV.
+    fn field(self, field: &str) -> String {
+        if self == OutputFormat::Tsv {
+            return field.replace(['\t', '\n', '\r'], " ");
+        }
+        if field.contains([',', '"', '\n', '\r']) {
+            format!("\"{}\"", field.replace('"', "\"\""))
+        } else {
+            field.to_string()
+        }
+    }
+
+    /// Writes `fields` as one row of this tabular format.
+    fn write_row(self, mut out: impl Write, fields: &[&str]) -> io::Result<()> {
+        let fields: Vec<String> = fields.iter().map(|field| self.field(field)).collect();
+        let separator = self.separator().to_string();
+        writeln!(out, "{}", fields.join(&separator))?;
+        out.flush()
+    }
+
+    /// Writes the header row of `--output csv` or `--output tsv`.
+    pub fn write_header(self, out: impl Write) -> io::Result<()> {
+        self.write_row(out, &TABLE_COLUMNS)
+    }
+}
+
+/// The columns of `--output csv` and `--output tsv`.
+const TABLE_COLUMNS: [&str; 6] = [
+    "path",
+    "branch",
+    "outcome",
+    "exit_code",
+    "duration_secs",
+    "message",
+];
+
+/// The most characters of a message a row holds.
+const MESSAGE_LIMIT: usize = 100;
+
+/// One repository as a row of `--output csv` or `--output tsv`.
+#[derive(Debug)]
+pub struct TableRow {
+    pub path: PathBuf,
+    /// The checked-out branch, or `None` on a detached `HEAD`.
+    pub branch: Option<String>,
+    pub success: bool,
+    pub exit_code: Option<i32>,
+    pub duration_secs: f64,
+    /// The first line of output, from stderr first when the command failed,
+    /// cut to [`MESSAGE_LIMIT`] characters.
+    pub message: String,
+}
+
+/// Returns the first line of `output` with something on it.
+fn first_line(output: &[u8]) -> Option<String> {
+    String::from_utf8_lossy(output)
+        .lines()
+        .map(str::trim)
+        .find(|line| !line.is_empty())
+        .map(str::to_string)
+}
+
+/// Returns the line that sums up a captured run: the first line of its
+/// output, from stderr first when it failed, or else why it failed.
+pub fn message(captured: &Captured) -> String {
+    let message = if captured.result.is_ok() {
+        first_line(&captured.stdout).or_else(|| first_line(&captured.stderr))
+    } else {
+        first_line(&captured.stderr)
+            .or_else(|| first_line(&captured.stdout))
+            .or_else(|| captured.result.as_ref().err().map(ToString::to_string))
+    };
+    message.unwrap_or_default()
+}
+
+impl TableRow {
+    /// Describes the captured run in `repo_path`, on `branch`.
+    pub fn new(
+        repo_path: &Path,
+        branch: Option<String>,
+        captured: &Captured,
+        elapsed: Duration,
+    ) -> Self {
+        let success = captured.result.is_ok();
+        let mut message = message(captured);
+        if let Some((cut, _)) = message.char_indices().nth(MESSAGE_LIMIT) {
+            message.truncate(cut);
+            message.push_str("...");
+        }
+        Self {
+            path: repo_path.to_path_buf(),
+            branch,
+            success,
+            exit_code: captured.exit_code,
+            duration_secs: elapsed.as_secs_f64(),
+            message,
+        }
+    }
+
+    /// Writes the row in the tabular `format`.
+    pub fn write(&self, format: OutputFormat, out: impl Write) -> io::Result<()> {
+        let path = self.path.to_string_lossy();
+        let exit_code = self
+            .exit_code
+            .map_or_else(String::new, |code| code.to_string());
+        let duration = format!("{:.3}", self.duration_secs);
+        let outcome = if self.success { "succeeded" } else { "failed" };
+        format.write_row(
+            out,
+            &[
+                &path,
+                self.branch.as_deref().unwrap_or(""),
+                outcome,
+                &exit_code,
+                &duration,
+                &self.message,
+            ],
+        )
+    }
+}
+
+/// What happened in one repository.
+#[derive(Debug, Serialize)]
+pub struct RepositoryReport {
+    pub path: PathBuf,
+    /// The command line, which differs between repositories in tasks.
+    pub command: Vec<String>,
+    /// The command's exit code, or `None` when it did not run or was killed
+    /// by a signal.
+    pub exit_code: Option<i32>,
+    pub success: bool,
+    pub duration_secs: f64,
+    pub stdout: String,
+    pub stderr: String,
+    /// Why the repository failed, such as a failing hook or exit code.
+    pub error: Option<String>,
+}
+
+impl RepositoryReport {
+    /// Describes the captured run of `command` in `repo_path`.
+    pub fn new(
+        repo_path: &Path,
+        command: &RepoCommand,
+        captured: &Captured,
+        elapsed: Duration,
+    ) -> Self {
+        Self {
+            path: repo_path.to_path_buf(),
+            command: command.words(),
+            exit_code: captured.exit_code,
+            success: captured.result.is_ok(),
+            duration_secs: elapsed.as_secs_f64(),
+            stdout: String::from_utf8_lossy(&captured.stdout).into_owned(),
+            stderr: String::from_utf8_lossy(&captured.stderr).into_owned(),
+            error: captured.result.as_ref().err().map(ToString::to_string),
+        }
+    }
+
+    /// Writes the report as one line of JSON, for `--output jsonl`.
+    pub fn write_line(&self, out: impl Write) -> io::Result<()> {
+        write_line(out, self)
+    }
+}
+
+/// Writes `value` as one line of JSON and flushes it, so readers see each
+/// line as soon as it is complete.
+fn write_line(mut out: impl Write, value: &impl Serialize) -> io::Result<()> {
+    serde_json::to_writer(&mut out, value)?;
+    writeln!(out)?;
+    out.flush()
+}
+
+/// Returns a new ID for a run, made of the time it started, in seconds
+/// since the epoch, grpr's process ID, and random bits, in hex, such as
+/// `6710e2bf-1f3a-9c04e1d2`. The random bits tell apart runs that share a
+/// second and a process ID, such as `--watch` reruns or CI jobs in fresh
+/// containers.
+pub fn new_run_id() -> String {
+    static RUNS: AtomicU64 = AtomicU64::new(0);
+
+    let started = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap_or_default();
+    // Randomly keyed for each process, and fed the run's number in it.
+    let mut hasher = RandomState::new().build_hasher();
+    hasher.write_u64(RUNS.fetch_add(1, Ordering::Relaxed));
+    hasher.write_u32(started.subsec_nanos());
+    format!(
+        "{:x}-{:x}-{:08x}",
+        started.as_secs(),
+        process::id(),
+        hasher.finish() as u32
+    )
+}
+
+/// Counts for the run as a whole.
+#[derive(Debug, Default, Serialize)]
+pub struct Summary {
+    /// The run's unique ID, to find it in other logs.
+    pub run_id: String,
+    /// Repositories the command ran in.
+    pub repositories: usize,
+    pub succeeded: usize,
+    pub failed: usize,
+    /// Repositories left out by `--require-clean` or while offline.
+    pub skipped: usize,
+    pub interrupted: bool,
+    pub duration_secs: f64,
+    /// Files holding the whole of output cut short by `--max-output`.
+    pub spilled: Vec<PathBuf>,
+}
+
+/// The last line `--output jsonl` prints.
+#[derive(Debug, Serialize)]
+pub struct SummaryLine {
+    pub summary: Summary,
+}
+
+impl SummaryLine {
+    /// Writes the summary as one line of JSON.
+    pub fn write(&self, out: impl Write) -> io::Result<()> {
+        write_line(out, self)
+    }
+}
+
+/// The JSON document `--output json` prints.
+#[derive(Debug, Serialize)]
+pub struct RunReport<'a> {
+    pub repositories: &'a [RepositoryReport],
+    pub summary: Summary,
+}
+
+impl RunReport<'_> {
+    /// Writes the report as pretty-printed JSON followed by a newline.
+    pub fn write(&self, mut out: impl Write) -> io::Result<()> {
+        serde_json::to_writer_pretty(&mut out, self)?;
+        writeln!(out)
+    }
+}
+
+/// The formats `--report` writes to a file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReportFormat {
+    /// A standalone page with a sortable, searchable table of repositories.
+    Html,
+    /// JUnit XML, with a test case per repository, for CI systems.
+    Junit,
+}
+
+/// A report to write once the run is over, given as `FORMAT:FILE`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReportTarget {
+    pub format: ReportFormat,
+    pub path: PathBuf,
+}
+
+impl ReportTarget {
+    /// Writes the report of the `repositories`, the `skipped` ones with the
+    /// reason, and the `summary` of the run.
+    pub fn write(
+        &self,
+        repositories: &[RepositoryReport],
+        skipped: &[(PathBuf, String)],
+        summary: &Summary,
+    ) -> io::Result<()> {
+        let mut out = BufWriter::new(File::create(&self.path)?);
+        match self.format {
+            ReportFormat::Html => html::write_report(&mut out, repositories, skipped, summary)?,
+            ReportFormat::Junit => junit::write_report(&mut out, repositories, skipped, summary)?,
+        }
+        out.flush()
+    }
+}
+
+/// Parses a `FORMAT:FILE` report target, as given to `--report`.
+pub fn parse_report_target(value: &str) -> Result<ReportTarget, String> {
+    let Some((format, path)) = value.split_once(':') else {
+        return Err(format!(
+            "invalid report `{value}`: expected FORMAT:FILE, such as html:report.html"
+        ));
+    };
+    let format = match format {
+        "html" => ReportFormat::Html,
+        "junit" => ReportFormat::Junit,
+        _ => {
+            return Err(format!(
+                "unknown report format `{format}`: expected html or junit"
+            ));
+        }
+    };
+    if path.is_empty() {
+        return Err(format!("invalid report `{value}`: missing the file"));
+    }
+    Ok(ReportTarget {
+        format,
+        path: PathBuf::from(path),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn run_report_serializes_repositories_and_summary() {
+        let captured = Captured {
+            stdout: b"On branch main\n".to_vec(),
+            stderr: Vec::new(),
+            exit_code: Some(1),
+            result: Err(io::Error::other("git command failed")),
+            spilled: Vec::new(),
+        };
+        let command = RepoCommand::git(vec!["status".to_string()]);
+        let repositories = [RepositoryReport::new(
+            Path::new("/src/app"),
+            &command,
+            &captured,
+            Duration::from_millis(1500),
+        )];
+        let report = RunReport {
+            repositories: &repositories,
+            summary: Summary {
+                repositories: 1,
+                failed: 1,
+                ..Summary::default()
+            },
+        };
+
+        let mut out = Vec::new();
+        report.write(&mut out).unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&out).unwrap();
+
+        let repository = &json["repositories"][0];
+        assert_eq!(repository["path"], "/src/app");
+        assert_eq!(repository["command"], serde_json::json!(["git", "status"]));
+        assert_eq!(repository["exit_code"], 1);
+        assert_eq!(repository["success"], false);
+        assert_eq!(repository["duration_secs"], 1.5);
+        assert_eq!(repository["stdout"], "On branch main\n");
+        assert_eq!(repository["error"], "git command failed");
+        assert_eq!(json["summary"]["failed"], 1);
+        assert_eq!(json["summary"]["run_id"], "");
+        assert_eq!(json["summary"]["interrupted"], false);
+    }
+
+    #[test]
+    fn report_targets_name_a_format_and_a_file() {
+        assert_eq!(
+            parse_report_target("html:out/report.html").unwrap(),
+            ReportTarget {
+                format: ReportFormat::Html,
+                path: PathBuf::from("out/report.html"),
+            }
+        );
+        assert_eq!(
+            parse_report_target("junit:results.xml").unwrap().format,
+            ReportFormat::Junit
+        );
+        assert!(parse_report_target("report.html").is_err());
+        assert!(parse_report_target("pdf:report.pdf").is_err());
+        assert!(parse_report_target("html:").is_err());
+    }
+
+    #[test]
+    fn csv_rows_quote_fields_and_cut_messages() {
+        let captured = Captured {
+            stdout: Vec::new(),
+            stderr: format!("\nerror: \"a, b\" {}\nmore\n", "x".repeat(200)).into_bytes(),
+            exit_code: Some(128),
+            result: Err(io::Error::other("git command failed")),
+            spilled: Vec::new(),
+        };
+        let row = TableRow::new(
+            Path::new("/src/app"),
+            Some("main".to_string()),
+            &captured,
+            Duration::from_millis(1500),
+        );
+        assert_eq!(row.message.chars().count(), MESSAGE_LIMIT + 3);
+
+        let mut out = Vec::new();
+        OutputFormat::Csv.write_header(&mut out).unwrap();
+        row.write(OutputFormat::Csv, &mut out).unwrap();
+        let csv = String::from_utf8(out).unwrap();
+        let mut lines = csv.lines();
+        assert_eq!(
+            lines.next(),
+            Some("path,branch,outcome,exit_code,duration_secs,message")
+        );
+        assert!(
+            lines
+                .next()
+                .unwrap()
+                .starts_with("/src/app,main,failed,128,1.500,\"error: \"\"a, b\"\" xxx")
+        );
+    }
+
+    #[test]
+    fn tsv_rows_keep_fields_on_one_line() {
+        let captured = Captured {
+            stdout: b"M\tsrc/main.rs\n".to_vec(),
+            stderr: Vec::new(),
+            exit_code: Some(0),
+            result: Ok(()),
+            spilled: Vec::new(),
+        };
+        let row = TableRow::new(Path::new("/src/app"), None, &captured, Duration::ZERO);
+
+        let mut out = Vec::new();
+        row.write(OutputFormat::Tsv, &mut out).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "/src/app\t\tsucceeded\t0\t0.000\tM src/main.rs\n"
+        );
+    }
+
+    #[test]
+    fn porcelain_lines_hold_four_fields() {
+        let captured = Captured {
+            stdout: Vec::new(),
+            stderr: b"fatal: bad\trevision\n".to_vec(),
+            exit_code: Some(128),
+            result: Err(io::Error::other("git command failed")),
+            spilled: Vec::new(),
+        };
+
+        let mut out = Vec::new();
+        write_porcelain(
+            &mut out,
+            Path::new("/src/app"),
+            "failed",
+            captured.exit_code,
+            &message(&captured),
+        )
+        .unwrap();
+        write_porcelain(&mut out, Path::new("/src/lib"), "skipped", None, "dirty").unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "/src/app\tfailed\t128\tfatal: bad revision\n/src/lib\tskipped\t-\tdirty\n"
+        );
+    }
+
+    #[test]
+    fn run_ids_hold_the_start_time_and_the_process() {
+        let run_id = new_run_id();
+        let [started, pid, random] = run_id.split('-').collect::<Vec<_>>()[..] else {
+            panic!("unexpected run ID {run_id}");
+        };
+
+        assert!(u64::from_str_radix(started, 16).unwrap() > 0);
+        assert_eq!(u32::from_str_radix(pid, 16).unwrap(), process::id());
+        assert_eq!(random.len(), 8);
+        assert_ne!(new_run_id(), run_id);
+    }
+
+    #[test]
+    fn json_lines_hold_one_object_each() {
+        let captured = Captured {
+            stdout: b"one\ntwo\n".to_vec(),
+            stderr: Vec::new(),
+            exit_code: Some(0),
+            result: Ok(()),
+            spilled: Vec::new(),
+        };
+        let command = RepoCommand::git(vec!["log".to_string()]);
+        let report =
+            RepositoryReport::new(Path::new("/src/app"), &command, &captured, Duration::ZERO);
+        let summary = SummaryLine {
+            summary: Summary {
+                repositories: 1,
+                succeeded: 1,
+                ..Summary::default()
+            },
+        };
+
+        let mut out = Vec::new();
+        report.write_line(&mut out).unwrap();
+        summary.write(&mut out).unwrap();
+        let lines: Vec<serde_json::Value> = String::from_utf8(out)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0]["stdout"], "one\ntwo\n");
+        assert_eq!(lines[1]["summary"]["succeeded"], 1);
+    }
+}
@@ -0,0 +1,181 @@
+/*
+ * grpr - A CLI tool for recursively executing git commands.
+ *
+ * Copyright (c) 2025 Anupam Sengupta
+ *
+ * This source code is licensed under the MIT license found in the LICENSE file
+ * in the root directory of this source tree.
+ */
+
+use crate::grpgit;
+use std::collections::{HashMap, VecDeque};
+use std::path::{Path, PathBuf};
+use std::sync::{Condvar, Mutex, MutexGuard};
+
+/// Limits how many repositories on each remote host run a network command at
+/// once, so a run across many repositories spreads its load over the hosts
+/// instead of hammering one of them. The host is taken from each
+/// repository's `origin` URL.
+#[derive(Debug)]
+pub struct HostSlots {
+    limit: usize,
+    /// The host of each repository looked up so far, or `None` for a
+    /// repository without a remote host.
+    hosts: Mutex<HashMap<PathBuf, Option<String>>>,
+    /// Permits held for each host.
+    held: Mutex<HashMap<String, usize>>,
+    released: Condvar,
+}
+
+/// A claim on one of a host's slots, returned when dropped.
+#[must_use]
+pub struct HostPermit<'a> {
+    slots: &'a HostSlots,
+    host: String,
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(|err| err.into_inner())
+}
+
+impl HostSlots {
+    /// Creates slots allowing `limit` repositories per host at once.
+    pub fn new(limit: usize) -> Self {
+        Self {
+            limit,
+            hosts: Mutex::new(HashMap::new()),
+            held: Mutex::new(HashMap::new()),
+            released: Condvar::new(),
+        }
+    }
+
+    /// Returns the host of the repository's `origin` remote.
+    fn host(&self, repo_path: &Path) -> Option<String> {
+        if let Some(host) = lock(&self.hosts).get(repo_path) {
+            return host.clone();
+        }
+
+        let host = grpgit::remote_url(repo_path, "origin")
+            .as_deref()
+            .and_then(grpgit::remote_host);
+        lock(&self.hosts).insert(repo_path.to_path_buf(), host.clone());
+        host
+    }
+
+    /// Waits until the repository's host has a free slot and takes it.
+    /// Repositories without a remote host are not limited.
+    pub fn acquire(&self, repo_path: &Path) -> Option<HostPermit<'_>> {
+        let host = self.host(repo_path)?;
+        let held = lock(&self.held);
+        let mut held = self
+            .released
+            .wait_while(held, |held| {
+                held.get(&host).is_some_and(|count| *count >= self.limit)
+            })
+            .unwrap_or_else(|err| err.into_inner());
+        *held.entry(host.clone()).or_default() += 1;
+        Some(HostPermit { slots: self, host })
+    }
+
+    /// Returns the order in which to start `repositories`: taking one
+    /// repository from each host in turn, so workers rarely wait on a busy
+    /// host while others are idle. Repositories of each host, and those
+    /// without one, keep their relative order.
+    pub fn interleave(&self, repositories: &[PathBuf]) -> Vec<usize> {
+        let mut hosts: Vec<Option<String>> = Vec::new();
+        let mut queues: Vec<VecDeque<usize>> = Vec::new();
+        for (index, repo_path) in repositories.iter().enumerate() {
+            let host = self.host(repo_path);
+            match hosts.iter().position(|known| *known == host) {
+                Some(position) => queues[position].push_back(index),
+                None => {
+                    hosts.push(host);
+                    queues.push(VecDeque::from([index]));
+                }
+            }
+        }
+
+        let mut order = Vec::with_capacity(repositories.len());
+        while order.len() < repositories.len() {
+            order.extend(queues.iter_mut().filter_map(VecDeque::pop_front));
+        }
+        order
+    }
+}
+
+impl Drop for HostPermit<'_> {
+    fn drop(&mut self) {
+        let mut held = lock(&self.slots.held);
+        if let Some(count) = held.get_mut(&self.host) {
+            *count -= 1;
+        }
+        self.slots.released.notify_all();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::git;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::thread;
+    use std::time::Duration;
+    use tempfile::tempdir;
+
+    fn repository_with_origin(path: &Path, url: &str) {
+        std::fs::create_dir_all(path).unwrap();
+        git(path, &["init", "--quiet"]);
+        git(path, &["remote", "add", "origin", url]);
+    }
+
+    #[test]
+    fn interleave_takes_hosts_in_turn() {
+        let dir = tempdir().unwrap();
+        let repositories: Vec<PathBuf> = ["a", "b", "c", "d", "e"]
+            .map(|name| dir.path().join(name))
+            .into();
+        repository_with_origin(&repositories[0], "git@github.com:o/a.git");
+        repository_with_origin(&repositories[1], "https://github.com/o/b.git");
+        repository_with_origin(&repositories[2], "https://github.com/o/c.git");
+        repository_with_origin(&repositories[3], "https://gitlab.com/o/d.git");
+        repository_with_origin(&repositories[4], "/srv/git/e.git");
+
+        assert_eq!(
+            HostSlots::new(1).interleave(&repositories),
+            vec![0, 3, 4, 1, 2]
+        );
+    }
+
+    #[test]
+    fn host_slots_limit_each_host() {
+        let dir = tempdir().unwrap();
+        let repositories: Vec<PathBuf> = (0..6).map(|n| dir.path().join(n.to_string())).collect();
+        for (n, repo_path) in repositories.iter().enumerate() {
+            let host = if n % 2 == 0 {
+                "github.com"
+            } else {
+                "gitlab.com"
+            };
+            repository_with_origin(repo_path, &format!("https://{host}/o/{n}.git"));
+        }
+        let slots = HostSlots::new(1);
+        let running = [AtomicUsize::new(0), AtomicUsize::new(0)];
+        let most_running = AtomicUsize::new(0);
+
+        thread::scope(|scope| {
+            for (n, repo_path) in repositories.iter().enumerate() {
+                let (slots, running, most_running) = (&slots, &running, &most_running);
+                scope.spawn(move || {
+                    let _permit = slots.acquire(repo_path).unwrap();
+                    let now = running[n % 2].fetch_add(1, Ordering::SeqCst) + 1;
+                    most_running.fetch_max(now, Ordering::SeqCst);
+                    thread::sleep(Duration::from_millis(10));
+                    running[n % 2].fetch_sub(1, Ordering::SeqCst);
+                });
+            }
+        });
+
+        assert_eq!(most_running.load(Ordering::SeqCst), 1);
+        assert!(slots.acquire(dir.path()).is_none());
+    }
+}
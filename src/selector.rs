@@ -0,0 +1,700 @@
+/*
+ * grpr - A CLI tool for recursively executing git commands.
+ *
+ * Copyright (c) 2025 Anupam Sengupta
+ *
+ * This source code is licensed under the MIT license found in the LICENSE file
+ * in the root directory of this source tree.
+ */
+
+use crate::expr::{self, Expr};
+use crate::grpgit::{self, UpstreamState};
+use clap::ValueEnum;
+use rayon::prelude::*;
+use regex::Regex;
+use std::collections::HashMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+/// One test a repository passes or fails. Criteria that take several values
+/// pass when any value applies, except exclusions, which fail when any value
+/// applies.
+#[derive(Debug, Clone)]
+pub enum Criterion {
+    /// The path relative to its root matches any of these patterns.
+    MatchPath(Vec<Regex>),
+    /// The path relative to its root matches none of these patterns.
+    NoMatchPath(Vec<Regex>),
+    /// The `origin` URL contains none of these patterns. Repositories
+    /// without an `origin` remote pass.
+    ExcludeRemote(Vec<String>),
+    /// The URL of any remote contains any of these patterns.
+    RemoteMatches(Vec<String>),
+    /// No remotes are configured.
+    NoRemote,
+    /// A remote with any of these names is configured.
+    HasRemote(Vec<String>),
+    /// The repository was last used at or after this time.
+    ActiveSince(SystemTime),
+    /// The `HEAD` commit was made at or after this time.
+    CommittedSince(SystemTime),
+    /// The `HEAD` commit was made before this time.
+    CommittedBefore(SystemTime),
+    /// The working tree is dirty (`true`) or clean (`false`). Repositories
+    /// whose status cannot be read, such as bare ones, fail either way.
+    Dirty(bool),
+    /// The index has changes that are not committed.
+    Staged,
+    /// Tracked files have changes that are not in the index.
+    Unstaged,
+    /// The working tree has files git neither tracks nor ignores.
+    Untracked,
+    /// `HEAD` is detached (`true`) or on a branch (`false`).
+    Detached(bool),
+    /// The repository has stashed changes.
+    HasStash,
+    /// The current branch tracks an upstream branch that no longer exists.
+    UpstreamGone,
+    /// The current branch is in any of these states relative to its
+    /// upstream. Repositories without an upstream fail.
+    Upstream(Vec<UpstreamState>),
+    /// The working tree declares submodules (`true`) or does not (`false`).
+    Submodules(bool),
+    /// The git directory holds at least this many bytes.
+    MinSize(u64),
+    /// The git directory holds at most this many bytes.
+    MaxSize(u64),
+    /// `origin/HEAD` points at any of these branches. Repositories without a
+    /// recorded `origin/HEAD` fail.
+    DefaultBranch(Vec<String>),
+    /// Any of these git config keys has the paired value.
+    ConfigEquals(Vec<(String, String)>),
+    /// The `--where` expression holds.
+    Where(Expr),
+}
+
+impl fmt::Display for Criterion {
+    /// Describes the criterion with the command-line option that sets it.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let values = |option: &str, values: &[String]| format!("{option} {}", values.join(" or "));
+        let patterns = |regexes: &[Regex]| -> Vec<String> {
+            regexes
+                .iter()
+                .map(|regex| regex.as_str().to_string())
+                .collect()
+        };
+        let description = match self {
+            Criterion::MatchPath(regexes) => values("--match", &patterns(regexes)),
+            Criterion::NoMatchPath(regexes) => values("--no-match", &patterns(regexes)),
+            Criterion::ExcludeRemote(remotes) => values("--exclude-remote", remotes),
+            Criterion::RemoteMatches(remotes) => values("--remote-matches", remotes),
+            Criterion::NoRemote => "--no-remote".to_string(),
+            Criterion::HasRemote(names) => values("--has-remote", names),
+            Criterion::ActiveSince(_) => "--active-within".to_string(),
+            Criterion::CommittedSince(_) => "--committed-since".to_string(),
+            Criterion::CommittedBefore(_) => "--committed-before".to_string(),
+            Criterion::Dirty(true) => "--dirty".to_string(),
+            Criterion::Dirty(false) => "--clean".to_string(),
+            Criterion::Staged => "--staged".to_string(),
+            Criterion::Unstaged => "--unstaged".to_string(),
+            Criterion::Untracked => "--untracked".to_string(),
+            Criterion::Detached(true) => "--only-detached".to_string(),
+            Criterion::Detached(false) => "--skip-detached".to_string(),
+            Criterion::HasStash => "--has-stash".to_string(),
+            Criterion::UpstreamGone => "--upstream-gone".to_string(),
+            Criterion::Upstream(states) => states
+                .iter()
+                .map(|state| match state {
+                    UpstreamState::UpToDate => "up to date",
+                    UpstreamState::Ahead => "--ahead",
+                    UpstreamState::Behind => "--behind",
+                    UpstreamState::Diverged => "--diverged",
+                })
+                .collect::<Vec<_>>()
+                .join(" or "),
+            Criterion::Submodules(true) => "--has-submodules".to_string(),
+            Criterion::Submodules(false) => "--no-submodules".to_string(),
+            Criterion::MinSize(bytes) => format!("--min-size {bytes}B"),
+            Criterion::MaxSize(bytes) => format!("--max-size {bytes}B"),
+            Criterion::DefaultBranch(names) => values("--default-branch", names),
+            Criterion::ConfigEquals(settings) => {
+                let settings: Vec<_> = settings
+                    .iter()
+                    .map(|(key, value)| format!("{key}={value}"))
+                    .collect();
+                values("--config-equals", &settings)
+            }
+            Criterion::Where(expr) => format!("--where '{expr}'"),
+        };
+        f.write_str(&description)
+    }
+}
+
+/// How the results of several criteria combine.
+#[derive(ValueEnum, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Combine {
+    /// Select repositories that pass every criterion.
+    #[default]
+    All,
+    /// Select repositories that pass at least one criterion.
+    Any,
+}
+
+/// Decides which target repositories a command runs in.
+#[derive(Debug, Clone, Default)]
+pub struct Selector {
+    /// The criteria, checked in order until the outcome is known, so cheap
+    /// criteria should come first.
+    pub criteria: Vec<Criterion>,
+    pub combine: Combine,
+    /// Select the repositories the criteria reject instead.
+    pub invert: bool,
+    /// The directories that repository paths are made relative to.
+    pub roots: Vec<PathBuf>,
+}
+
+impl Selector {
+    /// Returns true when `repo_path` is selected. Without criteria every
+    /// repository is selected, or none when inverted.
+    pub fn matches(&self, repo_path: &Path) -> bool {
+        let passes = |criterion: &Criterion| self.passes(criterion, repo_path);
+        let selected = match self.combine {
+            Combine::All => self.criteria.iter().all(passes),
+            Combine::Any => self.criteria.is_empty() || self.criteria.iter().any(passes),
+        };
+        selected != self.invert
+    }
+
+    /// Explains why `repo_path` is selected: the criteria it passes or, when
+    /// the selection is inverted, those it fails. Returns `None` when it is
+    /// not selected. Unlike [`Selector::matches`], every criterion is
+    /// checked.
+    pub fn reasons(&self, repo_path: &Path) -> Option<Vec<String>> {
+        let (passed, failed): (Vec<_>, Vec<_>) = self
+            .criteria
+            .iter()
+            .partition(|criterion| self.passes(criterion, repo_path));
+        let selected = match self.combine {
+            Combine::All => failed.is_empty(),
+            Combine::Any => self.criteria.is_empty() || !passed.is_empty(),
+        };
+        if selected == self.invert {
+            return None;
+        }
+
+        let reasons = if self.invert {
+            failed
+                .iter()
+                .map(|criterion| format!("not {criterion}"))
+                .collect()
+        } else {
+            passed.iter().map(ToString::to_string).collect()
+        };
+        Some(reasons)
+    }
+
+    /// Keeps the selected repositories, in order. Checks that run git are
+    /// spread across threads.
+    pub fn apply(&self, repositories: Vec<PathBuf>) -> Vec<PathBuf> {
+        repositories
+            .into_par_iter()
+            .filter(|repo_path| self.matches(repo_path))
+            .collect()
+    }
+
+    /// Returns true when `repo_path` passes `criterion`.
+    fn passes(&self, criterion: &Criterion, repo_path: &Path) -> bool {
+        match criterion {
+            Criterion::MatchPath(patterns) => {
+                let relative = relative_path(repo_path, &self.roots);
+                patterns.iter().any(|regex| regex.is_match(&relative))
+            }
+            Criterion::NoMatchPath(patterns) => {
+                let relative = relative_path(repo_path, &self.roots);
+                !patterns.iter().any(|regex| regex.is_match(&relative))
+            }
+            Criterion::ExcludeRemote(patterns) => !grpgit::remote_url(repo_path, "origin")
+                .is_some_and(|url| contains_any(&url, patterns)),
+            Criterion::RemoteMatches(patterns) => grpgit::remote_urls(repo_path)
+                .iter()
+                .any(|url| contains_any(url, patterns)),
+            Criterion::NoRemote => {
+                grpgit::remote_names(repo_path).is_some_and(|remotes| remotes.is_empty())
+            }
+            Criterion::HasRemote(names) => grpgit::remote_names(repo_path)
+                .is_some_and(|remotes| remotes.iter().any(|remote| names.contains(remote))),
+            Criterion::ActiveSince(since) => {
+                grpgit::last_activity(repo_path).is_some_and(|used| used >= *since)
+            }
+            Criterion::CommittedSince(since) => {
+                grpgit::head_commit_time(repo_path).is_some_and(|committed| committed >= *since)
+            }
+            Criterion::CommittedBefore(before) => {
+                grpgit::head_commit_time(repo_path).is_some_and(|committed| committed < *before)
+            }
+            Criterion::Dirty(dirty) => grpgit::is_dirty(repo_path) == Some(*dirty),
+            Criterion::Staged => {
+                grpgit::working_tree_changes(repo_path).is_some_and(|changes| changes.staged)
+            }
+            Criterion::Unstaged => {
+                grpgit::working_tree_changes(repo_path).is_some_and(|changes| changes.unstaged)
+            }
+            Criterion::Untracked => {
+                grpgit::working_tree_changes(repo_path).is_some_and(|changes| changes.untracked)
+            }
+            Criterion::Detached(detached) => grpgit::is_detached(repo_path) == Some(*detached),
+            Criterion::HasStash => grpgit::has_stash(repo_path),
+            Criterion::UpstreamGone => grpgit::upstream_gone(repo_path),
+            Criterion::Upstream(states) => {
+                grpgit::upstream_state(repo_path).is_some_and(|state| states.contains(&state))
+            }
+            Criterion::Submodules(present) => grpgit::has_submodules(repo_path) == *present,
+            Criterion::MinSize(bytes) => {
+                grpgit::git_dir_size(repo_path).is_some_and(|size| size >= *bytes)
+            }
+            Criterion::MaxSize(bytes) => {
+                grpgit::git_dir_size(repo_path).is_some_and(|size| size <= *bytes)
+            }
+            Criterion::DefaultBranch(names) => {
+                grpgit::default_branch(repo_path).is_some_and(|branch| names.contains(&branch))
+            }
+            Criterion::ConfigEquals(settings) => settings.iter().any(|(key, value)| {
+                grpgit::config_value(repo_path, key).is_some_and(|actual| actual == *value)
+            }),
+            Criterion::Where(expr) => self.expression_holds(expr, repo_path),
+        }
+    }
+
+    /// Evaluates a `--where` expression for `repo_path`, reading each
+    /// attribute from the repository at most once.
+    fn expression_holds(&self, expr: &Expr, repo_path: &Path) -> bool {
+        let mut values = HashMap::new();
+        expr.evaluate(&mut |attribute| {
+            if !values.contains_key(&attribute) {
+                values.extend(self.attributes(repo_path, attribute));
+            }
+            values[&attribute].clone()
+        })
+    }
+
+    /// Reads the `--where` `attribute` of `repo_path`, along with any other
+    /// attribute the same git command tells, such as `behind` with `ahead`.
+    fn attributes(
+        &self,
+        repo_path: &Path,
+        attribute: expr::Attribute,
+    ) -> Vec<(expr::Attribute, expr::Value)> {
+        use expr::{Attribute, Value};
+
+        let known = |value: Option<Value>| value.unwrap_or(Value::Unknown);
+        let count = |count: usize| Value::Int(i64::try_from(count).unwrap_or(i64::MAX));
+        let value = match attribute {
+            Attribute::Branch => grpgit::current_branch(repo_path).map(Value::Str),
+            Attribute::Dirty => grpgit::is_dirty(repo_path).map(Value::Bool),
+            Attribute::Detached => grpgit::is_detached(repo_path).map(Value::Bool),
+            Attribute::Ahead | Attribute::Behind => {
+                let counts = grpgit::ahead_behind(repo_path);
+                return vec![
+                    (
+                        Attribute::Ahead,
+                        known(counts.map(|(ahead, _)| count(ahead))),
+                    ),
+                    (
+                        Attribute::Behind,
+                        known(counts.map(|(_, behind)| count(behind))),
+                    ),
+                ];
+            }
+            Attribute::Remote => grpgit::remote_url(repo_path, "origin").map(Value::Str),
+            Attribute::Path => Some(Value::Str(relative_path(repo_path, &self.roots))),
+            Attribute::Tags => Some(Value::List(grpgit::head_tags(repo_path))),
+        };
+        vec![(attribute, known(value))]
+    }
+}
+
+/// Parses a `key=value` git config setting, as given to `--config-equals`.
+pub fn parse_config_setting(setting: &str) -> Result<(String, String), String> {
+    match setting.split_once('=') {
+        Some((key, value)) if !key.trim().is_empty() => {
+            Ok((key.trim().to_string(), value.to_string()))
+        }
+        _ => Err(format!("invalid setting `{setting}`: expected KEY=VALUE")),
+    }
+}
+
+/// Returns true when `url` contains any of `patterns`.
+fn contains_any(url: &str, patterns: &[String]) -> bool {
+    patterns
+        .iter()
+        .any(|pattern| url.contains(pattern.as_str()))
+}
+
+/// Returns `repo_path` relative to the deepest of `roots` containing it, with
+/// `/` separators. Paths outside every root are returned whole.
+pub fn relative_path(repo_path: &Path, roots: &[PathBuf]) -> String {
+    let Some(relative) = roots
+        .iter()
+        .filter_map(|root| repo_path.strip_prefix(root).ok())
+        .min_by_key(|relative| relative.components().count())
+    else {
+        return repo_path.to_string_lossy().into_owned();
+    };
+
+    relative
+        .components()
+        .map(|component| component.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::git;
+    use std::fs;
+    use tempfile::tempdir;
+
+    fn regexes(patterns: &[&str]) -> Vec<Regex> {
+        patterns
+            .iter()
+            .map(|pattern| Regex::new(pattern).unwrap())
+            .collect()
+    }
+
+    fn create_repo_on_branch(path: &Path, head: &str) {
+        let git_dir = path.join(".git");
+        fs::create_dir_all(&git_dir).unwrap();
+        fs::write(git_dir.join("config"), "[core]\n").unwrap();
+        fs::write(git_dir.join("HEAD"), head).unwrap();
+    }
+
+    #[test]
+    fn relative_path_uses_the_deepest_containing_root() {
+        let roots = vec![PathBuf::from("/src"), PathBuf::from("/src/work")];
+
+        assert_eq!(relative_path(Path::new("/src/work/app"), &roots), "app");
+        assert_eq!(
+            relative_path(Path::new("/src/lib/core"), &roots),
+            "lib/core"
+        );
+        assert_eq!(
+            relative_path(Path::new("/elsewhere/app"), &roots),
+            "/elsewhere/app"
+        );
+    }
+
+    #[test]
+    fn parse_config_setting_splits_at_the_first_equals_sign() {
+        assert_eq!(
+            parse_config_setting("user.email=me@work.com"),
+            Ok(("user.email".to_string(), "me@work.com".to_string()))
+        );
+        assert_eq!(
+            parse_config_setting("core.sshCommand=ssh -o A=b"),
+            Ok(("core.sshCommand".to_string(), "ssh -o A=b".to_string()))
+        );
+        assert_eq!(
+            parse_config_setting("pull.rebase="),
+            Ok(("pull.rebase".to_string(), String::new()))
+        );
+        assert!(parse_config_setting("user.email").is_err());
+        assert!(parse_config_setting("=me").is_err());
+    }
+
+    #[test]
+    fn selector_matches_git_config_values() {
+        let dir = tempdir().unwrap();
+        let work = dir.path().join("work");
+        let home = dir.path().join("home");
+        for (repo_dir, email) in [(&work, "me@work.com"), (&home, "me@home.org")] {
+            fs::create_dir_all(repo_dir).unwrap();
+            git(repo_dir, &["init", "--quiet"]);
+            git(repo_dir, &["config", "user.email", email]);
+        }
+        let selector = Selector {
+            criteria: vec![Criterion::ConfigEquals(vec![(
+                "user.email".to_string(),
+                "me@work.com".to_string(),
+            )])],
+            ..Selector::default()
+        };
+
+        assert_eq!(selector.apply(vec![work.clone(), home]), vec![work]);
+    }
+
+    #[test]
+    fn selector_bounds_git_directory_sizes() {
+        let dir = tempdir().unwrap();
+        let small = dir.path().join("small");
+        let large = dir.path().join("large");
+        create_repo_on_branch(&small, "ref: refs/heads/main\n");
+        create_repo_on_branch(&large, "ref: refs/heads/main\n");
+        fs::write(large.join(".git").join("pack"), vec![0; 64 * 1024]).unwrap();
+        let selector = |criterion| Selector {
+            criteria: vec![criterion],
+            ..Selector::default()
+        };
+        let repositories = vec![small.clone(), large.clone()];
+
+        assert_eq!(
+            selector(Criterion::MinSize(32 * 1024)).apply(repositories.clone()),
+            vec![large]
+        );
+        assert_eq!(
+            selector(Criterion::MaxSize(32 * 1024)).apply(repositories),
+            vec![small]
+        );
+    }
+
+    #[test]
+    fn selector_separates_staged_unstaged_and_untracked_changes() {
+        let dir = tempdir().unwrap();
+        let mut repositories = Vec::new();
+        for name in ["staged", "unstaged", "untracked"] {
+            let repo_dir = dir.path().join(name);
+            fs::create_dir_all(&repo_dir).unwrap();
+            git(&repo_dir, &["init", "--quiet"]);
+            fs::write(repo_dir.join("file.txt"), "first\n").unwrap();
+            git(&repo_dir, &["add", "file.txt"]);
+            git(&repo_dir, &["commit", "--quiet", "-m", "first"]);
+            match name {
+                "staged" => {
+                    fs::write(repo_dir.join("file.txt"), "second\n").unwrap();
+                    git(&repo_dir, &["add", "file.txt"]);
+                }
+                "unstaged" => fs::write(repo_dir.join("file.txt"), "second\n").unwrap(),
+                _ => fs::write(repo_dir.join("stray.txt"), "stray\n").unwrap(),
+            }
+            repositories.push(repo_dir);
+        }
+        let selector = |criterion| Selector {
+            criteria: vec![criterion],
+            ..Selector::default()
+        };
+
+        for (index, criterion) in [Criterion::Staged, Criterion::Unstaged, Criterion::Untracked]
+            .into_iter()
+            .enumerate()
+        {
+            assert_eq!(
+                selector(criterion).apply(repositories.clone()),
+                vec![repositories[index].clone()]
+            );
+        }
+    }
+
+    #[test]
+    fn selector_checks_remote_names() {
+        let dir = tempdir().unwrap();
+        let mut repositories = Vec::new();
+        for (name, remotes) in [("local", &[][..]), ("fork", &["origin", "upstream"][..])] {
+            let repo_dir = dir.path().join(name);
+            fs::create_dir_all(&repo_dir).unwrap();
+            git(&repo_dir, &["init", "--quiet"]);
+            for remote in remotes {
+                git(
+                    &repo_dir,
+                    &["remote", "add", remote, "https://example.com/app.git"],
+                );
+            }
+            repositories.push(repo_dir);
+        }
+        let selector = |criterion| Selector {
+            criteria: vec![criterion],
+            ..Selector::default()
+        };
+
+        assert_eq!(
+            selector(Criterion::NoRemote).apply(repositories.clone()),
+            vec![repositories[0].clone()]
+        );
+        assert_eq!(
+            selector(Criterion::HasRemote(vec!["upstream".to_string()]))
+                .apply(repositories.clone()),
+            vec![repositories[1].clone()]
+        );
+        assert!(
+            selector(Criterion::HasRemote(vec!["mirror".to_string()]))
+                .apply(repositories)
+                .is_empty()
+        );
+    }
+
+    #[test]
+    fn selector_matches_default_branches() {
+        let dir = tempdir().unwrap();
+        let mut repositories = Vec::new();
+        for (name, remote_head) in [
+            ("old", Some("master")),
+            ("new", Some("main")),
+            ("none", None),
+        ] {
+            let repo_dir = dir.path().join(name);
+            fs::create_dir_all(&repo_dir).unwrap();
+            git(&repo_dir, &["init", "--quiet"]);
+            if let Some(branch) = remote_head {
+                let target = format!("refs/remotes/origin/{branch}");
+                git(
+                    &repo_dir,
+                    &["symbolic-ref", "refs/remotes/origin/HEAD", &target],
+                );
+            }
+            repositories.push(repo_dir);
+        }
+        let selector = Selector {
+            criteria: vec![Criterion::DefaultBranch(vec!["master".to_string()])],
+            ..Selector::default()
+        };
+
+        assert_eq!(
+            selector.apply(repositories.clone()),
+            vec![repositories[0].clone()]
+        );
+    }
+
+    #[test]
+    fn selector_ors_values_within_a_criterion() {
+        let selector = Selector {
+            roots: vec![PathBuf::from("/src")],
+            criteria: vec![
+                Criterion::MatchPath(regexes(&["^apps/", "^libs/"])),
+                Criterion::NoMatchPath(regexes(&["legacy", "archive"])),
+            ],
+            ..Selector::default()
+        };
+
+        assert!(selector.matches(Path::new("/src/apps/web")));
+        assert!(selector.matches(Path::new("/src/libs/core")));
+        assert!(!selector.matches(Path::new("/src/tools/cli")));
+        assert!(!selector.matches(Path::new("/src/apps/legacy-web")));
+        assert!(!selector.matches(Path::new("/src/libs/archive/core")));
+    }
+
+    #[test]
+    fn selector_combines_criteria_with_all_or_any() {
+        let dir = tempdir().unwrap();
+        let detached_app = dir.path().join("apps").join("pinned");
+        let attached_app = dir.path().join("apps").join("web");
+        let detached_tool = dir.path().join("tools").join("pinned");
+        let attached_tool = dir.path().join("tools").join("cli");
+        let commit = "0123456789abcdef0123456789abcdef01234567\n";
+        create_repo_on_branch(&detached_app, commit);
+        create_repo_on_branch(&attached_app, "ref: refs/heads/main\n");
+        create_repo_on_branch(&detached_tool, commit);
+        create_repo_on_branch(&attached_tool, "ref: refs/heads/main\n");
+        let repositories = vec![
+            detached_app.clone(),
+            attached_app.clone(),
+            detached_tool.clone(),
+            attached_tool.clone(),
+        ];
+        let selector = |combine, invert| Selector {
+            criteria: vec![
+                Criterion::MatchPath(regexes(&["^apps/"])),
+                Criterion::Detached(true),
+            ],
+            combine,
+            invert,
+            roots: vec![dir.path().to_path_buf()],
+        };
+
+        assert_eq!(
+            selector(Combine::All, false).apply(repositories.clone()),
+            vec![detached_app.clone()]
+        );
+        assert_eq!(
+            selector(Combine::Any, false).apply(repositories.clone()),
+            vec![detached_app, attached_app, detached_tool]
+        );
+        assert_eq!(
+            selector(Combine::Any, true).apply(repositories.clone()),
+            vec![attached_tool]
+        );
+        assert_eq!(selector(Combine::All, true).apply(repositories).len(), 3);
+    }
+
+    #[test]
+    fn selector_without_criteria_selects_everything_unless_inverted() {
+        let repositories = vec![PathBuf::from("/src/a"), PathBuf::from("/src/b")];
+
+        for combine in [Combine::All, Combine::Any] {
+            let selector = Selector {
+                combine,
+                ..Selector::default()
+            };
+            assert_eq!(selector.apply(repositories.clone()), repositories);
+
+            let inverted = Selector {
+                combine,
+                invert: true,
+                ..Selector::default()
+            };
+            assert!(inverted.apply(repositories.clone()).is_empty());
+        }
+    }
+
+    #[test]
+    fn reasons_name_the_criteria_behind_each_selection() {
+        let dir = tempdir().unwrap();
+        let pinned = dir.path().join("apps").join("pinned");
+        let web = dir.path().join("apps").join("web");
+        let cli = dir.path().join("tools").join("cli");
+        create_repo_on_branch(&pinned, "0123456789abcdef0123456789abcdef01234567\n");
+        create_repo_on_branch(&web, "ref: refs/heads/main\n");
+        create_repo_on_branch(&cli, "ref: refs/heads/main\n");
+        let selector = |combine, invert| Selector {
+            criteria: vec![
+                Criterion::MatchPath(regexes(&["^apps/", "^libs/"])),
+                Criterion::Where(expr::parse("detached").unwrap()),
+            ],
+            combine,
+            invert,
+            roots: vec![dir.path().to_path_buf()],
+        };
+        let reasons = |selector: &Selector, repo_path: &Path| {
+            selector
+                .reasons(repo_path)
+                .map(|reasons| reasons.join(", "))
+        };
+
+        let all = selector(Combine::All, false);
+        assert_eq!(
+            reasons(&all, &pinned).as_deref(),
+            Some("--match ^apps/ or ^libs/, --where 'detached'")
+        );
+        assert_eq!(reasons(&all, &web), None);
+        let any = selector(Combine::Any, false);
+        assert_eq!(
+            reasons(&any, &web).as_deref(),
+            Some("--match ^apps/ or ^libs/")
+        );
+        let inverted = selector(Combine::All, true);
+        assert_eq!(
+            reasons(&inverted, &cli).as_deref(),
+            Some("not --match ^apps/ or ^libs/, not --where 'detached'")
+        );
+        assert_eq!(Selector::default().reasons(&cli), Some(Vec::new()));
+    }
+
+    #[test]
+    fn selector_evaluates_where_expressions() {
+        let dir = tempdir().unwrap();
+        let main_repo = dir.path().join("apps").join("main");
+        let feature_repo = dir.path().join("apps").join("feature");
+        create_repo_on_branch(&main_repo, "ref: refs/heads/main\n");
+        create_repo_on_branch(&feature_repo, "ref: refs/heads/feature\n");
+        let expr = expr::parse(r#"branch == "main" && path =~ "^apps/" && !detached"#).unwrap();
+        let selector = Selector {
+            roots: vec![dir.path().to_path_buf()],
+            criteria: vec![Criterion::Where(expr)],
+            ..Selector::default()
+        };
+
+        assert_eq!(
+            selector.apply(vec![main_repo.clone(), feature_repo]),
+            vec![main_repo]
+        );
+    }
+}
@@ -0,0 +1,86 @@
+/*
+ * grpr - A CLI tool for recursively executing git commands.
+ *
+ * Copyright (c) 2025 Anupam Sengupta
+ *
+ * This source code is licensed under the MIT license found in the LICENSE file
+ * in the root directory of this source tree.
+ */
+
+use std::sync::{Condvar, Mutex};
+
+/// Limits how many workers may do something at once, such as talking to
+/// remotes, independently of the number of worker threads.
+#[derive(Debug)]
+pub struct Semaphore {
+    available: Mutex<usize>,
+    released: Condvar,
+}
+
+/// A claim on one of a [`Semaphore`]'s permits, returned when dropped.
+#[must_use]
+pub struct Permit<'a> {
+    semaphore: &'a Semaphore,
+}
+
+impl Semaphore {
+    /// Creates a semaphore with `permits` permits.
+    pub fn new(permits: usize) -> Self {
+        Self {
+            available: Mutex::new(permits),
+            released: Condvar::new(),
+        }
+    }
+
+    /// Waits until a permit is available and takes it.
+    pub fn acquire(&self) -> Permit<'_> {
+        let available = self.available.lock().unwrap_or_else(|err| err.into_inner());
+        let mut available = self
+            .released
+            .wait_while(available, |available| *available == 0)
+            .unwrap_or_else(|err| err.into_inner());
+        *available -= 1;
+        Permit { semaphore: self }
+    }
+}
+
+impl Drop for Permit<'_> {
+    fn drop(&mut self) {
+        let mut available = self
+            .semaphore
+            .available
+            .lock()
+            .unwrap_or_else(|err| err.into_inner());
+        *available += 1;
+        self.semaphore.released.notify_one();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::thread;
+    use std::time::Duration;
+
+    #[test]
+    fn semaphore_limits_concurrent_holders() {
+        let semaphore = Semaphore::new(2);
+        let holders = AtomicUsize::new(0);
+        let most_holders = AtomicUsize::new(0);
+
+        thread::scope(|scope| {
+            for _ in 0..8 {
+                scope.spawn(|| {
+                    let _permit = semaphore.acquire();
+                    let now = holders.fetch_add(1, Ordering::SeqCst) + 1;
+                    most_holders.fetch_max(now, Ordering::SeqCst);
+                    thread::sleep(Duration::from_millis(10));
+                    holders.fetch_sub(1, Ordering::SeqCst);
+                });
+            }
+        });
+
+        assert_eq!(most_holders.load(Ordering::SeqCst), 2);
+    }
+}
@@ -0,0 +1,65 @@
+/*
+ * grpr - A CLI tool for recursively executing git commands.
+ *
+ * Copyright (c) 2025 Anupam Sengupta
+ *
+ * This source code is licensed under the MIT license found in the LICENSE file
+ * in the root directory of this source tree.
+ */
+
+const UNITS: [&str; 5] = ["", "K", "M", "G", "T"];
+
+/// Parses a size in bytes such as `4096`, `500K`, `1.5G`, or `2GiB`. Units are
+/// powers of 1024, case-insensitive, and may end in `B` or `iB`.
+pub fn parse_size(value: &str) -> Result<u64, String> {
+    let value = value.trim();
+    let split = value
+        .find(|c: char| !c.is_ascii_digit() && c != '.')
+        .unwrap_or(value.len());
+    let (amount, unit) = value.split_at(split);
+
+    let amount: f64 = amount
+        .parse()
+        .map_err(|_| format!("invalid size `{value}`: expected a number and a unit"))?;
+    let unit = unit.to_ascii_uppercase();
+    let unit = unit
+        .strip_suffix("IB")
+        .or_else(|| unit.strip_suffix('B'))
+        .unwrap_or(&unit);
+    let exponent = UNITS
+        .iter()
+        .position(|name| *name == unit)
+        .ok_or_else(|| format!("invalid size `{value}`: unit must be one of B, K, M, G, T"))?;
+
+    let bytes = amount * 1024_f64.powi(exponent as i32);
+    if bytes >= u64::MAX as f64 {
+        return Err(format!("invalid size `{value}`: too large"));
+    }
+    Ok(bytes.round() as u64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_size_accepts_each_unit() {
+        assert_eq!(parse_size("4096"), Ok(4096));
+        assert_eq!(parse_size("10B"), Ok(10));
+        assert_eq!(parse_size("500k"), Ok(500 * 1024));
+        assert_eq!(parse_size("20MB"), Ok(20 * 1024 * 1024));
+        assert_eq!(parse_size("1.5G"), Ok(3 * 512 * 1024 * 1024));
+        assert_eq!(parse_size("2GiB"), Ok(2 * 1024 * 1024 * 1024));
+        assert_eq!(parse_size("1T"), Ok(1024_u64.pow(4)));
+    }
+
+    #[test]
+    fn parse_size_rejects_malformed_values() {
+        assert!(parse_size("").is_err());
+        assert!(parse_size("M").is_err());
+        assert!(parse_size("5X").is_err());
+        assert!(parse_size("1.2.3M").is_err());
+        assert!(parse_size("-5M").is_err());
+        assert!(parse_size("99999999999T").is_err());
+    }
+}
@@ -0,0 +1,159 @@
+/*
+ * grpr - A CLI tool for recursively executing git commands.
+ *
+ * Copyright (c) 2025 Anupam Sengupta
+ *
+ * This source code is licensed under the MIT license found in the LICENSE file
+ * in the root directory of this source tree.
+ */
+
+use crate::grpgit::Captured;
+use crate::output::{self, Outcome};
+use std::io::{self, Write};
+
+/// The headings of the `--table` columns.
+const HEADINGS: [&str; 2] = ["Repository", "Output"];
+
+/// One repository in the `--table`.
+#[derive(Debug, PartialEq, Eq)]
+pub struct TableEntry {
+    /// The repository's path relative to its root.
+    pub name: String,
+    pub outcome: Outcome,
+    /// The lines of its output, the first beside the name and the rest
+    /// below it.
+    pub lines: Vec<String>,
+}
+
+/// Returns the lines of `output` up to its last line with something on it.
+fn lines(output: &[u8]) -> Vec<String> {
+    let output = String::from_utf8_lossy(output);
+    let mut lines: Vec<String> = output
+        .lines()
+        .map(|line| line.trim_end().to_string())
+        .collect();
+    while lines.last().is_some_and(String::is_empty) {
+        lines.pop();
+    }
+    lines
+}
+
+impl TableEntry {
+    /// Describes the captured run in the repository `name`: its stdout, or
+    /// its stderr when stdout is empty, or why it failed when both are.
+    pub fn new(name: String, captured: &Captured) -> Self {
+        let mut output = lines(&captured.stdout);
+        if output.is_empty() {
+            output = lines(&captured.stderr);
+        }
+        let outcome = match &captured.result {
+            Ok(()) => Outcome::Succeeded,
+            Err(err) => {
+                if output.is_empty() {
+                    output.push(err.to_string());
+                }
+                Outcome::Failed
+            }
+        };
+        Self {
+            name,
+            outcome,
+            lines: output,
+        }
+    }
+
+    /// Describes the repository `name`, skipped because of `reason`.
+    pub fn skipped(name: String, reason: &str) -> Self {
+        Self {
+            name,
+            outcome: Outcome::Skipped,
+            lines: vec![format!("(skipped: {reason})")],
+        }
+    }
+}
+
+/// Writes `entries` as a table with the repository names aligned in the
+/// first column, colored by outcome on a terminal, and their output in the
+/// second.
+pub fn write_table(mut out: impl Write, entries: &[TableEntry]) -> io::Result<()> {
+    let width = entries
+        .iter()
+        .map(|entry| entry.name.chars().count())
+        .chain([HEADINGS[0].len()])
+        .max()
+        .unwrap_or_default();
+    let [name, output] = HEADINGS;
+    writeln!(
+        out,
+        "{}  {}",
+        output::table_heading(&format!("{name:<width$}")),
+        output::table_heading(output)
+    )?;
+    for entry in entries {
+        let mut lines = entry.lines.iter();
+        match lines.next() {
+            Some(line) => {
+                let name = format!("{:<width$}", entry.name);
+                writeln!(out, "{}  {line}", output::table_cell(&name, entry.outcome))?;
+            }
+            None => writeln!(out, "{}", output::table_cell(&entry.name, entry.outcome))?,
+        }
+        for line in lines {
+            writeln!(out, "{:width$}  {line}", "")?;
+        }
+    }
+    out.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn captured(stdout: &str, failed: bool) -> Captured {
+        Captured {
+            stdout: stdout.as_bytes().to_vec(),
+            stderr: b"fatal: not a branch\n".to_vec(),
+            exit_code: Some(i32::from(failed)),
+            result: if failed {
+                Err(io::Error::other("git command failed"))
+            } else {
+                Ok(())
+            },
+            spilled: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn entries_hold_stdout_or_else_stderr() {
+        let entry = TableEntry::new("app".to_string(), &captured("main\n\n", false));
+        assert_eq!(entry.lines, ["main"]);
+        assert_eq!(entry.outcome, Outcome::Succeeded);
+
+        let entry = TableEntry::new("app".to_string(), &captured("", true));
+        assert_eq!(entry.lines, ["fatal: not a branch"]);
+        assert_eq!(entry.outcome, Outcome::Failed);
+    }
+
+    #[test]
+    fn table_aligns_the_output_column() {
+        let entries = [
+            TableEntry::new("app".to_string(), &captured("main\n", false)),
+            TableEntry::new(
+                "libs/shared".to_string(),
+                &captured("* develop\n  main\n", false),
+            ),
+            TableEntry::skipped("web".to_string(), "dirty"),
+        ];
+
+        let mut out = Vec::new();
+        write_table(&mut out, &entries).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "Repository   Output\n\
+             app          main\n\
+             libs/shared  * develop\n\
+             \x20              main\n\
+             web          (skipped: dirty)\n"
+        );
+    }
+}
@@ -0,0 +1,188 @@
+/*
+ * grpr - A CLI tool for recursively executing git commands.
+ *
+ * Copyright (c) 2025 Anupam Sengupta
+ *
+ * This source code is licensed under the MIT license found in the LICENSE file
+ * in the root directory of this source tree.
+ */
+
+use crate::config::TaskRule;
+use crate::expr::{self, Expr};
+use crate::grpgit::RepoCommand;
+use crate::selector::{Criterion, Selector};
+use rayon::prelude::*;
+use std::error::Error;
+use std::path::{Path, PathBuf};
+
+/// A command that differs between repositories, such as `pull --rebase` in
+/// your own repositories and `fetch` in read-only mirrors. Each repository
+/// runs the command of the first rule whose expression selects it.
+#[derive(Debug)]
+pub struct Task {
+    name: String,
+    steps: Vec<Step>,
+}
+
+#[derive(Debug)]
+struct Step {
+    /// The repositories the step applies to, or `None` for all of them.
+    condition: Option<Expr>,
+    command: RepoCommand,
+}
+
+impl Task {
+    /// Builds the task `name` from its configured rules, turning each rule's
+    /// git arguments into a command with `make_command`. Expressions are
+    /// checked here, before anything runs.
+    pub fn new(
+        name: &str,
+        rules: &[TaskRule],
+        rest: &[String],
+        mut make_command: impl FnMut(Vec<String>) -> Result<RepoCommand, Box<dyn Error>>,
+    ) -> Result<Self, Box<dyn Error>> {
+        let steps = rules
+            .iter()
+            .map(|rule| {
+                let condition = match &rule.condition {
+                    Some(condition) => Some(
+                        expr::parse(condition)
+                            .map_err(|err| format!("invalid `where` in task `{name}`: {err}"))?,
+                    ),
+                    None => None,
+                };
+                let command = make_command(rule.expand(rest))?;
+                Ok(Step { condition, command })
+            })
+            .collect::<Result<_, Box<dyn Error>>>()?;
+
+        Ok(Self {
+            name: name.to_string(),
+            steps,
+        })
+    }
+
+    /// Returns the command of every rule.
+    pub fn commands(&self) -> impl Iterator<Item = &RepoCommand> {
+        self.steps.iter().map(|step| &step.command)
+    }
+
+    /// Pairs each rule's command with the repositories it runs in, keeping
+    /// their order. Repositories that no rule selects are reported and left
+    /// out. Paths in expressions are relative to `roots`.
+    pub fn assign(
+        &self,
+        repositories: &[PathBuf],
+        roots: &[PathBuf],
+    ) -> Vec<(&RepoCommand, Vec<PathBuf>)> {
+        let selectors: Vec<Option<Selector>> = self
+            .steps
+            .iter()
+            .map(|step| {
+                step.condition.as_ref().map(|condition| Selector {
+                    criteria: vec![Criterion::Where(condition.clone())],
+                    roots: roots.to_vec(),
+                    ..Selector::default()
+                })
+            })
+            .collect();
+        let chosen: Vec<Option<usize>> = repositories
+            .par_iter()
+            .map(|repo_path| {
+                selectors
+                    .iter()
+                    .position(|selector| selector_matches(selector.as_ref(), repo_path))
+            })
+            .collect();
+
+        let mut groups: Vec<Vec<PathBuf>> = vec![Vec::new(); self.steps.len()];
+        for (repo_path, step) in repositories.iter().zip(chosen) {
+            match step {
+                Some(step) => groups[step].push(repo_path.clone()),
+                None => tracing::info!(
+                    "{}: skipped: no rule of task `{}` selects it",
+                    repo_path.display(),
+                    self.name
+                ),
+            }
+        }
+
+        self.commands()
+            .zip(groups)
+            .filter(|(_, repositories)| !repositories.is_empty())
+            .collect()
+    }
+}
+
+fn selector_matches(selector: Option<&Selector>, repo_path: &Path) -> bool {
+    selector.is_none_or(|selector| selector.matches(repo_path))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::tempdir;
+
+    fn rule(condition: Option<&str>, command: &str) -> TaskRule {
+        TaskRule {
+            condition: condition.map(String::from),
+            command: command.to_string(),
+        }
+    }
+
+    fn task(rules: &[TaskRule]) -> Result<Task, Box<dyn Error>> {
+        Task::new("sync-all", rules, &[], |args| Ok(RepoCommand::git(args)))
+    }
+
+    #[test]
+    fn assign_uses_the_first_matching_rule() {
+        let dir = tempdir().unwrap();
+        let repositories: Vec<PathBuf> = ["mine/app", "mirrors/linux", "other"]
+            .map(|name| dir.path().join(name))
+            .into();
+        for repo_path in &repositories {
+            fs::create_dir_all(repo_path).unwrap();
+        }
+        let task = task(&[
+            rule(Some("path =~ \"^mine/\""), "pull --rebase"),
+            rule(Some("path =~ \"^mirrors/\""), "fetch"),
+        ])
+        .unwrap();
+
+        let assigned = task.assign(&repositories, &[dir.path().to_path_buf()]);
+
+        assert_eq!(
+            assigned,
+            vec![
+                (
+                    &RepoCommand::git(vec!["pull".into(), "--rebase".into()]),
+                    vec![repositories[0].clone()]
+                ),
+                (
+                    &RepoCommand::git(vec!["fetch".into()]),
+                    vec![repositories[1].clone()]
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn rules_without_a_condition_take_the_rest() {
+        let dir = tempdir().unwrap();
+        let repositories = vec![dir.path().join("a"), dir.path().join("b")];
+        let task = task(&[rule(Some("path == \"a\""), "pull"), rule(None, "fetch")]).unwrap();
+
+        let assigned = task.assign(&repositories, &[dir.path().to_path_buf()]);
+
+        assert_eq!(assigned.len(), 2);
+        assert_eq!(assigned[1].1, vec![dir.path().join("b")]);
+    }
+
+    #[test]
+    fn new_rejects_invalid_expressions() {
+        let err = task(&[rule(Some("dirty =="), "pull")]).unwrap_err();
+
+        assert!(err.to_string().contains("task `sync-all`"));
+    }
+}
@@ -0,0 +1,32 @@
+/*
+ * grpr - A CLI tool for recursively executing git commands.
+ *
+ * Copyright (c) 2025 Anupam Sengupta
+ *
+ * This source code is licensed under the MIT license found in the LICENSE file
+ * in the root directory of this source tree.
+ */
+
+use std::path::Path;
+use std::process::{Command, Stdio};
+
+/// Runs git with `args` in `dir`, committing as `grpr`, and fails the test
+/// if it fails.
+pub fn git(dir: &Path, args: &[&str]) {
+    git_with_env(dir, &[], args);
+}
+
+/// Runs git as [`git`] does, with the environment variables `env` set, for
+/// settings such as `GIT_COMMITTER_DATE` that only the environment gives.
+pub fn git_with_env(dir: &Path, env: &[(&str, &str)], args: &[&str]) {
+    let status = Command::new("git")
+        .args(["-c", "user.name=grpr", "-c", "user.email=grpr@example.com"])
+        .args(args)
+        .envs(env.iter().copied())
+        .current_dir(dir)
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .unwrap();
+    assert!(status.success(), "git {args:?} failed in {}", dir.display());
+}
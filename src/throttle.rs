@@ -0,0 +1,106 @@
+/*
+ * grpr - A CLI tool for recursively executing git commands.
+ *
+ * Copyright (c) 2025 Anupam Sengupta
+ *
+ * This source code is licensed under the MIT license found in the LICENSE file
+ * in the root directory of this source tree.
+ */
+
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Spaces out the start of commands so no more than a fixed number start per
+/// second, however many workers are waiting, for servers whose hooks or APIs
+/// enforce quotas.
+#[derive(Debug)]
+pub struct Throttle {
+    interval: Duration,
+    /// The earliest time the next command may start.
+    next: Mutex<Option<Instant>>,
+}
+
+/// The longest interval between commands, some 136 years, so that adding
+/// up intervals cannot overflow an [`Instant`].
+const MAX_INTERVAL: Duration = Duration::from_secs(u32::MAX as u64);
+
+/// Returns the interval between commands started at `rate` per second, or
+/// `None` when it is too long to wait.
+fn interval(rate: f64) -> Option<Duration> {
+    Duration::try_from_secs_f64(1.0 / rate)
+        .ok()
+        .filter(|interval| *interval <= MAX_INTERVAL)
+}
+
+/// Parses a rate in commands per second, such as `5` or `0.5`.
+pub fn parse_rate(value: &str) -> Result<f64, String> {
+    let rate: f64 = value
+        .trim()
+        .parse()
+        .map_err(|_| format!("invalid rate `{value}`: expected commands per second"))?;
+    if !rate.is_finite() || rate <= 0.0 {
+        return Err(format!("invalid rate `{value}`: must be greater than 0"));
+    }
+    if interval(rate).is_none() {
+        return Err(format!("invalid rate `{value}`: too low"));
+    }
+    Ok(rate)
+}
+
+impl Throttle {
+    /// Creates a throttle allowing `rate` commands to start per second, as
+    /// accepted by [`parse_rate`]. Lower rates wait the longest interval.
+    pub fn new(rate: f64) -> Self {
+        Self {
+            interval: interval(rate).unwrap_or(MAX_INTERVAL),
+            next: Mutex::new(None),
+        }
+    }
+
+    /// Waits until the next command may start. Callers are let through one
+    /// interval apart, in the order they arrive.
+    pub fn wait(&self) {
+        let now = Instant::now();
+        let start = {
+            let mut next = self.next.lock().unwrap_or_else(|err| err.into_inner());
+            let start = next.map_or(now, |next| next.max(now));
+            *next = Some(start + self.interval);
+            start
+        };
+        thread::sleep(start - now);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_rate_accepts_positive_numbers() {
+        assert_eq!(parse_rate("5"), Ok(5.0));
+        assert_eq!(parse_rate("0.5"), Ok(0.5));
+        assert!(parse_rate("0").is_err());
+        assert!(parse_rate("-2").is_err());
+        assert!(parse_rate("fast").is_err());
+        assert!(parse_rate("inf").is_err());
+        assert!(parse_rate("1e-300").is_err());
+        assert!(parse_rate("1e-10").is_err());
+        assert_eq!(Throttle::new(1e-300).interval, MAX_INTERVAL);
+    }
+
+    #[test]
+    fn throttle_spaces_out_callers() {
+        let throttle = Throttle::new(50.0);
+        let started = Instant::now();
+
+        thread::scope(|scope| {
+            for _ in 0..4 {
+                scope.spawn(|| throttle.wait());
+            }
+        });
+
+        // The first caller starts at once and the others 20ms apart.
+        assert!(started.elapsed() >= Duration::from_millis(60));
+    }
+}
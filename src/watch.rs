@@ -0,0 +1,251 @@
+/*
+ * grpr - A CLI tool for recursively executing git commands.
+ *
+ * Copyright (c) 2025 Anupam Sengupta
+ *
+ * This source code is licensed under the MIT license found in the LICENSE file
+ * in the root directory of this source tree.
+ */
+
+use crate::interrupt;
+use notify::{Event, EventKind, RecursiveMode, Watcher};
+use std::collections::BTreeSet;
+use std::error::Error;
+use std::ffi::OsStr;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
+use std::time::{Duration, Instant};
+
+/// How long to keep collecting events after the first one before re-running,
+/// so a burst of writes triggers a single run.
+const DEBOUNCE: Duration = Duration::from_millis(300);
+
+/// How often an idle watch checks whether grpr was interrupted.
+const INTERRUPT_POLL_INTERVAL: Duration = Duration::from_millis(200);
+
+const GIT_PATH_NAME: &str = ".git";
+const LOCK_EXTENSION: &str = "lock";
+
+/// Entries inside `.git` whose changes mean the repository state changed.
+const GIT_STATE_ENTRIES: [&str; 3] = ["HEAD", "index", "refs"];
+
+/// Returns the repository containing `path`, preferring the most deeply nested
+/// one when repositories are nested.
+fn owning_repository<'a>(repositories: &'a [PathBuf], path: &Path) -> Option<&'a PathBuf> {
+    repositories
+        .iter()
+        .filter(|repo_path| path.starts_with(repo_path))
+        .max_by_key(|repo_path| repo_path.components().count())
+}
+
+/// Returns true when a change to `path` inside `repo_path` should trigger a
+/// re-run: working tree changes, or updates to `HEAD`, the index, or refs.
+/// Lock files and object store writes are ignored.
+fn is_relevant_change(repo_path: &Path, path: &Path) -> bool {
+    let Ok(relative) = path.strip_prefix(repo_path) else {
+        return false;
+    };
+    if path.extension() == Some(OsStr::new(LOCK_EXTENSION)) {
+        return false;
+    }
+
+    let mut components = relative.components();
+    match components.next() {
+        Some(first) if first.as_os_str() == GIT_PATH_NAME => {
+            components.next().is_some_and(|entry| {
+                GIT_STATE_ENTRIES
+                    .iter()
+                    .any(|name| entry.as_os_str() == *name)
+            })
+        }
+        Some(_) => true,
+        None => false,
+    }
+}
+
+/// Adds the repositories affected by `event` to `changed`.
+fn collect_changes(
+    repositories: &[PathBuf],
+    event: notify::Result<Event>,
+    changed: &mut BTreeSet<PathBuf>,
+) {
+    let event = match event {
+        Ok(event) => event,
+        Err(err) => {
+            tracing::error!("cannot watch repositories: {err}");
+            return;
+        }
+    };
+
+    if !matches!(
+        event.kind,
+        EventKind::Create(_) | EventKind::Modify(_) | EventKind::Remove(_)
+    ) {
+        return;
+    }
+
+    for path in &event.paths {
+        if let Some(repo_path) = owning_repository(repositories, path) {
+            if is_relevant_change(repo_path, path) {
+                changed.insert(repo_path.clone());
+            }
+        }
+    }
+}
+
+/// Collects events until the channel has been quiet for [`DEBOUNCE`], or
+/// discards them when `changed` is `None`.
+fn drain_events(
+    repositories: &[PathBuf],
+    events: &Receiver<notify::Result<Event>>,
+    mut changed: Option<&mut BTreeSet<PathBuf>>,
+) {
+    let deadline = Instant::now() + DEBOUNCE;
+    while let Ok(event) = events.recv_timeout(deadline.saturating_duration_since(Instant::now())) {
+        if let Some(changed) = changed.as_deref_mut() {
+            collect_changes(repositories, event, changed);
+        }
+    }
+}
+
+/// Watches `repositories` for changes and calls `on_change` with the changed
+/// repositories, in their original order, after each burst of changes. Events
+/// raised while `on_change` runs, such as git refreshing the index, are
+/// discarded so a run does not trigger itself. Returns once grpr is
+/// interrupted.
+pub fn watch_repositories<F>(
+    repositories: &[PathBuf],
+    mut on_change: F,
+) -> Result<(), Box<dyn Error>>
+where
+    F: FnMut(&[PathBuf]),
+{
+    let (sender, events) = mpsc::channel();
+    let mut watcher = notify::recommended_watcher(sender)?;
+
+    for repo_path in repositories {
+        if let Err(err) = watcher.watch(repo_path, RecursiveMode::Recursive) {
+            tracing::warn!("cannot watch {}: {err}", repo_path.display());
+        }
+    }
+    tracing::info!(
+        "watching {} repositories for changes (Ctrl-C to stop)",
+        repositories.len()
+    );
+
+    loop {
+        let mut changed = BTreeSet::new();
+        let event = loop {
+            match events.recv_timeout(INTERRUPT_POLL_INTERVAL) {
+                Ok(event) => break event,
+                Err(RecvTimeoutError::Timeout) if interrupt::interrupted() => return Ok(()),
+                Err(RecvTimeoutError::Timeout) => {}
+                Err(RecvTimeoutError::Disconnected) => return Err(mpsc::RecvError.into()),
+            }
+        };
+        collect_changes(repositories, event, &mut changed);
+        drain_events(repositories, &events, Some(&mut changed));
+
+        if changed.is_empty() {
+            continue;
+        }
+
+        let changed: Vec<PathBuf> = repositories
+            .iter()
+            .filter(|repo_path| changed.contains(*repo_path))
+            .cloned()
+            .collect();
+        tracing::info!("changes detected in {} repositories", changed.len());
+        on_change(&changed);
+        drain_events(repositories, &events, None);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use notify::event::{AccessKind, CreateKind, ModifyKind};
+
+    fn event(kind: EventKind, path: &str) -> notify::Result<Event> {
+        Ok(Event::new(kind).add_path(PathBuf::from(path)))
+    }
+
+    #[test]
+    fn owning_repository_prefers_the_innermost_repository() {
+        let repositories = vec![
+            PathBuf::from("/src/app"),
+            PathBuf::from("/src/app/vendor/lib"),
+        ];
+
+        assert_eq!(
+            owning_repository(&repositories, Path::new("/src/app/vendor/lib/README.md")),
+            Some(&repositories[1])
+        );
+        assert_eq!(
+            owning_repository(&repositories, Path::new("/src/app/main.rs")),
+            Some(&repositories[0])
+        );
+        assert_eq!(
+            owning_repository(&repositories, Path::new("/src/application")),
+            None
+        );
+    }
+
+    #[test]
+    fn is_relevant_change_tracks_working_tree_and_repository_state() {
+        let repo_path = Path::new("/src/app");
+
+        assert!(is_relevant_change(
+            repo_path,
+            Path::new("/src/app/src/main.rs")
+        ));
+        assert!(is_relevant_change(
+            repo_path,
+            Path::new("/src/app/.git/HEAD")
+        ));
+        assert!(is_relevant_change(
+            repo_path,
+            Path::new("/src/app/.git/index")
+        ));
+        assert!(is_relevant_change(
+            repo_path,
+            Path::new("/src/app/.git/refs/heads/main")
+        ));
+        assert!(!is_relevant_change(
+            repo_path,
+            Path::new("/src/app/.git/index.lock")
+        ));
+        assert!(!is_relevant_change(
+            repo_path,
+            Path::new("/src/app/.git/objects/ab/cdef")
+        ));
+        assert!(!is_relevant_change(repo_path, Path::new("/src/app")));
+    }
+
+    #[test]
+    fn collect_changes_ignores_access_events_and_unrelated_paths() {
+        let repositories = vec![PathBuf::from("/src/app"), PathBuf::from("/src/lib")];
+        let mut changed = BTreeSet::new();
+
+        collect_changes(
+            &repositories,
+            event(EventKind::Access(AccessKind::Any), "/src/lib/file"),
+            &mut changed,
+        );
+        collect_changes(
+            &repositories,
+            event(EventKind::Create(CreateKind::File), "/elsewhere/file"),
+            &mut changed,
+        );
+        collect_changes(
+            &repositories,
+            event(EventKind::Modify(ModifyKind::Any), "/src/app/file"),
+            &mut changed,
+        );
+
+        assert_eq!(
+            changed.into_iter().collect::<Vec<_>>(),
+            vec![PathBuf::from("/src/app")]
+        );
+    }
+}